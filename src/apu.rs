@@ -0,0 +1,695 @@
+use wasm_bindgen::prelude::*;
+
+use crate::clock::TvSystem;
+
+// APU
+// The 2A03's audio processing unit. Its channels are clocked by the CPU clock,
+// the pulse channels' timers only on every second CPU cycle (an APU cycle).
+// Envelopes, sweeps and length counters are clocked by the frame counter in
+// quarter and half frame steps. The frame counter and the DMC each have their
+// own IRQ.
+// https://www.nesdev.org/wiki/APU
+
+// Note lengths loaded into the length counters, indexed by the top five bits
+// of the fourth register of a channel
+// https://www.nesdev.org/wiki/APU_Length_Counter
+const LENGTH_TABLE: [u8; 32] = [
+    10, 254, 20,  2, 40,  4, 80,  6, 160,  8, 60, 10, 14, 12, 26, 14,
+    12,  16, 24, 18, 48, 20, 96, 22, 192, 24, 72, 26, 16, 28, 32, 30,
+];
+
+// Waveforms of the four duty cycles: 12.5%, 25%, 50% and 25% negated. The
+// sequencer counts down, so they are read from right to left.
+const DUTY_TABLE: [[u8; 8]; 4] = [
+    [0, 1, 0, 0, 0, 0, 0, 0],
+    [0, 1, 1, 0, 0, 0, 0, 0],
+    [0, 1, 1, 1, 1, 0, 0, 0],
+    [1, 0, 0, 1, 1, 1, 1, 1],
+];
+
+
+// Envelope
+// Either a constant volume or a sawtooth that decays from 15 to 0, one step
+// per period + 1 quarter frames, and optionally starts over
+// https://www.nesdev.org/wiki/APU_Envelope
+#[derive(Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Envelope {
+    start:           bool,
+    looping:         bool, // shares its bit with the length counter halt flag
+    constant_volume: bool,
+    period:          u8,   // also the constant volume
+    divider:         u8,
+    decay:           u8,
+}
+
+impl Envelope {
+    pub fn clock(&mut self) {
+        if self.start {
+            self.start   = false;
+            self.decay   = 15;
+            self.divider = self.period;
+        } else if self.divider == 0 {
+            self.divider = self.period;
+            if self.decay > 0 {
+                self.decay -= 1;
+            } else if self.looping {
+                self.decay = 15;
+            }
+        } else {
+            self.divider -= 1;
+        }
+    }
+
+    pub fn volume(&self) -> u8 {
+        if self.constant_volume { self.period } else { self.decay }
+    }
+}
+
+
+// Length counter
+// Silences the channel once the note has been played for its length, unless
+// it is halted. Loading it has no effect while the channel is disabled.
+#[derive(Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LengthCounter {
+    enabled: bool,
+    halt:    bool,
+    counter: u8,
+}
+
+impl LengthCounter {
+    pub fn load(&mut self, index: u8) {
+        if self.enabled {
+            self.counter = LENGTH_TABLE[(index & 0x1F) as usize];
+        }
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.counter = 0;
+        }
+    }
+
+    pub fn clock(&mut self) {
+        if !self.halt && self.counter > 0 {
+            self.counter -= 1;
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.counter > 0
+    }
+}
+
+
+// Pulse channel
+//     0x4000 / 0x4004: DDLC VVVV - duty, length counter halt / envelope loop, constant volume, volume / envelope period
+//     0x4001 / 0x4005: EPPP NSSS - sweep enable, period, negate, shift
+//     0x4002 / 0x4006: LLLL LLLL - timer low
+//     0x4003 / 0x4007: LLLL LHHH - length counter load, timer high
+// The sweep of pulse 1 negates with the ones' complement, pulse 2 with the
+// two's complement, so their target periods differ by one when going down.
+// https://www.nesdev.org/wiki/APU_Pulse
+#[derive(Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Pulse {
+    ones_complement: bool, // pulse 1
+    duty:            u8,
+    sequence:        u8,   // step of the sequencer, counts down
+    timer_period:    u16,  // 11 bit
+    timer:           u16,
+    pub envelope:    Envelope,
+    pub length:      LengthCounter,
+
+    // sweep unit
+    sweep_enabled:   bool,
+    sweep_period:    u8,
+    sweep_negate:    bool,
+    sweep_shift:     u8,
+    sweep_divider:   u8,
+    sweep_reload:    bool,
+}
+
+impl Pulse {
+    pub fn new(ones_complement: bool) -> Self {
+        Self {
+            ones_complement,
+            ..Default::default()
+        }
+    }
+
+    // reg is the register within the channel, 0 - 3
+    pub fn write(&mut self, reg: u16, data: u8) {
+        match reg {
+            0 => {
+                self.duty                     = data >> 6;
+                self.length.halt              = data & 0x20 != 0;
+                self.envelope.looping         = data & 0x20 != 0;
+                self.envelope.constant_volume = data & 0x10 != 0;
+                self.envelope.period          = data & 0x0F;
+            },
+            1 => {
+                self.sweep_enabled = data & 0x80 != 0;
+                self.sweep_period  = (data >> 4) & 0x07;
+                self.sweep_negate  = data & 0x08 != 0;
+                self.sweep_shift   = data & 0x07;
+                self.sweep_reload  = true;
+            },
+            2 => {
+                self.timer_period = (self.timer_period & 0x0700) | data as u16;
+            },
+            _ => {
+                self.timer_period = (self.timer_period & 0x00FF) | (((data & 0x07) as u16) << 8);
+                self.length.load(data >> 3);
+                // Starts a new note: the envelope restarts and the sequencer
+                // goes back to the first step, the timer is not touched
+                self.envelope.start = true;
+                self.sequence       = 0;
+            },
+        }
+    }
+
+    // Once per APU cycle
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer    = self.timer_period;
+            self.sequence = self.sequence.wrapping_sub(1) & 0x07;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub fn clock_quarter_frame(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub fn clock_half_frame(&mut self) {
+        self.length.clock();
+
+        // The sweep only adjusts the period if it has something to do, but
+        // its divider runs regardless
+        if self.sweep_divider == 0 && self.sweep_enabled && self.sweep_shift > 0 && !self.sweep_muting() {
+            self.timer_period = self.sweep_target();
+        }
+        if self.sweep_divider == 0 || self.sweep_reload {
+            self.sweep_divider = self.sweep_period;
+            self.sweep_reload  = false;
+        } else {
+            self.sweep_divider -= 1;
+        }
+    }
+
+    // The period the sweep unit is heading for, computed continuously
+    fn sweep_target(&self) -> u16 {
+        let change = self.timer_period >> self.sweep_shift;
+        if !self.sweep_negate {
+            self.timer_period + change
+        } else if self.ones_complement {
+            self.timer_period.saturating_sub(change + 1)
+        } else {
+            self.timer_period.saturating_sub(change)
+        }
+    }
+
+    // Too high notes and targets out of the timer's range mute the channel,
+    // even if the sweep is disabled
+    fn sweep_muting(&self) -> bool {
+        self.timer_period < 8 || self.sweep_target() > 0x07FF
+    }
+
+    pub fn period(&self) -> u16 {
+        self.timer_period
+    }
+
+    pub fn state(&self, cpu_clock_rate: f64) -> ChannelState {
+        ChannelState {
+            active:    self.length.is_active(),
+            period:    self.timer_period,
+            frequency: (cpu_clock_rate / (16.0 * (self.timer_period as f64 + 1.0))) as f32,
+            volume:    self.envelope.volume(),
+            duty:      self.duty,
+            output:    self.output(),
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        if !self.length.is_active() || self.sweep_muting() || DUTY_TABLE[self.duty as usize][self.sequence as usize] == 0 {
+            0
+        } else {
+            self.envelope.volume()
+        }
+    }
+}
+
+
+// Periods of the DMC's output unit in CPU cycles, NTSC (also Dendy) and PAL
+const DMC_RATE_TABLE: [u16; 16] = [
+    428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
+];
+const DMC_RATE_TABLE_PAL: [u16; 16] = [
+    398, 354, 316, 298, 276, 236, 210, 198, 176, 148, 132, 118, 98, 78, 66, 50,
+];
+
+// Delta modulation channel (DMC)
+//     0x4010: IL-- RRRR - IRQ enable, loop, rate index
+//     0x4011: -DDD DDDD - direct load of the output level
+//     0x4012: AAAA AAAA - sample address, $C000 + A * 64
+//     0x4013: LLLL LLLL - sample length, L * 16 + 1 bytes
+// The memory reader doesn't access memory itself: wants_sample() tells the
+// bus to fetch the byte at sample_addr() with a DMC DMA, which stalls the CPU,
+// and the bus hands it back through fill_sample(). Each bit of a sample byte
+// moves the 7 bit output level up or down by 2.
+// https://www.nesdev.org/wiki/APU_DMC
+#[derive(Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Dmc {
+    pal:             bool,
+    irq_enabled:     bool,
+    looping:         bool,
+    rate:            u16,
+    timer:           u16,
+    level:           u8,
+
+    // memory reader
+    sample_address:  u16,
+    sample_length:   u16,
+    current_address: u16,
+    bytes_remaining: u16,
+    buffer:          Option<u8>,
+
+    // output unit
+    shift:           u8,
+    bits_remaining:  u8,
+    silence:         bool,
+
+    pub irq:         bool,
+}
+
+impl Dmc {
+    pub fn new() -> Self {
+        Self {
+            rate:           DMC_RATE_TABLE[0],
+            sample_address: 0xC000,
+            sample_length:  1,
+            bits_remaining: 8,
+            silence:        true,
+            ..Default::default()
+        }
+    }
+
+    // reg is the register within the channel, 0 - 3
+    pub fn write(&mut self, reg: u16, data: u8) {
+        match reg {
+            0 => {
+                self.irq_enabled = data & 0x80 != 0;
+                self.looping     = data & 0x40 != 0;
+                self.rate        = if self.pal { DMC_RATE_TABLE_PAL } else { DMC_RATE_TABLE }[(data & 0x0F) as usize];
+                if !self.irq_enabled {
+                    self.irq = false;
+                }
+            },
+            1 => self.level          = data & 0x7F,
+            2 => self.sample_address = 0xC000 | ((data as u16) << 6),
+            _ => self.sample_length  = ((data as u16) << 4) | 0x0001,
+        }
+    }
+
+    // Enabling only restarts the sample if the last one has finished
+    pub fn set_enabled(&mut self, enabled: bool) {
+        if !enabled {
+            self.bytes_remaining = 0;
+        } else if self.bytes_remaining == 0 {
+            self.restart();
+        }
+    }
+
+    fn restart(&mut self) {
+        self.current_address = self.sample_address;
+        self.bytes_remaining = self.sample_length;
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.bytes_remaining > 0
+    }
+
+    // The sample buffer is empty and there are bytes left to play
+    pub fn wants_sample(&self) -> bool {
+        self.buffer.is_none() && self.bytes_remaining > 0
+    }
+
+    pub fn sample_addr(&self) -> u16 {
+        self.current_address
+    }
+
+    // The byte fetched from sample_addr(). The address wraps around to $8000.
+    pub fn fill_sample(&mut self, data: u8) {
+        self.buffer          = Some(data);
+        self.current_address = self.current_address.checked_add(1).unwrap_or(0x8000);
+        self.bytes_remaining = self.bytes_remaining.saturating_sub(1);
+
+        if self.bytes_remaining == 0 {
+            if self.looping {
+                self.restart();
+            } else if self.irq_enabled {
+                self.irq = true;
+            }
+        }
+    }
+
+    // Once per CPU cycle
+    pub fn clock(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = self.rate - 1;
+
+        if !self.silence {
+            if self.shift & 0x01 != 0 {
+                if self.level <= 125 {
+                    self.level += 2;
+                }
+            } else if self.level >= 2 {
+                self.level -= 2;
+            }
+        }
+        self.shift >>= 1;
+
+        self.bits_remaining -= 1;
+        if self.bits_remaining == 0 {
+            self.bits_remaining = 8;
+            match self.buffer.take() {
+                Some(data) => {
+                    self.silence = false;
+                    self.shift   = data;
+                },
+                None => self.silence = true,
+            }
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        self.level
+    }
+
+    pub fn state(&self, cpu_clock_rate: f64) -> ChannelState {
+        ChannelState {
+            active:    self.is_active(),
+            period:    self.rate,
+            frequency: (cpu_clock_rate / self.rate as f64) as f32,
+            volume:    self.level,
+            duty:      0,
+            output:    self.level,
+        }
+    }
+}
+
+
+// Frame counter
+//     0x4017: MI-- ---- - mode (0: 4-step, 1: 5-step), IRQ inhibit
+// Divides the CPU clock into quarter and half frames that clock the envelopes,
+// sweeps and length counters, at these CPU cycles (NTSC and Dendy):
+//     4-step: 7457 Q, 14913 QH, 22371 Q, 29829 QH, IRQ at 29828 - 29830
+//     5-step: 7457 Q, 14913 QH, 22371 Q, 37281 QH, no IRQ
+// The PAL APU takes its steps at 8313, 16627, 24939, 33253 and 41565.
+// A write to $4017 only resets the sequence 3 or 4 CPU cycles later,
+// depending on whether it happened on the first or second half of an APU
+// cycle. Switching to the 5-step mode clocks a half frame right away then.
+// https://www.nesdev.org/wiki/APU_Frame_Counter
+const FRAME_STEPS:     [u32; 5] = [7457, 14913, 22371, 29829, 37281];
+const FRAME_STEPS_PAL: [u32; 5] = [8313, 16627, 24939, 33253, 41565];
+
+#[derive(Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FrameCounter {
+    pal:         bool,
+    five_step:   bool,
+    irq_inhibit: bool,
+    cycle:       u32,
+    pending:     Option<(bool, u8)>, // mode of a $4017 write and the cycles until it takes effect
+    pub irq:     bool,
+}
+
+impl FrameCounter {
+    pub fn write(&mut self, data: u8, odd_cycle: bool) {
+        self.irq_inhibit = data & 0x40 != 0;
+        if self.irq_inhibit {
+            self.irq = false;
+        }
+        self.pending = Some((data & 0x80 != 0, if odd_cycle { 4 } else { 3 }));
+    }
+
+    pub fn reset(&mut self) {
+        self.cycle   = 0;
+        self.pending = None;
+        self.irq     = false;
+    }
+
+    pub fn is_five_step(&self) -> bool {
+        self.five_step
+    }
+
+    // Once per CPU cycle, returns whether a quarter and a half frame are due
+    pub fn clock(&mut self) -> (bool, bool) {
+        if let Some((five_step, delay)) = self.pending {
+            if delay > 1 {
+                self.pending = Some((five_step, delay - 1));
+            } else {
+                self.pending   = None;
+                self.five_step = five_step;
+                self.cycle     = 0;
+                return (five_step, five_step);
+            }
+        }
+
+        self.cycle += 1;
+        let set_irq = !self.five_step && !self.irq_inhibit;
+        let steps   = if self.pal { FRAME_STEPS_PAL } else { FRAME_STEPS };
+        match (self.five_step, self.cycle) {
+            (_, cycle) if cycle == steps[0] || cycle == steps[2] => (true, false),
+            (_, cycle) if cycle == steps[1] => (true, true),
+            (false, cycle) if cycle == steps[3] - 1 => {
+                self.irq |= set_irq;
+                (false, false)
+            },
+            (false, cycle) if cycle == steps[3] => {
+                self.irq |= set_irq;
+                (true, true)
+            },
+            (false, cycle) if cycle == steps[3] + 1 => {
+                self.irq |= set_irq;
+                self.cycle = 0;
+                (false, false)
+            },
+            (true, cycle) if cycle == steps[4] => (true, true),
+            (true, cycle) if cycle == steps[4] + 1 => {
+                self.cycle = 0;
+                (false, false)
+            },
+            _ => (false, false),
+        }
+    }
+}
+
+
+// The sound sources that can be muted for listening to or debugging the music.
+// Triangle and noise are not emulated, they are silent either way.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Channel {
+    Pulse1,
+    Pulse2,
+    Triangle,
+    Noise,
+    Dmc,
+    Expansion, // the cartridge's sound chip
+}
+
+impl Channel {
+    fn mask(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+// What a channel is playing, for oscilloscope and piano roll views. For the
+// DMC the period is the one of its output unit and the volume its 7 bit level.
+#[wasm_bindgen]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ChannelState {
+    pub active:    bool, // length counter / sample bytes left
+    pub period:    u16,  // timer period
+    pub frequency: f32,  // in Hz, of the note or of the DMC's bits
+    pub volume:    u8,
+    pub duty:      u8,   // 0..3, pulse channels only
+    pub output:    u8,   // current output, before muting
+}
+
+#[cfg(feature = "serde")]
+fn all_channels() -> u8 {
+    0xFF
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Apu {
+    pub pulse1:        Pulse,
+    pub pulse2:        Pulse,
+    pub dmc:           Dmc,
+    pub frame_counter: FrameCounter,
+    odd_cycle:         bool, // the second CPU cycle of an APU cycle
+    tv_system:         TvSystem,
+    // Masks of the channels that are mixed into the output, a setting of the
+    // session and not part of the state
+    #[cfg_attr(feature = "serde", serde(skip, default = "all_channels"))]
+    enabled:           u8,
+}
+
+impl Default for Apu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Apu {
+    pub fn new() -> Self {
+        Self {
+            pulse1:        Pulse::new(true),
+            pulse2:        Pulse::new(false),
+            dmc:           Dmc::new(),
+            frame_counter: FrameCounter::default(),
+            odd_cycle:     false,
+            tv_system:     TvSystem::Ntsc,
+            enabled:       0xFF,
+        }
+    }
+
+    // The PAL APU has its own frame counter steps and DMC rates, Dendy uses
+    // the NTSC ones
+    pub fn set_tv_system(&mut self, tv_system: TvSystem) {
+        self.tv_system         = tv_system;
+        self.frame_counter.pal = tv_system == TvSystem::Pal;
+        self.dmc.pal           = tv_system == TvSystem::Pal;
+    }
+
+    // Takes over a deserialized APU, the channels muted by the host stay muted
+    pub fn restore(&mut self, mut state: Apu) {
+        state.enabled = self.enabled;
+        *self = state;
+    }
+
+    // A reset silences the APU as if 0 had been written to $4015
+    pub fn reset(&mut self) {
+        self.pulse1.length.set_enabled(false);
+        self.pulse2.length.set_enabled(false);
+        self.dmc.set_enabled(false);
+        self.dmc.irq   = false;
+        self.frame_counter.reset();
+        self.odd_cycle = false;
+    }
+
+    pub fn write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x4000..=0x4003 => self.pulse1.write(addr & 0x0003, data),
+            0x4004..=0x4007 => self.pulse2.write(addr & 0x0003, data),
+            0x4010..=0x4013 => self.dmc.write(addr & 0x0003, data),
+            // Channel enables, also acknowledges the DMC IRQ
+            0x4015 => {
+                self.dmc.irq = false;
+                self.pulse1.length.set_enabled(data & 0x01 != 0);
+                self.pulse2.length.set_enabled(data & 0x02 != 0);
+                self.dmc.set_enabled(data & 0x10 != 0);
+            },
+            0x4017 => self.frame_counter.write(data, self.odd_cycle),
+            _ => {},
+        }
+    }
+
+    // $4015 status, bit 5 is open bus
+    //     IF-D NT21 - DMC IRQ, frame IRQ, DMC active, length counters of noise,
+    //                 triangle and the pulses
+    // Triangle and noise are not emulated, their bits are always clear.
+    pub fn peek_status(&self) -> u8 {
+          (self.dmc.irq                     as u8) << 7
+        | (self.frame_counter.irq           as u8) << 6
+        | (self.dmc.is_active()             as u8) << 4
+        | (self.pulse2.length.is_active()   as u8) << 1
+        | (self.pulse1.length.is_active()   as u8)
+    }
+
+    // Reading the status acknowledges the frame IRQ, but not the DMC's
+    pub fn read_status(&mut self) -> u8 {
+        let status = self.peek_status();
+        self.frame_counter.irq = false;
+        status
+    }
+
+    // Once per CPU cycle
+    pub fn clock(&mut self) {
+        let (quarter, half) = self.frame_counter.clock();
+        if quarter {
+            self.clock_quarter_frame();
+        }
+        if half {
+            self.clock_half_frame();
+        }
+
+        self.dmc.clock();
+        if self.odd_cycle {
+            self.pulse1.clock_timer();
+            self.pulse2.clock_timer();
+        }
+        self.odd_cycle = !self.odd_cycle;
+    }
+
+    // Envelopes
+    pub fn clock_quarter_frame(&mut self) {
+        self.pulse1.clock_quarter_frame();
+        self.pulse2.clock_quarter_frame();
+    }
+
+    // Length counters and sweeps
+    pub fn clock_half_frame(&mut self) {
+        self.pulse1.clock_half_frame();
+        self.pulse2.clock_half_frame();
+    }
+
+    // Muting a channel only leaves it out of the mix, it keeps running
+    pub fn set_channel_enabled(&mut self, channel: Channel, enabled: bool) {
+        if enabled {
+            self.enabled |= channel.mask();
+        } else {
+            self.enabled &= !channel.mask();
+        }
+    }
+
+    pub fn is_channel_enabled(&self, channel: Channel) -> bool {
+        self.enabled & channel.mask() != 0
+    }
+
+    // None for the channels that aren't emulated
+    pub fn channel_state(&self, channel: Channel) -> Option<ChannelState> {
+        match channel {
+            Channel::Pulse1 => Some(self.pulse1.state(self.tv_system.cpu_clock_rate())),
+            Channel::Pulse2 => Some(self.pulse2.state(self.tv_system.cpu_clock_rate())),
+            Channel::Dmc    => Some(self.dmc.state(self.tv_system.cpu_clock_rate())),
+            _               => None,
+        }
+    }
+
+    // Mixed output in 0.0 - 1.0, using the usual approximation of the
+    // nonlinear mixer
+    // https://www.nesdev.org/wiki/APU_Mixer
+    pub fn output(&self) -> f32 {
+        let pulse1 = if self.is_channel_enabled(Channel::Pulse1) { self.pulse1.output() } else { 0 };
+        let pulse2 = if self.is_channel_enabled(Channel::Pulse2) { self.pulse2.output() } else { 0 };
+        let dmc    = if self.is_channel_enabled(Channel::Dmc)    { self.dmc.output()    } else { 0 };
+
+        let pulse = (pulse1 + pulse2) as f32;
+        let pulse_out = if pulse == 0.0 { 0.0 } else { 95.88 / (8128.0 / pulse + 100.0) };
+
+        let tnd = dmc as f32 / 22638.0;
+        let tnd_out = if tnd == 0.0 { 0.0 } else { 159.79 / (1.0 / tnd + 100.0) };
+
+        pulse_out + tnd_out
+    }
+}
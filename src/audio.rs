@@ -0,0 +1,372 @@
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+
+// Audio output
+// The APU's output only changes in steps, at most once per CPU cycle. Sampling
+// it at the host rate aliases the harmonics of high notes back into the
+// audible range, so every step is instead drawn as a band-limited step: the
+// difference of the amplitudes, smeared over a few samples with a windowed
+// sinc. The buffer holds these differences and the samples are their running
+// sum, the same idea as blargg's Blip_Buffer.
+// http://slack.net/~ant/bl-synth/
+
+pub const CPU_CLOCK_RATE:      f64 = 1_789_773.0; // NTSC
+pub const DEFAULT_SAMPLE_RATE: u32 = 44_100;
+
+// Host rates the output can be resampled to. The kernels only band-limit
+// properly if a sample spans many CPU cycles.
+pub const SAMPLE_RATES: std::ops::RangeInclusive<u32> = 8_000..=192_000;
+
+const PHASES: usize = 64; // resolution of a step's position between two samples
+const TAPS:   usize = 16; // length of a step's kernel in samples, half of it is latency
+
+// Windowed sinc kernels for each phase, every one sums up to 1 so a step of
+// delta adds up to delta once it has passed
+fn step_kernels() -> Vec<[f32; TAPS]> {
+    let cutoff = 0.9; // of the Nyquist frequency, leaves room for the window's transition
+    (0..PHASES).map(|phase| {
+        let offset = phase as f64 / PHASES as f64;
+        let mut kernel = [0.0f64; TAPS];
+        for (k, tap) in kernel.iter_mut().enumerate() {
+            let x = k as f64 - (TAPS / 2) as f64 - offset;
+            let sinc = if x == 0.0 { cutoff } else { (PI * cutoff * x).sin() / (PI * x) };
+            let window = 0.42 + 0.5 * (2.0 * PI * x / TAPS as f64).cos() + 0.08 * (4.0 * PI * x / TAPS as f64).cos();
+            *tap = sinc * window;
+        }
+        let sum: f64 = kernel.iter().sum();
+        kernel.map(|tap| (tap / sum) as f32)
+    }).collect()
+}
+
+// Sound chips on cartridges. A board declares its chip so the mixer knows
+// which volume applies to it, no board carries more than one.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ExpansionChip {
+    Vrc6,
+    Vrc7,
+    Fds,
+    Mmc5,
+    N163,
+    Sunsoft5b,
+}
+
+pub const EXPANSION_CHIPS: usize = 6;
+
+// Mixes the APU with the expansion audio of the inserted cartridge. Every
+// source has its own volume: the chips' outputs span -1.0 - 1.0, at the
+// default of 0.5 their full swing is as loud as the APU's.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Mixer {
+    apu_volume: f32,
+    volumes:    [f32; EXPANSION_CHIPS],
+    chip:       Option<ExpansionChip>, // registered by the cartridge
+}
+
+impl Default for Mixer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Mixer {
+    pub fn new() -> Self {
+        Self {
+            apu_volume: 1.0,
+            volumes:    [0.5; EXPANSION_CHIPS],
+            chip:       None,
+        }
+    }
+
+    pub fn with_chip(chip: Option<ExpansionChip>) -> Self {
+        Self { chip, ..Self::new() }
+    }
+
+    // Called when a cartridge is inserted, None for boards without a sound chip
+    pub fn register(&mut self, chip: Option<ExpansionChip>) {
+        self.chip = chip;
+    }
+
+    pub fn chip(&self) -> Option<ExpansionChip> {
+        self.chip
+    }
+
+    pub fn set_apu_volume(&mut self, volume: f32) {
+        self.apu_volume = volume;
+    }
+
+    // Kept for the chip even while a cartridge without it is inserted
+    pub fn set_volume(&mut self, chip: ExpansionChip, volume: f32) {
+        self.volumes[chip as usize] = volume;
+    }
+
+    pub fn volume(&self, chip: ExpansionChip) -> f32 {
+        self.volumes[chip as usize]
+    }
+
+    pub fn mix(&self, apu: f32, expansion: f32) -> f32 {
+        let expansion = self.chip.map_or(0.0, |chip| self.volumes[chip as usize] * expansion);
+        self.apu_volume * apu + expansion
+    }
+}
+
+
+// The filters between the 2A03 and the audio out jack of a NES. They are
+// applied to the samples at the host rate, the low-pass also takes off the
+// last bit of the band-limited steps' ringing.
+// https://www.nesdev.org/wiki/APU_Mixer
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AudioFilter {
+    HighPass90,
+    HighPass440,
+    LowPass14k,
+}
+
+impl AudioFilter {
+    const ALL: [AudioFilter; 3] = [AudioFilter::HighPass90, AudioFilter::HighPass440, AudioFilter::LowPass14k];
+
+    fn cutoff(self) -> f64 {
+        match self {
+            AudioFilter::HighPass90  => 90.0,
+            AudioFilter::HighPass440 => 440.0,
+            AudioFilter::LowPass14k  => 14_000.0,
+        }
+    }
+}
+
+// First order RC filter
+#[derive(Clone, Debug)]
+struct FilterStage {
+    filter:      AudioFilter,
+    enabled:     bool,
+    coefficient: f32,
+    input:       f32, // last input and output
+    output:      f32,
+}
+
+impl FilterStage {
+    fn new(filter: AudioFilter, sample_rate: u32) -> Self {
+        let mut stage = Self { filter, enabled: true, coefficient: 0.0, input: 0.0, output: 0.0 };
+        stage.set_sample_rate(sample_rate);
+        stage
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        let rc = 1.0 / (2.0 * PI * self.filter.cutoff());
+        let dt = 1.0 / sample_rate as f64;
+        self.coefficient = match self.filter {
+            AudioFilter::LowPass14k => dt / (rc + dt),
+            _                       => rc / (rc + dt),
+        } as f32;
+    }
+
+    fn process(&mut self, input: f32) -> f32 {
+        self.output = match self.filter {
+            AudioFilter::LowPass14k => self.output + self.coefficient * (input - self.output),
+            _                       => self.coefficient * (self.output + input - self.input),
+        };
+        self.input = input;
+        self.output
+    }
+}
+
+// The filters the samples pass on their way out, all of them are enabled by
+// default
+#[derive(Clone, Debug)]
+pub struct FilterChain {
+    stages: Vec<FilterStage>,
+}
+
+impl FilterChain {
+    pub fn new(sample_rate: u32) -> Self {
+        Self { stages: AudioFilter::ALL.iter().map(|&filter| FilterStage::new(filter, sample_rate)).collect() }
+    }
+
+    pub fn set_enabled(&mut self, filter: AudioFilter, enabled: bool) {
+        for stage in self.stages.iter_mut().filter(|stage| stage.filter == filter) {
+            stage.enabled = enabled;
+        }
+    }
+
+    pub fn is_enabled(&self, filter: AudioFilter) -> bool {
+        self.stages.iter().any(|stage| stage.filter == filter && stage.enabled)
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        for stage in &mut self.stages {
+            stage.set_sample_rate(sample_rate);
+        }
+    }
+
+    pub fn reset(&mut self) {
+        for stage in &mut self.stages {
+            stage.input  = 0.0;
+            stage.output = 0.0;
+        }
+    }
+
+    // Disabled filters still follow the input, so enabling one doesn't pop
+    pub fn process(&mut self, sample: f32) -> f32 {
+        self.stages.iter_mut().fold(sample, |sample, stage| {
+            let filtered = stage.process(sample);
+            if stage.enabled { filtered } else { sample }
+        })
+    }
+}
+
+
+pub struct BlipBuffer {
+    kernels:           Vec<[f32; TAPS]>,
+    clock_rate:        f64,
+    sample_rate:       u32,
+    samples_per_clock: f64,
+    time:              f64,      // position of the current clock in the buffer, in samples
+    deltas:            Vec<f32>, // differences of the samples that are not complete yet
+    amplitude:         f32,      // last input
+    integrator:        f32,      // last output
+    pub filters:       FilterChain,
+    samples:           VecDeque<f32>,
+    recording:         Option<Vec<f32>>, // every sample since start_recording(), also the ones that were dropped
+}
+
+impl BlipBuffer {
+    pub fn new(clock_rate: f64, sample_rate: u32) -> Self {
+        Self {
+            kernels:           step_kernels(),
+            clock_rate,
+            sample_rate,
+            samples_per_clock: sample_rate as f64 / clock_rate,
+            time:              0.0,
+            deltas:            vec![0.0; 2 * TAPS],
+            amplitude:         0.0,
+            integrator:        0.0,
+            filters:           FilterChain::new(sample_rate),
+            samples:           VecDeque::new(),
+            recording:         None,
+        }
+    }
+
+    // The input's amplitude during the next clock
+    pub fn clock(&mut self, amplitude: f32) {
+        if amplitude != self.amplitude {
+            self.add_delta(amplitude - self.amplitude);
+            self.amplitude = amplitude;
+        }
+        self.time += self.samples_per_clock;
+
+        // Samples before the current position can't change any more
+        if self.time >= TAPS as f64 {
+            self.flush();
+        }
+    }
+
+    fn add_delta(&mut self, delta: f32) {
+        let start = self.time as usize;
+        let phase = ((self.time - start as f64) * PHASES as f64) as usize;
+        if self.deltas.len() < start + TAPS {
+            self.deltas.resize(start + TAPS, 0.0);
+        }
+        for (sample, tap) in self.deltas[start..start + TAPS].iter_mut().zip(&self.kernels[phase.min(PHASES - 1)]) {
+            *sample += delta * tap;
+        }
+    }
+
+    fn flush(&mut self) {
+        let complete = self.time as usize;
+        for &delta in &self.deltas[..complete] {
+            self.integrator += delta;
+            let sample = self.filters.process(self.integrator);
+            self.samples.push_back(sample);
+            if let Some(recording) = &mut self.recording {
+                recording.push(sample);
+            }
+        }
+        self.deltas.drain(..complete);
+        self.deltas.resize(self.deltas.len().max(2 * TAPS), 0.0);
+        self.time -= complete as f64;
+
+        // Samples that weren't picked up are dropped after a second
+        let overflow = self.samples.len().saturating_sub(self.sample_rate as usize);
+        self.samples.drain(..overflow);
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    // Switches to another host rate, the samples that haven't been picked up
+    // are dropped
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate       = sample_rate;
+        self.samples_per_clock = sample_rate as f64 / self.clock_rate;
+        self.filters.set_sample_rate(sample_rate);
+        self.clear();
+    }
+
+    // The rate of the CPU that clocks the buffer, differs between regions
+    pub fn set_clock_rate(&mut self, clock_rate: f64) {
+        self.clock_rate        = clock_rate;
+        self.samples_per_clock = self.sample_rate as f64 / clock_rate;
+        self.clear();
+    }
+
+    // Restarts the recording if one is running already
+    pub fn start_recording(&mut self) {
+        self.recording = Some(Vec::new());
+    }
+
+    // The recording as a WAV file, None if none was started
+    pub fn stop_recording(&mut self) -> Option<Vec<u8>> {
+        self.recording.take().map(|samples| encode_wav(&samples, self.sample_rate))
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    pub fn samples_available(&self) -> usize {
+        self.samples.len()
+    }
+
+    // The samples produced since the last call
+    pub fn take_samples(&mut self) -> Vec<f32> {
+        self.samples.drain(..).collect()
+    }
+
+    pub fn clear(&mut self) {
+        self.time       = 0.0;
+        self.amplitude  = 0.0;
+        self.integrator = 0.0;
+        self.filters.reset();
+        self.deltas.iter_mut().for_each(|delta| *delta = 0.0);
+        self.samples.clear();
+    }
+}
+
+
+// 16 bit mono PCM in a RIFF container, samples are clipped to -1.0 - 1.0
+// http://soundfile.sapp.org/doc/WaveFormat/
+pub fn encode_wav(samples: &[f32], sample_rate: u32) -> Vec<u8> {
+    let data_len = 2 * samples.len() as u32;
+    let mut wav  = Vec::with_capacity(44 + data_len as usize);
+
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+    wav.extend_from_slice(b"WAVE");
+
+    wav.extend_from_slice(b"fmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());              // size of the format chunk
+    wav.extend_from_slice(&1u16.to_le_bytes());               // PCM
+    wav.extend_from_slice(&1u16.to_le_bytes());               // channels
+    wav.extend_from_slice(&sample_rate.to_le_bytes());
+    wav.extend_from_slice(&(2 * sample_rate).to_le_bytes());  // bytes per second
+    wav.extend_from_slice(&2u16.to_le_bytes());               // bytes per frame
+    wav.extend_from_slice(&16u16.to_le_bytes());              // bits per sample
+
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_len.to_le_bytes());
+    for &sample in samples {
+        let pcm = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+        wav.extend_from_slice(&pcm.to_le_bytes());
+    }
+    wav
+}
@@ -1,23 +1,59 @@
-use crate::interfaces::{CartridgeInterface, BusInterface, PpuInterface};
+use std::ops::RangeInclusive;
+
+use crate::interfaces::{CartridgeInterface, BusInterface, BusDevice};
 use crate::ppu::Olc2c02;
+use crate::apu::{Apu, Channel};
+use crate::audio::Mixer;
+use crate::controller::{Controllers, DeviceKind, BUTTON_A, BUTTON_B, BUTTON_SELECT, BUTTON_START, BUTTON_UP, BUTTON_DOWN, BUTTON_LEFT, BUTTON_RIGHT};
+use crate::dma::{OamDma, DmcDma};
+use crate::devices::{Ram, PpuRegs, ApuIo, CartridgePort, CallbackDevice, memory_slice, memory_load};
+use crate::error::EmuError;
+use crate::recorder::{BusRecorder, AccessKind, AccessOrigin};
 
-// SimpleBus only containing 64 KB of RAM used in 6502 demo
+// SimpleBus only containing 64 KB of RAM used in 6502 demo. Attached devices
+// take the place of the RAM in their range.
 pub struct SimpleBus {
     ram: [u8; 1024*64],
+    devices: Vec<Box<dyn BusDevice>>,
+    pub recorder: BusRecorder,
 }
 
 impl SimpleBus {
     pub fn new() -> Self {
         Self {
             ram: [0; 1024 * 64],
+            devices: Vec::new(),
+            recorder: BusRecorder::new(),
         }
     }
 
-    pub fn get_ram(&self, start: u16, len: usize) -> Vec<u8> {
-        let start = start as usize;
-        let end = start + len;
+    pub fn attach(&mut self, device: Box<dyn BusDevice>) {
+        self.devices.push(device);
+    }
+
+    // Maps the range to host closures, see CallbackDevice
+    pub fn map(&mut self, range: RangeInclusive<u16>, read: impl FnMut(u16) -> u8 + 'static, write: impl FnMut(u16, u8) + 'static) {
+        self.attach(Box::new(CallbackDevice::new(range, read, write)));
+    }
+
+    pub fn detach_all(&mut self) {
+        self.devices.clear();
+    }
+
+    fn device(&mut self, addr: u16) -> Option<&mut Box<dyn BusDevice>> {
+        self.devices.iter_mut().find(|device| device.range().contains(&addr))
+    }
 
-        self.ram[start..end].to_vec()
+    pub fn ram_slice(&self, range: RangeInclusive<u16>) -> Result<&[u8], EmuError> {
+        memory_slice(&self.ram, *range.start(), range.len())
+    }
+
+    pub fn get_ram(&self, start: u16, len: usize) -> Result<Vec<u8>, EmuError> {
+        memory_slice(&self.ram, start, len).map(|ram| ram.to_vec())
+    }
+
+    pub fn load_ram(&mut self, offset: u16, data: &[u8]) -> Result<(), EmuError> {
+        memory_load(&mut self.ram, offset, data)
     }
 
     pub fn reset(&mut self) {
@@ -26,14 +62,31 @@ impl SimpleBus {
 }
 
 impl BusInterface for SimpleBus {
-    fn read(&mut self, addr: u16, _read_only: bool) -> u8 {
+    fn read(&mut self, addr: u16, read_only: bool) -> u8 {
+        if let Some(device) = self.device(addr) {
+            let data = if read_only { device.peek(addr, 0x00) } else { device.read(addr, 0x00) };
+            if !read_only {
+                self.recorder.record(addr, data, AccessKind::Read);
+            }
+            return data;
+        }
+
         if addr >= 0x0000 && addr <= 0xFFFF {
-           self.ram[addr as usize]
+            let data = self.ram[addr as usize];
+            if !read_only {
+                self.recorder.record(addr, data, AccessKind::Read);
+            }
+            data
         } else {
             0
         }
     }
     fn write(&mut self, addr: u16, data: u8) {
+        self.recorder.record(addr, data, AccessKind::Write);
+        if let Some(device) = self.device(addr) {
+            device.write(addr, data);
+            return;
+        }
         if addr >= 0x0000 && addr <= 0xFFFF {
             self.ram[addr as usize] = data;
         }
@@ -42,21 +95,60 @@ impl BusInterface for SimpleBus {
 }
 
 
-// NES bus containing 2 KB of RAM 
+// NES bus, made of the built-in devices plus any hardware attached to it
+// The cartridge is not part of the serialized state, a deserialized bus holds
+// an empty cartridge until the game is inserted again
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Bus {
-    cpu_ram:              [u8; 2048],
+    ram:                  Ram,
     pub ppu:              Olc2c02,
+    #[cfg_attr(feature = "serde", serde(skip, default = "empty_cartridge"))]
     cartridge:            Box<dyn CartridgeInterface>,
-    pub controller:       [u8; 2], // this needs to be set externally
-    controller_state:     [u8; 2], // store snapshots of the inputs when the corresponding memory address is written to. 
+    io:                   ApuIo,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub mixer:            Mixer,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    devices:              Vec<Box<dyn BusDevice>>,
+    open_bus:             u8, // last value driven on the data bus, unmapped reads return it
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub recorder:         BusRecorder,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    read_only:            Vec<RangeInclusive<u16>>, // writes to these regions never reach the device
 
-    
     // DMA
-    pub dma_page:         u8, 
-    pub dma_addr:         u8, 
-    pub dma_data:         u8, 
-    pub dma_transfer:     bool, 
-    pub dma_dummy:        bool, 
+    pub oam_dma:          OamDma,
+    pub dmc_dma:          DmcDma,
+}
+
+#[cfg(feature = "serde")]
+fn empty_cartridge() -> Box<dyn CartridgeInterface> {
+    Box::new(crate::cartridge::EmptyCartridge)
+}
+
+// What a save state holds of the bus: only the state, none of the host's
+// devices and settings, so that loading doesn't build a whole bus first
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize)]
+struct BusStateRef<'a> {
+    ram:       &'a Ram,
+    ppu:       &'a Olc2c02,
+    io:        &'a ApuIo,
+    open_bus:  u8,
+    oam_dma:   &'a OamDma,
+    dmc_dma:   &'a DmcDma,
+    cartridge: Vec<u8>, // CartridgeInterface::save_state()
+}
+
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct BusState {
+    ram:       Ram,
+    ppu:       Olc2c02,
+    io:        ApuIo,
+    open_bus:  u8,
+    oam_dma:   OamDma,
+    dmc_dma:   DmcDma,
+    cartridge: Vec<u8>,
 }
 
 impl Bus {
@@ -64,25 +156,83 @@ impl Bus {
         cartridge: Box<dyn CartridgeInterface>,
     ) -> Self {
         Self {
-            cpu_ram:             [0; 2048],
+            ram:                 Ram::new(),
             ppu:                 Olc2c02::new(),
+            mixer:               Mixer::with_chip(cartridge.audio_chip()),
             cartridge:           cartridge,
-            controller:          [0; 2],
-            controller_state:    [0; 2],
+            io:                  ApuIo::new(),
+            devices:             Vec::new(),
+            open_bus:            0x00,
+            recorder:            BusRecorder::new(),
+            read_only:           Vec::new(),
             // DMA
-            dma_page:             0x00,
-            dma_addr:             0x00,
-            dma_data:             0x00,
-            dma_transfer:         false, 
-            dma_dummy:            true,
+            oam_dma:             OamDma::new(),
+            dmc_dma:             DmcDma::new(),
         }
     }
 
-    pub fn get_ram(&self, start: u16, len: usize) -> Vec<u8> {
-        let start = start as usize;
-        let end = start + len;
+    // Access to the 2 KB internal RAM, without the mirrors
+    pub fn ram_slice(&self, range: RangeInclusive<u16>) -> Result<&[u8], EmuError> {
+        memory_slice(self.ram.as_slice(), *range.start(), range.len())
+    }
+
+    pub fn get_ram(&self, start: u16, len: usize) -> Result<Vec<u8>, EmuError> {
+        memory_slice(self.ram.as_slice(), start, len).map(|ram| ram.to_vec())
+    }
+
+    pub fn load_ram(&mut self, offset: u16, data: &[u8]) -> Result<(), EmuError> {
+        memory_load(self.ram.as_mut_slice(), offset, data)
+    }
+
+    // Maps additional hardware into the address space. Attached devices are
+    // asked before the built-in ones, so they can also shadow them.
+    pub fn attach(&mut self, device: Box<dyn BusDevice>) {
+        self.devices.push(device);
+    }
+
+    // Maps the range to host closures, see CallbackDevice
+    pub fn map(&mut self, range: RangeInclusive<u16>, read: impl FnMut(u16) -> u8 + 'static, write: impl FnMut(u16, u8) + 'static) {
+        self.attach(Box::new(CallbackDevice::new(range, read, write)));
+    }
+
+    pub fn detach_all(&mut self) {
+        self.devices.clear();
+    }
+
+    // Marks a region as ROM. Writes to it still drive the data bus but are
+    // dropped before they reach the device, so a game can't patch its PRG ROM.
+    // Only mark regions without mapper registers.
+    pub fn add_read_only_region(&mut self, start: u16, end: u16) {
+        self.read_only.push(start..=end);
+    }
+
+    pub fn clear_read_only_regions(&mut self) {
+        self.read_only.clear();
+    }
+
+    pub fn read_only_regions(&self) -> &[RangeInclusive<u16>] {
+        &self.read_only
+    }
+
+    fn is_read_only(&self, addr: u16) -> bool {
+        self.read_only.iter().any(|range| range.contains(&addr))
+    }
+
+    // Hands the device mapped at addr to f
+    fn with_device<R>(&mut self, addr: u16, f: impl FnOnce(&mut dyn BusDevice) -> R) -> R {
+        if let Some(device) = self.devices.iter_mut().find(|device| device.range().contains(&addr)) {
+            return f(device.as_mut());
+        }
 
-        self.cpu_ram[start..end].to_vec()
+        if self.ram.range().contains(&addr) {
+            f(&mut self.ram)
+        } else if PpuRegs::RANGE.contains(&addr) {
+            f(&mut PpuRegs { ppu: &mut self.ppu, cartridge: self.cartridge.as_mut() })
+        } else if self.io.range().contains(&addr) {
+            f(&mut self.io)
+        } else {
+            f(&mut CartridgePort { cartridge: self.cartridge.as_mut() })
+        }
     }
 
     
@@ -93,96 +243,219 @@ impl Bus {
     pub fn get_name_table(&self) -> Vec<u8> {
         self.ppu.get_name_table()
     }
+
+    pub fn get_sprite_thumbnail(&self, index: u8) -> Vec<u8> {
+        self.ppu.get_sprite_thumbnail(index, self.cartridge.as_ref())
+    }
     
+    // Switching the console off loses the internal RAM
+    pub fn clear_ram(&mut self) {
+        self.ram = Ram::new();
+    }
+
     pub fn reset(&mut self) {
         self.ppu.reset(); 
         self.cartridge.reset();
         
-        self.dma_page     = 0x00;
-        self.dma_addr     = 0x00;
-        self.dma_data     = 0x00;
-        self.dma_transfer = false;
-        self.dma_dummy    = true;
+        self.io.apu.reset();
+
+        self.oam_dma.reset();
+        self.dmc_dma.reset();
+    }
+
+    // The bus and the cartridge's RAM and registers, for save states
+    #[cfg(feature = "serde")]
+    pub fn save_state(&self) -> Vec<u8> {
+        let state = BusStateRef {
+            ram:       &self.ram,
+            ppu:       &self.ppu,
+            io:        &self.io,
+            open_bus:  self.open_bus,
+            oam_dma:   &self.oam_dma,
+            dmc_dma:   &self.dmc_dma,
+            cartridge: self.cartridge.save_state(),
+        };
+        bincode::serialize(&state).expect("the bus state always serializes")
+    }
+
+    // The cartridge, the attached devices and the host's settings stay
+    #[cfg(feature = "serde")]
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), EmuError> {
+        let state: Box<BusState> = bincode::deserialize(state).map_err(|_| EmuError::InvalidSaveState)?;
+        self.cartridge.load_state(&state.cartridge)?;
+
+        self.ram      = state.ram;
+        self.ppu.restore(state.ppu);
+        self.io.restore(state.io);
+        self.open_bus = state.open_bus;
+        self.oam_dma  = state.oam_dma;
+        self.dmc_dma  = state.dmc_dma;
+        Ok(())
     }
 
     pub fn clock(&mut self) {
         self.ppu.clock(self.cartridge.as_mut());
     }
 
+    // Once per CPU cycle, the APU keeps running while the CPU is halted. The
+    // DMC's sample fetches are done by the DMC DMA unit, the byte it read on
+    // the previous cycle is handed over before the channel is clocked.
+    pub fn clock_apu(&mut self) {
+        if let Some(data) = self.dmc_dma.take_sample() {
+            self.io.apu.dmc.fill_sample(data);
+        }
+
+        self.io.apu.clock();
+
+        if self.io.apu.dmc.wants_sample() && !self.dmc_dma.is_active() {
+            self.dmc_dma.request(self.io.apu.dmc.sample_addr());
+        }
+    }
+
+    pub fn frame_irq(&self) -> bool {
+        self.io.apu.frame_counter.irq
+    }
+
+    pub fn dmc_irq(&self) -> bool {
+        self.io.apu.dmc.irq
+    }
+
+    pub fn apu(&self) -> &Apu {
+        &self.io.apu
+    }
+
+    pub fn apu_mut(&mut self) -> &mut Apu {
+        &mut self.io.apu
+    }
+
+    pub fn apu_output(&self) -> f32 {
+        self.io.apu.output()
+    }
+
+    // The APU mixed with the cartridge's sound chip
+    pub fn audio_output(&self) -> f32 {
+        let expansion = if self.io.apu.is_channel_enabled(Channel::Expansion) { self.cartridge.audio_output() } else { 0.0 };
+        self.mixer.mix(self.io.apu.output(), expansion)
+    }
+
+    // One CPU cycle of a running OAM DMA transfer, the CPU is halted meanwhile
+    pub fn clock_oam_dma(&mut self, odd_cycle: bool) {
+        if !self.oam_dma.aligned {
+            // Wait for the halt cycle and, if necessary, one more cycle to get in sync
+            if odd_cycle {
+                self.oam_dma.aligned = true;
+            }
+        }
+        // On even cycles, read data from the CPU 
+        else if !odd_cycle {
+            let addr              = self.oam_dma.source_addr();
+            self.recorder.origin  = AccessOrigin::OamDma;
+            self.oam_dma.data     = self.read(addr, false);
+            self.recorder.origin  = AccessOrigin::Cpu;
+        }
+        // On odd cycles, write to the PPU's memory 
+        else {
+            self.ppu.write_oam_data(self.oam_dma.data);
+            self.oam_dma.advance();
+        }
+    }
+
+    // One CPU cycle of a DMC sample fetch, called once the CPU has halted:
+    // a halt cycle, a dummy cycle, then the read on the next get (even) cycle
+    pub fn clock_dmc_dma(&mut self, odd_cycle: bool) {
+        self.dmc_dma.halted_cycles += 1;
+
+        if self.dmc_dma.halted_cycles >= 3 && !odd_cycle {
+            let addr             = self.dmc_dma.addr;
+            self.recorder.origin = AccessOrigin::DmcDma;
+            self.dmc_dma.sample  = Some(self.read(addr, false));
+            self.recorder.origin = AccessOrigin::Cpu;
+            self.dmc_dma.active = false;
+        }
+    }
+
     pub fn insert_cartridge(&mut self, cartridge: Box<dyn CartridgeInterface>) {
+        self.mixer.register(cartridge.audio_chip());
         self.cartridge = cartridge;
     }
+
+    pub fn cartridge_irq(&self) -> bool {
+        self.cartridge.irq_state()
+    }
+
+    pub fn clock_cartridge(&mut self) {
+        self.cartridge.cpu_clock();
+    }
+
+    pub fn cartridge_audio(&self) -> f32 {
+        self.cartridge.audio_output()
+    }
+
+    pub fn battery_ram(&self) -> Option<&[u8]> {
+        self.cartridge.battery_ram()
+    }
+
+    pub fn load_battery_ram(&mut self, data: &[u8]) -> Result<(), EmuError> {
+        self.cartridge.load_battery_ram(data)
+    }
     
+    // buttons holds the BUTTON_* bits, players 3 and 4 need the Four Score
+    pub fn set_buttons(&mut self, player: usize, buttons: u8) {
+        self.io.controllers.set_buttons(player, buttons);
+    }
+
+    pub fn controllers(&self) -> &Controllers {
+        &self.io.controllers
+    }
+
+    pub fn set_button_state(&mut self, port: usize, button: u8, pressed: bool) {
+        self.io.controllers.set_button_state(port, button, pressed);
+    }
+
+    pub fn connect_device(&mut self, port: usize, kind: DeviceKind) -> Result<(), EmuError> {
+        self.io.controllers.connect_device(port, kind)
+    }
+
+    pub fn set_four_score(&mut self, connected: bool) {
+        self.io.controllers.set_four_score(connected);
+    }
+
     pub fn set_controller(&mut self, i: usize, x: bool, z: bool, a: bool, s: bool, up: bool, down: bool, left: bool, right: bool) {
-        // Only two controllers :/ 
-        if i > 1 {
-            return; 
-        }
-        self.controller[i]  = 
-          (x     as u8) * (1 << 7) 
-        + (z     as u8) * (1 << 6) 
-        + (a     as u8) * (1 << 5) 
-        + (s     as u8) * (1 << 4) 
-        + (up    as u8) * (1 << 3) 
-        + (down  as u8) * (1 << 2) 
-        + (left  as u8) * (1 << 1) 
-        + (right as u8) * (1 << 0);
-    } 
+        self.set_buttons(i,
+              if x     { BUTTON_A      } else { 0 }
+            | if z     { BUTTON_B      } else { 0 }
+            | if a     { BUTTON_SELECT } else { 0 }
+            | if s     { BUTTON_START  } else { 0 }
+            | if up    { BUTTON_UP     } else { 0 }
+            | if down  { BUTTON_DOWN   } else { 0 }
+            | if left  { BUTTON_LEFT   } else { 0 }
+            | if right { BUTTON_RIGHT  } else { 0 });
+    }
 }
 
 impl BusInterface for Bus {
     fn read(&mut self, addr: u16, read_only: bool) -> u8 {
-        // Cartridge gets first chance
-        if let Some(data) = self.cartridge.read_cpu(addr) {
-            return data;
-        }
-        // System RAM (mirrored every 2 KB)
-        if (addr >= 0x0000 && addr <= 0x1FFF)
-        {
-           return self.cpu_ram[(addr & 0x07FF) as usize];
-        }
-        // PPU Address range, mirrored every 8 bytes
-        if (addr >= 0x2000 && addr <= 0x3FFF)
-        {
-            return self.ppu.read_cpu(addr & 0x0007, read_only, self.cartridge.as_mut());
-        }
-        // Read most significant bit of controller state via pop
-        else if (addr >= 0x4016 && addr <= 0x4017)
-        {
-            let temp = ((self.controller_state[(addr & 0x0001) as usize] & 0x80) > 0) as u8;
-            self.controller_state[(addr & 0x0001) as usize] <<= 1; 
-            return temp;
+        let open_bus = self.open_bus;
+
+        if read_only {
+            return self.with_device(addr, |device| device.peek(addr, open_bus));
         }
-        0
+
+        self.open_bus = self.with_device(addr, |device| device.read(addr, open_bus));
+        self.recorder.record(addr, self.open_bus, AccessKind::Read);
+        self.open_bus
     }
-    fn write(&mut self, addr: u16, data: u8) {
 
-        // Cartridge gets first chance
-        if self.cartridge.write_cpu(addr, data).is_some() {
-        }
-        // System RAM (mirrored every 2 KB)
-        else if (addr >= 0x0000 && addr <= 0x1FFF)
-        {
-           self.cpu_ram[(addr & 0x07FF) as usize] = data;
-        }
-        // PPU Address range, mirrored every 8 bytes
-        else if (addr >= 0x2000 && addr <= 0x3FFF)
-        {
-            self.ppu.write_cpu(addr & 0x0007, data, self.cartridge.as_mut());
-        }
-        // DMA - Start DMA transfer in bus when this address is written to 
-        else if (addr == 0x4014)
-        {
-            self.dma_page     = data; 
-            self.dma_addr     = 0x00; 
-            self.dma_transfer = true;
-            self.dma_dummy    = true;
+    fn write(&mut self, addr: u16, data: u8) {
+        self.open_bus = data;
+        self.recorder.record(addr, data, AccessKind::Write);
+        if self.is_read_only(addr) {
+            return;
         }
-        // Copy external controller state into internal register
-        else if (addr >= 0x4016 && addr <= 0x4017)
-        {
-            self.controller_state[(addr & 0x0001) as usize] = self.controller[(addr & 0x0001) as usize];
+        self.with_device(addr, |device| device.write(addr, data));
+
+        if let Some(page) = self.io.take_oam_dma() {
+            self.oam_dma.start(page);
         }
-        
     }
-}
\ No newline at end of file
+}
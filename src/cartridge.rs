@@ -1,15 +1,78 @@
+use wasm_bindgen::prelude::*;
+
 use crate::interfaces::{CartridgeInterface, MapperInterface};
-use crate::mapper::Mapper000;
+use crate::mapper::MapperRegistry;
+use crate::rom_db::{RomDatabase, crc32};
+use crate::error::EmuError;
+use crate::audio::ExpansionChip;
 
 // Documentation on cartridge formats
 // https://nescartdb.com/
 
-enum MIRROR
+// Nametable mirroring, soldered on the board or controlled by the mapper.
+// The console has VRAM for two of the four nametables, four screen boards
+// bring another 2 KB for the other two.
+#[wasm_bindgen]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MIRROR
 {
     Horizontal,
     Vertical,
     OnescreenLo,
     OnescreenHi,
+    FourScreen,
+}
+
+impl MIRROR {
+    // The 1 KB page behind a nametable address, pages 0 and 1 are the
+    // console's VRAM, 2 and 3 the one on a four screen board
+    //     nametable:      0  1  2  3
+    //     Horizontal:     0  0  1  1
+    //     Vertical:       0  1  0  1
+    //     OnescreenLo:    0  0  0  0
+    //     OnescreenHi:    1  1  1  1
+    //     FourScreen:     0  1  2  3
+    pub fn nametable_page(self, addr: u16) -> usize {
+        let nametable = ((addr >> 10) & 0x03) as usize;
+        match self {
+            MIRROR::Horizontal  => nametable >> 1,
+            MIRROR::Vertical    => nametable & 0x01,
+            MIRROR::OnescreenLo => 0,
+            MIRROR::OnescreenHi => 1,
+            MIRROR::FourScreen  => nametable,
+        }
+    }
+}
+
+// TV system the game was made for
+#[wasm_bindgen]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Region
+{
+    Ntsc,
+    Pal,
+    Multi, // runs on both
+    Dendy,
+}
+
+// What the header says about the ROM, for display in a frontend. Sizes are in
+// bytes, a CHR ROM size of 0 means the board has CHR RAM. Mirroring is the
+// one soldered on the board, the mapper may change it. Warnings describe
+// oddities of the file that were worked around while loading it.
+#[wasm_bindgen(getter_with_clone)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RomInfo {
+    pub mapper:       u8,
+    pub submapper:    u8,
+    pub crc32:        u32, // of PRG and CHR ROM, see rom_db
+    pub prg_rom_size: u32,
+    pub chr_rom_size: u32,
+    pub mirroring:    MIRROR,
+    pub battery:      bool,
+    pub trainer:      bool,
+    pub nes2:         bool,
+    pub region:       Region,
+    pub warnings:     Vec<String>,
 }
 
 // iNES format header
@@ -38,46 +101,77 @@ impl CartridgeInterface for EmptyCartridge {
 pub struct Cartridge {
     v_prg_memory: Vec<u8>,
     v_chr_memory: Vec<u8>,
+    v_prg_ram:    Vec<u8>,                  // 8 KB work RAM at 0x6000 -> 0x7FFF
     n_mapper_id:  u8,                       // which mapper are we using?
     n_prg_banks:  u8,                       // how many banks of prg memory? 
     n_chr_banks:  u8,                       // how many banks of chr memory?
     mirror:       MIRROR,
+    v_vram:       Vec<u8>,                  // nametables 2 and 3 of four screen boards
+    battery:      bool,                     // the PRG RAM keeps its contents
+    info:         RomInfo,
     mapper:       Box<dyn MapperInterface>, // Reference to mapper
 }
 
 impl Cartridge {
 
     
-    pub fn from_bytes(data: &[u8]) -> Result<Self, String> {
+    pub fn from_bytes(data: &[u8]) -> Result<Self, EmuError> {
+        Self::from_bytes_with(data, &MapperRegistry::default())
+    }
+
+    // Looks the mapper up in the given registry, for boards not built in
+    pub fn from_bytes_with(data: &[u8], mappers: &MapperRegistry) -> Result<Self, EmuError> {
+        Self::from_bytes_with_db(data, mappers, &RomDatabase::default())
+    }
+
+    // Headers of ROMs found in the database are corrected before the mapper
+    // is created
+    pub fn from_bytes_with_db(data: &[u8], mappers: &MapperRegistry, db: &RomDatabase) -> Result<Self, EmuError> {
         if data.len() < 16 {
-            return Err("File too small".into())
+            return Err(EmuError::RomTooSmall)
         }
         
         // Validate NES magic number
         if &data[0..4] != b"NES\x1A" {
-            return Err("Not a valid iNES file".into());
+            return Err(EmuError::InvalidRom);
         }
 
 
+        // Old dumping tools left their name, e.g. "DiskDude!", in the unused
+        // bytes 7 to 15 of the header. Unless it is a NES 2.0 header, nonzero
+        // bytes at the end mean byte 7 is garbage as well.
+        let nes2  = data[7] & 0x0C == 0x08;
+        let dirty = !nes2 && data[12..16].iter().any(|&b| b != 0);
+
         let header = INesHeader {
             prg_rom_chunks: data[4],
             chr_rom_chunks: data[5],
             mapper1: data[6],
-            mapper2: data[7],
+            mapper2: if dirty { 0 } else { data[7] },
             prg_ram_size: data[8],
             tv_system1: data[9],
             tv_system2: data[10],
         };
 
 		// Determine Mapper ID
-		let n_mapper_id: u8 = ((header.mapper2 >> 4) << 4) | (header.mapper1 >> 4);
-		let mirror: MIRROR  = if (header.mapper1 & 0x01) != 0 {MIRROR::Vertical} else {MIRROR::Horizontal};
+		let mut n_mapper_id: u8 = ((header.mapper2 >> 4) << 4) | (header.mapper1 >> 4);
+		let mut mirror: MIRROR  = if (header.mapper1 & 0x08) != 0 {MIRROR::FourScreen}
+		                      else if (header.mapper1 & 0x01) != 0 {MIRROR::Vertical} else {MIRROR::Horizontal};
+
+        let mut warnings = Vec::new();
+        if dirty {
+            let text: String = data[7..16].iter().filter(|c| c.is_ascii_graphic()).map(|&c| c as char).collect();
+            warnings.push(format!("garbage in header bytes 7 to 15 (\"{}\") ignored", text));
+        }
 
         let mut offset: usize = 16; 
         
-		// If a "trainer" exists we just need to read past
-		// it before we get to the good stuff
+		// A "trainer" sits between the header and the PRG ROM, it is
+		// loaded into the PRG RAM at 0x7000
+		let mut prg_ram = vec![0; 8192];
 		if header.mapper1 & 0x04 != 0 {
+			let trainer = data.get(offset..offset + 512).ok_or(EmuError::RomTruncated)?;
+			prg_ram[0x1000..0x1200].copy_from_slice(trainer);
 			offset += 512;
         }
 
@@ -86,6 +180,7 @@ impl Cartridge {
 		let n_file_type : u8 = 1;
         let prg_memory: Vec<u8>;
         let chr_memory: Vec<u8>;
+        let crc:        u32;
 
 		if (n_file_type == 1)
 		{
@@ -93,9 +188,13 @@ impl Cartridge {
             let chr_size = (header.chr_rom_chunks as usize) *  8192;
 
             if data.len() < offset + prg_size + chr_size {
-                return Err("File truncated".into());
+                return Err(EmuError::RomTruncated);
+            }
+            if data.len() > offset + prg_size + chr_size {
+                warnings.push(format!("trailing data after the ROM ignored ({} bytes)", data.len() - offset - prg_size - chr_size));
             }
 
+            crc        = crc32(&data[offset..offset + prg_size + chr_size]);
             prg_memory = data[offset..offset+prg_size].to_vec();
             offset += prg_size;
             
@@ -105,43 +204,170 @@ impl Cartridge {
                 data[offset..offset + chr_size].to_vec()
             };
 		} else  {
-            return Err("Unsupported file type".into());
+            return Err(EmuError::UnsupportedFileType);
 		}
 
 
-		// Load appropriate mapper
-		let mapper: Box<dyn MapperInterface> = match n_mapper_id {
-		 0 => Box::new(Mapper000 { prg_banks: header.prg_rom_chunks, chr_banks: header.chr_rom_chunks }),
-         _ => return Err("Unsupported mapper".into()),
-		};
+        // Known bad headers, see rom_db
+        let mut battery = header.mapper1 & 0x02 != 0;
+        if let Some(fix) = db.get(crc) {
+            if let Some(mapper) = fix.mapper.filter(|&mapper| mapper != n_mapper_id) {
+                warnings.push(format!("mapper {} corrected to {} by the ROM database", n_mapper_id, mapper));
+                n_mapper_id = mapper;
+            }
+            if let Some(mirroring) = fix.mirroring.filter(|&mirroring| mirroring != mirror) {
+                warnings.push(format!("{:?} mirroring corrected to {:?} by the ROM database", mirror, mirroring));
+                mirror = mirroring;
+            }
+            if let Some(fixed) = fix.battery.filter(|&fixed| fixed != battery) {
+                warnings.push(format!("battery flag corrected to {} by the ROM database", fixed));
+                battery = fixed;
+            }
+        }
+
+		// Load appropriate mapper, NES 2.0 headers can name the board variant
+		let submapper = if nes2 { header.prg_ram_size >> 4 } else { 0 };
+		let mapper    = mappers.create_submapper(n_mapper_id, submapper, header.prg_rom_chunks, header.chr_rom_chunks)?;
+
+        // NES 2.0 has the region in byte 12, iNES only tells NTSC and PAL apart
+        let region = match if nes2 { data[12] & 0x03 } else { header.tv_system1 & 0x01 } {
+            0 => Region::Ntsc,
+            1 => Region::Pal,
+            2 => Region::Multi,
+            _ => Region::Dendy,
+        };
+
+        let info = RomInfo {
+            mapper:       n_mapper_id,
+            submapper,
+            crc32:        crc,
+            prg_rom_size: header.prg_rom_chunks as u32 * 16384,
+            chr_rom_size: header.chr_rom_chunks as u32 * 8192,
+            mirroring:    mirror,
+            battery,
+            trainer:      header.mapper1 & 0x04 != 0,
+            nes2,
+            region,
+            warnings,
+        };
 
         Ok(Self {
             v_prg_memory: prg_memory,
             v_chr_memory: chr_memory,
+            v_prg_ram:    prg_ram,
             n_mapper_id:  n_mapper_id,
             n_prg_banks:  header.prg_rom_chunks,
             n_chr_banks:  header.chr_rom_chunks,
             mirror,
+            v_vram:       if mirror == MIRROR::FourScreen { vec![0; 2048] } else { Vec::new() },
+            battery:      info.battery,
+            info,
             mapper
         })
 
     }
+
+    pub fn mapper_id(&self) -> u8 {
+        self.n_mapper_id
+    }
+
+    pub fn info(&self) -> &RomInfo {
+        &self.info
+    }
+
+    // Four screen boards ignore the mapper's mirroring
+    pub fn mirroring(&self) -> MIRROR {
+        if self.mirror == MIRROR::FourScreen {
+            return MIRROR::FourScreen;
+        }
+        self.mapper.mirror().unwrap_or(self.mirror)
+    }
+
+    // Offset into the board's own VRAM for nametables 2 and 3
+    fn four_screen_offset(&self, addr: u16) -> Option<usize> {
+        if !(0x2000..=0x3EFF).contains(&addr) || self.mirror != MIRROR::FourScreen {
+            return None;
+        }
+        let page = MIRROR::FourScreen.nametable_page(addr);
+        (page >= 2).then(|| (page - 2) * 1024 + (addr & 0x03FF) as usize)
+    }
+
+    pub fn has_battery(&self) -> bool {
+        self.battery
+    }
+
+    // PRG RAM and ROM as seen by the CPU
+    fn read_memory(&self, addr: u16) -> Option<u8> {
+        if (0x6000..=0x7FFF).contains(&addr) && self.mapper.prg_ram_readable() {
+            return Some(self.v_prg_ram[(addr & 0x1FFF) as usize]);
+        }
+        self.mapper.cpu_map_read(addr).map(|mapped_addr| self.v_prg_memory[mapped_addr])
+    }
+
+    // Registers of the board, see MapperInterface::save_state()
+    pub fn mapper_state(&self) -> Vec<u8> {
+        self.mapper.save_state()
+    }
+
+    pub fn load_mapper_state(&mut self, state: &[u8]) -> Result<(), EmuError> {
+        self.mapper.load_state(state)
+    }
 }
 
 
 // Rust has cool functional elements
 // map captures the Option returned by read and write functions as mapped_addr, returns it if it is None, else it applies it to the Lambda function
+// Every cartridge gets PRG RAM, test ROMs and many games expect it even if the
+// header doesn't announce it. The mapper can disable or write protect it.
 impl CartridgeInterface for Cartridge {
     fn read_cpu(&mut self, addr: u16) -> Option<u8> {
-        self.mapper.cpu_map_read( addr      ).map(|mapped_addr|  self.v_prg_memory[mapped_addr])
+        if let Some(data) = self.mapper.read_register(addr) {
+            return Some(data);
+        }
+        self.read_memory(addr)
+    }
+    fn peek_cpu(&mut self, addr: u16) -> Option<u8> {
+        if let Some(data) = self.mapper.peek_register(addr) {
+            return Some(data);
+        }
+        self.read_memory(addr)
     }
     fn write_cpu(&mut self, addr: u16, data: u8) -> Option<()> {
+        if (0x6000..=0x7FFF).contains(&addr) {
+            if self.mapper.prg_ram_writable() {
+                self.v_prg_ram[(addr & 0x1FFF) as usize] = data;
+            }
+            // Some boards have registers that overlap the PRG RAM
+            self.mapper.cpu_map_write(addr, data);
+            return Some(());
+        }
+        let data = if self.mapper.has_bus_conflicts() { self.read_memory(addr).map_or(data, |rom| rom & data) } else { data };
         self.mapper.cpu_map_write(addr, data).map(|mapped_addr| {self.v_prg_memory[mapped_addr] = data;})
     }
+    fn battery_ram(&self) -> Option<&[u8]> {
+        self.battery.then_some(self.v_prg_ram.as_slice())
+    }
+    fn load_battery_ram(&mut self, data: &[u8]) -> Result<(), EmuError> {
+        if !self.battery {
+            return Err(EmuError::NoBatteryRam);
+        }
+        if data.len() != self.v_prg_ram.len() {
+            return Err(EmuError::SaveRamSize { expected: self.v_prg_ram.len(), len: data.len() });
+        }
+        self.v_prg_ram.copy_from_slice(data);
+        Ok(())
+    }
     fn read_ppu(&    self, addr: u16) -> Option<u8> {
+        if let Some(offset) = self.four_screen_offset(addr) {
+            return Some(self.v_vram[offset]);
+        }
         self.mapper.ppu_map_read( addr      ).map(|mapped_addr|  self.v_chr_memory[mapped_addr])
     }
     fn write_ppu(&mut self, addr: u16, data: u8) -> Option<()> {
+        if let Some(offset) = self.four_screen_offset(addr) {
+            self.v_vram[offset] = data;
+            return Some(());
+        }
         self.mapper.ppu_map_write(addr, data).map(|mapped_addr| {self.v_chr_memory[mapped_addr] = data;})
     }
 
@@ -149,27 +375,69 @@ impl CartridgeInterface for Cartridge {
     // on the mirroring mode
     // I represent the two nametables as a 2*1024 byte array - we therefore need to offset by 1024 = 0x0400 to get to the second nametable (page 1)
     fn map_nametable_addr(&self, addr: u16) -> u16 {
-        let offset = addr & 0x0FFF;
-        match self.mirror {
-            MIRROR::Vertical => match offset {
-                0x0000..=0x03FF =>         offset & 0x03FF,    // NT0 -> page 0
-                0x0400..=0x07FF => 1024 + (offset & 0x03FF),   // NT1 -> page 1
-                0x0800..=0x0BFF =>         offset & 0x03FF,    // NT2 -> page 0
-                0x0C00..=0x0FFF => 1024 + (offset & 0x03FF),   // NT3 -> page 1
-                _ => unreachable!(),
-            },
-            MIRROR::Horizontal => match offset {
-                0x0000..=0x03FF =>         offset & 0x03FF,    // NT0 -> page 0
-                0x0400..=0x07FF =>         offset & 0x03FF,    // NT1 -> page 0
-                0x0800..=0x0BFF => 1024 + (offset & 0x03FF),   // NT2 -> page 1
-                0x0C00..=0x0FFF => 1024 + (offset & 0x03FF),   // NT3 -> page 1
-                _ => unreachable!(),
-            },
-            MIRROR::OnescreenLo =>         offset & 0x03FF,    // all -> page 0
-            MIRROR::OnescreenHi => 1024 + (offset & 0x03FF),   // all -> page 1
-        }
+        ((self.mirroring().nametable_page(addr) & 0x01) * 1024) as u16 + (addr & 0x03FF)
     }
     
+    fn irq_state(&self) -> bool {
+        self.mapper.irq_state()
+    }
+
+    fn ppu_a12(&mut self, high: bool) {
+        self.mapper.ppu_a12(high);
+    }
+
+    fn ppu_fetch(&mut self, addr: u16) {
+        self.mapper.ppu_fetch(addr);
+    }
+
+    fn cpu_clock(&mut self) {
+        self.mapper.cpu_clock();
+    }
+
+    fn audio_output(&self) -> f32 {
+        self.mapper.audio_output()
+    }
+
+    fn audio_chip(&self) -> Option<ExpansionChip> {
+        self.mapper.audio_chip()
+    }
+
+    // Each part is stored with its length: PRG RAM, CHR RAM (empty for
+    // boards with CHR ROM), the four screen VRAM and the mapper registers
+    fn save_state(&self) -> Vec<u8> {
+        let chr_ram: &[u8] = if self.n_chr_banks == 0 { &self.v_chr_memory } else { &[] };
+        let mut state = Vec::new();
+        for part in [&self.v_prg_ram[..], chr_ram, &self.v_vram[..], &self.mapper.save_state()] {
+            state.extend((part.len() as u32).to_le_bytes());
+            state.extend(part);
+        }
+        state
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), EmuError> {
+        let mut parts = Vec::new();
+        let mut rest  = state;
+        while !rest.is_empty() {
+            let len = u32::from_le_bytes(rest.get(0..4).ok_or(EmuError::InvalidSaveState)?.try_into().unwrap()) as usize;
+            parts.push(rest.get(4..4 + len).ok_or(EmuError::InvalidSaveState)?);
+            rest = &rest[4 + len..];
+        }
+
+        let [prg_ram, chr_ram, vram, mapper] = parts[..] else {
+            return Err(EmuError::InvalidSaveState);
+        };
+        let chr_ram_len = if self.n_chr_banks == 0 { self.v_chr_memory.len() } else { 0 };
+        if prg_ram.len() != self.v_prg_ram.len() || chr_ram.len() != chr_ram_len || vram.len() != self.v_vram.len() {
+            return Err(EmuError::InvalidSaveState);
+        }
+
+        self.mapper.load_state(mapper)?;
+        self.v_prg_ram.copy_from_slice(prg_ram);
+        self.v_chr_memory[..chr_ram_len].copy_from_slice(chr_ram);
+        self.v_vram.copy_from_slice(vram);
+        Ok(())
+    }
+
     fn reset(&mut self) {
         self.mapper.reset();
     }
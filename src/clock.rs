@@ -0,0 +1,131 @@
+use crate::cartridge::Region;
+
+// Master clock
+// All chips run off one crystal, divided down by a different amount in each
+// region. The PPU draws one dot per PPU divider, the CPU and the APU run one
+// cycle per CPU divider: exactly 3 dots per CPU cycle on NTSC and Dendy,
+// 3.2 on PAL.
+//              master clock    CPU   PPU   scanlines   vblank from
+//     NTSC     21.477272 MHz   /12   /4    262         241
+//     PAL      26.601712 MHz   /16   /5    312         241
+//     Dendy    26.601712 MHz   /15   /5    312         291
+// https://www.nesdev.org/wiki/Cycle_reference_chart
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TvSystem {
+    #[default]
+    Ntsc,
+    Pal,
+    Dendy, // the common Famicom clone, PAL timing with an NTSC like CPU
+}
+
+impl TvSystem {
+    pub fn master_clock_rate(self) -> f64 {
+        match self {
+            TvSystem::Ntsc                  => 236.25e6 / 11.0,
+            TvSystem::Pal | TvSystem::Dendy => 26_601_712.5,
+        }
+    }
+
+    pub fn cpu_divider(self) -> u32 {
+        match self {
+            TvSystem::Ntsc  => 12,
+            TvSystem::Pal   => 16,
+            TvSystem::Dendy => 15,
+        }
+    }
+
+    pub fn ppu_divider(self) -> u32 {
+        match self {
+            TvSystem::Ntsc                  => 4,
+            TvSystem::Pal | TvSystem::Dendy => 5,
+        }
+    }
+
+    pub fn cpu_clock_rate(self) -> f64 {
+        self.master_clock_rate() / self.cpu_divider() as f64
+    }
+
+    // Scanlines per frame, the last one is the pre-render line
+    pub fn scanlines(self) -> u16 {
+        match self {
+            TvSystem::Ntsc                  => 262,
+            TvSystem::Pal | TvSystem::Dendy => 312,
+        }
+    }
+
+    // The first scanline of vertical blank, Dendy has 50 post-render lines
+    pub fn vblank_line(self) -> u16 {
+        match self {
+            TvSystem::Ntsc | TvSystem::Pal => 241,
+            TvSystem::Dendy                => 291,
+        }
+    }
+
+    // Only the NTSC PPU skips a dot on every other frame
+    pub fn skips_odd_dot(self) -> bool {
+        self == TvSystem::Ntsc
+    }
+
+    // 60.0988 Hz on NTSC, 50.007 Hz on PAL and Dendy
+    pub fn frame_rate(self) -> f64 {
+        let mut dots = 341.0 * self.scanlines() as f64;
+        if self.skips_odd_dot() {
+            dots -= 0.5;
+        }
+        self.master_clock_rate() / self.ppu_divider() as f64 / dots
+    }
+
+    // What the ROM header asks for, multi region games run as NTSC
+    pub fn from_region(region: Region) -> Self {
+        match region {
+            Region::Ntsc | Region::Multi => TvSystem::Ntsc,
+            Region::Pal                  => TvSystem::Pal,
+            Region::Dendy                => TvSystem::Dendy,
+        }
+    }
+}
+
+// Counts the master clock in PPU dots and tells when a CPU cycle is due
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MasterClock {
+    tv_system: TvSystem,
+    phase:     u32, // master cycles since the last CPU cycle started
+    cpu_cycle: u64, // index of the CPU cycle in progress, the first one after the reset is 0
+}
+
+impl MasterClock {
+    pub fn new(tv_system: TvSystem) -> Self {
+        let mut clock = Self { tv_system, phase: 0, cpu_cycle: 0 };
+        clock.reset();
+        clock
+    }
+
+    pub fn tv_system(&self) -> TvSystem {
+        self.tv_system
+    }
+
+    // No CPU cycle is in progress until the first tick(), it wraps to 0 then
+    pub fn reset(&mut self) {
+        self.phase     = 0;
+        self.cpu_cycle = u64::MAX;
+    }
+
+    // Advances the master clock by one PPU dot, returns whether a CPU cycle
+    // starts with it
+    pub fn tick(&mut self) -> bool {
+        let cpu = self.phase < self.tv_system.ppu_divider();
+        if cpu {
+            self.cpu_cycle = self.cpu_cycle.wrapping_add(1);
+        }
+        self.phase = (self.phase + self.tv_system.ppu_divider()) % self.tv_system.cpu_divider();
+        cpu
+    }
+
+    // Whether the CPU cycle in progress is an odd one, DMA transfers align
+    // to these
+    pub fn odd_cpu_cycle(&self) -> bool {
+        !self.cpu_cycle.is_multiple_of(2)
+    }
+}
@@ -0,0 +1,183 @@
+use crate::error::EmuError;
+use crate::interfaces::InputDevice;
+
+// Standard controller
+// The pad holds its eight buttons in a shift register. While the strobe
+// (bit 0 of $4016) is high the register keeps being reloaded from the buttons
+// and reads return the A button. Once the strobe goes low every read shifts
+// out the next button, in the order of the bits below. After all eight the
+// official pads return 1s.
+// https://www.nesdev.org/wiki/Standard_controller
+pub const BUTTON_A:      u8 = 1 << 0;
+pub const BUTTON_B:      u8 = 1 << 1;
+pub const BUTTON_SELECT: u8 = 1 << 2;
+pub const BUTTON_START:  u8 = 1 << 3;
+pub const BUTTON_UP:     u8 = 1 << 4;
+pub const BUTTON_DOWN:   u8 = 1 << 5;
+pub const BUTTON_LEFT:   u8 = 1 << 6;
+pub const BUTTON_RIGHT:  u8 = 1 << 7;
+
+// What the host can plug into a port
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DeviceKind {
+    Unplugged,
+    StandardController,
+}
+
+pub fn connect(kind: DeviceKind) -> Box<dyn InputDevice> {
+    match kind {
+        DeviceKind::Unplugged          => Box::new(Unplugged),
+        DeviceKind::StandardController => Box::new(StandardController::default()),
+    }
+}
+
+// An empty port, the data line stays low
+pub struct Unplugged;
+
+impl InputDevice for Unplugged {
+    fn kind(&self) -> DeviceKind { DeviceKind::Unplugged }
+    fn set_button_state(&mut self, _button: u8, _pressed: bool) {}
+    fn buttons(&self) -> u8 { 0 }
+    fn report(&self) -> u32 { 0 }
+}
+
+#[derive(Default)]
+pub struct StandardController {
+    buttons: u8,
+}
+
+impl InputDevice for StandardController {
+    fn kind(&self) -> DeviceKind { DeviceKind::StandardController }
+
+    fn set_button_state(&mut self, button: u8, pressed: bool) {
+        if pressed { self.buttons |= button; } else { self.buttons &= !button; }
+    }
+
+    fn buttons(&self) -> u8 { self.buttons }
+
+    fn report(&self) -> u32 { self.buttons as u32 | 0xFFFF_FF00 }
+}
+
+// Four Score
+// With the four player adapter each port reports 24 bits: the pad of player
+// 1 or 2, then the one of player 3 or 4, then a signature that tells the game
+// the adapter is there.
+// https://www.nesdev.org/wiki/Four_Score
+const FOUR_SCORE_SIGNATURE: [u8; 2] = [0x08, 0x04];
+
+// Like the cartridge the devices belong to the host and are not part of the
+// serialized state, a deserialized console has standard pads plugged in
+#[cfg(feature = "serde")]
+fn standard_controllers() -> [Box<dyn InputDevice>; 4] {
+    std::array::from_fn(|_| connect(DeviceKind::StandardController))
+}
+
+// The two ports, and the two more behind the Four Score. What they shift out
+// is kept from bit 0 up.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Controllers {
+    #[cfg_attr(feature = "serde", serde(skip, default = "standard_controllers"))]
+    devices:    [Box<dyn InputDevice>; 4],
+    four_score: bool, // ports 2 and 3 are only read through the adapter
+    strobe:     bool,
+    shift:      [u32; 2],
+}
+
+impl Default for Controllers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Controllers {
+    pub fn new() -> Self {
+        Self {
+            devices:    std::array::from_fn(|_| connect(DeviceKind::StandardController)),
+            four_score: false,
+            strobe:     false,
+            shift:      [0; 2],
+        }
+    }
+
+    // Takes over deserialized ports, the host's devices stay plugged in
+    pub fn restore(&mut self, mut state: Controllers) {
+        std::mem::swap(&mut state.devices, &mut self.devices);
+        *self = state;
+        self.reload();
+    }
+
+    pub fn connect_device(&mut self, port: usize, kind: DeviceKind) -> Result<(), EmuError> {
+        let device = self.devices.get_mut(port).ok_or(EmuError::InvalidPort(port))?;
+        *device = connect(kind);
+        self.reload();
+        Ok(())
+    }
+
+    pub fn device_kind(&self, port: usize) -> Option<DeviceKind> {
+        self.devices.get(port).map(|device| device.kind())
+    }
+
+    // button is one of the BUTTON_* bits
+    pub fn set_button_state(&mut self, port: usize, button: u8, pressed: bool) {
+        if let Some(device) = self.devices.get_mut(port) {
+            device.set_button_state(button, pressed);
+        }
+        self.reload();
+    }
+
+    pub fn set_buttons(&mut self, port: usize, buttons: u8) {
+        if let Some(device) = self.devices.get_mut(port) {
+            device.set_buttons(buttons);
+        }
+        self.reload();
+    }
+
+    pub fn buttons(&self, port: usize) -> u8 {
+        self.devices.get(port).map_or(0, |device| device.buttons())
+    }
+
+    pub fn set_four_score(&mut self, connected: bool) {
+        self.four_score = connected;
+        self.reload();
+    }
+
+    pub fn has_four_score(&self) -> bool {
+        self.four_score
+    }
+
+    fn report(&self, port: usize) -> u32 {
+        if self.four_score {
+              self.devices[port    ].report() & 0xFF
+            | (self.devices[port + 2].report() & 0xFF) << 8
+            | (FOUR_SCORE_SIGNATURE[port] as u32) << 16
+            | 0xFF00_0000
+        } else {
+            self.devices[port].report()
+        }
+    }
+    fn reload(&mut self) {
+        if self.strobe {
+            self.shift = [self.report(0), self.report(1)];
+        }
+    }
+
+    // Bit 0 of a write to $4016, the strobe goes to both ports
+    pub fn write_strobe(&mut self, strobe: bool) {
+        self.strobe = strobe;
+        self.reload();
+    }
+
+    // The serial data line of port 0 ($4016) or 1 ($4017), only bit 0 is driven
+    pub fn read(&mut self, port: usize) -> u8 {
+        let data = self.peek(port);
+        if !self.strobe {
+            self.shift[port] = (self.shift[port] >> 1) | 0x8000_0000;
+        }
+        data
+    }
+
+    pub fn peek(&self, port: usize) -> u8 {
+        (self.shift[port] & 0x01) as u8
+    }
+}
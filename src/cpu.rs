@@ -74,7 +74,13 @@
     No copy-paste from LLMs or Copilot for this project (except for the opcode lookup table) as I realised I understand what I am doing less when I rely on their code too much :) 
 
 */
-use crate::interfaces::BusInterface;
+use std::io::Write;
+
+use wasm_bindgen::prelude::*;
+
+use crate::interfaces::{BusInterface, Cpu6502};
+use crate::debugger::Debugger;
+use crate::error::EmuError;
 
 // Note that https://www.nesdev.org/wiki/Instruction_reference refers to the U bit as 1 
 // when they write something like the bit order is NV1BDIZC (high to low). 
@@ -87,6 +93,40 @@ pub const FLAG6502_U: u8 = 1 << 5; // Unused
 pub const FLAG6502_V: u8 = 1 << 6; // Overflow
 pub const FLAG6502_N: u8 = 1 << 7; // Negative
 
+// The status register with named flags, so front ends don't have to mask P
+#[wasm_bindgen]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct StatusFlags {
+    pub carry:       bool,
+    pub zero:        bool,
+    pub irq_disable: bool,
+    pub decimal:     bool,
+    pub break_:      bool,
+    pub overflow:    bool,
+    pub negative:    bool,
+}
+
+impl StatusFlags {
+    pub fn from_status(status: u8) -> Self {
+        Self {
+            carry:       status & FLAG6502_C != 0,
+            zero:        status & FLAG6502_Z != 0,
+            irq_disable: status & FLAG6502_I != 0,
+            decimal:     status & FLAG6502_D != 0,
+            break_:      status & FLAG6502_B != 0,
+            overflow:    status & FLAG6502_V != 0,
+            negative:    status & FLAG6502_N != 0,
+        }
+    }
+}
+
+// Every device that can pull the shared IRQ line low gets its own bit, the
+// line is asserted as long as any of them is set
+pub const IRQ_SOURCE_FRAME_COUNTER: u8 = 1 << 0; // APU frame counter
+pub const IRQ_SOURCE_DMC:           u8 = 1 << 1; // APU delta modulation channel
+pub const IRQ_SOURCE_MAPPER:        u8 = 1 << 2; // Cartridge mapper, e.g. the MMC3 scanline counter
+pub const IRQ_SOURCE_EXTERNAL:      u8 = 1 << 3; // Anything driving the line through set_irq_line()
+
 // Javid9x' code compares function pointers to determine the addressing mode
 // I think it would be nicer to store integers in the instruction table and compare these
 // The actual lookup is then done using a match instruction
@@ -214,6 +254,36 @@ pub enum Operation {
     SED,
     SEI,
 
+    // Unofficial - combined read-modify-write
+    SLO,
+    RLA,
+    SRE,
+    RRA,
+    DCP,
+    ISC,
+
+    // Unofficial - combined loads / stores
+    LAX,
+    SAX,
+    LAS,
+
+    // Unofficial - immediate
+    ANC,
+    ALR,
+    ARR,
+    XAA,
+    LXA,
+    SBX,
+
+    // Unofficial - unstable stores
+    AHX,
+    SHX,
+    SHY,
+    TAS,
+
+    // Unofficial - locks up the processor (also known as KIL or HLT)
+    JAM,
+
     // Illegal / placeholder
     XXX,
 }
@@ -414,11 +484,193 @@ const fn build_lookup() -> [Instruction; 256] {
     op!(0xFD, "SBC", ABX, SBC, 4);
     op!(0xFE, "INC", ABX, INC, 7);
 
+    // ----- Unofficial NMOS 6502 opcodes (base cycles) -----
+    // https://www.nesdev.org/wiki/CPU_unofficial_opcodes
+    // https://www.nesdev.org/6502_cpu.txt
+    op!(0x03, "SLO", IZX, SLO, 8);
+    op!(0x07, "SLO", ZP0, SLO, 5);
+    op!(0x0F, "SLO", ABS, SLO, 6);
+    op!(0x13, "SLO", IZY, SLO, 8);
+    op!(0x17, "SLO", ZPX, SLO, 6);
+    op!(0x1B, "SLO", ABY, SLO, 7);
+    op!(0x1F, "SLO", ABX, SLO, 7);
+
+    op!(0x23, "RLA", IZX, RLA, 8);
+    op!(0x27, "RLA", ZP0, RLA, 5);
+    op!(0x2F, "RLA", ABS, RLA, 6);
+    op!(0x33, "RLA", IZY, RLA, 8);
+    op!(0x37, "RLA", ZPX, RLA, 6);
+    op!(0x3B, "RLA", ABY, RLA, 7);
+    op!(0x3F, "RLA", ABX, RLA, 7);
+
+    op!(0x43, "SRE", IZX, SRE, 8);
+    op!(0x47, "SRE", ZP0, SRE, 5);
+    op!(0x4F, "SRE", ABS, SRE, 6);
+    op!(0x53, "SRE", IZY, SRE, 8);
+    op!(0x57, "SRE", ZPX, SRE, 6);
+    op!(0x5B, "SRE", ABY, SRE, 7);
+    op!(0x5F, "SRE", ABX, SRE, 7);
+
+    op!(0x63, "RRA", IZX, RRA, 8);
+    op!(0x67, "RRA", ZP0, RRA, 5);
+    op!(0x6F, "RRA", ABS, RRA, 6);
+    op!(0x73, "RRA", IZY, RRA, 8);
+    op!(0x77, "RRA", ZPX, RRA, 6);
+    op!(0x7B, "RRA", ABY, RRA, 7);
+    op!(0x7F, "RRA", ABX, RRA, 7);
+
+    op!(0x83, "SAX", IZX, SAX, 6);
+    op!(0x87, "SAX", ZP0, SAX, 3);
+    op!(0x8F, "SAX", ABS, SAX, 4);
+    op!(0x97, "SAX", ZPY, SAX, 4);
+
+    op!(0xA3, "LAX", IZX, LAX, 6);
+    op!(0xA7, "LAX", ZP0, LAX, 3);
+    op!(0xAF, "LAX", ABS, LAX, 4);
+    op!(0xB3, "LAX", IZY, LAX, 5);
+    op!(0xB7, "LAX", ZPY, LAX, 4);
+    op!(0xBF, "LAX", ABY, LAX, 4);
+
+    op!(0xC3, "DCP", IZX, DCP, 8);
+    op!(0xC7, "DCP", ZP0, DCP, 5);
+    op!(0xCF, "DCP", ABS, DCP, 6);
+    op!(0xD3, "DCP", IZY, DCP, 8);
+    op!(0xD7, "DCP", ZPX, DCP, 6);
+    op!(0xDB, "DCP", ABY, DCP, 7);
+    op!(0xDF, "DCP", ABX, DCP, 7);
+
+    op!(0xE3, "ISC", IZX, ISC, 8);
+    op!(0xE7, "ISC", ZP0, ISC, 5);
+    op!(0xEF, "ISC", ABS, ISC, 6);
+    op!(0xF3, "ISC", IZY, ISC, 8);
+    op!(0xF7, "ISC", ZPX, ISC, 6);
+    op!(0xFB, "ISC", ABY, ISC, 7);
+    op!(0xFF, "ISC", ABX, ISC, 7);
+
+    op!(0x0B, "ANC", IMM, ANC, 2);
+    op!(0x2B, "ANC", IMM, ANC, 2);
+    op!(0x4B, "ALR", IMM, ALR, 2);
+    op!(0x6B, "ARR", IMM, ARR, 2);
+    op!(0x8B, "XAA", IMM, XAA, 2);
+    op!(0xAB, "LXA", IMM, LXA, 2);
+    op!(0xCB, "SBX", IMM, SBX, 2);
+    op!(0xEB, "SBC", IMM, SBC, 2);
+
+    op!(0x93, "AHX", IZY, AHX, 6);
+    op!(0x9F, "AHX", ABY, AHX, 5);
+    op!(0x9E, "SHX", ABY, SHX, 5);
+    op!(0x9C, "SHY", ABX, SHY, 5);
+    op!(0x9B, "TAS", ABY, TAS, 5);
+    op!(0xBB, "LAS", ABY, LAS, 4);
+
+    // Unofficial NOPs with the operand widths and timings of their addressing modes
+    op!(0x1A, "NOP", IMP, NOP, 2);
+    op!(0x3A, "NOP", IMP, NOP, 2);
+    op!(0x5A, "NOP", IMP, NOP, 2);
+    op!(0x7A, "NOP", IMP, NOP, 2);
+    op!(0xDA, "NOP", IMP, NOP, 2);
+    op!(0xFA, "NOP", IMP, NOP, 2);
+
+    op!(0x80, "NOP", IMM, NOP, 2);
+    op!(0x82, "NOP", IMM, NOP, 2);
+    op!(0x89, "NOP", IMM, NOP, 2);
+    op!(0xC2, "NOP", IMM, NOP, 2);
+    op!(0xE2, "NOP", IMM, NOP, 2);
+
+    op!(0x04, "NOP", ZP0, NOP, 3);
+    op!(0x44, "NOP", ZP0, NOP, 3);
+    op!(0x64, "NOP", ZP0, NOP, 3);
+
+    op!(0x14, "NOP", ZPX, NOP, 4);
+    op!(0x34, "NOP", ZPX, NOP, 4);
+    op!(0x54, "NOP", ZPX, NOP, 4);
+    op!(0x74, "NOP", ZPX, NOP, 4);
+    op!(0xD4, "NOP", ZPX, NOP, 4);
+    op!(0xF4, "NOP", ZPX, NOP, 4);
+
+    op!(0x0C, "NOP", ABS, NOP, 4);
+
+    op!(0x1C, "NOP", ABX, NOP, 4);
+    op!(0x3C, "NOP", ABX, NOP, 4);
+    op!(0x5C, "NOP", ABX, NOP, 4);
+    op!(0x7C, "NOP", ABX, NOP, 4);
+    op!(0xDC, "NOP", ABX, NOP, 4);
+    op!(0xFC, "NOP", ABX, NOP, 4);
+
+    // JAM opcodes halt the CPU until the next reset
+    op!(0x02, "JAM", IMP, JAM, 2);
+    op!(0x12, "JAM", IMP, JAM, 2);
+    op!(0x22, "JAM", IMP, JAM, 2);
+    op!(0x32, "JAM", IMP, JAM, 2);
+    op!(0x42, "JAM", IMP, JAM, 2);
+    op!(0x52, "JAM", IMP, JAM, 2);
+    op!(0x62, "JAM", IMP, JAM, 2);
+    op!(0x72, "JAM", IMP, JAM, 2);
+    op!(0x92, "JAM", IMP, JAM, 2);
+    op!(0xB2, "JAM", IMP, JAM, 2);
+    op!(0xD2, "JAM", IMP, JAM, 2);
+    op!(0xF2, "JAM", IMP, JAM, 2);
+
     t
 }
 
 pub static LOOKUP: [Instruction; 256] = build_lookup();
 
+// True for everything that is not part of the documented instruction set,
+// including the NOP variants and the SBC copy at 0xEB
+pub fn is_unofficial(opcode: u8) -> bool {
+    match LOOKUP[opcode as usize].operation {
+        Operation::NOP => opcode != 0xEA,
+        Operation::SBC => opcode == 0xEB,
+        Operation::SLO | Operation::RLA | Operation::SRE | Operation::RRA |
+        Operation::DCP | Operation::ISC | Operation::LAX | Operation::SAX |
+        Operation::LAS | Operation::ANC | Operation::ALR | Operation::ARR |
+        Operation::XAA | Operation::LXA | Operation::SBX | Operation::AHX |
+        Operation::SHX | Operation::SHY | Operation::TAS | Operation::JAM |
+        Operation::XXX => true,
+        _ => false,
+    }
+}
+
+// How an operation touches the memory operand. The cycle-stepped mode needs
+// this to know which bus accesses (and dummy accesses) happen on which cycle.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MemoryAccess {
+    Read,
+    Write,
+    ReadModifyWrite,
+}
+
+impl Operation {
+    pub fn memory_access(self) -> MemoryAccess {
+        match self {
+            Operation::STA | Operation::STX | Operation::STY | Operation::SAX |
+            Operation::AHX | Operation::SHX | Operation::SHY | Operation::TAS => MemoryAccess::Write,
+
+            Operation::ASL | Operation::LSR | Operation::ROL | Operation::ROR |
+            Operation::INC | Operation::DEC |
+            Operation::SLO | Operation::RLA | Operation::SRE | Operation::RRA |
+            Operation::DCP | Operation::ISC => MemoryAccess::ReadModifyWrite,
+
+            _ => MemoryAccess::Read,
+        }
+    }
+}
+
+// The default mode executes a whole instruction on its first cycle and then
+// idles for the remaining ones, exactly like javidx9's original. The
+// cycle-stepped mode performs one bus access per clock() like the real chip,
+// at the price of being a bit slower.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ExecutionMode {
+    Instant,
+    CycleStepped,
+}
+
+// With the serde feature the whole CPU state can be saved and restored, the
+// trace output and the debugger belong to the session and are not part of it
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Olc6502 {
     // registers
     a      : u8,  // Accumulator register
@@ -434,16 +686,87 @@ pub struct Olc6502 {
     addr_rel : u16,
     opcode   : u8, 
     cycles   : u8,
+    halted   : bool, // set by the JAM opcodes, only a reset (or unjam) gets the CPU going again
+    total_cycles       : u64, // clock() calls since power on
+    total_instructions : u64, // instructions started since power on
+    instruction_pc : u16, // address of the instruction in flight
+
+    // cycle-stepped execution
+    mode            : ExecutionMode,
+    step            : u8,   // cycle of the instruction in flight, 0 if there is none (also counted in instant mode)
+    base            : u16,  // un-indexed address / pointer while the effective address is formed
+    operand_latched : bool, // fetch() returns the operand the microcode already read
+
+    // interrupts
+    nmi_line       : bool, // level of the NMI input, an NMI is triggered by its rising edge
+    nmi_pending    : bool, // an NMI edge was detected and has not been serviced yet
+    irq_sources    : u8,   // IRQ_SOURCE_* bits currently pulling the IRQ line, an IRQ is triggered as long as one is set
+    interrupt_poll : bool, // result of the last poll, the interrupt sequence runs after this instruction
+    in_sequence    : bool, // the cycles being counted down belong to reset or an interrupt sequence
+    delayed_i      : Option<bool>, // I flag seen by the polls while CLI, SEI or PLP finish
+
+    // RDY
+    rdy_line       : bool, // the CPU halts on its next read cycle while this is low (false)
+    stalled        : bool, // the last cycle was spent halted
+    last_read_addr : u16,  // the read that is repeated while halted
 
     // debugging
-    trace_enabled : bool
+    trace_enabled : bool,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    trace_writer  : Option<Box<dyn Write>>, // receives a nestest style line per instruction
+    #[cfg_attr(feature = "serde", serde(skip))]
+    debugger      : Debugger,
 }
 
 
 
 
+impl Cpu6502 for Olc6502 {
+    fn reset           (&mut self, bus: &mut dyn BusInterface) { Olc6502::reset(self, bus) }
+    fn clock           (&mut self, bus: &mut dyn BusInterface) { Olc6502::clock(self, bus) }
+    fn step_instruction(&mut self, bus: &mut dyn BusInterface) { Olc6502::step_instruction(self, bus) }
+
+    fn set_nmi_line(&mut self, level: bool) { Olc6502::set_nmi_line(self, level) }
+    fn set_irq_line(&mut self, level: bool) { Olc6502::set_irq_line(self, level) }
+    fn assert_irq  (&mut self, source: u8)  { Olc6502::assert_irq(self, source) }
+    fn ack_irq     (&mut self, source: u8)  { Olc6502::ack_irq(self, source) }
+    fn set_rdy_line(&mut self, level: bool) { Olc6502::set_rdy_line(self, level) }
+    fn is_stalled  (&    self) -> bool      { Olc6502::is_stalled(self) }
+
+    fn get_registers       (&    self) -> (u8, u8, u8, u8, u16, u8) { Olc6502::get_registers(self) }
+    fn set_registers       (&mut self, a: u8, x: u8, y: u8, s: u8, pc: u16, p: u8) { Olc6502::set_registers(self, a, x, y, s, pc, p) }
+    fn get_state           (&    self) -> (u8, u16, u16, u8, u8) { Olc6502::get_state(self) }
+    fn set_state           (&mut self, fetched: u8, addr_abs: u16, addr_rel: u16, opcode: u8, cycles: u8) { Olc6502::set_state(self, fetched, addr_abs, addr_rel, opcode, cycles) }
+    fn set_pc              (&mut self, pc: u16) { Olc6502::set_pc(self, pc) }
+    fn get_remaining_cycles(&    self) -> u8 { Olc6502::get_remaining_cycles(self) }
+    fn get_counters        (&    self) -> (u64, u64) { Olc6502::get_counters(self) }
+
+    fn is_halted(&    self) -> bool { Olc6502::is_halted(self) }
+    fn unjam    (&mut self)         { Olc6502::unjam(self) }
+
+    fn debugger(&mut self) -> &mut Debugger { Olc6502::debugger(self) }
+
+    #[cfg(feature = "serde")]
+    fn save_state(&self) -> Vec<u8> {
+        bincode::serialize(self).expect("the CPU state always serializes")
+    }
+
+    #[cfg(feature = "serde")]
+    fn load_state(&mut self, state: &[u8]) -> Result<(), EmuError> {
+        let mut state: Olc6502 = bincode::deserialize(state).map_err(|_| EmuError::InvalidSaveState)?;
+        state.trace_writer = self.trace_writer.take();
+        state.debugger     = std::mem::take(&mut self.debugger);
+        *self = state;
+        Ok(())
+    }
+}
+
 impl Olc6502 {
     pub fn new() -> Self {
+        Self::with_mode(ExecutionMode::Instant)
+    }
+
+    pub fn with_mode(mode: ExecutionMode) -> Self {
         Self {
             // init registers etc
             a:        0,
@@ -458,12 +781,34 @@ impl Olc6502 {
             addr_rel: 0, 
             opcode:   0,
             cycles:   0,
-
-            trace_enabled: false
+            halted:   false,
+            total_cycles:       0,
+            total_instructions: 0,
+            instruction_pc: 0,
+
+            mode,
+            step:            0,
+            base:            0,
+            operand_latched: false,
+
+            nmi_line:       false,
+            nmi_pending:    false,
+            irq_sources:    0,
+            interrupt_poll: false,
+            in_sequence:    false,
+            delayed_i:      None,
+
+            rdy_line:       true,
+            stalled:        false,
+            last_read_addr: 0x0000,
+
+            trace_enabled: false,
+            trace_writer:  None,
+            debugger:      Debugger::new(),
         }
     }
 
-    pub fn read(&self, bus: &mut dyn BusInterface, addr: u16) -> u8 {
+    pub fn read(&mut self, bus: &mut dyn BusInterface, addr: u16) -> u8 {
         
         // In normal operation "read only" is set to false. This may seem odd. Some
         // devices on the bus may change state when they are read from, and this 
@@ -471,25 +816,54 @@ impl Olc6502 {
         // want to read the data at an address without changing the state of the
         // devices on the bus
         let read_only: bool = false;
-        bus.read(addr, read_only)
+        self.last_read_addr = addr;
+        let data = bus.read(addr, read_only);
+        self.debugger.on_read(addr, data, self.instruction_pc);
+        data
     }
 
     // Writes a byte to the bus at the specified address
-    pub fn write(&self, bus: &mut dyn BusInterface, addr: u16, data: u8) {
+    pub fn write(&mut self, bus: &mut dyn BusInterface, addr: u16, data: u8) {
+        let previous = if self.debugger.needs_previous_value(addr) { Some(bus.peek(addr)) } else { None };
+        self.debugger.on_write(addr, data, previous, self.instruction_pc);
         bus.write(addr, data)
     }
 
+    // Stack pointer moves after a push and before a pull. The stack silently
+    // wraps around within page $01, which is legal but almost always a bug in
+    // the program, so the debugger is told about it.
+    fn dec_stkp(&mut self) {
+        if self.stkp == 0x00 {
+            self.debugger.on_stack_overflow(self.instruction_pc);
+        }
+        self.stkp = self.stkp.wrapping_sub(1);
+    }
+
+    fn inc_stkp(&mut self) {
+        if self.stkp == 0xFF {
+            self.debugger.on_stack_underflow(self.instruction_pc);
+        }
+        self.stkp = self.stkp.wrapping_add(1);
+    }
+
+    pub fn debugger(&mut self) -> &mut Debugger {
+        &mut self.debugger
+    }
+
     // Print debug trace 
     // 1. Read current opcode from RAM
     // 2. Look up instruction
     // 3. Check the two bytes following the instruction
     // 4. Print pc location, length of instruction, name, arguments of instruction and CPU state for debuggig
+    // The columns follow the Nintendulator log of nestest (without the PPU position),
+    // unofficial opcodes are marked with a *
     pub fn trace(&self, bus: &mut dyn BusInterface) -> String {
-        let opcode        = self.read(bus, self.pc);
+        // Only peek, tracing must not disturb the devices on the bus
+        let opcode        = bus.peek(self.pc);
         let inst = LOOKUP[opcode as usize];
 
-        let b1 = self.read(bus,self.pc.wrapping_add(1));
-        let b2 = self.read(bus,self.pc.wrapping_add(2));
+        let b1 = bus.peek(self.pc.wrapping_add(1));
+        let b2 = bus.peek(self.pc.wrapping_add(2));
 
         let bytes = match inst.addrmode.len() {
             1 => format!("{:02X}      ", opcode),
@@ -517,24 +891,61 @@ impl Olc6502 {
             }
         };
 
+        let marker = if is_unofficial(opcode) { "*" } else { " " };
+
+        // nestest calls ISC by its other name
+        let name = if inst.operation == Operation::ISC { "ISB" } else { inst.name };
+
         format!(
-            "{:04X}  {} {:<8} {:<8}  A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X}",
+            "{:04X}  {} {}{} {:<28}A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} CYC:{}",
             self.pc,
             bytes,
-            inst.name,
+            marker,
+            name,
             operand,
             self.a,
             self.x,
             self.y,
             self.status,
             self.stkp,
+            self.total_cycles,
         )
     }
 
     
+    // Every instruction is traced right before its opcode is fetched, to stdout
+    // if tracing is enabled and to the trace writer if there is one
+    fn trace_instruction(&mut self, bus: &mut dyn BusInterface) {
+        if !self.trace_enabled && self.trace_writer.is_none() {
+            return;
+        }
+
+        let line = self.trace(bus);
+        if self.trace_enabled {
+            println!("{}", line);
+        }
+        if let Some(writer) = self.trace_writer.as_mut() {
+            // A trace that cannot be written should not stop the emulation
+            let _ = writeln!(writer, "{}", line);
+        }
+    }
+
+    pub fn set_trace_writer(&mut self, writer: Option<Box<dyn Write>>) {
+        self.trace_writer = writer;
+    }
+
+    // (total cycles, total instructions)
+    pub fn get_counters(&self) -> (u64, u64) {
+        (self.total_cycles, self.total_instructions)
+    }
+
     pub fn get_registers(&self) -> (u8, u8, u8, u8, u16, u8) {
         (self.a, self.x, self.y, self.stkp, self.pc, self.status)
     }
+    pub fn get_flags(&self) -> StatusFlags {
+        StatusFlags::from_status(self.status)
+    }
+
     pub fn set_registers(&mut self, a:u8,x:u8,y:u8,s:u8,pc:u16,p:u8) {
         (self.a, self.x, self.y, self.stkp, self.pc, self.status) = (a, x, y, s, pc, p);
     }
@@ -543,14 +954,41 @@ impl Olc6502 {
         (self.fetched, self.addr_abs, self.addr_rel, self.opcode, self.cycles)
     }
 
+    // The counterpart of get_state() for save states and debuggers
+    pub fn set_state(&mut self, fetched: u8, addr_abs: u16, addr_rel: u16, opcode: u8, cycles: u8) {
+        (self.fetched, self.addr_abs, self.addr_rel, self.opcode, self.cycles) = (fetched, addr_abs, addr_rel, opcode, cycles);
+    }
+
+    // Continue execution somewhere else. Done at an instruction boundary
+    // (get_remaining_cycles() == 0) the next instruction is fetched from pc.
+    pub fn set_pc(&mut self, pc: u16) {
+        self.pc = pc;
+    }
+
     pub fn get_remaining_cycles(&self) -> u8 {
         self.cycles
     }
 
+    pub fn get_mode(&self) -> ExecutionMode {
+        self.mode
+    }
+
     pub fn force_cycles_zero(&mut self) {
         self.cycles = 0;
     }
 
+    // True once a JAM opcode has locked up the CPU
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    // Recover from a JAM without a full reset - execution continues with the
+    // byte following the JAM opcode
+    pub fn unjam(&mut self) {
+        self.halted = false;
+        self.cycles = 0;
+    }
+
     ///////////////////////////////////////////////////////////////////////////////
     // EXTERNAL INPUTS
 
@@ -576,55 +1014,192 @@ impl Olc6502 {
         self.addr_rel = 0x0000;
         self.addr_abs = 0x0000;
         self.fetched  = 0x00;
+        self.halted   = false;
+
+        self.step           = 0;
+        self.nmi_pending    = false;
+        self.interrupt_poll = false;
+        self.in_sequence    = true;
         
-        self.cycles  = 8;
+        // Like the interrupt sequence it is derived from, reset takes 7 cycles
+        self.cycles  = 7;
 
     }
 
-    // Interrupt requests are a complex operation and only happen if the
-    // "disable interrupt" flag is 0. IRQs can happen at any time, but
-    // you dont want them to be destructive to the operation of the running 
-    // program. Therefore the current instruction is allowed to finish
-    // (which I facilitate by doing the whole thing when cycles == 0) and 
-    // then the current program counter is stored on the stack. Then the
-    // current status register is stored on the stack. When the routine
-    // that services the interrupt has finished, the status register
-    // and program counter can be restored to how they where before it 
-    // occurred. This is impemented by the "RTI" instruction. Once the IRQ
-    // has happened, in a similar way to a reset, a programmable address
-    // is read form hard coded location 0xFFFE, which is subsequently
-    // set to the program counter.
-    pub fn irq(&mut self, bus: &mut dyn BusInterface) {
-        if self.get_flag(FLAG6502_I) != 0 {
-            self.nmi(bus);
+    // Interrupts are requested by driving the IRQ and NMI input lines. Instead
+    // of interrupting the CPU right away, the lines are polled once per
+    // instruction at the start of its last cycle, so the instruction is allowed
+    // to finish. If the poll found an interrupt, the interrupt sequence runs in
+    // place of the next instruction: the program counter and the status register
+    // are stored on the stack and a programmable address is read from a hard
+    // coded location, 0xFFFA for an NMI and 0xFFFE for an IRQ. When the routine
+    // that services the interrupt has finished, the status register and program
+    // counter can be restored to how they where before it occurred. This is
+    // impemented by the "RTI" instruction.
+    //
+    // A Non-Maskable Interrupt cannot be ignored. It is edge triggered: pulling
+    // the line high once requests exactly one NMI, no matter how long it is held.
+    pub fn set_nmi_line(&mut self, level: bool) {
+        if level && !self.nmi_line {
+            self.nmi_pending = true;
         }
+        self.nmi_line = level;
+    }
+
+    // Regular IRQs are level triggered and only happen if the "disable
+    // interrupt" flag is 0. The device has to hold the line until the CPU
+    // has serviced it, otherwise the request is lost. Several devices share
+    // the line, so each one asserts and acknowledges its own IRQ_SOURCE_* bit.
+    pub fn assert_irq(&mut self, source: u8) {
+        self.irq_sources |= source;
+    }
+
+    pub fn ack_irq(&mut self, source: u8) {
+        self.irq_sources &= !source;
+    }
+
+    pub fn set_irq_line(&mut self, level: bool) {
+        if level {
+            self.assert_irq(IRQ_SOURCE_EXTERNAL);
+        } else {
+            self.ack_irq(IRQ_SOURCE_EXTERNAL);
+        }
+    }
+
+    pub fn irq_line(&self) -> bool {
+        self.irq_sources != 0
+    }
+
+    // RDY is pulled low by the DMA units to borrow the bus
+    pub fn set_rdy_line(&mut self, level: bool) {
+        self.rdy_line = level;
+    }
+
+    pub fn is_stalled(&self) -> bool {
+        self.stalled
+    }
+
+    // Whether the coming cycle writes to the bus, following the same cycle
+    // layout as the cycle-stepped microcode
+    fn next_cycle_writes(&self) -> bool {
+        // Opcode fetches and the reset sequence only read
+        if self.cycles == 0 || self.step == 0 {
+            return false;
+        }
+
+        let next = self.step + 1;
+        if self.in_sequence {
+            return (3..=5).contains(&next);
+        }
+
+        let inst = LOOKUP[self.opcode as usize];
+        match inst.operation {
+            Operation::BRK                  => (3..=5).contains(&next),
+            Operation::JSR                  => next == 4 || next == 5,
+            Operation::PHA | Operation::PHP => next == 3,
+            Operation::JMP | Operation::RTS | Operation::RTI |
+            Operation::PLA | Operation::PLP => false,
+            operation => {
+                // First cycle after the effective address is known
+                let operand_step = match inst.addrmode {
+                    AddressMode::ZP0                                      => 3,
+                    AddressMode::ZPX | AddressMode::ZPY | AddressMode::ABS => 4,
+                    AddressMode::ABX | AddressMode::ABY                   => 5,
+                    AddressMode::IZX | AddressMode::IZY                   => 6,
+                    _                                                     => return false,
+                };
+
+                match operation.memory_access() {
+                    MemoryAccess::Read            => false,
+                    MemoryAccess::Write           => next == operand_step,
+                    MemoryAccess::ReadModifyWrite => next == operand_step + 1 || next == operand_step + 2,
+                }
+            }
+        }
+    }
+
+    // The lines are polled before every cycle but the opcode fetch, whatever the
+    // poll before the last cycle finds wins. BRK and the interrupt sequence do
+    // not poll at all, and taken branches skip the poll before their third
+    // cycle: a branch that does not cross a page therefore delays an interrupt
+    // by one more instruction.
+    fn polls_this_cycle(&self) -> bool {
+        !self.in_sequence
+            && self.opcode != 0x00
+            && !(LOOKUP[self.opcode as usize].addrmode == AddressMode::REL && self.step == 3)
+    }
+
+    fn poll_interrupts(&mut self) {
+        let interrupts_disabled = self.delayed_i.unwrap_or(self.get_flag(FLAG6502_I) != 0);
+        self.interrupt_poll = self.nmi_pending || (self.irq_line() && !interrupts_disabled);
     }
 
-    // A Non-Maskable Interrupt cannot be ignored. It behaves in exactly the
-    // same way as a regular IRQ, but reads the new program counter address
-    // form location 0xFFFA.
-    pub fn nmi(&mut self, bus: &mut dyn BusInterface) {
+    // The instant version of the interrupt sequence, performed in one hit just
+    // like the instructions
+    fn interrupt(&mut self, bus: &mut dyn BusInterface) {
         // no wrapping because stkp is u8 so there are no overflows
         self.write(bus, 0x0100 + self.stkp as u16, ((self.pc >> 8) & 0x00FF) as u8);
-        self.stkp = self.stkp.wrapping_sub(1); 
+        self.dec_stkp();
         self.write(bus, 0x0100 + self.stkp as u16, ((self.pc     ) & 0x00FF) as u8);
-        self.stkp = self.stkp.wrapping_sub(1); 
+        self.dec_stkp();
 
         self.set_flag(FLAG6502_B, false);
         self.set_flag(FLAG6502_U, true);
         self.set_flag(FLAG6502_I, true);
 
         self.write(bus, 0x0100 + self.stkp as u16, self.status);
-        self.stkp = self.stkp.wrapping_sub(1); 
+        self.dec_stkp();
 
-        self.addr_abs = 0xFFFA;
+        self.addr_abs = self.interrupt_vector();
         let lo: u16 = self.read(bus,self.addr_abs                ) as u16;
         let hi: u16 = self.read(bus,self.addr_abs.wrapping_add(1)) as u16;
         self.pc = (hi << 8) | lo; 
-        
-        self.cycles = 8;
+
+        self.interrupt_poll = false;
+        self.in_sequence    = true;
+        self.step           = 1;
+        self.cycles         = 7;
     }
 
+    // An NMI takes precedence over an IRQ and is acknowledged by fetching its vector
+    fn interrupt_vector(&mut self) -> u16 {
+        if self.nmi_pending {
+            self.nmi_pending = false;
+            0xFFFA
+        } else {
+            0xFFFE
+        }
+    }
+
+
+    // Perform one clock cycle's worth of emulation
+    pub fn clock(&mut self, bus: &mut dyn BusInterface) {
+
+        // Pulling RDY low halts the CPU on its next read cycle, which it keeps
+        // repeating until RDY is released. Write cycles can't be halted.
+        self.stalled = !self.rdy_line && !self.halted && !self.next_cycle_writes();
+
+        if self.stalled {
+            // In instant mode the read has already happened long ago
+            if self.mode == ExecutionMode::CycleStepped {
+                self.read(bus, self.last_read_addr);
+            }
+        }
+        // A jammed CPU does nothing at all until it is reset
+        else if !self.halted {
+            match self.mode {
+                ExecutionMode::Instant      => self.clock_instant(bus),
+                ExecutionMode::CycleStepped => self.clock_cycle_stepped(bus),
+            }
+        }
+        // The instant mode halts in the first cycle of the JAM, which still
+        // has its second cycle to run out like in the cycle-stepped mode
+        else if self.cycles > 0 {
+            self.cycles -= 1;
+        }
+
+        self.total_cycles += 1;
+    }
 
     // Each instruction requires a variable number of clock cycles to execute.
     // In my emulation, I only care about the final result and so I perform
@@ -636,21 +1211,40 @@ impl Olc6502 {
     // implement that delay by simply counting down the cycles required by 
     // the instruction. When it reaches 0, the instruction is complete, and
     // the next one is ready to be executed.
-    pub fn clock(&mut self, bus: &mut dyn BusInterface) {
+    fn clock_instant(&mut self, bus: &mut dyn BusInterface) {
     
         // Only actually do work once enough time has passed
         if self.cycles == 0 {
-            if self.trace_enabled {
-                println!("{}", self.trace(bus));
+            self.in_sequence = false;
+
+            if self.interrupt_poll {
+                self.interrupt(bus);
+                self.cycles -= 1;
+                return;
             }
 
+            self.trace_instruction(bus);
+
             // Read one byte from bus containing the opcode
-            self.opcode = bus.read(self.pc, true);
+            self.instruction_pc = self.pc;
+            self.total_instructions += 1;
+            self.last_read_addr = self.pc;
+            self.opcode = bus.read(self.pc, false);
             self.set_flag(FLAG6502_U, true);
             self.pc = self.pc.wrapping_add(1);
+            self.step = 1;
 
             let inst = LOOKUP[self.opcode as usize];
             self.cycles = inst.cycles;
+
+            // CLI, SEI and PLP change the I flag on their last cycle, after the
+            // poll. The new value is only seen one instruction later, so the polls
+            // have to keep using the old one. The cycle-stepped mode gets this
+            // for free since it polls before executing the last cycle.
+            self.delayed_i = match inst.operation {
+                Operation::CLI | Operation::SEI | Operation::PLP => Some(self.get_flag(FLAG6502_I) != 0),
+                _ => None,
+            };
             
             // addressing mode
             let additional_cycle1 = match inst.addrmode {
@@ -668,92 +1262,18 @@ impl Olc6502 {
                 AddressMode::REL => self.rel(bus),
             };
 
-            let additional_cycle2 = match inst.operation {
-                // System
-                Operation::BRK => self.brk(bus),
-                Operation::NOP => self.nop(bus),
-
-                // Loads
-                Operation::LDA => self.lda(bus),
-                Operation::LDX => self.ldx(bus),
-                Operation::LDY => self.ldy(bus),
-
-                // Stores
-                Operation::STA => self.sta(bus),
-                Operation::STX => self.stx(bus),
-                Operation::STY => self.sty(bus),
-
-                // Register transfers
-                Operation::TAX => self.tax(bus),
-                Operation::TAY => self.tay(bus),
-                Operation::TXA => self.txa(bus),
-                Operation::TYA => self.tya(bus),
-                Operation::TSX => self.tsx(bus),
-                Operation::TXS => self.txs(bus),
-
-                // Stack
-                Operation::PHA => self.pha(bus),
-                Operation::PHP => self.php(bus),
-                Operation::PLA => self.pla(bus),
-                Operation::PLP => self.plp(bus),
-
-                // Logical
-                Operation::AND => self.and(bus),
-                Operation::EOR => self.eor(bus),
-                Operation::ORA => self.ora(bus),
-                Operation::BIT => self.bit(bus),
-
-                // Arithmetic / Compare
-                Operation::ADC => self.adc(bus),
-                Operation::SBC => self.sbc(bus),
-                Operation::CMP => self.cmp(bus),
-                Operation::CPX => self.cpx(bus),
-                Operation::CPY => self.cpy(bus),
-
-                // Inc / Dec
-                Operation::INC => self.inc(bus),
-                Operation::INX => self.inx(bus),
-                Operation::INY => self.iny(bus),
-                Operation::DEC => self.dec(bus),
-                Operation::DEX => self.dex(bus),
-                Operation::DEY => self.dey(bus),
-
-                // Shifts / Rotates
-                Operation::ASL => self.asl(bus),
-                Operation::LSR => self.lsr(bus),
-                Operation::ROL => self.rol(bus),
-                Operation::ROR => self.ror(bus),
-
-                // Jumps / Calls
-                Operation::JMP => self.jmp(bus),
-                Operation::JSR => self.jsr(bus),
-                Operation::RTS => self.rts(bus),
-                Operation::RTI => self.rti(bus),
-
-                // Branches
-                Operation::BCC => self.bcc(bus),
-                Operation::BCS => self.bcs(bus),
-                Operation::BEQ => self.beq(bus),
-                Operation::BMI => self.bmi(bus),
-                Operation::BNE => self.bne(bus),
-                Operation::BPL => self.bpl(bus),
-                Operation::BVC => self.bvc(bus),
-                Operation::BVS => self.bvs(bus),
-
-                // Flag operations
-                Operation::CLC => self.clc(bus),
-                Operation::CLD => self.cld(bus),
-                Operation::CLI => self.cli(bus),
-                Operation::CLV => self.clv(bus),
-                Operation::SEC => self.sec(bus),
-                Operation::SED => self.sed(bus),
-                Operation::SEI => self.sei(bus),
-
-                // Illegal / placeholder
-                Operation::XXX => self.xxx(bus),
-            };
+            let additional_cycle2 = self.execute(bus);
             self.cycles += additional_cycle1 & additional_cycle2;
 
+        } else {
+            // The work is already done, but the interrupt lines are still polled as
+            // if the instruction was being executed cycle by cycle
+            if self.step != 0 {
+                self.step += 1;
+            }
+            if self.polls_this_cycle() {
+                self.poll_interrupts();
+            }
         }
 
         self.cycles -= 1;
@@ -761,6 +1281,122 @@ impl Olc6502 {
     }
 
 
+    // Runs the operation of the current opcode. The addressing mode has already
+    // been resolved at this point, so addr_abs/addr_rel (or the latched operand)
+    // are ready to be used. Returns 1 if the operation can take an extra cycle
+    // when the addressing mode crossed a page.
+    fn execute(&mut self, bus: &mut dyn BusInterface) -> u8 {
+        let inst = LOOKUP[self.opcode as usize];
+
+        match inst.operation {
+            // System
+            Operation::BRK => self.brk(bus),
+            Operation::NOP => self.nop(bus),
+
+            // Loads
+            Operation::LDA => self.lda(bus),
+            Operation::LDX => self.ldx(bus),
+            Operation::LDY => self.ldy(bus),
+
+            // Stores
+            Operation::STA => self.sta(bus),
+            Operation::STX => self.stx(bus),
+            Operation::STY => self.sty(bus),
+
+            // Register transfers
+            Operation::TAX => self.tax(bus),
+            Operation::TAY => self.tay(bus),
+            Operation::TXA => self.txa(bus),
+            Operation::TYA => self.tya(bus),
+            Operation::TSX => self.tsx(bus),
+            Operation::TXS => self.txs(bus),
+
+            // Stack
+            Operation::PHA => self.pha(bus),
+            Operation::PHP => self.php(bus),
+            Operation::PLA => self.pla(bus),
+            Operation::PLP => self.plp(bus),
+
+            // Logical
+            Operation::AND => self.and(bus),
+            Operation::EOR => self.eor(bus),
+            Operation::ORA => self.ora(bus),
+            Operation::BIT => self.bit(bus),
+
+            // Arithmetic / Compare
+            Operation::ADC => self.adc(bus),
+            Operation::SBC => self.sbc(bus),
+            Operation::CMP => self.cmp(bus),
+            Operation::CPX => self.cpx(bus),
+            Operation::CPY => self.cpy(bus),
+
+            // Inc / Dec
+            Operation::INC => self.inc(bus),
+            Operation::INX => self.inx(bus),
+            Operation::INY => self.iny(bus),
+            Operation::DEC => self.dec(bus),
+            Operation::DEX => self.dex(bus),
+            Operation::DEY => self.dey(bus),
+
+            // Shifts / Rotates
+            Operation::ASL => self.asl(bus),
+            Operation::LSR => self.lsr(bus),
+            Operation::ROL => self.rol(bus),
+            Operation::ROR => self.ror(bus),
+
+            // Jumps / Calls
+            Operation::JMP => self.jmp(bus),
+            Operation::JSR => self.jsr(bus),
+            Operation::RTS => self.rts(bus),
+            Operation::RTI => self.rti(bus),
+
+            // Branches
+            Operation::BCC => self.bcc(bus),
+            Operation::BCS => self.bcs(bus),
+            Operation::BEQ => self.beq(bus),
+            Operation::BMI => self.bmi(bus),
+            Operation::BNE => self.bne(bus),
+            Operation::BPL => self.bpl(bus),
+            Operation::BVC => self.bvc(bus),
+            Operation::BVS => self.bvs(bus),
+
+            // Flag operations
+            Operation::CLC => self.clc(bus),
+            Operation::CLD => self.cld(bus),
+            Operation::CLI => self.cli(bus),
+            Operation::CLV => self.clv(bus),
+            Operation::SEC => self.sec(bus),
+            Operation::SED => self.sed(bus),
+            Operation::SEI => self.sei(bus),
+
+            // Unofficial
+            Operation::SLO => self.slo(bus),
+            Operation::RLA => self.rla(bus),
+            Operation::SRE => self.sre(bus),
+            Operation::RRA => self.rra(bus),
+            Operation::DCP => self.dcp(bus),
+            Operation::ISC => self.isc(bus),
+            Operation::LAX => self.lax(bus),
+            Operation::SAX => self.sax(bus),
+            Operation::LAS => self.las(bus),
+            Operation::ANC => self.anc(bus),
+            Operation::ALR => self.alr(bus),
+            Operation::ARR => self.arr(bus),
+            Operation::XAA => self.xaa(bus),
+            Operation::LXA => self.lxa(bus),
+            Operation::SBX => self.sbx(bus),
+            Operation::AHX => self.ahx(bus),
+            Operation::SHX => self.shx(bus),
+            Operation::SHY => self.shy(bus),
+            Operation::TAS => self.tas(bus),
+            Operation::JAM => self.jam(bus),
+
+            // Illegal / placeholder
+            Operation::XXX => self.xxx(bus),
+        }
+    }
+
+
     // Execute one instruction by calling clock until cycles == 0
     pub fn step_instruction(&mut self, bus: &mut dyn BusInterface) {
         // Finish pending cycles
@@ -892,6 +1528,7 @@ impl Olc6502 {
         self.addr_abs  = (hi << 8) | lo; 
 
         self.addr_abs  = self.addr_abs.wrapping_add(self.x as u16);
+        self.indexed_dummy_read(bus, hi);
 
         // If the whole address has changed to a different page, we may need one more clock cycle
         // Overflow: Carry bit from the low byte has carried into the high byt
@@ -913,6 +1550,7 @@ impl Olc6502 {
         self.pc        = self.pc.wrapping_add(1);
         self.addr_abs  = (hi << 8) | lo; 
         self.addr_abs  = self.addr_abs.wrapping_add(self.y as u16);
+        self.indexed_dummy_read(bus, hi);
 
         // If the whole address has changed to a different page, we may need one more clock cycle
         // Overflow: Carry bit from the low byte has carried into the high byt
@@ -977,6 +1615,7 @@ impl Olc6502 {
 
         self.addr_abs  = (hi << 8) | lo; 
         self.addr_abs  = self.addr_abs.wrapping_add(self.y as u16); 
+        self.indexed_dummy_read(bus, hi);
 
         if (self.addr_abs & 0xFF00) != (hi << 8) {
             1
@@ -985,6 +1624,19 @@ impl Olc6502 {
         }
      }
 
+    // While the index is added, the CPU already reads from the address whose
+    // high byte has not been fixed up by the carry yet. A read that did not
+    // cross a page gets its operand from exactly that read (done by fetch()
+    // later), for page crossings, stores and read-modify-write instructions it
+    // is a dummy read of the "wrong" address - visible to registers like $2007.
+    fn indexed_dummy_read(&mut self, bus: &mut dyn BusInterface, hi: u16) {
+        let uncorrected: u16 = (hi << 8) | (self.addr_abs & 0x00FF);
+        let operation        = LOOKUP[self.opcode as usize].operation;
+
+        if uncorrected != self.addr_abs || operation.memory_access() != MemoryAccess::Read {
+            self.read(bus, uncorrected);
+        }
+    }
      
 
 
@@ -1003,17 +1655,37 @@ impl Olc6502 {
     pub fn fetch(&mut self, bus: &mut dyn BusInterface) -> u8 {
         let inst = LOOKUP[self.opcode as usize];
 
-        if inst.addrmode != AddressMode::IMP {
+        if inst.addrmode != AddressMode::IMP && !self.operand_latched {
             self.fetched = self.read(bus, self.addr_abs);
         }
 
         self.fetched
     }
 
+    // The counterpart of fetch() for the read-modify-write instructions. The
+    // 6502 writes the unmodified value back while it is computing the result,
+    // and only then writes the result, which registers that react to every
+    // write (e.g. the MMC1 shift register) can see. The cycle-stepped mode has
+    // already done the first write by the time the operation runs.
+    fn write_result(&mut self, bus: &mut dyn BusInterface, data: u8) {
+        if !self.operand_latched {
+            self.write(bus, self.addr_abs, self.fetched);
+        }
+        self.write(bus, self.addr_abs, data);
+    }
+
 
     // This function captures illegal opcodes
     fn xxx(&mut self, _bus: &mut dyn BusInterface) -> u8 { 0 }
 
+    // Instruction: Jam the processor
+    // On hardware the CPU gets stuck fetching forever and only a reset helps.
+    // We simply stop clocking until reset() or unjam() is called.
+    fn jam(&mut self, _bus: &mut dyn BusInterface) -> u8 {
+        self.halted = true;
+        0
+    }
+
     // Addition!
     // Add data fetched from memory to accumulator, including the carry bit
     // A += M + C
@@ -1047,24 +1719,29 @@ impl Olc6502 {
     // 1  1  1 | 0 |  0  |  0  |   1   |
     fn adc(&mut self, bus: &mut dyn BusInterface) -> u8 { 
         self.fetch(bus);
+        self.add_with_carry(self.fetched);
+        1 // can require an additional clock cycle
+    }
 
+    // The actual addition behind ADC, shared with SBC and the unofficial RRA/ISC
+    // which feed it a value that did not come from fetch()
+    fn add_with_carry(&mut self, value: u8) {
         // Perform the addition
-        let temp: u16 = self.a as u16 + self.fetched as u16 + self.get_flag(FLAG6502_C) as u16; 
+        let temp: u16 = self.a as u16 + value as u16 + self.get_flag(FLAG6502_C) as u16; 
 
         self.set_flag(FLAG6502_C,  temp > 0x00FF);            // Check whether addition led to carry bit being set
         self.set_flag(FLAG6502_Z, (temp & 0x00FF) == 0x0000); // Check whether result of addition is zero
         self.set_flag(FLAG6502_N, (temp & 0x0080) != 0x0000); // Check the most significant bit of the result for sign
         // (A^M) 
-        let t1 = (self.a as u16) ^ (self.fetched as u16);
+        let t1 = (self.a as u16) ^ (value as u16);
         // (A^R) 
-        let t2 = (self.a as u16) ^ (temp as u16);
+        let t2 = (self.a as u16) ^ temp;
 
         // Set up signed overflow bit based on the truth table up there
         // V = ~(A^M) & (A^R) = ~t1 & t2
-        self.set_flag(FLAG6502_V,  ((!t1 & t2 & 0x0080) != 0));
+        self.set_flag(FLAG6502_V,  (!t1 & t2 & 0x0080) != 0);
 
         self.a = (temp & 0x00FF) as u8; 
-        1 // can require an additional clock cycle
     }
 
     // Instruction: Bitwise Logic AND
@@ -1093,7 +1770,7 @@ impl Olc6502 {
         if inst.addrmode == AddressMode::IMP {
             self.a = (temp & 0x00FF) as u8;
         } else {
-            self.write(bus, self.addr_abs, (temp & 0x00FF) as u8); 
+            self.write_result(bus, (temp & 0x00FF) as u8); 
         }
         0
      }
@@ -1114,23 +1791,26 @@ impl Olc6502 {
     
     // Trigger an interrupt request in software
     // Push current program counter and process flags to the stack
-    // Set interrupt disable flag and jump to IRQ handler
+    // Set interrupt disable flag and jump to IRQ handler. A pending NMI
+    // hijacks the vector fetch, BRK then jumps to the NMI handler instead
+    // (with the B flag still set on the stack). Only the cycle-stepped mode
+    // sees an NMI that arrives while BRK is already running.
     fn brk(&mut self, bus: &mut dyn BusInterface) -> u8 {
         self.pc = self.pc.wrapping_add(1);
         self.write(bus, 0x0100 + self.stkp as u16, ((self.pc >> 8) & 0x00FF) as u8);
-        self.stkp = self.stkp.wrapping_sub(1); 
+        self.dec_stkp();
         self.write(bus, 0x0100 + self.stkp as u16, ((self.pc     ) & 0x00FF) as u8);
-        self.stkp = self.stkp.wrapping_sub(1); 
+        self.dec_stkp();
 
         self.set_flag(FLAG6502_B, true);
 
         self.write(bus, 0x0100 + self.stkp as u16, self.status);
-        self.stkp = self.stkp.wrapping_sub(1); 
+        self.dec_stkp();
 
         self.set_flag(FLAG6502_B, false);
         self.set_flag(FLAG6502_I, true);
 
-        self.addr_abs = 0xFFFE;
+        self.addr_abs = self.interrupt_vector();
         let lo: u16 = self.read(bus,self.addr_abs + 0) as u16;
         let hi: u16 = self.read(bus,self.addr_abs + 1) as u16;
         self.pc = (hi << 8) | lo; 
@@ -1319,7 +1999,7 @@ impl Olc6502 {
     fn dec(&mut self, bus: &mut dyn BusInterface) -> u8 { 
         self.fetch(bus);
         let temp: u8 = self.fetched.wrapping_sub(1);
-        self.write(bus, self.addr_abs, temp);
+        self.write_result(bus, temp);
         self.set_flag(FLAG6502_Z, temp        == 0x00);
         self.set_flag(FLAG6502_N, temp & 0x80 != 0x00);
         0
@@ -1351,7 +2031,7 @@ impl Olc6502 {
     fn inc(&mut self, bus: &mut dyn BusInterface) -> u8 { 
         self.fetch(bus);
         let temp: u8 = self.fetched.wrapping_add(1);
-        self.write(bus, self.addr_abs, temp);
+        self.write_result(bus, temp);
         self.set_flag(FLAG6502_Z, temp        == 0x00);
         self.set_flag(FLAG6502_N, temp & 0x80 != 0x00);
         0 
@@ -1392,9 +2072,9 @@ impl Olc6502 {
         
         self.pc  = self.pc.wrapping_sub(1);
         self.write(bus, 0x0100 + self.stkp as u16, ((self.pc >> 8) & 0x00FF) as u8);
-        self.stkp = self.stkp.wrapping_sub(1); 
+        self.dec_stkp();
         self.write(bus, 0x0100 + self.stkp as u16, ((self.pc     ) & 0x00FF) as u8);
-        self.stkp = self.stkp.wrapping_sub(1); 
+        self.dec_stkp();
 
         self.pc = self.addr_abs; 
         0
@@ -1449,7 +2129,7 @@ impl Olc6502 {
         if inst.addrmode == AddressMode::IMP {
             self.a = temp;
         } else {
-            self.write(bus, self.addr_abs, temp);
+            self.write_result(bus, temp);
         }
 
         return 0;
@@ -1457,7 +2137,11 @@ impl Olc6502 {
     }
 
     // No operation codes based on https://wiki.nesdev.com/w/index.php/CPU_unofficial_opcodes
-    fn nop(&mut self, _bus: &mut dyn BusInterface) -> u8 { 
+    // The unofficial multi-byte NOPs still read their operand from the bus (which
+    // matters for registers with read side effects) and the absolute,X variants
+    // pay the usual page crossing penalty
+    fn nop(&mut self, bus: &mut dyn BusInterface) -> u8 { 
+        self.fetch(bus);
         match self.opcode {
             0x1C | 0x3C | 0x5C | 0x7C | 0xDC | 0xFC => 1,
             _ => 0,
@@ -1469,7 +2153,7 @@ impl Olc6502 {
     // 0x0100 is hard-coded stack-location
     fn pha(&mut self, bus: &mut dyn BusInterface) -> u8 {
         self.write(bus, 0x0100 + (self.stkp as u16), self.a);
-        self.stkp = self.stkp.wrapping_sub(1);
+        self.dec_stkp();
         0
     }
     
@@ -1481,13 +2165,13 @@ impl Olc6502 {
         self.write(bus, 0x0100 + (self.stkp as u16), self.status | FLAG6502_B | FLAG6502_U);
         self.set_flag(FLAG6502_B, false); 
         // self.set_flag(FLAG6502_U, false);  Comment this out compared to Javid9x' implementation to satisfy Harte
-        self.stkp = self.stkp.wrapping_sub(1);
+        self.dec_stkp();
         0
     }
     // Pop from stack
     // 0x0100 is hard-coded stack-location
     fn pla(&mut self, bus: &mut dyn BusInterface) -> u8 { 
-        self.stkp = self.stkp.wrapping_add(1); 
+        self.inc_stkp();
         self.a = self.read(bus, (0x0100 as u16) + (self.stkp as u16));
         self.set_flag(FLAG6502_Z, self.a == 0x00); 
         self.set_flag(FLAG6502_N, self.a & 0x80 != 0); 
@@ -1497,7 +2181,7 @@ impl Olc6502 {
     // Instruction: Pop Status Register off Stack
     // Function:    Status <- stack
     fn plp(&mut self, bus: &mut dyn BusInterface) -> u8 { 
-        self.stkp   = self.stkp.wrapping_add(1); 
+        self.inc_stkp();
         // Per spec, this should ignore U and B
         // U (bit 5) should always be 1 internally.
         // B (bit 4) should always be 0 internally.
@@ -1528,7 +2212,7 @@ impl Olc6502 {
         if inst.addrmode == AddressMode::IMP {
             self.a = (temp & 0x00FF) as u8;
         } else {
-            self.write(bus, self.addr_abs, (temp & 0x00FF) as u8);
+            self.write_result(bus, (temp & 0x00FF) as u8);
         }
         0
     }
@@ -1545,7 +2229,7 @@ impl Olc6502 {
         if inst.addrmode == AddressMode::IMP {
             self.a = (temp & 0x00FF) as u8;
         } else {
-            self.write(bus, self.addr_abs, (temp & 0x00FF) as u8);
+            self.write_result(bus, (temp & 0x00FF) as u8);
         }
         0
     }
@@ -1555,14 +2239,14 @@ impl Olc6502 {
     // pull PC low byte from stack
     // pull PC high byte from stack 
     fn rti(&mut self, bus: &mut dyn BusInterface) -> u8 { 
-        self.stkp    = self.stkp.wrapping_add(1); 
+        self.inc_stkp();
         self.status  = self.read(bus, 0x0100 + self.stkp as u16);
         // Add this compared to Javidx9's implementation to satisfy Harte
         self.set_flag(FLAG6502_U, true); 
         self.set_flag(FLAG6502_B, false); 
-        self.stkp    = self.stkp.wrapping_add(1); 
+        self.inc_stkp();
         self.pc      = self.read(bus, 0x0100 + self.stkp as u16) as u16;
-        self.stkp    = self.stkp.wrapping_add(1); 
+        self.inc_stkp();
         self.pc     |= (self.read(bus, 0x0100 + self.stkp as u16) as u16) << 8;
         0
     }
@@ -1574,9 +2258,9 @@ impl Olc6502 {
     // PC = PC + 1     
     fn rts(&mut self, bus: &mut dyn BusInterface) -> u8 { 
         
-        self.stkp   = self.stkp.wrapping_add(1); 
+        self.inc_stkp();
         self.pc     = self.read(bus, 0x0100 + self.stkp as u16) as u16;
-        self.stkp   = self.stkp.wrapping_add(1); 
+        self.inc_stkp();
         self.pc    |= (self.read(bus, 0x0100 + self.stkp as u16) as u16) << 8;
         
         self.pc     = self.pc.wrapping_add(1);
@@ -1591,24 +2275,8 @@ impl Olc6502 {
     fn sbc(&mut self, bus: &mut dyn BusInterface) -> u8 {
         self.fetch(bus);
 
-        
-        // Perform the subtraction via an addition of 
-        let value : u16 = (self.fetched as u16) ^ 0x00FF;
-        let temp: u16 = self.a as u16 + value as u16 + self.get_flag(FLAG6502_C) as u16; 
-
-        self.set_flag(FLAG6502_C,  temp > 0x00FF);            // Check whether addition led to carry bit being set
-        self.set_flag(FLAG6502_Z, (temp & 0x00FF) == 0x0000); // Check whether result of addition is zero
-        self.set_flag(FLAG6502_N, (temp & 0x0080) != 0x0000); // Check the most significant bit of the result for sign
-        // (A^M) 
-        let t1 = (self.a as u16) ^ (value as u16);
-        // (A^R) 
-        let t2 = (self.a as u16) ^ (temp as u16);
-
-        // Set up signed overflow bit based on the truth table up there
-        // V = ~(A^M) & (A^R) = ~t1 & t2
-        self.set_flag(FLAG6502_V,  ((!t1 & t2 & 0x0080) != 0));
-
-        self.a = (temp & 0x00FF) as u8; 
+        // Perform the subtraction via an addition of the inverted value
+        self.add_with_carry(self.fetched ^ 0xFF);
         1 // can require an additional clock cycle
     }
     
@@ -1717,4 +2385,774 @@ impl Olc6502 {
     }
 
 
+    ///////////////////////////////////////////////////////////////////////////////
+    // CYCLE-STEPPED EXECUTION
+    //
+    // Every call to clock() performs exactly the bus access the 6502 performs in
+    // that cycle, including all the dummy reads and writes, following
+    // https://www.nesdev.org/6502_cpu.txt. The instructions themselves are shared
+    // with the instant mode: the microcode below only forms the effective address
+    // and moves the operand around, and calls execute() with the operand latched
+    // once the data is there. The cycle counter keeps its meaning from the instant
+    // mode: it is 0 at an instruction boundary and counts down the (estimated)
+    // remaining cycles otherwise.
+
+    fn clock_cycle_stepped(&mut self, bus: &mut dyn BusInterface) {
+
+        if self.cycles == 0 {
+            self.in_sequence = false;
+
+            // The interrupt sequence starts with an opcode fetch that is thrown away
+            if self.interrupt_poll {
+                self.read(bus, self.pc);
+                self.interrupt_poll = false;
+                self.in_sequence    = true;
+                self.step           = 1;
+                self.cycles         = 6;
+                return;
+            }
+
+            self.trace_instruction(bus);
+
+            // Cycle 1 is always the opcode fetch
+            self.instruction_pc = self.pc;
+            self.total_instructions += 1;
+            self.last_read_addr = self.pc;
+            self.opcode = bus.read(self.pc, false);
+            self.set_flag(FLAG6502_U, true);
+            self.pc = self.pc.wrapping_add(1);
+
+            self.step   = 1;
+            self.cycles = LOOKUP[self.opcode as usize].cycles - 1;
+            return;
+        }
+
+        // No instruction in flight, e.g. the cycles spent on reset
+        if self.step == 0 {
+            self.cycles -= 1;
+            return;
+        }
+
+        self.step += 1;
+        if self.polls_this_cycle() {
+            self.poll_interrupts();
+        }
+
+        let done = if self.in_sequence {
+            self.step_interrupt(bus)
+        } else {
+            self.execute_step(bus)
+        };
+
+        if done {
+            self.step   = 0;
+            self.cycles = 0;
+        } else {
+            // Page crossings and taken branches stretch an instruction beyond its base cycles
+            self.cycles = self.cycles.saturating_sub(1).max(1);
+        }
+    }
+
+    // Performs cycle self.step of the current instruction and returns true once
+    // the instruction has completed
+    fn execute_step(&mut self, bus: &mut dyn BusInterface) -> bool {
+        let inst = LOOKUP[self.opcode as usize];
+
+        match inst.operation {
+            Operation::BRK                   => self.step_brk(bus),
+            Operation::JSR                   => self.step_jsr(bus),
+            Operation::RTS                   => self.step_rts(bus),
+            Operation::RTI                   => self.step_rti(bus),
+            Operation::PHA | Operation::PHP  => self.step_push(bus),
+            Operation::PLA | Operation::PLP  => self.step_pull(bus),
+            Operation::JMP                   => self.step_jmp(bus),
+            _ if inst.addrmode == AddressMode::REL => self.step_branch(bus),
+            _                                => self.step_addressing(bus),
+        }
+    }
+
+    // Run the operation on an operand the microcode has already read into fetched
+    fn execute_latched(&mut self, bus: &mut dyn BusInterface) {
+        self.operand_latched = true;
+        self.execute(bus);
+        self.operand_latched = false;
+    }
+
+    // Microcode shared by all instructions that just read, write or modify an
+    // operand: form the effective address, then hand over to step_operand()
+    fn step_addressing(&mut self, bus: &mut dyn BusInterface) -> bool {
+        let inst = LOOKUP[self.opcode as usize];
+        let step = self.step;
+
+        match inst.addrmode {
+            // Implied instructions still read the byte following the opcode and throw it away
+            AddressMode::IMP => {
+                self.read(bus, self.pc);
+                self.fetched = self.a;
+                self.execute(bus);
+                true
+            }
+            AddressMode::IMM => {
+                self.addr_abs = self.pc;
+                self.pc       = self.pc.wrapping_add(1);
+                self.step_operand(bus, 1)
+            }
+            AddressMode::ZP0 => match step {
+                2 => {
+                    self.addr_abs = self.read(bus, self.pc) as u16;
+                    self.pc       = self.pc.wrapping_add(1);
+                    false
+                }
+                _ => self.step_operand(bus, step - 2),
+            },
+            AddressMode::ZPX | AddressMode::ZPY => match step {
+                2 => {
+                    self.addr_abs = self.read(bus, self.pc) as u16;
+                    self.pc       = self.pc.wrapping_add(1);
+                    false
+                }
+                3 => {
+                    // The un-indexed address is read while the index is added
+                    self.read(bus, self.addr_abs);
+                    let index     = if inst.addrmode == AddressMode::ZPX { self.x } else { self.y };
+                    self.addr_abs = (self.addr_abs + index as u16) & 0x00FF;
+                    false
+                }
+                _ => self.step_operand(bus, step - 3),
+            },
+            AddressMode::ABS => match step {
+                2 => {
+                    self.addr_abs = self.read(bus, self.pc) as u16;
+                    self.pc       = self.pc.wrapping_add(1);
+                    false
+                }
+                3 => {
+                    self.addr_abs |= (self.read(bus, self.pc) as u16) << 8;
+                    self.pc        = self.pc.wrapping_add(1);
+                    false
+                }
+                _ => self.step_operand(bus, step - 3),
+            },
+            AddressMode::ABX | AddressMode::ABY => match step {
+                2 => {
+                    self.base = self.read(bus, self.pc) as u16;
+                    self.pc   = self.pc.wrapping_add(1);
+                    false
+                }
+                3 => {
+                    self.base    |= (self.read(bus, self.pc) as u16) << 8;
+                    self.pc       = self.pc.wrapping_add(1);
+                    let index     = if inst.addrmode == AddressMode::ABX { self.x } else { self.y };
+                    self.addr_abs = self.base.wrapping_add(index as u16);
+                    false
+                }
+                4 => self.step_indexed(bus),
+                _ => self.step_operand(bus, step - 4),
+            },
+            AddressMode::IZX => match step {
+                2 => {
+                    self.base = self.read(bus, self.pc) as u16;
+                    self.pc   = self.pc.wrapping_add(1);
+                    false
+                }
+                3 => {
+                    self.read(bus, self.base);
+                    self.base = (self.base + self.x as u16) & 0x00FF;
+                    false
+                }
+                4 => {
+                    self.addr_abs = self.read(bus, self.base) as u16;
+                    false
+                }
+                5 => {
+                    self.addr_abs |= (self.read(bus, (self.base + 1) & 0x00FF) as u16) << 8;
+                    false
+                }
+                _ => self.step_operand(bus, step - 5),
+            },
+            AddressMode::IZY => match step {
+                2 => {
+                    self.base = self.read(bus, self.pc) as u16;
+                    self.pc   = self.pc.wrapping_add(1);
+                    false
+                }
+                3 => {
+                    self.addr_abs = self.read(bus, self.base) as u16;
+                    false
+                }
+                4 => {
+                    let hi: u16   = self.read(bus, (self.base + 1) & 0x00FF) as u16;
+                    self.base     = (hi << 8) | self.addr_abs;
+                    self.addr_abs = self.base.wrapping_add(self.y as u16);
+                    false
+                }
+                5 => self.step_indexed(bus),
+                _ => self.step_operand(bus, step - 5),
+            },
+            // Only used by JMP and the branches, which have their own microcode
+            AddressMode::IND | AddressMode::REL => unreachable!(),
+        }
+    }
+
+    // The cycle after an indexed address was formed. The CPU reads from the
+    // address before the carry into the high byte is fixed up. A read that did
+    // not cross a page can use that value directly and finish early, for
+    // everything else it is a dummy read.
+    fn step_indexed(&mut self, bus: &mut dyn BusInterface) -> bool {
+        let operation        = LOOKUP[self.opcode as usize].operation;
+        let uncorrected: u16 = (self.base & 0xFF00) | (self.addr_abs & 0x00FF);
+
+        if uncorrected == self.addr_abs && operation.memory_access() == MemoryAccess::Read {
+            return self.step_operand(bus, 1);
+        }
+
+        self.read(bus, uncorrected);
+        false
+    }
+
+    // The cycles after the effective address is known, n counts from 1
+    fn step_operand(&mut self, bus: &mut dyn BusInterface, n: u8) -> bool {
+        let operation = LOOKUP[self.opcode as usize].operation;
+
+        match (operation.memory_access(), n) {
+            (MemoryAccess::Read, _) => {
+                self.fetched = self.read(bus, self.addr_abs);
+                self.execute_latched(bus);
+                true
+            }
+            (MemoryAccess::Write, _) => {
+                self.execute(bus);
+                true
+            }
+            (MemoryAccess::ReadModifyWrite, 1) => {
+                self.fetched = self.read(bus, self.addr_abs);
+                false
+            }
+            // Read-modify-write instructions write the unmodified value back first
+            (MemoryAccess::ReadModifyWrite, 2) => {
+                self.write(bus, self.addr_abs, self.fetched);
+                false
+            }
+            (MemoryAccess::ReadModifyWrite, _) => {
+                self.execute_latched(bus);
+                true
+            }
+        }
+    }
+
+    fn branch_condition(&self, operation: Operation) -> bool {
+        match operation {
+            Operation::BCC => self.get_flag(FLAG6502_C) == 0,
+            Operation::BCS => self.get_flag(FLAG6502_C) == 1,
+            Operation::BNE => self.get_flag(FLAG6502_Z) == 0,
+            Operation::BEQ => self.get_flag(FLAG6502_Z) == 1,
+            Operation::BPL => self.get_flag(FLAG6502_N) == 0,
+            Operation::BMI => self.get_flag(FLAG6502_N) == 1,
+            Operation::BVC => self.get_flag(FLAG6502_V) == 0,
+            Operation::BVS => self.get_flag(FLAG6502_V) == 1,
+            _ => unreachable!(),
+        }
+    }
+
+    // Branches take 2 cycles, 3 if taken and 4 if the target is on another page
+    fn step_branch(&mut self, bus: &mut dyn BusInterface) -> bool {
+        match self.step {
+            2 => {
+                self.addr_rel = self.read(bus, self.pc) as u16;
+                self.pc       = self.pc.wrapping_add(1);
+                if self.addr_rel & 0x80 != 0 {
+                    self.addr_rel |= 0xFF00;
+                }
+                !self.branch_condition(LOOKUP[self.opcode as usize].operation)
+            }
+            3 => {
+                self.read(bus, self.pc);
+                self.addr_abs = self.pc.wrapping_add(self.addr_rel);
+                if (self.addr_abs & 0xFF00) == (self.pc & 0xFF00) {
+                    self.pc = self.addr_abs;
+                    return true;
+                }
+                // The low byte is fixed up first, the high byte one cycle later
+                self.pc = (self.pc & 0xFF00) | (self.addr_abs & 0x00FF);
+                false
+            }
+            _ => {
+                self.read(bus, self.pc);
+                self.pc = self.addr_abs;
+                true
+            }
+        }
+    }
+
+    fn step_jmp(&mut self, bus: &mut dyn BusInterface) -> bool {
+        let inst = LOOKUP[self.opcode as usize];
+
+        match self.step {
+            2 => {
+                self.base = self.read(bus, self.pc) as u16;
+                self.pc   = self.pc.wrapping_add(1);
+                false
+            }
+            3 => {
+                self.base |= (self.read(bus, self.pc) as u16) << 8;
+                self.pc    = self.pc.wrapping_add(1);
+                if inst.addrmode == AddressMode::ABS {
+                    self.addr_abs = self.base;
+                    self.execute(bus);
+                    return true;
+                }
+                false
+            }
+            4 => {
+                self.addr_abs = self.read(bus, self.base) as u16;
+                false
+            }
+            _ => {
+                // Same page boundary hardware bug as in ind()
+                let hi_addr: u16 = (self.base & 0xFF00) | (self.base.wrapping_add(1) & 0x00FF);
+                self.addr_abs   |= (self.read(bus, hi_addr) as u16) << 8;
+                self.execute(bus);
+                true
+            }
+        }
+    }
+
+    fn step_jsr(&mut self, bus: &mut dyn BusInterface) -> bool {
+        match self.step {
+            2 => {
+                self.addr_abs = self.read(bus, self.pc) as u16;
+                self.pc       = self.pc.wrapping_add(1);
+                false
+            }
+            // Internal operation, the CPU peeks at the top of the stack
+            3 => {
+                self.read(bus, 0x0100 + self.stkp as u16);
+                false
+            }
+            4 => {
+                self.write(bus, 0x0100 + self.stkp as u16, ((self.pc >> 8) & 0x00FF) as u8);
+                self.dec_stkp();
+                false
+            }
+            5 => {
+                self.write(bus, 0x0100 + self.stkp as u16, (self.pc & 0x00FF) as u8);
+                self.dec_stkp();
+                false
+            }
+            _ => {
+                self.addr_abs |= (self.read(bus, self.pc) as u16) << 8;
+                self.pc        = self.addr_abs;
+                true
+            }
+        }
+    }
+
+    fn step_rts(&mut self, bus: &mut dyn BusInterface) -> bool {
+        match self.step {
+            2 => {
+                self.read(bus, self.pc);
+                false
+            }
+            3 => {
+                self.read(bus, 0x0100 + self.stkp as u16);
+                self.inc_stkp();
+                false
+            }
+            4 => {
+                self.pc   = self.read(bus, 0x0100 + self.stkp as u16) as u16;
+                self.inc_stkp();
+                false
+            }
+            5 => {
+                self.pc |= (self.read(bus, 0x0100 + self.stkp as u16) as u16) << 8;
+                false
+            }
+            _ => {
+                self.read(bus, self.pc);
+                self.pc = self.pc.wrapping_add(1);
+                true
+            }
+        }
+    }
+
+    fn step_rti(&mut self, bus: &mut dyn BusInterface) -> bool {
+        match self.step {
+            2 => {
+                self.read(bus, self.pc);
+                false
+            }
+            3 => {
+                self.read(bus, 0x0100 + self.stkp as u16);
+                self.inc_stkp();
+                false
+            }
+            4 => {
+                self.status = self.read(bus, 0x0100 + self.stkp as u16);
+                self.set_flag(FLAG6502_U, true);
+                self.set_flag(FLAG6502_B, false);
+                self.inc_stkp();
+                false
+            }
+            5 => {
+                self.pc   = self.read(bus, 0x0100 + self.stkp as u16) as u16;
+                self.inc_stkp();
+                false
+            }
+            _ => {
+                self.pc |= (self.read(bus, 0x0100 + self.stkp as u16) as u16) << 8;
+                true
+            }
+        }
+    }
+
+    // PHA and PHP
+    fn step_push(&mut self, bus: &mut dyn BusInterface) -> bool {
+        match self.step {
+            2 => {
+                self.read(bus, self.pc);
+                false
+            }
+            _ => {
+                self.execute(bus);
+                true
+            }
+        }
+    }
+
+    // PLA and PLP
+    fn step_pull(&mut self, bus: &mut dyn BusInterface) -> bool {
+        match self.step {
+            2 => {
+                self.read(bus, self.pc);
+                false
+            }
+            3 => {
+                self.read(bus, 0x0100 + self.stkp as u16);
+                false
+            }
+            _ => {
+                self.execute(bus);
+                true
+            }
+        }
+    }
+
+    fn step_brk(&mut self, bus: &mut dyn BusInterface) -> bool {
+        match self.step {
+            // BRK is followed by a padding byte which is skipped
+            2 => {
+                self.read(bus, self.pc);
+                self.pc = self.pc.wrapping_add(1);
+                false
+            }
+            3 => {
+                self.write(bus, 0x0100 + self.stkp as u16, ((self.pc >> 8) & 0x00FF) as u8);
+                self.dec_stkp();
+                false
+            }
+            4 => {
+                self.write(bus, 0x0100 + self.stkp as u16, (self.pc & 0x00FF) as u8);
+                self.dec_stkp();
+                false
+            }
+            5 => {
+                self.write(bus, 0x0100 + self.stkp as u16, self.status | FLAG6502_B | FLAG6502_U);
+                self.dec_stkp();
+                self.set_flag(FLAG6502_B, false);
+                self.set_flag(FLAG6502_I, true);
+                // An NMI that arrived up to here hijacks the vector fetch
+                self.base = self.interrupt_vector();
+                false
+            }
+            6 => {
+                self.addr_abs = self.read(bus, self.base) as u16;
+                false
+            }
+            _ => {
+                self.pc = ((self.read(bus, self.base.wrapping_add(1)) as u16) << 8) | self.addr_abs;
+                true
+            }
+        }
+    }
+
+
+    // Same as BRK, except that the program counter is not advanced and the
+    // status is pushed with the B flag clear
+    fn step_interrupt(&mut self, bus: &mut dyn BusInterface) -> bool {
+        match self.step {
+            2 => {
+                self.read(bus, self.pc);
+                false
+            }
+            3 => {
+                self.write(bus, 0x0100 + self.stkp as u16, ((self.pc >> 8) & 0x00FF) as u8);
+                self.dec_stkp();
+                false
+            }
+            4 => {
+                self.write(bus, 0x0100 + self.stkp as u16, (self.pc & 0x00FF) as u8);
+                self.dec_stkp();
+                false
+            }
+            5 => {
+                self.set_flag(FLAG6502_B, false);
+                self.set_flag(FLAG6502_U, true);
+                self.write(bus, 0x0100 + self.stkp as u16, self.status);
+                self.dec_stkp();
+                self.set_flag(FLAG6502_I, true);
+                self.base = self.interrupt_vector();
+                false
+            }
+            6 => {
+                self.addr_abs = self.read(bus, self.base) as u16;
+                false
+            }
+            _ => {
+                self.pc = ((self.read(bus, self.base.wrapping_add(1)) as u16) << 8) | self.addr_abs;
+                true
+            }
+        }
+    }
+
+    ///////////////////////////////////////////////////////////////////////////////
+    // UNOFFICIAL OPCODES
+    // Based on https://www.nesdev.org/wiki/CPU_unofficial_opcodes and
+    // https://www.nesdev.org/6502_cpu.txt. Most of them are the side effect of
+    // the decode logic enabling two official instructions at the same time, so
+    // they are written here as exactly that combination.
+
+    // Instruction: ASL memory, then ORA with the result
+    // Function:    M = M << 1, A = A | M
+    // Flags Out:   N, Z, C
+    fn slo(&mut self, bus: &mut dyn BusInterface) -> u8 {
+        self.fetch(bus);
+        let temp: u8 = self.fetched << 1;
+        self.set_flag(FLAG6502_C, self.fetched & 0x80 != 0x00);
+        self.write_result(bus, temp);
+        self.a |= temp;
+        self.set_flag(FLAG6502_Z, self.a        == 0x00);
+        self.set_flag(FLAG6502_N, self.a & 0x80 != 0x00);
+        0
+    }
+
+    // Instruction: ROL memory, then AND with the result
+    // Function:    M = C <- M <- C, A = A & M
+    // Flags Out:   N, Z, C
+    fn rla(&mut self, bus: &mut dyn BusInterface) -> u8 {
+        self.fetch(bus);
+        let temp: u8 = (self.fetched << 1) | self.get_flag(FLAG6502_C);
+        self.set_flag(FLAG6502_C, self.fetched & 0x80 != 0x00);
+        self.write_result(bus, temp);
+        self.a &= temp;
+        self.set_flag(FLAG6502_Z, self.a        == 0x00);
+        self.set_flag(FLAG6502_N, self.a & 0x80 != 0x00);
+        0
+    }
+
+    // Instruction: LSR memory, then EOR with the result
+    // Function:    M = M >> 1, A = A ^ M
+    // Flags Out:   N, Z, C
+    fn sre(&mut self, bus: &mut dyn BusInterface) -> u8 {
+        self.fetch(bus);
+        let temp: u8 = self.fetched >> 1;
+        self.set_flag(FLAG6502_C, self.fetched & 0x01 != 0x00);
+        self.write_result(bus, temp);
+        self.a ^= temp;
+        self.set_flag(FLAG6502_Z, self.a        == 0x00);
+        self.set_flag(FLAG6502_N, self.a & 0x80 != 0x00);
+        0
+    }
+
+    // Instruction: ROR memory, then ADC with the result
+    // Function:    M = C -> M -> C, A = A + M + C
+    // Flags Out:   N, V, Z, C
+    fn rra(&mut self, bus: &mut dyn BusInterface) -> u8 {
+        self.fetch(bus);
+        let temp: u8 = (self.fetched >> 1) | (self.get_flag(FLAG6502_C) << 7);
+        self.set_flag(FLAG6502_C, self.fetched & 0x01 != 0x00);
+        self.write_result(bus, temp);
+        // The carry shifted out by ROR is the carry going into the addition
+        self.add_with_carry(temp);
+        0
+    }
+
+    // Instruction: DEC memory, then CMP with the result
+    // Function:    M = M - 1, C <- A >= M, Z <- (A - M) == 0
+    // Flags Out:   N, Z, C
+    fn dcp(&mut self, bus: &mut dyn BusInterface) -> u8 {
+        self.fetch(bus);
+        let temp: u8 = self.fetched.wrapping_sub(1);
+        self.write_result(bus, temp);
+        let diff: u8 = self.a.wrapping_sub(temp);
+        self.set_flag(FLAG6502_C, self.a >= temp);
+        self.set_flag(FLAG6502_Z, diff        == 0x00);
+        self.set_flag(FLAG6502_N, diff & 0x80 != 0x00);
+        0
+    }
+
+    // Instruction: INC memory, then SBC with the result (also known as ISB)
+    // Function:    M = M + 1, A = A - M - (1 - C)
+    // Flags Out:   N, V, Z, C
+    fn isc(&mut self, bus: &mut dyn BusInterface) -> u8 {
+        self.fetch(bus);
+        let temp: u8 = self.fetched.wrapping_add(1);
+        self.write_result(bus, temp);
+        self.add_with_carry(temp ^ 0xFF);
+        0
+    }
+
+    // Instruction: Load Accumulator and X Register
+    // Function:    A = X = M
+    // Flags Out:   N, Z
+    fn lax(&mut self, bus: &mut dyn BusInterface) -> u8 {
+        self.fetch(bus);
+        self.a = self.fetched;
+        self.x = self.fetched;
+        self.set_flag(FLAG6502_Z, self.a        == 0x00);
+        self.set_flag(FLAG6502_N, self.a & 0x80 != 0x00);
+        1
+    }
+
+    // Instruction: Store Accumulator AND X Register
+    // Function:    M = A & X
+    fn sax(&mut self, bus: &mut dyn BusInterface) -> u8 {
+        self.write(bus, self.addr_abs, self.a & self.x);
+        0
+    }
+
+    // Instruction: AND memory with the Stack Pointer
+    // Function:    A = X = S = M & S
+    // Flags Out:   N, Z
+    fn las(&mut self, bus: &mut dyn BusInterface) -> u8 {
+        self.fetch(bus);
+        let temp: u8 = self.fetched & self.stkp;
+        self.a    = temp;
+        self.x    = temp;
+        self.stkp = temp;
+        self.set_flag(FLAG6502_Z, temp        == 0x00);
+        self.set_flag(FLAG6502_N, temp & 0x80 != 0x00);
+        1
+    }
+
+    // Instruction: AND immediate, then copy N into C
+    // Function:    A = A & M, C = N
+    // Flags Out:   N, Z, C
+    fn anc(&mut self, bus: &mut dyn BusInterface) -> u8 {
+        self.fetch(bus);
+        self.a &= self.fetched;
+        self.set_flag(FLAG6502_Z, self.a        == 0x00);
+        self.set_flag(FLAG6502_N, self.a & 0x80 != 0x00);
+        self.set_flag(FLAG6502_C, self.a & 0x80 != 0x00);
+        0
+    }
+
+    // Instruction: AND immediate, then LSR the accumulator (also known as ASR)
+    // Function:    A = (A & M) >> 1
+    // Flags Out:   N, Z, C
+    fn alr(&mut self, bus: &mut dyn BusInterface) -> u8 {
+        self.fetch(bus);
+        let temp: u8 = self.a & self.fetched;
+        self.set_flag(FLAG6502_C, temp & 0x01 != 0x00);
+        self.a = temp >> 1;
+        self.set_flag(FLAG6502_Z, self.a        == 0x00);
+        self.set_flag(FLAG6502_N, self.a & 0x80 != 0x00);
+        0
+    }
+
+    // Instruction: AND immediate, then ROR the accumulator
+    // Function:    A = (A & M) >> 1 with C shifted into bit 7
+    // Flags Out:   N, V, Z, C
+    // The flags come out of the adder rather than the shifter: C is bit 6 of the
+    // result and V is bit 6 xor bit 5. The 2A03 has no decimal mode, so the BCD
+    // fix-up of the original 6502 does not apply.
+    fn arr(&mut self, bus: &mut dyn BusInterface) -> u8 {
+        self.fetch(bus);
+        let temp: u8 = self.a & self.fetched;
+        self.a = (temp >> 1) | (self.get_flag(FLAG6502_C) << 7);
+        self.set_flag(FLAG6502_Z, self.a        == 0x00);
+        self.set_flag(FLAG6502_N, self.a & 0x80 != 0x00);
+        self.set_flag(FLAG6502_C, self.a & 0x40 != 0x00);
+        self.set_flag(FLAG6502_V, ((self.a >> 6) ^ (self.a >> 5)) & 0x01 != 0x00);
+        0
+    }
+
+    // Instruction: Transfer X to Accumulator and AND immediate (also known as ANE)
+    // Function:    A = (A | magic) & X & M
+    // Flags Out:   N, Z
+    // Highly unstable on real hardware, the "magic" constant depends on the chip
+    // and its temperature. 0xEE is what the Harte test suite expects.
+    fn xaa(&mut self, bus: &mut dyn BusInterface) -> u8 {
+        self.fetch(bus);
+        self.a = (self.a | 0xEE) & self.x & self.fetched;
+        self.set_flag(FLAG6502_Z, self.a        == 0x00);
+        self.set_flag(FLAG6502_N, self.a & 0x80 != 0x00);
+        0
+    }
+
+    // Instruction: Load Accumulator and X Register immediate (also known as ATX or OAL)
+    // Function:    A = X = (A | magic) & M
+    // Flags Out:   N, Z
+    // Same "magic" constant caveat as XAA
+    fn lxa(&mut self, bus: &mut dyn BusInterface) -> u8 {
+        self.fetch(bus);
+        self.a = (self.a | 0xEE) & self.fetched;
+        self.x = self.a;
+        self.set_flag(FLAG6502_Z, self.a        == 0x00);
+        self.set_flag(FLAG6502_N, self.a & 0x80 != 0x00);
+        0
+    }
+
+    // Instruction: Subtract immediate from A & X without borrow (also known as AXS)
+    // Function:    X = (A & X) - M
+    // Flags Out:   N, Z, C
+    fn sbx(&mut self, bus: &mut dyn BusInterface) -> u8 {
+        self.fetch(bus);
+        let temp: u8 = self.a & self.x;
+        self.set_flag(FLAG6502_C, temp >= self.fetched);
+        self.x = temp.wrapping_sub(self.fetched);
+        self.set_flag(FLAG6502_Z, self.x        == 0x00);
+        self.set_flag(FLAG6502_N, self.x & 0x80 != 0x00);
+        0
+    }
+
+    // Helper for the unstable stores AHX/SHX/SHY/TAS
+    // The value written is ANDed with the high byte of the un-indexed base
+    // address plus one. If indexing crossed a page, the high byte of the
+    // target address gets replaced by the written value as well.
+    fn unstable_store(&mut self, bus: &mut dyn BusInterface, value: u8, index: u8) {
+        let base: u16    = self.addr_abs.wrapping_sub(index as u16);
+        let hi: u8       = (base >> 8) as u8;
+        let data: u8     = value & hi.wrapping_add(1);
+
+        if (base & 0xFF00) != (self.addr_abs & 0xFF00) {
+            self.addr_abs = ((data as u16) << 8) | (self.addr_abs & 0x00FF);
+        }
+        self.write(bus, self.addr_abs, data);
+    }
+
+    // Instruction: Store A & X & (H + 1) (also known as SHA)
+    fn ahx(&mut self, bus: &mut dyn BusInterface) -> u8 {
+        self.unstable_store(bus, self.a & self.x, self.y);
+        0
+    }
+
+    // Instruction: Store X & (H + 1)
+    fn shx(&mut self, bus: &mut dyn BusInterface) -> u8 {
+        self.unstable_store(bus, self.x, self.y);
+        0
+    }
+
+    // Instruction: Store Y & (H + 1)
+    fn shy(&mut self, bus: &mut dyn BusInterface) -> u8 {
+        self.unstable_store(bus, self.y, self.x);
+        0
+    }
+
+    // Instruction: Transfer A & X to Stack Pointer, then store S & (H + 1) (also known as SHS)
+    fn tas(&mut self, bus: &mut dyn BusInterface) -> u8 {
+        self.stkp = self.a & self.x;
+        self.unstable_store(bus, self.stkp, self.y);
+        0
+    }
+
 }
@@ -0,0 +1,219 @@
+use std::collections::{HashSet, VecDeque};
+use std::ops::RangeInclusive;
+use wasm_bindgen::prelude::*;
+
+// Only the most recent watchpoint hits and ROM writes are kept
+const MAX_WATCH_HITS: usize = 4096;
+const MAX_ROM_WRITES: usize = 4096;
+
+// Why the emulator stopped. Memory breakpoints report the address of the
+// instruction that accessed the memory, the emulator stops once that
+// instruction has completed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BreakReason {
+    Execute(u16),
+    Read  { addr: u16, pc: u16 },
+    Write { addr: u16, data: u8, pc: u16 },
+}
+
+// Watchpoints don't stop the emulation, they record every matching access
+// to an address range. A change watchpoint only records writes that modify
+// the stored value.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    Change,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub start: u16, // inclusive
+    pub end:   u16, // inclusive
+    pub kind:  WatchKind,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct WatchHit {
+    pub kind:  WatchKind,
+    pub addr:  u16,
+    pub pc:    u16, // instruction that accessed the memory
+    pub value: u8,  // value read or written
+}
+
+// A write the bus dropped because it went to a read-only region
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RomWrite {
+    pub addr: u16,
+    pub data: u8,
+    pub pc:   u16, // instruction that wrote to ROM
+}
+
+// Pushes that wrap the stack pointer from $0100 to $01FF (overflow) and pulls
+// that wrap it from $01FF to $0100 (underflow). The PCs are the instructions
+// that wrapped the stack last time and only meaningful if the count is non-zero.
+#[wasm_bindgen]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct StackDiagnostics {
+    pub overflows:         u32,
+    pub last_overflow_pc:  u16,
+    pub underflows:        u32,
+    pub last_underflow_pc: u16,
+}
+
+// The debugger is owned by the CPU, which reports every memory access to it.
+// Nes::run_until_break() checks for hits at each instruction boundary.
+#[derive(Default)]
+pub struct Debugger {
+    exec_breakpoints:  HashSet<u16>,
+    read_breakpoints:  HashSet<u16>,
+    write_breakpoints: HashSet<u16>,
+    hit:               Option<BreakReason>, // first memory breakpoint hit by the instruction in flight
+    watchpoints:       Vec<Watchpoint>,
+    watch_hits:        VecDeque<WatchHit>,
+    stack:             StackDiagnostics,
+    rom_regions:       Vec<RangeInclusive<u16>>, // writes to these are reported, empty unless warning on ROM writes
+    rom_writes:        VecDeque<RomWrite>,
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Self {
+            exec_breakpoints:  HashSet::new(),
+            read_breakpoints:  HashSet::new(),
+            write_breakpoints: HashSet::new(),
+            hit:               None,
+            watchpoints:       Vec::new(),
+            watch_hits:        VecDeque::new(),
+            stack:             StackDiagnostics::default(),
+            rom_regions:       Vec::new(),
+            rom_writes:        VecDeque::new(),
+        }
+    }
+
+    pub fn add_breakpoint        (&mut self, pc: u16)   { self.exec_breakpoints.insert(pc); }
+    pub fn remove_breakpoint     (&mut self, pc: u16)   { self.exec_breakpoints.remove(&pc); }
+    pub fn add_read_breakpoint   (&mut self, addr: u16) { self.read_breakpoints.insert(addr); }
+    pub fn remove_read_breakpoint(&mut self, addr: u16) { self.read_breakpoints.remove(&addr); }
+    pub fn add_write_breakpoint   (&mut self, addr: u16) { self.write_breakpoints.insert(addr); }
+    pub fn remove_write_breakpoint(&mut self, addr: u16) { self.write_breakpoints.remove(&addr); }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.exec_breakpoints.clear();
+        self.read_breakpoints.clear();
+        self.write_breakpoints.clear();
+        self.hit = None;
+    }
+
+    pub fn is_breakpoint(&self, pc: u16) -> bool {
+        self.exec_breakpoints.contains(&pc)
+    }
+
+    pub fn add_watchpoint(&mut self, start: u16, end: u16, kind: WatchKind) {
+        self.watchpoints.push(Watchpoint { start, end, kind });
+    }
+
+    pub fn remove_watchpoint(&mut self, start: u16, end: u16, kind: WatchKind) {
+        self.watchpoints.retain(|w| *w != Watchpoint { start, end, kind });
+    }
+
+    pub fn clear_watchpoints(&mut self) {
+        self.watchpoints.clear();
+    }
+
+    pub fn get_watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    // Oldest hit first
+    pub fn get_watch_hits(&self) -> Vec<WatchHit> {
+        self.watch_hits.iter().copied().collect()
+    }
+
+    pub fn clear_watch_hits(&mut self) {
+        self.watch_hits.clear();
+    }
+
+    fn is_watched(&self, addr: u16, kind: WatchKind) -> bool {
+        self.watchpoints.iter().any(|w| w.kind == kind && (w.start..=w.end).contains(&addr))
+    }
+
+    fn record(&mut self, kind: WatchKind, addr: u16, pc: u16, value: u8) {
+        if self.watch_hits.len() == MAX_WATCH_HITS {
+            self.watch_hits.pop_front();
+        }
+        self.watch_hits.push_back(WatchHit { kind, addr, pc, value });
+    }
+
+    // The CPU has to look up the old value for change watchpoints before it writes
+    pub fn needs_previous_value(&self, addr: u16) -> bool {
+        self.is_watched(addr, WatchKind::Change)
+    }
+
+    // Called by the CPU for every read and write it performs, pc is the
+    // address of the instruction that is executing
+    pub fn on_read(&mut self, addr: u16, data: u8, pc: u16) {
+        if self.hit.is_none() && self.read_breakpoints.contains(&addr) {
+            self.hit = Some(BreakReason::Read { addr, pc });
+        }
+        if self.is_watched(addr, WatchKind::Read) {
+            self.record(WatchKind::Read, addr, pc, data);
+        }
+    }
+
+    pub fn on_write(&mut self, addr: u16, data: u8, previous: Option<u8>, pc: u16) {
+        if self.hit.is_none() && self.write_breakpoints.contains(&addr) {
+            self.hit = Some(BreakReason::Write { addr, data, pc });
+        }
+        if self.is_watched(addr, WatchKind::Write) {
+            self.record(WatchKind::Write, addr, pc, data);
+        }
+        if previous.is_some_and(|old| old != data) {
+            self.record(WatchKind::Change, addr, pc, data);
+        }
+        if self.rom_regions.iter().any(|range| range.contains(&addr)) {
+            if self.rom_writes.len() == MAX_ROM_WRITES {
+                self.rom_writes.pop_front();
+            }
+            self.rom_writes.push_back(RomWrite { addr, data, pc });
+        }
+    }
+
+    // The regions the bus treats as read-only, kept in sync by the Nes while
+    // warning on ROM writes is enabled
+    pub fn set_rom_regions(&mut self, regions: Vec<RangeInclusive<u16>>) {
+        self.rom_regions = regions;
+    }
+
+    // Oldest write first
+    pub fn get_rom_writes(&self) -> Vec<RomWrite> {
+        self.rom_writes.iter().copied().collect()
+    }
+
+    pub fn clear_rom_writes(&mut self) {
+        self.rom_writes.clear();
+    }
+
+    pub fn on_stack_overflow(&mut self, pc: u16) {
+        self.stack.overflows        = self.stack.overflows.saturating_add(1);
+        self.stack.last_overflow_pc = pc;
+    }
+
+    pub fn on_stack_underflow(&mut self, pc: u16) {
+        self.stack.underflows        = self.stack.underflows.saturating_add(1);
+        self.stack.last_underflow_pc = pc;
+    }
+
+    pub fn get_stack_diagnostics(&self) -> StackDiagnostics {
+        self.stack
+    }
+
+    pub fn clear_stack_diagnostics(&mut self) {
+        self.stack = StackDiagnostics::default();
+    }
+
+    // Returns and clears the memory breakpoint hit since the last call
+    pub fn take_hit(&mut self) -> Option<BreakReason> {
+        self.hit.take()
+    }
+}
@@ -0,0 +1,258 @@
+use std::ops::RangeInclusive;
+
+use crate::interfaces::{BusDevice, CartridgeInterface, PpuInterface};
+use crate::ppu::Olc2c02;
+use crate::apu::Apu;
+use crate::controller::Controllers;
+use crate::error::EmuError;
+
+// The devices the NES bus is made of. The CPU address space looks like this:
+//     0x0000 -> 0x1FFF: 2 KB internal RAM, mirrored
+//     0x2000 -> 0x3FFF: PPU registers, mirrored every 8 bytes
+//     0x4000 -> 0x401F: APU and I/O registers (controllers, OAM DMA)
+//     0x4020 -> 0xFFFF: cartridge
+// https://www.nesdev.org/wiki/CPU_memory_map
+
+
+// Bounds checked access to a block of memory, addresses don't wrap around
+pub(crate) fn memory_slice(memory: &[u8], start: u16, len: usize) -> Result<&[u8], EmuError> {
+    (start as usize).checked_add(len)
+        .and_then(|end| memory.get(start as usize..end))
+        .ok_or(EmuError::OutOfRange { start, len })
+}
+
+pub(crate) fn memory_load(memory: &mut [u8], offset: u16, data: &[u8]) -> Result<(), EmuError> {
+    let first = offset as usize;
+
+    memory.get_mut(first..first + data.len())
+        .ok_or(EmuError::OutOfRange { start: offset, len: data.len() })?
+        .copy_from_slice(data);
+    Ok(())
+}
+
+
+// Hardware provided by the host as closures, e.g. a fake UART in a test
+// fixture. The closures get the full address. Reads can have side effects, so
+// peeks only reach the device if it has a separate peek closure, otherwise
+// they return open bus.
+pub struct CallbackDevice {
+    range: RangeInclusive<u16>,
+    read:  Box<dyn FnMut(u16) -> u8>,
+    peek:  Option<Box<dyn FnMut(u16) -> u8>>,
+    write: Box<dyn FnMut(u16, u8)>,
+}
+
+impl CallbackDevice {
+    pub fn new(
+        range: RangeInclusive<u16>,
+        read:  impl FnMut(u16) -> u8 + 'static,
+        write: impl FnMut(u16, u8) + 'static,
+    ) -> Self {
+        Self {
+            range,
+            read:  Box::new(read),
+            peek:  None,
+            write: Box::new(write),
+        }
+    }
+
+    pub fn with_peek(mut self, peek: impl FnMut(u16) -> u8 + 'static) -> Self {
+        self.peek = Some(Box::new(peek));
+        self
+    }
+}
+
+impl BusDevice for CallbackDevice {
+    fn range(&self) -> RangeInclusive<u16> { self.range.clone() }
+
+    fn read(&mut self, addr: u16, _open_bus: u8) -> u8 {
+        (self.read)(addr)
+    }
+
+    fn peek(&mut self, addr: u16, open_bus: u8) -> u8 {
+        match &mut self.peek {
+            Some(peek) => peek(addr),
+            None       => open_bus,
+        }
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        (self.write)(addr, data);
+    }
+}
+
+
+// Internal RAM
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Ram {
+    #[cfg_attr(feature = "serde", serde(with = "serde_big_array::BigArray"))]
+    ram: [u8; 2048],
+}
+
+impl Ram {
+    pub fn new() -> Self {
+        Self {
+            ram: [0; 2048],
+        }
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        &self.ram
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+}
+
+impl Default for Ram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BusDevice for Ram {
+    fn range(&self) -> RangeInclusive<u16> { 0x0000..=0x1FFF }
+
+    fn read(&mut self, addr: u16, open_bus: u8) -> u8 {
+        self.peek(addr, open_bus)
+    }
+
+    fn peek(&mut self, addr: u16, _open_bus: u8) -> u8 {
+        self.ram[(addr & 0x07FF) as usize]
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        self.ram[(addr & 0x07FF) as usize] = data;
+    }
+}
+
+
+// PPU registers
+// The PPU reaches the pattern memory through the cartridge, so the registers
+// are a view of both that the bus creates for each access.
+pub struct PpuRegs<'a> {
+    pub ppu:       &'a mut Olc2c02,
+    pub cartridge: &'a mut dyn CartridgeInterface,
+}
+
+impl PpuRegs<'_> {
+    pub const RANGE: RangeInclusive<u16> = 0x2000..=0x3FFF;
+}
+
+impl BusDevice for PpuRegs<'_> {
+    fn range(&self) -> RangeInclusive<u16> { Self::RANGE }
+
+    fn read(&mut self, addr: u16, _open_bus: u8) -> u8 {
+        self.ppu.read_cpu(addr & 0x0007, false, self.cartridge)
+    }
+
+    fn peek(&mut self, addr: u16, _open_bus: u8) -> u8 {
+        self.ppu.read_cpu(addr & 0x0007, true, self.cartridge)
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        self.ppu.write_cpu(addr & 0x0007, data, self.cartridge);
+    }
+}
+
+
+// APU and I/O registers
+//     0x4000 -> 0x4013: APU channels, write only
+//     0x4014          : OAM DMA, write only
+//     0x4015          : APU status / channel enable
+//     0x4016          : controller strobe (write), controller 1 (read)
+//     0x4017          : APU frame counter (write), controller 2 (read)
+//     0x4018 -> 0x401F: CPU test mode, disabled on a retail console
+// The channel registers are passed on to the APU. Writing the page to $4014
+// is latched here and picked up by the bus, which owns the DMA unit. The
+// controller ports share their strobe on $4016, the two addresses only differ
+// on reads: a write to $4017 only reaches the frame counter.
+// Reads of anything but the status and the controllers are open bus, and the
+// controllers only drive the lowest five bits.
+#[derive(Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ApuIo {
+    pub apu:          Apu,
+    pub controllers:  Controllers,
+    oam_dma_page:     Option<u8>,
+}
+
+impl ApuIo {
+    pub fn new() -> Self {
+        Self {
+            apu:              Apu::new(),
+            controllers:      Controllers::new(),
+            oam_dma_page:     None,
+        }
+    }
+
+    pub fn restore(&mut self, state: ApuIo) {
+        self.apu.restore(state.apu);
+        self.controllers.restore(state.controllers);
+        self.oam_dma_page = state.oam_dma_page;
+    }
+
+    pub fn take_oam_dma(&mut self) -> Option<u8> {
+        self.oam_dma_page.take()
+    }
+}
+
+impl BusDevice for ApuIo {
+    fn range(&self) -> RangeInclusive<u16> { 0x4000..=0x401F }
+
+    fn read(&mut self, addr: u16, open_bus: u8) -> u8 {
+        match addr {
+            0x4015          => (open_bus & 0x20) | self.apu.read_status(),
+            0x4016..=0x4017 => (open_bus & 0xE0) | self.controllers.read((addr & 0x0001) as usize),
+            _               => self.peek(addr, open_bus),
+        }
+    }
+
+    fn peek(&mut self, addr: u16, open_bus: u8) -> u8 {
+        match addr {
+            0x4015          => (open_bus & 0x20) | self.apu.peek_status(), // bit 5 is not driven
+            0x4016..=0x4017 => (open_bus & 0xE0) | self.controllers.peek((addr & 0x0001) as usize),
+            _               => open_bus,
+        }
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x4000..=0x4013 | 0x4015 => self.apu.write(addr, data),
+            // DMA - Start DMA transfer in bus when this address is written to
+            0x4014 => self.oam_dma_page = Some(data),
+            // The strobe goes to both controllers
+            0x4016 => self.controllers.write_strobe(data & 0x01 != 0),
+            0x4017 => self.apu.write(addr, data), // frame counter
+            _      => {}, // test mode
+        }
+    }
+}
+
+
+// Cartridge
+// The mapper decides what is behind an address, unmapped addresses are open bus
+pub struct CartridgePort<'a> {
+    pub cartridge: &'a mut dyn CartridgeInterface,
+}
+
+impl CartridgePort<'_> {
+    pub const RANGE: RangeInclusive<u16> = 0x4020..=0xFFFF;
+}
+
+impl BusDevice for CartridgePort<'_> {
+    fn range(&self) -> RangeInclusive<u16> { Self::RANGE }
+
+    fn read(&mut self, addr: u16, open_bus: u8) -> u8 {
+        self.cartridge.read_cpu(addr).unwrap_or(open_bus)
+    }
+
+    fn peek(&mut self, addr: u16, open_bus: u8) -> u8 {
+        self.cartridge.peek_cpu(addr).unwrap_or(open_bus)
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        self.cartridge.write_cpu(addr, data);
+    }
+}
@@ -0,0 +1,102 @@
+// OAM DMA
+// Writing a page number XX to $4014 copies the 256 bytes from $XX00-$XXFF
+// into the PPU's object attribute memory, much faster than the CPU could do
+// it via $2004. The CPU is halted for the whole transfer. Every CPU cycle is
+// either a get (even) or a put (odd) cycle: the unit reads on get cycles and
+// writes to OAM on put cycles, so it first has to wait for a put cycle to
+// pass. Including the cycle needed to halt the CPU the transfer takes 513 or
+// 514 cycles, depending on which cycle the write to $4014 happened.
+// https://www.nesdev.org/wiki/DMA
+#[derive(Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OamDma {
+    pub page:    u8,   // source page, the high byte of the CPU address
+    pub addr:    u8,   // current byte within the page, OAM is written through OAMDATA
+    pub data:    u8,   // byte read on the last get cycle
+    pub active:  bool,
+    pub aligned: bool, // halt/alignment cycles are over, the transfer is running
+}
+
+impl OamDma {
+    pub fn new() -> Self {
+        Self {
+            page:    0x00,
+            addr:    0x00,
+            data:    0x00,
+            active:  false,
+            aligned: false,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    pub fn start(&mut self, page: u8) {
+        self.page    = page;
+        self.addr    = 0x00;
+        self.active  = true;
+        self.aligned = false;
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    pub fn source_addr(&self) -> u16 {
+        ((self.page as u16) << 8) | self.addr as u16
+    }
+
+    // Moves on to the next byte after a put cycle, the transfer has finished
+    // once the address wraps around after 256 bytes
+    pub fn advance(&mut self) {
+        self.addr = self.addr.wrapping_add(1);
+        if self.addr == 0x00 {
+            self.active = false;
+        }
+    }
+}
+
+
+// DMC DMA
+// The APU's delta modulation channel fetches its samples from CPU memory by
+// pulling the CPU's RDY line. Once the CPU has halted on a read cycle, the
+// unit spends a dummy cycle, waits for a get cycle if necessary and then
+// reads the sample byte - the CPU usually loses 3 or 4 cycles.
+#[derive(Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DmcDma {
+    pub addr:          u16,
+    pub active:        bool,
+    pub halted_cycles: u8,         // cycles the CPU has been halted for
+    pub sample:        Option<u8>, // fetched byte, waiting to be picked up by the APU
+}
+
+impl DmcDma {
+    pub fn new() -> Self {
+        Self {
+            addr:          0x0000,
+            active:        false,
+            halted_cycles: 0,
+            sample:        None,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    pub fn request(&mut self, addr: u16) {
+        self.addr          = addr;
+        self.active        = true;
+        self.halted_cycles = 0;
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    pub fn take_sample(&mut self) -> Option<u8> {
+        self.sample.take()
+    }
+}
@@ -0,0 +1,88 @@
+use thiserror::Error;
+
+// Everything that can go wrong when the emulator is driven from outside. The
+// emulation itself never fails, a read from an unmapped address is open bus
+// just like on the real console.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum EmuError {
+    #[error("ROM file too small")]
+    RomTooSmall,
+
+    #[error("not a valid iNES file")]
+    InvalidRom,
+
+    #[error("ROM file truncated")]
+    RomTruncated,
+
+    #[error("unsupported file type")]
+    UnsupportedFileType,
+
+    #[error("unsupported mapper {0}")]
+    UnsupportedMapper(u8),
+
+    #[error("invalid mapper state")]
+    InvalidMapperState,
+
+    #[error("cartridge has no battery backed RAM")]
+    NoBatteryRam,
+
+    #[error("save RAM is {len} bytes, the cartridge has {expected}")]
+    SaveRamSize { expected: usize, len: usize },
+
+    #[error("invalid ROM database entry on line {0}")]
+    InvalidRomDatabase(usize),
+
+    #[error("memory range ${start:04X} + {len} bytes is out of bounds")]
+    OutOfRange { start: u16, len: usize },
+
+    #[error("unknown watchpoint kind {0}")]
+    UnknownWatchKind(u8),
+
+    #[error("palette file is {0} bytes, expected 192 or 1536")]
+    InvalidPalette(usize),
+
+    #[error("unsupported sample rate {0} Hz")]
+    UnsupportedSampleRate(u32),
+
+    #[error("unknown audio channel {0}")]
+    UnknownChannel(u8),
+
+    #[error("unknown expansion sound chip {0}")]
+    UnknownExpansionChip(u8),
+
+    #[error("unknown audio filter {0}")]
+    UnknownAudioFilter(u8),
+
+    #[error("no controller port {0}")]
+    InvalidPort(usize),
+
+    #[error("unknown input device {0}")]
+    UnknownInputDevice(u8),
+
+    #[error("unknown button {0}")]
+    UnknownButton(u8),
+
+    #[error("unknown TV system {0}")]
+    UnknownTvSystem(u8),
+
+    #[error("not a valid input movie")]
+    InvalidMovie,
+
+    #[error("invalid save state")]
+    InvalidSaveState,
+
+    #[error("save state is from version {0} of the format, expected {1}")]
+    SaveStateVersion(u32, u32),
+
+    #[error("save state is from another game")]
+    SaveStateMismatch,
+
+    #[error("no save slot {0}")]
+    InvalidSlot(usize),
+
+    #[error("save slot {0} is empty")]
+    EmptySlot(usize),
+
+    #[error("save states need the serde feature and a CPU core that supports them")]
+    SaveStatesUnsupported,
+}
@@ -0,0 +1,108 @@
+use wasm_bindgen::prelude::*;
+
+use crate::bus::SimpleBus;
+use crate::cpu::Olc6502;
+use crate::interfaces::BusInterface;
+
+// Klaus Dormann's 6502 functional test
+// https://github.com/Klaus2m5/6502_65C02_functional_tests
+// The 64 KB binary is loaded at $0000 and started at $0400. Every check that
+// fails ends in a trap, a jump or branch to itself, and so does the end of the
+// test. Where the success trap sits depends on how the binary was assembled,
+// $3469 for the prebuilt one. The 2A03 has no decimal mode, so the NES CPU
+// needs a binary assembled with disable_decimal = 1.
+pub const START_ADDR:   u16 = 0x0400;
+pub const SUCCESS_ADDR: u16 = 0x3469;
+
+// The prebuilt test finishes after roughly 30 million instructions
+pub const MAX_INSTRUCTIONS: u64 = 100_000_000;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Passed,
+    Trapped(u16), // stuck in the trap of a failing check
+    Timeout,      // no trap within the instruction limit
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Report {
+    pub outcome:      Outcome,
+    pub cycles:       u64,
+    pub instructions: u64,
+}
+
+// Runs the test binary until it traps, at most for max_instructions
+pub fn run(binary: &[u8], success_addr: u16, max_instructions: u64) -> Report {
+    let mut bus = SimpleBus::new();
+    bus.load_ram(0x0000, &binary[..binary.len().min(0x10000)]).unwrap();
+
+    let mut cpu = Olc6502::new();
+    cpu.set_registers(0x00, 0x00, 0x00, 0xFD, START_ADDR, 0x24);
+    cpu.force_cycles_zero();
+
+    let mut outcome = Outcome::Timeout;
+    for _ in 0..max_instructions {
+        let pc = cpu.get_registers().4;
+        cpu.step_instruction(&mut bus);
+
+        if cpu.get_registers().4 == pc {
+            outcome = if pc == success_addr { Outcome::Passed } else { Outcome::Trapped(pc) };
+            break;
+        }
+    }
+
+    let (cycles, instructions) = cpu.get_counters();
+    Report { outcome, cycles, instructions }
+}
+
+
+// blargg's NES test ROMs report through the PRG RAM
+// https://github.com/christopherpow/nes-test-roms
+//     0x6000          : status, 0x80 while running, 0x81 if the reset button
+//                       needs to be pressed, the result code once done (0 = passed)
+//     0x6001 -> 0x6003: signature DE B0 61, only valid once it has been written
+//     0x6004 ->       : zero terminated result text
+pub const STATUS_ADDR:    u16 = 0x6000;
+pub const SIGNATURE:      [u8; 3] = [0xDE, 0xB0, 0x61];
+pub const STATUS_RUNNING: u8 = 0x80;
+pub const STATUS_RESET:   u8 = 0x81;
+
+#[wasm_bindgen(getter_with_clone)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TestStatus {
+    pub code:    u8,
+    pub message: String,
+}
+
+#[wasm_bindgen]
+impl TestStatus {
+    pub fn is_running(&self) -> bool {
+        self.code == STATUS_RUNNING
+    }
+
+    pub fn needs_reset(&self) -> bool {
+        self.code == STATUS_RESET
+    }
+
+    pub fn is_passed(&self) -> bool {
+        self.code == 0
+    }
+}
+
+// Reads the status without side effects, None until the ROM wrote the signature
+pub fn read_test_status(bus: &mut dyn BusInterface) -> Option<TestStatus> {
+    let signature = [bus.peek(STATUS_ADDR + 1), bus.peek(STATUS_ADDR + 2), bus.peek(STATUS_ADDR + 3)];
+    if signature != SIGNATURE {
+        return None;
+    }
+
+    let text: Vec<u8> = (STATUS_ADDR + 4..=0x7FFF)
+        .map(|addr| bus.peek(addr))
+        .take_while(|&c| c != 0)
+        .collect();
+
+    Some(TestStatus {
+        code:    bus.peek(STATUS_ADDR),
+        message: String::from_utf8_lossy(&text).into_owned(),
+    })
+}
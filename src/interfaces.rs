@@ -1,8 +1,71 @@
-use crate::cartridge::Cartridge;
+use std::ops::RangeInclusive;
+
+use crate::cartridge::{Cartridge, MIRROR};
+use crate::debugger::Debugger;
+use crate::error::EmuError;
+use crate::audio::ExpansionChip;
+use crate::controller::DeviceKind;
 
 pub trait BusInterface { 
     fn read (&mut self, addr: u16, _read_only: bool) -> u8; 
     fn write(&mut self, addr: u16, data: u8); 
+
+    // Looks at memory without any side effects on the devices, for tracing,
+    // disassembly and memory viewers
+    fn peek(&mut self, addr: u16) -> u8 {
+        self.read(addr, true)
+    }
+}
+
+
+// Hardware mapped into a range of the CPU address space. The full address is
+// passed on, mirroring is up to the device. read() may have side effects like
+// clearing a flag, peek() must not - it is what debuggers and the trace use.
+// Bits a device doesn't drive keep the value last seen on the data bus, which
+// is handed in as open_bus.
+pub trait BusDevice {
+    fn range(&    self) -> RangeInclusive<u16>;
+    fn read (&mut self, addr: u16, open_bus: u8) -> u8;
+    fn peek (&mut self, addr: u16, open_bus: u8) -> u8;
+    fn write(&mut self, addr: u16, data: u8);
+}
+
+
+// Everything the rest of the emulator needs from a CPU core, so that
+// alternative cores can be swapped in for Olc6502
+pub trait Cpu6502 {
+    fn reset           (&mut self, bus: &mut dyn BusInterface);
+    fn clock           (&mut self, bus: &mut dyn BusInterface);
+    fn step_instruction(&mut self, bus: &mut dyn BusInterface);
+
+    fn set_nmi_line(&mut self, level: bool);
+    fn set_irq_line(&mut self, level: bool);
+    fn assert_irq  (&mut self, source: u8);
+    fn ack_irq     (&mut self, source: u8);
+    fn set_rdy_line(&mut self, level: bool);
+    fn is_stalled  (&    self) -> bool;
+
+    // (a, x, y, stkp, pc, status)
+    fn get_registers       (&    self) -> (u8, u8, u8, u8, u16, u8);
+    fn set_registers       (&mut self, a: u8, x: u8, y: u8, s: u8, pc: u16, p: u8);
+    // (fetched, addr_abs, addr_rel, opcode, cycles)
+    fn get_state           (&    self) -> (u8, u16, u16, u8, u8);
+    fn set_state           (&mut self, fetched: u8, addr_abs: u16, addr_rel: u16, opcode: u8, cycles: u8);
+    fn set_pc              (&mut self, pc: u16);
+    fn get_remaining_cycles(&    self) -> u8;
+    // (total cycles, total instructions)
+    fn get_counters        (&    self) -> (u64, u64);
+
+    fn is_halted(&    self) -> bool;
+    fn unjam    (&mut self);
+
+    // The core reports its memory accesses to the debugger it owns
+    fn debugger(&mut self) -> &mut Debugger;
+
+    // Snapshot of the core for save states and run-ahead, the debugger is not
+    // part of it. Cores that can't do this return an empty one.
+    fn save_state(&self) -> Vec<u8> { Vec::new() }
+    fn load_state(&mut self, state: &[u8]) -> Result<(), EmuError> { Err(EmuError::SaveStatesUnsupported) }
 }
 
 
@@ -17,17 +80,106 @@ pub trait PpuInterface {
 // Option return values indicate write and read success 
 pub trait CartridgeInterface { 
     fn read_cpu (&mut self, addr: u16          ) -> Option<u8>; 
+    // Like read_cpu, without side effects on the cartridge's registers
+    fn peek_cpu (&mut self, addr: u16          ) -> Option<u8> { self.read_cpu(addr) }
     fn write_cpu(&mut self, addr: u16, data: u8) -> Option<()>; 
     fn read_ppu (&    self, addr: u16          ) -> Option<u8>; 
     fn write_ppu(&mut self, addr: u16, data: u8) -> Option<()>; 
     fn map_nametable_addr(&self, addr: u16)      -> u16;
+    // Level of the cartridge's IRQ line
+    fn irq_state(&self) -> bool { false }
+    // Called by the PPU with the level of address line A12 during rendering
+    fn ppu_a12(&mut self, high: bool) {}
+    // Called by the PPU after it read pattern memory at addr
+    fn ppu_fetch(&mut self, addr: u16) {}
+    // Called once per CPU cycle
+    fn cpu_clock(&mut self) {}
+    // Output of the cartridge's sound chip, see MapperInterface::audio_output()
+    fn audio_output(&self) -> f32 { 0.0 }
+    fn audio_chip(&self) -> Option<ExpansionChip> { None }
+    // PRG RAM kept alive by a battery, None for cartridges without one
+    fn battery_ram(&self) -> Option<&[u8]> { None }
+    fn load_battery_ram(&mut self, data: &[u8]) -> Result<(), EmuError> { Err(EmuError::NoBatteryRam) }
+    // RAM and board registers for save states, the ROM is not part of it
+    fn save_state(&self) -> Vec<u8> { Vec::new() }
+    fn load_state(&mut self, state: &[u8]) -> Result<(), EmuError> {
+        if state.is_empty() { Ok(()) } else { Err(EmuError::InvalidSaveState) }
+    }
     fn reset(&mut self);
 }
 
+// A cartridge board. The map functions translate an address into an offset
+// into PRG or CHR memory, or None if the board doesn't respond. Writes also
+// reach the board's registers, that's how bank switching works. Boards are
+// created through a MapperRegistry, keyed by their iNES mapper number.
 pub trait MapperInterface {
     fn cpu_map_read (&    self, addr: u16          ) -> Option<usize>;
     fn cpu_map_write(&mut self, addr: u16, data: u8) -> Option<usize>;
     fn ppu_map_read (&    self, addr: u16          ) -> Option<usize>;
     fn ppu_map_write(&mut self, addr: u16, data: u8) -> Option<usize>;
     fn reset(&mut self);
-}
\ No newline at end of file
+
+    // Registers the board returns on reads, before the address is mapped to
+    // memory. peek_register() is the same without side effects.
+    fn read_register(&mut self, addr: u16) -> Option<u8> { None }
+    fn peek_register(&    self, addr: u16) -> Option<u8> { None }
+
+    // Mirroring set by the board, None if it is fixed by the header
+    fn mirror(&self) -> Option<MIRROR> { None }
+
+    // Level of the board's IRQ line, acknowledging it is up to the board
+    fn irq_state(&self) -> bool { false }
+
+    // Level of PPU address line A12 while rendering, see Olc2c02::clock()
+    fn ppu_a12(&mut self, high: bool) {}
+
+    // Pattern memory read by the PPU, after the data has been returned
+    fn ppu_fetch(&mut self, addr: u16) {}
+
+    // Once per CPU cycle, for cycle based IRQ counters and sound chips
+    fn cpu_clock(&mut self) {}
+
+    // Expansion audio, the current output of the board's sound chip in
+    // -1.0 ..= 1.0. It is up to the host to mix it with the APU.
+    fn audio_output(&self) -> f32 { 0.0 }
+
+    // The sound chip behind audio_output(), it picks the chip's volume in the mixer
+    fn audio_chip(&self) -> Option<ExpansionChip> { None }
+
+    // Whether the PRG RAM at 0x6000 -> 0x7FFF responds to reads and writes.
+    // Reads it doesn't answer go to cpu_map_read(), so a board can put ROM there.
+    fn prg_ram_readable(&self) -> bool { true }
+    fn prg_ram_writable(&self) -> bool { true }
+
+    // Whether the ROM drives the data bus during writes to the board's
+    // registers, the board then sees the AND of the written and the ROM value
+    fn has_bus_conflicts(&self) -> bool { false }
+
+    // Registers of the board for save states. Boards without registers
+    // don't need to implement these.
+    fn save_state(&self) -> Vec<u8> { Vec::new() }
+    fn load_state(&mut self, state: &[u8]) -> Result<(), EmuError> {
+        if state.is_empty() { Ok(()) } else { Err(EmuError::InvalidMapperState) }
+    }
+}
+// Something plugged into a controller port. The console latches the report
+// on the strobe and shifts it out one bit per read of $4016 / $4017, see
+// controller::Controllers. Hosts pick the device with Nes::connect_device()
+// and only ever talk to it in terms of buttons.
+pub trait InputDevice {
+    fn kind(&self) -> DeviceKind;
+
+    // button is one of the controller::BUTTON_* bits
+    fn set_button_state(&mut self, button: u8, pressed: bool);
+    fn buttons(&self) -> u8;
+
+    fn set_buttons(&mut self, buttons: u8) {
+        for bit in 0..8 {
+            self.set_button_state(1 << bit, buttons & (1 << bit) != 0);
+        }
+    }
+
+    // The bits shifted out after a strobe, bit 0 first. Behind a Four Score
+    // only the lowest 8 are used.
+    fn report(&self) -> u32;
+}
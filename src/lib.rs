@@ -4,13 +4,80 @@ pub mod bus;
 pub mod cpu;
 pub mod interfaces;
 pub mod ppu;
+pub mod apu;
+pub mod audio;
+pub mod clock;
+pub mod controller;
+pub mod movie;
+pub mod slots;
+pub mod rewind;
+pub mod palette;
 pub mod cartridge;
 pub mod mapper;
 pub mod nes;
+pub mod debugger;
+pub mod dma;
+pub mod devices;
+pub mod recorder;
+pub mod functional_test;
+pub mod rom_db;
+pub mod error;
 
 pub use nes::Nes;
+use debugger::{BreakReason, WatchKind, StackDiagnostics};
+use cpu::StatusFlags;
+use error::EmuError;
+use recorder::{AccessKind, AccessOrigin};
+use functional_test::TestStatus;
+use cartridge::RomInfo;
+use ppu::{PpuTiming, RenderMode};
+use apu::{Channel, ChannelState};
+use audio::{AudioFilter, ExpansionChip};
+use clock::TvSystem;
+use controller::DeviceKind;
+use movie::{Movie, PORTS};
+use slots::SLOTS;
 
 use wasm_bindgen::prelude::*;
+use wasm_bindgen::Clamped;
+
+fn channel_from_js(channel: u8) -> Result<Channel, EmuError> {
+    match channel {
+        0 => Ok(Channel::Pulse1),
+        1 => Ok(Channel::Pulse2),
+        2 => Ok(Channel::Triangle),
+        3 => Ok(Channel::Noise),
+        4 => Ok(Channel::Dmc),
+        5 => Ok(Channel::Expansion),
+        _ => Err(EmuError::UnknownChannel(channel)),
+    }
+}
+
+fn device_from_js(kind: u8) -> Result<DeviceKind, EmuError> {
+    match kind {
+        0 => Ok(DeviceKind::Unplugged),
+        1 => Ok(DeviceKind::StandardController),
+        _ => Err(EmuError::UnknownInputDevice(kind)),
+    }
+}
+
+fn button_from_js(button: u8) -> Result<u8, EmuError> {
+    match button {
+        0..=7 => Ok(1 << button),
+        _     => Err(EmuError::UnknownButton(button)),
+    }
+}
+
+// 0 follows the ROM header
+fn tv_system_from_js(tv_system: u8) -> Result<Option<TvSystem>, EmuError> {
+    match tv_system {
+        0 => Ok(None),
+        1 => Ok(Some(TvSystem::Ntsc)),
+        2 => Ok(Some(TvSystem::Pal)),
+        3 => Ok(Some(TvSystem::Dendy)),
+        _ => Err(EmuError::UnknownTvSystem(tv_system)),
+    }
+}
 
 #[wasm_bindgen]
 pub struct NES {
@@ -36,42 +103,544 @@ impl NES {
         self.inner.clock();
     }
 
-    pub fn run_frame(&mut self) {
-        self.inner.run_frame();
+    // One frame of picture and sound, returns the number of audio samples
+    // ready for take_audio_samples()
+    pub fn run_frame(&mut self) -> usize {
+        self.inner.run_frame()
+    }
+
+    // inputs holds the buttons of up to four ports, as for set_buttons().
+    // Returns the hash of the frame.
+    pub fn advance_frame_with_input(&mut self, inputs: &[u8]) -> Result<u32, JsError> {
+        if inputs.len() > PORTS {
+            return Err(EmuError::InvalidPort(inputs.len() - 1).into());
+        }
+        let mut buttons = [0; PORTS];
+        buttons[..inputs.len()].copy_from_slice(inputs);
+        Ok(self.inner.advance_frame_with_input(buttons))
+    }
+
+    pub fn frame_hash(&self) -> u32 {
+        self.inner.frame_hash()
+    }
+
+    // While paused run_frame() only delivers audio to keep the AudioWorklet
+    // fed, advance_one_frame() steps through the game
+    pub fn pause(&mut self) {
+        self.inner.pause();
+    }
+
+    pub fn resume(&mut self) {
+        self.inner.resume();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.inner.is_paused()
+    }
+
+    pub fn advance_one_frame(&mut self) -> usize {
+        self.inner.advance_one_frame()
+    }
+
+    // Frames run ahead to hide the games' input lag, 0 turns it off
+    pub fn set_run_ahead(&mut self, frames: u8) -> Result<(), JsError> {
+        Ok(self.inner.set_run_ahead(frames)?)
+    }
+
+    // 0 = from the ROM header, 1 = NTSC, 2 = PAL, 3 = Dendy
+    pub fn set_tv_system(&mut self, tv_system: u8) -> Result<(), JsError> {
+        self.inner.set_tv_system(tv_system_from_js(tv_system)?);
+        Ok(())
+    }
+
+    // 1 = NTSC, 2 = PAL, 3 = Dendy
+    pub fn get_tv_system(&self) -> u8 {
+        match self.inner.tv_system() {
+            TvSystem::Ntsc  => 1,
+            TvSystem::Pal   => 2,
+            TvSystem::Dendy => 3,
+        }
+    }
+
+    // Frames per second to pace requestAnimationFrame to, 60.0988 or 50.007
+    pub fn get_frame_rate(&self) -> f64 {
+        self.inner.frame_rate()
+    }
+
+    // Frames of history to keep for rewind(), 0 turns it off
+    pub fn set_rewind(&mut self, frames: usize) -> Result<(), JsError> {
+        Ok(self.inner.set_rewind(frames)?)
+    }
+
+    // Called instead of run_frame() while the rewind button is held, false
+    // once the history is used up
+    pub fn rewind(&mut self) -> bool {
+        self.inner.rewind()
+    }
+
+    pub fn get_rewind_frames(&self) -> usize {
+        self.inner.rewind_frames()
+    }
+
+    pub fn insert_cartridge(&mut self, cartridge_data: &[u8]) -> Result<(), JsError> {
+        Ok(self.inner.insert_cartridge(cartridge_data)?)
+    }
+
+    // An iNES or NES 2.0 file, the console is switched on with it. Throws
+    // with the reason if the file can't be loaded.
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), JsError> {
+        Ok(self.inner.load_rom(rom)?)
+    }
+
+    // Header information for display, undefined until a ROM is loaded
+    pub fn get_rom_info(&self) -> Option<RomInfo> {
+        self.inner.rom_info().cloned()
+    }
+
+    // Returns [kind, address, pc, data] with kind 0 = execute, 1 = read and
+    // 2 = write, or an empty vector if the frame completed without a break
+    pub fn run_until_break(&mut self) -> Vec<u32> {
+        match self.inner.run_until_break() {
+            Some(BreakReason::Execute(pc))             => vec![0, pc as u32, pc as u32, 0],
+            Some(BreakReason::Read { addr, pc })       => vec![1, addr as u32, pc as u32, 0],
+            Some(BreakReason::Write { addr, data, pc }) => vec![2, addr as u32, pc as u32, data as u32],
+            None                                       => vec![],
+        }
+    }
+
+    pub fn add_breakpoint(&mut self, pc: u16) {
+        self.inner.debugger().add_breakpoint(pc);
+    }
+
+    pub fn remove_breakpoint(&mut self, pc: u16) {
+        self.inner.debugger().remove_breakpoint(pc);
+    }
+
+    pub fn add_read_breakpoint(&mut self, addr: u16) {
+        self.inner.debugger().add_read_breakpoint(addr);
+    }
+
+    pub fn remove_read_breakpoint(&mut self, addr: u16) {
+        self.inner.debugger().remove_read_breakpoint(addr);
+    }
+
+    pub fn add_write_breakpoint(&mut self, addr: u16) {
+        self.inner.debugger().add_write_breakpoint(addr);
+    }
+
+    pub fn remove_write_breakpoint(&mut self, addr: u16) {
+        self.inner.debugger().remove_write_breakpoint(addr);
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.inner.debugger().clear_breakpoints();
+    }
+
+    // kind 0 = read, 1 = write, 2 = change
+    pub fn add_watchpoint(&mut self, start: u16, end: u16, kind: u8) -> Result<(), JsError> {
+        let kind = match kind {
+            0 => WatchKind::Read,
+            1 => WatchKind::Write,
+            2 => WatchKind::Change,
+            _ => return Err(EmuError::UnknownWatchKind(kind).into()),
+        };
+        self.inner.debugger().add_watchpoint(start, end, kind);
+        Ok(())
+    }
+
+    pub fn clear_watchpoints(&mut self) {
+        self.inner.debugger().clear_watchpoints();
+    }
+
+    // Flattened [kind, address, pc, value] per hit, oldest first
+    pub fn get_watch_hits(&mut self) -> Vec<u32> {
+        self.inner.debugger().get_watch_hits().iter()
+            .flat_map(|hit| {
+                let kind = match hit.kind {
+                    WatchKind::Read   => 0,
+                    WatchKind::Write  => 1,
+                    WatchKind::Change => 2,
+                };
+                [kind, hit.addr as u32, hit.pc as u32, hit.value as u32]
+            })
+            .collect()
+    }
+
+    pub fn clear_watch_hits(&mut self) {
+        self.inner.debugger().clear_watch_hits();
+    }
+
+    pub fn set_bus_trace(&mut self, enabled: bool) {
+        self.inner.bus_recorder().set_enabled(enabled);
+    }
+
+    pub fn add_bus_trace_filter(&mut self, start: u16, end: u16) {
+        self.inner.bus_recorder().add_filter(start, end);
+    }
+
+    pub fn clear_bus_trace_filters(&mut self) {
+        self.inner.bus_recorder().clear_filters();
+    }
+
+    // Flattened [cycle, address, value, kind, origin] per access, oldest first,
+    // with kind 0 = read, 1 = write and origin 0 = CPU, 1 = OAM DMA, 2 = DMC DMA.
+    // The cycle is truncated to 32 bits.
+    pub fn get_bus_trace(&mut self) -> Vec<u32> {
+        self.inner.bus_recorder().get_log().iter()
+            .flat_map(|access| {
+                let kind = match access.kind {
+                    AccessKind::Read  => 0,
+                    AccessKind::Write => 1,
+                };
+                let origin = match access.origin {
+                    AccessOrigin::Cpu    => 0,
+                    AccessOrigin::OamDma => 1,
+                    AccessOrigin::DmcDma => 2,
+                };
+                [access.cycle as u32, access.addr as u32, access.value as u32, kind, origin]
+            })
+            .collect()
+    }
+
+    pub fn clear_bus_trace(&mut self) {
+        self.inner.bus_recorder().clear();
+    }
+
+    pub fn add_read_only_region(&mut self, start: u16, end: u16) {
+        self.inner.add_read_only_region(start, end);
+    }
+
+    pub fn clear_read_only_regions(&mut self) {
+        self.inner.clear_read_only_regions();
+    }
+
+    pub fn set_warn_on_rom_write(&mut self, enabled: bool) {
+        self.inner.set_warn_on_rom_write(enabled);
+    }
+
+    pub fn set_layer_visibility(&mut self, background: bool, sprites: bool) {
+        self.inner.set_layer_visibility(background, sprites);
+    }
+
+    // Faster, less accurate rendering a whole scanline at a time, for slow devices
+    pub fn set_scanline_rendering(&mut self, enabled: bool) {
+        self.inner.set_render_mode(if enabled { RenderMode::Scanline } else { RenderMode::Dot });
+    }
+
+    pub fn set_oam_decay(&mut self, enabled: bool) {
+        self.inner.set_oam_decay(enabled);
+    }
+
+    // Flattened [address, value, pc] per dropped ROM write, oldest first
+    pub fn get_rom_writes(&mut self) -> Vec<u32> {
+        self.inner.debugger().get_rom_writes().iter()
+            .flat_map(|write| [write.addr as u32, write.data as u32, write.pc as u32])
+            .collect()
+    }
+
+    pub fn clear_rom_writes(&mut self) {
+        self.inner.debugger().clear_rom_writes();
+    }
+
+    pub fn get_stack_diagnostics(&mut self) -> StackDiagnostics {
+        self.inner.debugger().get_stack_diagnostics()
     }
 
-    pub fn insert_cartridge(&mut self, cartridge_data: &[u8]) -> Result<(), String> {
-        self.inner.insert_cartridge(cartridge_data)
+    pub fn clear_stack_diagnostics(&mut self) {
+        self.inner.debugger().clear_stack_diagnostics();
     }
 
     pub fn frame(&self) -> Vec<u8> {
         self.inner.frame()
     }
 
+    // Uint8ClampedArray of 256x240 RGBA pixels, ready for new ImageData().
+    // Also acknowledges the frame, frame_complete() is false until the next
+    pub fn get_frame_buffer(&mut self) -> Clamped<Vec<u8>> {
+        Clamped(self.inner.take_frame_rgba())
+    }
+
+    // Uint8Array of 256x240 master palette indices, a quarter of the RGBA
+    // frame. Acknowledges the frame like get_frame_buffer().
+    pub fn get_frame_indexed(&mut self) -> Vec<u8> {
+        self.inner.take_frame_indexed()
+    }
+
+    // Uint8Array of the 64 RGBA colours the indices of get_frame_indexed() map to
+    pub fn get_palette_lut(&self) -> Vec<u8> {
+        self.inner.palette_lut()
+    }
+
+    // Contents of a .pal file with 64 colours, or 512 including emphasis
+    pub fn set_palette(&mut self, pal: &[u8]) -> Result<(), JsError> {
+        Ok(self.inner.set_palette(pal)?)
+    }
+
+    // Rate of the AudioContext, e.g. 44100 or 48000
+    pub fn set_sample_rate(&mut self, sample_rate: u32) -> Result<(), JsError> {
+        Ok(self.inner.set_sample_rate(sample_rate)?)
+    }
+
+    // Float32Array of the mono samples produced since the last call, at the
+    // rate given to set_sample_rate(). Meant to be pulled once per frame and
+    // queued into an AudioWorklet.
+    pub fn take_audio_samples(&mut self) -> Vec<f32> {
+        self.inner.take_audio_samples()
+    }
+
+    pub fn audio_samples_available(&self) -> usize {
+        self.inner.audio_samples_available()
+    }
+
+    pub fn start_audio_recording(&mut self) {
+        self.inner.start_audio_recording();
+    }
+
+    // Uint8Array with the WAV file, for a download link
+    pub fn stop_audio_recording(&mut self) -> Vec<u8> {
+        self.inner.stop_audio_recording()
+    }
+
+    // Uint8Array with the state of the console, for load_state()
+    pub fn save_state(&self) -> Vec<u8> {
+        self.inner.save_state()
+    }
+
+    // Only states of the game that is running load
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), JsError> {
+        Ok(self.inner.load_state(state)?)
+    }
+
+    // Slots 0 - 9, see Nes::save_slot()
+    pub fn save_slot(&mut self, slot: usize) -> Result<(), JsError> {
+        Ok(self.inner.save_slot(slot)?)
+    }
+
+    pub fn load_slot(&mut self, slot: usize) -> Result<(), JsError> {
+        Ok(self.inner.load_slot(slot)?)
+    }
+
+    // Uint8ClampedArray of the frame the slot was saved at, 128 x 120 RGBA,
+    // for the previews of a load-state menu
+    pub fn get_slot_thumbnail(&self, slot: usize) -> Result<Clamped<Vec<u8>>, JsError> {
+        Ok(Clamped(self.inner.slot_thumbnail(slot)?))
+    }
+
+    pub fn delete_slot(&mut self, slot: usize) -> Result<(), JsError> {
+        Ok(self.inner.delete_slot(slot)?)
+    }
+
+    // Float64Array with the time every slot was saved at in ms since the Unix
+    // epoch, as Date.now() gives it. 0 for an empty slot.
+    pub fn list_slots(&self) -> Vec<f64> {
+        let mut timestamps = vec![0.0; SLOTS];
+        for info in self.inner.list_slots() {
+            timestamps[info.slot] = info.timestamp as f64;
+        }
+        timestamps
+    }
+
+    // Uint8Array with the save state of the slot, to keep it in IndexedDB
+    pub fn export_slot(&self, slot: usize) -> Result<Vec<u8>, JsError> {
+        Ok(self.inner.export_slot(slot)?)
+    }
+
+    pub fn import_slot(&mut self, slot: usize, state: &[u8], timestamp: f64) -> Result<(), JsError> {
+        Ok(self.inner.import_slot(slot, state.to_vec(), timestamp as u64)?)
+    }
+
+    // Input movies start from a reset, see Nes::play_movie()
+    pub fn start_recording(&mut self) {
+        self.inner.start_recording();
+    }
+
+    // Uint8Array with the movie file
+    pub fn stop_recording(&mut self) -> Vec<u8> {
+        self.inner.stop_recording().to_bytes()
+    }
+
+    pub fn play_movie(&mut self, data: &[u8]) -> Result<(), JsError> {
+        self.inner.play_movie(Movie::from_bytes(data)?);
+        Ok(())
+    }
+
+    pub fn stop_movie(&mut self) {
+        self.inner.stop_movie();
+    }
+
+    pub fn is_playing_movie(&self) -> bool {
+        self.inner.is_playing_movie()
+    }
+
+    // channel 0 = pulse 1, 1 = pulse 2, 2 = triangle, 3 = noise, 4 = DMC,
+    // 5 = expansion audio
+    pub fn set_channel_enabled(&mut self, channel: u8, enabled: bool) -> Result<(), JsError> {
+        self.inner.set_channel_enabled(channel_from_js(channel)?, enabled);
+        Ok(())
+    }
+
+    // Channels as for set_channel_enabled(), undefined for the ones that
+    // aren't emulated
+    pub fn get_channel_state(&self, channel: u8) -> Result<Option<ChannelState>, JsError> {
+        Ok(self.inner.channel_state(channel_from_js(channel)?))
+    }
+
+    pub fn set_apu_volume(&mut self, volume: f32) {
+        self.inner.set_apu_volume(volume);
+    }
+
+    // chip 0 = VRC6, 1 = VRC7, 2 = FDS, 3 = MMC5, 4 = Namco 163, 5 = Sunsoft 5B
+    pub fn set_expansion_volume(&mut self, chip: u8, volume: f32) -> Result<(), JsError> {
+        let chip = match chip {
+            0 => ExpansionChip::Vrc6,
+            1 => ExpansionChip::Vrc7,
+            2 => ExpansionChip::Fds,
+            3 => ExpansionChip::Mmc5,
+            4 => ExpansionChip::N163,
+            5 => ExpansionChip::Sunsoft5b,
+            _ => return Err(EmuError::UnknownExpansionChip(chip).into()),
+        };
+        self.inner.set_expansion_volume(chip, volume);
+        Ok(())
+    }
+
+    // filter 0 = 90 Hz high-pass, 1 = 440 Hz high-pass, 2 = 14 kHz low-pass
+    pub fn set_audio_filter(&mut self, filter: u8, enabled: bool) -> Result<(), JsError> {
+        let filter = match filter {
+            0 => AudioFilter::HighPass90,
+            1 => AudioFilter::HighPass440,
+            2 => AudioFilter::LowPass14k,
+            _ => return Err(EmuError::UnknownAudioFilter(filter).into()),
+        };
+        self.inner.set_audio_filter(filter, enabled);
+        Ok(())
+    }
+
+    pub fn frame_complete(&self) -> bool {
+        self.inner.frame_complete()
+    }
+
     pub fn step_instruction(&mut self) {
         self.inner.step_instruction();
     }
 
-    pub fn load_program(&mut self, bytes: &[u8], offset: u16) {
-        self.inner.load_program(bytes, offset);
+    // Raw 6502 code poked into RAM, for the assembler panel. Games go
+    // through load_rom().
+    pub fn load_program(&mut self, bytes: &[u8], offset: u16) -> Result<(), JsError> {
+        Ok(self.inner.load_program(bytes, offset)?)
     }
 
     pub fn get_registers(&self) -> Vec<u32> {
         self.inner.get_registers()
     }
 
+    pub fn set_registers(&mut self, a: u8, x: u8, y: u8, s: u8, pc: u16, p: u8) {
+        self.inner.set_registers(a, x, y, s, pc, p);
+    }
+
+    pub fn set_pc(&mut self, pc: u16) {
+        self.inner.set_pc(pc);
+    }
+
+    pub fn get_flags(&self) -> StatusFlags {
+        self.inner.get_flags()
+    }
+
     pub fn get_cpu_state(&self) -> Vec<u32> {
         self.inner.get_cpu_state()
     }
 
-    pub fn get_ram(&self, start: u16, len: usize) -> Vec<u8> {
-        self.inner.get_ram(start, len)
+    // [total CPU cycles, total instructions]
+    pub fn set_cpu_state(&mut self, fetched: u8, addr_abs: u16, addr_rel: u16, opcode: u8, cycles: u8) {
+        self.inner.set_cpu_state(fetched, addr_abs, addr_rel, opcode, cycles);
+    }
+
+    pub fn get_counters(&self) -> Vec<u64> {
+        let (cycles, instructions) = self.inner.get_counters();
+        vec![cycles, instructions]
+    }
+
+    pub fn get_ppu_timing(&self) -> PpuTiming {
+        self.inner.get_ppu_timing()
+    }
+
+    pub fn get_ram(&self, start: u16, len: usize) -> Result<Vec<u8>, JsError> {
+        Ok(self.inner.get_ram(start, len)?)
+    }
+
+    pub fn peek_memory(&mut self, start: u16, len: usize) -> Result<Vec<u8>, JsError> {
+        Ok(self.inner.peek_memory(start, len)?)
+    }
+
+    pub fn get_test_status(&mut self) -> Option<TestStatus> {
+        self.inner.get_test_status()
+    }
+
+    // Uint8Array of the battery backed PRG RAM, undefined without a battery
+    pub fn export_sram(&self) -> Option<Vec<u8>> {
+        self.inner.export_sram()
+    }
+
+    pub fn import_sram(&mut self, data: &[u8]) -> Result<(), JsError> {
+        Ok(self.inner.import_sram(data)?)
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.inner.is_halted()
+    }
+
+    pub fn unjam(&mut self) {
+        self.inner.unjam();
     }
 
     pub fn get_pattern_table(&self, table: u8, palette: u8) -> Vec<u8> {
         self.inner.get_pattern_table(table, palette)
     }
 
+    // Flattened [x, y, tile, palette, flip horizontal, flip vertical, behind
+    // background] per sprite, for all 64 sprites in OAM
+    pub fn get_oam(&self) -> Vec<u8> {
+        self.inner.get_oam_entries().iter()
+            .flat_map(|sprite| [
+                sprite.x,
+                sprite.y,
+                sprite.tile,
+                sprite.palette,
+                sprite.flip_horizontal as u8,
+                sprite.flip_vertical as u8,
+                sprite.behind_background as u8,
+            ])
+            .collect()
+    }
+
+    // Uint8ClampedArray of the sprite's RGBA pixels, 8 wide and 8 or 16 high
+    pub fn get_sprite_thumbnail(&self, index: u8) -> Clamped<Vec<u8>> {
+        Clamped(self.inner.get_sprite_thumbnail(index))
+    }
+
+    // Bitmask of the buttons held down: bit 0 = A, 1 = B, 2 = select,
+    // 3 = start, 4 = up, 5 = down, 6 = left, 7 = right
+    pub fn set_buttons(&mut self, player: usize, buttons: u8) {
+        self.inner.set_buttons(player, buttons);
+    }
+
+    // button as the bits of set_buttons(): 0 = A, 1 = B, ... 7 = right
+    pub fn set_button_state(&mut self, port: usize, button: u8, pressed: bool) -> Result<(), JsError> {
+        self.inner.set_button_state(port, button_from_js(button)?, pressed);
+        Ok(())
+    }
+
+    // kind 0 = nothing, 1 = standard controller. Ports 2 and 3 are only read
+    // with the Four Score.
+    pub fn connect_device(&mut self, port: usize, kind: u8) -> Result<(), JsError> {
+        Ok(self.inner.connect_device(port, device_from_js(kind)?)?)
+    }
+
+    // With the Four Score set_buttons() also takes players 2 and 3
+    pub fn set_four_score(&mut self, connected: bool) {
+        self.inner.set_four_score(connected);
+    }
+
     pub fn set_controller(&mut self, i: usize, x: bool, z: bool, a: bool, s: bool, up: bool, down: bool, left: bool, right: bool) {
         self.inner
             .set_controller(i, x, z, a, s, up, down, left, right);
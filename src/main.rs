@@ -2,9 +2,24 @@ pub mod bus;
 pub mod cpu;
 pub mod interfaces;
 pub mod ppu;
+pub mod apu;
+pub mod audio;
+pub mod clock;
+pub mod controller;
+pub mod movie;
+pub mod slots;
+pub mod rewind;
+pub mod palette;
 pub mod cartridge;
 pub mod mapper;
 pub mod nes;
+pub mod debugger;
+pub mod dma;
+pub mod devices;
+pub mod recorder;
+pub mod functional_test;
+pub mod rom_db;
+pub mod error;
 
 pub use nes::Nes;
 
@@ -112,7 +127,37 @@ fn output_frame(emu: &Nes, path: &str) -> Result<(), Box<dyn Error>> {
 }
 
 
+// Headless mode: nes_cli --klaus <binary> [success address in hex]
+fn run_functional_test(path: &str, success: Option<&String>) -> std::io::Result<()> {
+    let binary = fs::read(path)?;
+
+    let success_addr = match success {
+        Some(addr) => u16::from_str_radix(addr.trim_start_matches("0x"), 16)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?,
+        None       => functional_test::SUCCESS_ADDR,
+    };
+
+    let report = functional_test::run(&binary, success_addr, functional_test::MAX_INSTRUCTIONS);
+    println!("{} instructions, {} cycles", report.instructions, report.cycles);
+
+    match report.outcome {
+        functional_test::Outcome::Passed      => println!("PASS: reached ${:04X}", success_addr),
+        functional_test::Outcome::Trapped(pc) => println!("FAIL: trapped at ${:04X}", pc),
+        functional_test::Outcome::Timeout     => println!("FAIL: no trap after {} instructions", report.instructions),
+    }
+
+    if report.outcome != functional_test::Outcome::Passed {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
 fn main() -> std::io::Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() >= 3 && args[1] == "--klaus" {
+        return run_functional_test(&args[2], args.get(3));
+    }
+
     // adjust this path to your Downloads folder
     let rom_path = r"roms/dk.nes";
 
@@ -122,8 +167,14 @@ fn main() -> std::io::Result<()> {
     // create emulator
     let mut emu = Nes::new();
 
-    // load ROM
+    // load ROM, with the game's saves if it has battery RAM
     emu.insert_cartridge(&bytes).expect("failed to load ROM");
+    let sav_path = std::path::Path::new(rom_path).with_extension("sav");
+    if let Ok(sram) = fs::read(&sav_path) {
+        if let Err(e) = emu.import_sram(&sram) {
+            println!("Ignoring {}: {}", sav_path.display(), e);
+        }
+    }
     emu.reset();
 
     
@@ -134,14 +185,22 @@ fn main() -> std::io::Result<()> {
     output_name_table   (&emu, "output/name_table_before.txt")?;
     output_frame        (&emu, "output/frame_before.txt");
 
+    emu.start_audio_recording();
     for frame in 0..100 {
         emu.run_frame();
     }
+    fs::write("output/audio.wav", emu.stop_audio_recording())?;
+    println!("Wrote output/audio.wav");
     
     // Dump after running
     output_pattern_table(&emu, "output/pattern_table_after.txt")?;
     output_name_table   (&emu, "output/name_table_after.txt")?;
     output_frame        (&emu, "output/frame_after.txt");
 
+    if let Some(sram) = emu.export_sram() {
+        fs::write(&sav_path, sram)?;
+        println!("Wrote {}", sav_path.display());
+    }
+
     Ok(())
 }
\ No newline at end of file
@@ -1,5 +1,3 @@
-use serde_json::Map;
-
 use crate::interfaces::{MapperInterface};
 
 
@@ -26,13 +24,9 @@ impl MapperInterface for Mapper000 {
         }
     }
 
+    // NROM has no registers and the PRG memory is ROM, writes are ignored
     fn cpu_map_write(&mut self, addr: u16, data: u8) -> Option<usize> {
-        if addr >= 0x8000 && addr <= 0xFFFF {
-            let mapped: usize = (addr & (if self.prg_banks > 1 {0x7FFF} else {0x3FFF})) as usize;
-            Some(mapped)
-        } else {
-            None
-        }
+        None
     }
 
 	// There is no mapping required for PPU
@@ -46,6 +40,7 @@ impl MapperInterface for Mapper000 {
         }
     }
 
+    // Boards without CHR ROM have 8 KB of CHR RAM instead
     fn ppu_map_write(&mut self, addr: u16, data: u8) -> Option<usize> {
         if addr >= 0x0000 && addr <= 0x1FFF && self.chr_banks == 0 {
             Some(addr as usize)
@@ -0,0 +1,85 @@
+use crate::interfaces::{MapperInterface};
+use crate::error::EmuError;
+
+
+// CNROM, NROM with switchable CHR ROM
+// https://www.nesdev.org/wiki/INES_Mapper_003
+// Any write to 0x8000 -> 0xFFFF selects the 8 KB CHR bank. The original
+// boards have bus conflicts, games write a value to a ROM location that holds
+// the same value, so they are off unless asked for.
+pub struct Mapper003 {
+    pub prg_banks:     u8,
+    pub chr_banks:     u8,
+    pub bus_conflicts: bool,
+    chr_bank:          u8,
+}
+
+impl Mapper003 {
+    pub fn new(prg_banks: u8, chr_banks: u8) -> Self {
+        Self {
+            prg_banks,
+            chr_banks,
+            bus_conflicts: false,
+            chr_bank:      0,
+        }
+    }
+
+    pub fn with_bus_conflicts(mut self, bus_conflicts: bool) -> Self {
+        self.bus_conflicts = bus_conflicts;
+        self
+    }
+}
+
+impl MapperInterface for Mapper003 {
+
+	// PRG ROM is mapped like NROM
+	//     CPU Address Bus          PRG ROM
+	//     0x8000 -> 0xFFFF: Map    0x0000 -> 0x3FFF or 0x7FFF, 16 KB mirrored
+    fn cpu_map_read(&self, addr: u16) -> Option<usize> {
+        if addr >= 0x8000 {
+            Some((addr & (if self.prg_banks > 1 {0x7FFF} else {0x3FFF})) as usize)
+        } else {
+            None
+        }
+    }
+
+    fn cpu_map_write(&mut self, addr: u16, data: u8) -> Option<usize> {
+        if addr >= 0x8000 {
+            self.chr_bank = data % self.chr_banks.max(1);
+        }
+        None
+    }
+
+	// PPU Address Bus          CHR ROM
+	// 0x0000 -> 0x1FFF: Map    bank * 0x2000 + 0x0000 -> 0x1FFF
+    fn ppu_map_read(&self, addr: u16) -> Option<usize> {
+        if addr <= 0x1FFF {
+            Some(self.chr_bank as usize * 0x2000 + addr as usize)
+        } else {
+            None
+        }
+    }
+
+    fn ppu_map_write(&mut self, addr: u16, data: u8) -> Option<usize> {
+        None
+    }
+
+    fn reset(&mut self) {
+        self.chr_bank = 0;
+    }
+
+    fn has_bus_conflicts(&self) -> bool {
+        self.bus_conflicts
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        vec![self.chr_bank]
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), EmuError> {
+        match state {
+            [chr_bank] => { self.chr_bank = *chr_bank % self.chr_banks.max(1); Ok(()) },
+            _          => Err(EmuError::InvalidMapperState),
+        }
+    }
+}
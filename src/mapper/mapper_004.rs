@@ -0,0 +1,196 @@
+use crate::interfaces::{MapperInterface};
+use crate::cartridge::MIRROR;
+use crate::error::EmuError;
+use super::{mirror_to_u8, mirror_from_u8};
+
+
+// MMC3, TxROM boards
+// https://www.nesdev.org/wiki/MMC3
+// The registers are selected by the address range and whether the address
+// is even or odd:
+//     0x8000 -> 0x9FFF: bank select (even), bank data (odd)
+//     0xA000 -> 0xBFFF: mirroring (even), PRG RAM protect (odd)
+//     0xC000 -> 0xDFFF: IRQ latch (even), IRQ reload (odd)
+//     0xE000 -> 0xFFFF: IRQ disable (even), IRQ enable (odd)
+// PRG ROM is switched in 8 KB banks and CHR in 1 and 2 KB banks. The IRQ
+// counter is clocked by rising edges of PPU A12, once per scanline when the
+// background and the sprites use different pattern tables.
+pub struct Mapper004 {
+    pub prg_banks:   u8,
+    pub chr_banks:   u8,
+    bank_select:     u8,
+    registers:       [u8; 8],
+    mirror:          Option<MIRROR>,
+    prg_ram_protect: u8,
+
+    irq_latch:       u8,
+    irq_counter:     u8,
+    irq_reload:      bool,
+    irq_enabled:     bool,
+    irq_pending:     bool,
+    a12:             bool,
+}
+
+impl Mapper004 {
+    pub fn new(prg_banks: u8, chr_banks: u8) -> Self {
+        Self {
+            prg_banks,
+            chr_banks,
+            bank_select:     0,
+            registers:       [0; 8],
+            mirror:          None,
+            prg_ram_protect: 0x80,
+            irq_latch:       0,
+            irq_counter:     0,
+            irq_reload:      false,
+            irq_enabled:     false,
+            irq_pending:     false,
+            a12:             false,
+        }
+    }
+
+    // Number of 8 KB PRG banks and 1 KB CHR banks
+    fn prg_banks_8k(&self) -> usize {
+        (self.prg_banks as usize * 2).max(1)
+    }
+
+    fn chr_banks_1k(&self) -> usize {
+        (self.chr_banks as usize * 8).max(8)
+    }
+
+    fn clock_irq_counter(&mut self) {
+        if self.irq_counter == 0 || self.irq_reload {
+            self.irq_counter = self.irq_latch;
+            self.irq_reload  = false;
+        } else {
+            self.irq_counter -= 1;
+        }
+
+        if self.irq_counter == 0 && self.irq_enabled {
+            self.irq_pending = true;
+        }
+    }
+}
+
+impl MapperInterface for Mapper004 {
+
+	// Bit 6 of the bank select swaps the first and the third 8 KB window
+	//     CPU Address Bus          PRG ROM bank
+	//     0x8000 -> 0x9FFF:        R6 or second to last
+	//     0xA000 -> 0xBFFF:        R7
+	//     0xC000 -> 0xDFFF:        second to last or R6
+	//     0xE000 -> 0xFFFF:        last
+    fn cpu_map_read(&self, addr: u16) -> Option<usize> {
+        if addr < 0x8000 {
+            return None;
+        }
+
+        let last = self.prg_banks_8k() - 1;
+        let swap = self.bank_select & 0x40 != 0;
+        let bank = match (addr >> 13) & 0x03 {
+            0 => if swap { last - 1 } else { self.registers[6] as usize },
+            1 => self.registers[7] as usize,
+            2 => if swap { self.registers[6] as usize } else { last - 1 },
+            _ => last,
+        };
+        Some((bank % self.prg_banks_8k()) * 0x2000 + (addr & 0x1FFF) as usize)
+    }
+
+    fn cpu_map_write(&mut self, addr: u16, data: u8) -> Option<usize> {
+        match (addr, addr & 0x0001) {
+            (0x8000..=0x9FFF, 0) => self.bank_select = data,
+            (0x8000..=0x9FFF, _) => self.registers[(self.bank_select & 0x07) as usize] = data,
+            (0xA000..=0xBFFF, 0) => self.mirror = Some(if data & 0x01 != 0 { MIRROR::Horizontal } else { MIRROR::Vertical }),
+            (0xA000..=0xBFFF, _) => self.prg_ram_protect = data,
+            (0xC000..=0xDFFF, 0) => self.irq_latch = data,
+            (0xC000..=0xDFFF, _) => { self.irq_counter = 0; self.irq_reload = true; },
+            (0xE000..=0xFFFF, 0) => { self.irq_enabled = false; self.irq_pending = false; },
+            (0xE000..=0xFFFF, _) => self.irq_enabled = true,
+            _                    => {},
+        }
+        None
+    }
+
+	// Bit 7 of the bank select swaps the pattern tables
+	//     PPU Address Bus          CHR bank (1 KB)
+	//     0x0000 -> 0x07FF:        R0, 2 KB     or R2, R3
+	//     0x0800 -> 0x0FFF:        R1, 2 KB     or R4, R5
+	//     0x1000 -> 0x17FF:        R2, R3       or R0, 2 KB
+	//     0x1800 -> 0x1FFF:        R4, R5       or R1, 2 KB
+    fn ppu_map_read(&self, addr: u16) -> Option<usize> {
+        if addr > 0x1FFF {
+            return None;
+        }
+
+        let inverted = self.bank_select & 0x80 != 0;
+        let slot     = ((addr >> 10) as usize) ^ if inverted { 4 } else { 0 };
+        let bank = match slot {
+            0 | 1 => (self.registers[0] & 0xFE) as usize + slot,
+            2 | 3 => (self.registers[1] & 0xFE) as usize + slot - 2,
+            _     => self.registers[slot - 2] as usize,
+        };
+        Some((bank % self.chr_banks_1k()) * 0x0400 + (addr & 0x03FF) as usize)
+    }
+
+    // Boards without CHR ROM have 8 KB of CHR RAM, banked the same way
+    fn ppu_map_write(&mut self, addr: u16, data: u8) -> Option<usize> {
+        if self.chr_banks == 0 { self.ppu_map_read(addr) } else { None }
+    }
+
+    fn reset(&mut self) {
+        self.bank_select = 0;
+        self.irq_enabled = false;
+        self.irq_pending = false;
+    }
+
+    fn mirror(&self) -> Option<MIRROR> {
+        self.mirror
+    }
+
+    fn irq_state(&self) -> bool {
+        self.irq_pending
+    }
+
+    fn ppu_a12(&mut self, high: bool) {
+        if high && !self.a12 {
+            self.clock_irq_counter();
+        }
+        self.a12 = high;
+    }
+
+    // PRG RAM protect: bit 7 enables the RAM, bit 6 protects it from writes
+    fn prg_ram_readable(&self) -> bool {
+        self.prg_ram_protect & 0x80 != 0
+    }
+
+    fn prg_ram_writable(&self) -> bool {
+        self.prg_ram_protect & 0xC0 == 0x80
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        let mut state = vec![self.bank_select];
+        state.extend(self.registers);
+        state.extend([mirror_to_u8(self.mirror), self.prg_ram_protect, self.irq_latch, self.irq_counter,
+                      self.irq_reload as u8, self.irq_enabled as u8, self.irq_pending as u8, self.a12 as u8]);
+        state
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), EmuError> {
+        let [bank_select, r0, r1, r2, r3, r4, r5, r6, r7, mirror, prg_ram_protect, irq_latch,
+             irq_counter, irq_reload, irq_enabled, irq_pending, a12] = *state else {
+            return Err(EmuError::InvalidMapperState);
+        };
+
+        self.bank_select     = bank_select;
+        self.registers       = [r0, r1, r2, r3, r4, r5, r6, r7];
+        self.mirror          = mirror_from_u8(mirror);
+        self.prg_ram_protect = prg_ram_protect;
+        self.irq_latch       = irq_latch;
+        self.irq_counter     = irq_counter;
+        self.irq_reload      = irq_reload  != 0;
+        self.irq_enabled     = irq_enabled != 0;
+        self.irq_pending     = irq_pending != 0;
+        self.a12             = a12 != 0;
+        Ok(())
+    }
+}
@@ -0,0 +1,87 @@
+use crate::interfaces::{MapperInterface};
+use crate::cartridge::MIRROR;
+use crate::error::EmuError;
+
+
+// AxROM, 32 KB PRG banks and single screen mirroring
+// https://www.nesdev.org/wiki/AxROM
+// Any write to 0x8000 -> 0xFFFF sets the bank register:
+//     bits 0 -> 2: 32 KB PRG bank at 0x8000
+//     bit  4     : nametable page used for all four nametables
+// The boards have CHR RAM. AMROM and ANROM have bus conflicts, AOROM doesn't.
+pub struct Mapper007 {
+    pub prg_banks:     u8,
+    pub bus_conflicts: bool,
+    bank:              u8,
+}
+
+impl Mapper007 {
+    pub fn new(prg_banks: u8) -> Self {
+        Self {
+            prg_banks,
+            bus_conflicts: false,
+            bank:          0,
+        }
+    }
+
+    pub fn with_bus_conflicts(mut self, bus_conflicts: bool) -> Self {
+        self.bus_conflicts = bus_conflicts;
+        self
+    }
+}
+
+impl MapperInterface for Mapper007 {
+
+	//     CPU Address Bus          PRG ROM
+	//     0x8000 -> 0xFFFF: Map    bank * 0x8000 + 0x0000 -> 0x7FFF, 16 KB mirrored
+    fn cpu_map_read(&self, addr: u16) -> Option<usize> {
+        if addr >= 0x8000 {
+            let banks = (self.prg_banks as usize / 2).max(1);
+            Some(((self.bank & 0x07) as usize % banks) * 0x8000 + (addr & (if self.prg_banks > 1 {0x7FFF} else {0x3FFF})) as usize)
+        } else {
+            None
+        }
+    }
+
+    fn cpu_map_write(&mut self, addr: u16, data: u8) -> Option<usize> {
+        if addr >= 0x8000 {
+            self.bank = data;
+        }
+        None
+    }
+
+    fn ppu_map_read(&self, addr: u16) -> Option<usize> {
+        if addr <= 0x1FFF {
+            Some(addr as usize)
+        } else {
+            None
+        }
+    }
+
+    fn ppu_map_write(&mut self, addr: u16, data: u8) -> Option<usize> {
+        self.ppu_map_read(addr)
+    }
+
+    fn reset(&mut self) {
+        self.bank = 0;
+    }
+
+    fn mirror(&self) -> Option<MIRROR> {
+        Some(if self.bank & 0x10 != 0 { MIRROR::OnescreenHi } else { MIRROR::OnescreenLo })
+    }
+
+    fn has_bus_conflicts(&self) -> bool {
+        self.bus_conflicts
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        vec![self.bank]
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), EmuError> {
+        match state {
+            [bank] => { self.bank = *bank; Ok(()) },
+            _      => Err(EmuError::InvalidMapperState),
+        }
+    }
+}
@@ -0,0 +1,155 @@
+use crate::interfaces::{MapperInterface};
+use crate::cartridge::MIRROR;
+use crate::error::EmuError;
+use super::{mirror_to_u8, mirror_from_u8};
+
+
+// The CHR latches of the MMC2, also used by the MMC4. Each 4 KB pattern table
+// has one bank for latch value $FD and one for $FE. The latch of a table flips
+// once the PPU has fetched tile $FD or $FE from it, the tile itself is still
+// drawn from the old bank.
+pub(crate) struct ChrLatch {
+    pub banks:   [[u8; 2]; 2], // [table][$FD, $FE]
+    latch:       [usize; 2],   // 0 = $FD, 1 = $FE
+    pub ranged:  bool,         // the MMC4 watches the whole tile for table 0 as well
+}
+
+impl ChrLatch {
+    pub fn new(ranged: bool) -> Self {
+        Self {
+            banks:  [[0; 2]; 2],
+            latch:  [1; 2],
+            ranged,
+        }
+    }
+
+    // Offset into CHR ROM, 4 KB banks
+    pub fn map(&self, addr: u16, chr_banks: u8) -> usize {
+        let table = (addr >> 12) as usize & 1;
+        let banks = (chr_banks as usize * 2).max(1);
+        (self.banks[table][self.latch[table]] as usize % banks) * 0x1000 + (addr & 0x0FFF) as usize
+    }
+
+    pub fn fetch(&mut self, addr: u16) {
+        match addr {
+            0x0FD8                            => self.latch[0] = 0,
+            0x0FE8                            => self.latch[0] = 1,
+            0x0FD9..=0x0FDF if self.ranged    => self.latch[0] = 0,
+            0x0FE9..=0x0FEF if self.ranged    => self.latch[0] = 1,
+            0x1FD8..=0x1FDF                   => self.latch[1] = 0,
+            0x1FE8..=0x1FEF                   => self.latch[1] = 1,
+            _                                 => {},
+        }
+    }
+
+    pub fn save_state(&self) -> [u8; 6] {
+        [self.banks[0][0], self.banks[0][1], self.banks[1][0], self.banks[1][1], self.latch[0] as u8, self.latch[1] as u8]
+    }
+
+    pub fn load_state(&mut self, state: [u8; 6]) {
+        self.banks = [[state[0], state[1]], [state[2], state[3]]];
+        self.latch = [state[4] as usize & 1, state[5] as usize & 1];
+    }
+}
+
+
+// MMC2, PxROM boards (Punch-Out!!)
+// https://www.nesdev.org/wiki/MMC2
+//     0xA000 -> 0xAFFF: 8 KB PRG bank at 0x8000
+//     0xB000 -> 0xBFFF: CHR bank for 0x0000, latch $FD
+//     0xC000 -> 0xCFFF: CHR bank for 0x0000, latch $FE
+//     0xD000 -> 0xDFFF: CHR bank for 0x1000, latch $FD
+//     0xE000 -> 0xEFFF: CHR bank for 0x1000, latch $FE
+//     0xF000 -> 0xFFFF: mirroring
+pub struct Mapper009 {
+    pub prg_banks: u8,
+    pub chr_banks: u8,
+    prg_bank:      u8,
+    chr:           ChrLatch,
+    mirror:        Option<MIRROR>,
+}
+
+impl Mapper009 {
+    pub fn new(prg_banks: u8, chr_banks: u8) -> Self {
+        Self {
+            prg_banks,
+            chr_banks,
+            prg_bank: 0,
+            chr:      ChrLatch::new(false),
+            mirror:   None,
+        }
+    }
+}
+
+impl MapperInterface for Mapper009 {
+
+	//     CPU Address Bus          PRG ROM bank (8 KB)
+	//     0x8000 -> 0x9FFF:        switchable
+	//     0xA000 -> 0xFFFF:        fixed to the last three banks
+    fn cpu_map_read(&self, addr: u16) -> Option<usize> {
+        if addr < 0x8000 {
+            return None;
+        }
+
+        // Counted back from the end, so a ROM under 32 KB wraps onto its own banks
+        let banks = (self.prg_banks as usize * 2).max(1);
+        let bank  = match addr {
+            0x8000..=0x9FFF => self.prg_bank as usize & 0x0F,
+            _               => banks * 4 - 4 + ((addr - 0x8000) >> 13) as usize,
+        };
+        Some((bank % banks) * 0x2000 + (addr & 0x1FFF) as usize)
+    }
+
+    fn cpu_map_write(&mut self, addr: u16, data: u8) -> Option<usize> {
+        match addr {
+            0xA000..=0xAFFF => self.prg_bank        = data & 0x0F,
+            0xB000..=0xBFFF => self.chr.banks[0][0] = data & 0x1F,
+            0xC000..=0xCFFF => self.chr.banks[0][1] = data & 0x1F,
+            0xD000..=0xDFFF => self.chr.banks[1][0] = data & 0x1F,
+            0xE000..=0xEFFF => self.chr.banks[1][1] = data & 0x1F,
+            0xF000..=0xFFFF => self.mirror = Some(if data & 0x01 != 0 { MIRROR::Horizontal } else { MIRROR::Vertical }),
+            _               => {},
+        }
+        None
+    }
+
+    fn ppu_map_read(&self, addr: u16) -> Option<usize> {
+        if addr <= 0x1FFF {
+            Some(self.chr.map(addr, self.chr_banks))
+        } else {
+            None
+        }
+    }
+
+    fn ppu_map_write(&mut self, addr: u16, data: u8) -> Option<usize> {
+        None
+    }
+
+    fn reset(&mut self) {}
+
+    fn mirror(&self) -> Option<MIRROR> {
+        self.mirror
+    }
+
+    fn ppu_fetch(&mut self, addr: u16) {
+        self.chr.fetch(addr);
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        let mut state = vec![self.prg_bank, mirror_to_u8(self.mirror)];
+        state.extend(self.chr.save_state());
+        state
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), EmuError> {
+        let [prg_bank, mirror, ref chr @ ..] = *state else {
+            return Err(EmuError::InvalidMapperState);
+        };
+        let chr: [u8; 6] = chr.try_into().map_err(|_| EmuError::InvalidMapperState)?;
+
+        self.prg_bank = prg_bank;
+        self.mirror   = mirror_from_u8(mirror);
+        self.chr.load_state(chr);
+        Ok(())
+    }
+}
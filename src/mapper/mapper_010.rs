@@ -0,0 +1,105 @@
+use crate::interfaces::{MapperInterface};
+use crate::cartridge::MIRROR;
+use crate::error::EmuError;
+use super::{mirror_to_u8, mirror_from_u8};
+use super::mapper_009::ChrLatch;
+
+
+// MMC4, FxROM boards (Fire Emblem, Famicom Wars)
+// https://www.nesdev.org/wiki/MMC4
+// The CHR latches work like on the MMC2, but the latch of table 0 watches the
+// whole $FD and $FE tiles. PRG ROM is switched in 16 KB banks.
+//     0xA000 -> 0xAFFF: 16 KB PRG bank at 0x8000
+//     0xB000 -> 0xEFFF: CHR banks, see Mapper009
+//     0xF000 -> 0xFFFF: mirroring
+pub struct Mapper010 {
+    pub prg_banks: u8,
+    pub chr_banks: u8,
+    prg_bank:      u8,
+    chr:           ChrLatch,
+    mirror:        Option<MIRROR>,
+}
+
+impl Mapper010 {
+    pub fn new(prg_banks: u8, chr_banks: u8) -> Self {
+        Self {
+            prg_banks,
+            chr_banks,
+            prg_bank: 0,
+            chr:      ChrLatch::new(true),
+            mirror:   None,
+        }
+    }
+}
+
+impl MapperInterface for Mapper010 {
+
+	//     CPU Address Bus          PRG ROM bank (16 KB)
+	//     0x8000 -> 0xBFFF:        switchable
+	//     0xC000 -> 0xFFFF:        fixed to the last bank
+    fn cpu_map_read(&self, addr: u16) -> Option<usize> {
+        if addr < 0x8000 {
+            return None;
+        }
+
+        let banks = (self.prg_banks as usize).max(1);
+        let bank  = match addr {
+            0x8000..=0xBFFF => self.prg_bank as usize % banks,
+            _               => banks - 1,
+        };
+        Some(bank * 0x4000 + (addr & 0x3FFF) as usize)
+    }
+
+    fn cpu_map_write(&mut self, addr: u16, data: u8) -> Option<usize> {
+        match addr {
+            0xA000..=0xAFFF => self.prg_bank        = data & 0x0F,
+            0xB000..=0xBFFF => self.chr.banks[0][0] = data & 0x1F,
+            0xC000..=0xCFFF => self.chr.banks[0][1] = data & 0x1F,
+            0xD000..=0xDFFF => self.chr.banks[1][0] = data & 0x1F,
+            0xE000..=0xEFFF => self.chr.banks[1][1] = data & 0x1F,
+            0xF000..=0xFFFF => self.mirror = Some(if data & 0x01 != 0 { MIRROR::Horizontal } else { MIRROR::Vertical }),
+            _               => {},
+        }
+        None
+    }
+
+    fn ppu_map_read(&self, addr: u16) -> Option<usize> {
+        if addr <= 0x1FFF {
+            Some(self.chr.map(addr, self.chr_banks))
+        } else {
+            None
+        }
+    }
+
+    fn ppu_map_write(&mut self, addr: u16, data: u8) -> Option<usize> {
+        None
+    }
+
+    fn reset(&mut self) {}
+
+    fn mirror(&self) -> Option<MIRROR> {
+        self.mirror
+    }
+
+    fn ppu_fetch(&mut self, addr: u16) {
+        self.chr.fetch(addr);
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        let mut state = vec![self.prg_bank, mirror_to_u8(self.mirror)];
+        state.extend(self.chr.save_state());
+        state
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), EmuError> {
+        let [prg_bank, mirror, ref chr @ ..] = *state else {
+            return Err(EmuError::InvalidMapperState);
+        };
+        let chr: [u8; 6] = chr.try_into().map_err(|_| EmuError::InvalidMapperState)?;
+
+        self.prg_bank = prg_bank;
+        self.mirror   = mirror_from_u8(mirror);
+        self.chr.load_state(chr);
+        Ok(())
+    }
+}
@@ -0,0 +1,88 @@
+use crate::interfaces::{MapperInterface};
+use crate::error::EmuError;
+
+
+// Color Dreams, unlicensed boards
+// https://www.nesdev.org/wiki/Color_Dreams
+// Any write to 0x8000 -> 0xFFFF sets the bank register:
+//     bits 0 -> 1: 32 KB PRG bank at 0x8000
+//     bits 4 -> 7: 8 KB CHR bank at 0x0000
+// The boards have bus conflicts and the games are written with them in mind,
+// so unlike on the licensed discrete boards they are on by default.
+pub struct Mapper011 {
+    pub prg_banks:     u8,
+    pub chr_banks:     u8,
+    pub bus_conflicts: bool,
+    bank:              u8,
+}
+
+impl Mapper011 {
+    pub fn new(prg_banks: u8, chr_banks: u8) -> Self {
+        Self {
+            prg_banks,
+            chr_banks,
+            bus_conflicts: true,
+            bank:          0,
+        }
+    }
+
+    pub fn with_bus_conflicts(mut self, bus_conflicts: bool) -> Self {
+        self.bus_conflicts = bus_conflicts;
+        self
+    }
+}
+
+impl MapperInterface for Mapper011 {
+
+	//     CPU Address Bus          PRG ROM
+	//     0x8000 -> 0xFFFF: Map    bank * 0x8000 + 0x0000 -> 0x7FFF, 16 KB mirrored
+    fn cpu_map_read(&self, addr: u16) -> Option<usize> {
+        if addr >= 0x8000 {
+            let banks = (self.prg_banks as usize / 2).max(1);
+            Some(((self.bank & 0x03) as usize % banks) * 0x8000 + (addr & (if self.prg_banks > 1 {0x7FFF} else {0x3FFF})) as usize)
+        } else {
+            None
+        }
+    }
+
+    fn cpu_map_write(&mut self, addr: u16, data: u8) -> Option<usize> {
+        if addr >= 0x8000 {
+            self.bank = data;
+        }
+        None
+    }
+
+	//     PPU Address Bus          CHR ROM
+	//     0x0000 -> 0x1FFF: Map    bank * 0x2000 + 0x0000 -> 0x1FFF
+    fn ppu_map_read(&self, addr: u16) -> Option<usize> {
+        if addr <= 0x1FFF {
+            let banks = (self.chr_banks as usize).max(1);
+            Some(((self.bank >> 4) as usize % banks) * 0x2000 + addr as usize)
+        } else {
+            None
+        }
+    }
+
+    fn ppu_map_write(&mut self, addr: u16, data: u8) -> Option<usize> {
+        None
+    }
+
+    fn reset(&mut self) {
+        self.bank = 0;
+    }
+
+    fn has_bus_conflicts(&self) -> bool {
+        self.bus_conflicts
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        vec![self.bank]
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), EmuError> {
+        match state {
+            [bank] => { self.bank = *bank; Ok(()) },
+            _      => Err(EmuError::InvalidMapperState),
+        }
+    }
+}
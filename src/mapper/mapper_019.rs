@@ -0,0 +1,208 @@
+use crate::interfaces::{MapperInterface};
+use crate::cartridge::MIRROR;
+use crate::error::EmuError;
+use crate::audio::ExpansionChip;
+use super::n163::N163Audio;
+
+
+// Namco 163 (Erika to Satoru no Yume Bouken, Megami Tensei II)
+// https://www.nesdev.org/wiki/INES_Mapper_019
+//     0x4800 -> 0x4FFF: sound data port (read / write)
+//     0x5000 -> 0x57FF: IRQ counter, bits 0 -> 7 (read / write)
+//     0x5800 -> 0x5FFF: IRQ counter, bits 8 -> 14 and IRQ enable (bit 7) (read / write)
+//     0x8000 -> 0xBFFF: 1 KB CHR banks, one register every 0x800
+//     0xC000 -> 0xDFFF: nametables, one register every 0x800
+//     0xE000 -> 0xE7FF: 8 KB PRG bank at 0x8000, sound disable (bit 6)
+//     0xE800 -> 0xEFFF: 8 KB PRG bank at 0xA000
+//     0xF000 -> 0xF7FF: 8 KB PRG bank at 0xC000
+//     0xF800 -> 0xFFFF: sound address port, PRG RAM write enable (0x4X)
+// The IRQ counter counts up every CPU cycle and fires when it reaches 0x7FFF.
+// Nametable registers 0xE0 and above select a page of the console's VRAM, the
+// combinations that match a mirroring mode are supported, until the game sets
+// them the header decides. CHR ROM as nametables and VRAM in the pattern
+// tables are not.
+pub struct Mapper019 {
+    pub prg_banks: u8,
+    pub chr_banks: u8,
+    prg:           [u8; 3],
+    chr:           [u8; 8],
+    nametable:     [u8; 4],
+    sound_disable: bool,
+    ram_protect:   u8,
+    irq_counter:   u16,
+    irq_enabled:   bool,
+    irq_pending:   bool,
+    audio:         N163Audio,
+}
+
+impl Mapper019 {
+    pub fn new(prg_banks: u8, chr_banks: u8) -> Self {
+        Self {
+            prg_banks,
+            chr_banks,
+            prg:           [0; 3],
+            chr:           [0; 8],
+            nametable:     [0; 4],
+            sound_disable: false,
+            ram_protect:   0,
+            irq_counter:   0,
+            irq_enabled:   false,
+            irq_pending:   false,
+            audio:         N163Audio::new(),
+        }
+    }
+}
+
+impl MapperInterface for Mapper019 {
+
+	//     CPU Address Bus          PRG ROM bank (8 KB)
+	//     0x8000 -> 0xDFFF:        switchable
+	//     0xE000 -> 0xFFFF:        fixed to the last bank
+    fn cpu_map_read(&self, addr: u16) -> Option<usize> {
+        if addr < 0x8000 {
+            return None;
+        }
+
+        let banks = (self.prg_banks as usize * 2).max(1);
+        let bank  = match addr {
+            0xE000..=0xFFFF => banks - 1,
+            _               => self.prg[((addr - 0x8000) >> 13) as usize] as usize,
+        };
+        Some((bank % banks) * 0x2000 + (addr & 0x1FFF) as usize)
+    }
+
+    fn cpu_map_write(&mut self, addr: u16, data: u8) -> Option<usize> {
+        match addr {
+            0x4800..=0x4FFF => self.audio.write_data(data),
+            0x5000..=0x57FF => {
+                self.irq_counter = (self.irq_counter & 0x7F00) | data as u16;
+                self.irq_pending = false;
+            },
+            0x5800..=0x5FFF => {
+                self.irq_counter = (self.irq_counter & 0x00FF) | ((data & 0x7F) as u16) << 8;
+                self.irq_enabled = data & 0x80 != 0;
+                self.irq_pending = false;
+            },
+            0x8000..=0xBFFF => self.chr[((addr - 0x8000) >> 11) as usize]       = data,
+            0xC000..=0xDFFF => self.nametable[((addr - 0xC000) >> 11) as usize] = data,
+            0xE000..=0xE7FF => {
+                self.prg[0]        = data & 0x3F;
+                self.sound_disable = data & 0x40 != 0;
+            },
+            0xE800..=0xEFFF => self.prg[1] = data & 0x3F,
+            0xF000..=0xF7FF => self.prg[2] = data & 0x3F,
+            0xF800..=0xFFFF => {
+                self.ram_protect = data;
+                self.audio.write_address(data);
+            },
+            _ => {},
+        }
+        None
+    }
+
+    fn read_register(&mut self, addr: u16) -> Option<u8> {
+        match addr {
+            0x4800..=0x4FFF => Some(self.audio.read_data()),
+            _               => self.peek_register(addr),
+        }
+    }
+
+    fn peek_register(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0x4800..=0x4FFF => Some(self.audio.peek_data()),
+            0x5000..=0x57FF => Some(self.irq_counter as u8),
+            0x5800..=0x5FFF => Some((self.irq_counter >> 8) as u8 | (self.irq_enabled as u8) << 7),
+            _               => None,
+        }
+    }
+
+	//     PPU Address Bus          CHR bank (1 KB)
+	//     0x0000 -> 0x1FFF:        eight switchable banks
+    fn ppu_map_read(&self, addr: u16) -> Option<usize> {
+        if addr > 0x1FFF {
+            return None;
+        }
+
+        let banks = (self.chr_banks as usize * 8).max(8);
+        let bank  = self.chr[(addr >> 10) as usize] as usize;
+        Some((bank % banks) * 0x0400 + (addr & 0x03FF) as usize)
+    }
+
+    // Boards without CHR ROM have 8 KB of CHR RAM, banked the same way
+    fn ppu_map_write(&mut self, addr: u16, data: u8) -> Option<usize> {
+        if self.chr_banks == 0 { self.ppu_map_read(addr) } else { None }
+    }
+
+    fn reset(&mut self) {
+        self.irq_enabled = false;
+        self.irq_pending = false;
+        self.audio.reset();
+    }
+
+    fn mirror(&self) -> Option<MIRROR> {
+        if self.nametable.iter().any(|&page| page < 0xE0) {
+            return None;
+        }
+        match self.nametable.map(|page| page & 0x01) {
+            [0, 1, 0, 1] => Some(MIRROR::Vertical),
+            [0, 0, 1, 1] => Some(MIRROR::Horizontal),
+            [0, 0, 0, 0] => Some(MIRROR::OnescreenLo),
+            [1, 1, 1, 1] => Some(MIRROR::OnescreenHi),
+            _            => None,
+        }
+    }
+
+    fn irq_state(&self) -> bool {
+        self.irq_pending
+    }
+
+    fn cpu_clock(&mut self) {
+        if self.irq_enabled && self.irq_counter < 0x7FFF {
+            self.irq_counter += 1;
+            self.irq_pending  = self.irq_counter == 0x7FFF;
+        }
+        self.audio.cpu_clock();
+    }
+
+    fn audio_output(&self) -> f32 {
+        if self.sound_disable { 0.0 } else { self.audio.output() }
+    }
+
+    fn audio_chip(&self) -> Option<ExpansionChip> {
+        Some(ExpansionChip::N163)
+    }
+
+    // The write protection per 2 KB (bits 0 -> 3) is not emulated
+    fn prg_ram_writable(&self) -> bool {
+        self.ram_protect & 0xF0 == 0x40
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        let mut state = self.prg.to_vec();
+        state.extend(self.chr);
+        state.extend(self.nametable);
+        state.extend(self.irq_counter.to_be_bytes());
+        state.push(self.sound_disable as u8 | (self.irq_enabled as u8) << 1 | (self.irq_pending as u8) << 2);
+        state.push(self.ram_protect);
+        state.extend(self.audio.save_state());
+        state
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), EmuError> {
+        if state.len() < 19 {
+            return Err(EmuError::InvalidMapperState);
+        }
+        let (registers, audio) = state.split_at(19);
+        self.audio.load_state(audio).ok_or(EmuError::InvalidMapperState)?;
+
+        self.prg.copy_from_slice(&registers[0..3]);
+        self.chr.copy_from_slice(&registers[3..11]);
+        self.nametable.copy_from_slice(&registers[11..15]);
+        self.irq_counter   = u16::from_be_bytes([registers[15], registers[16]]) & 0x7FFF;
+        self.sound_disable = registers[17] & 0x01 != 0;
+        self.irq_enabled   = registers[17] & 0x02 != 0;
+        self.irq_pending   = registers[17] & 0x04 != 0;
+        self.ram_protect   = registers[18];
+        Ok(())
+    }
+}
@@ -0,0 +1,109 @@
+use crate::interfaces::{MapperInterface};
+use crate::error::EmuError;
+
+
+// Mapper 34 covers two unrelated boards
+// https://www.nesdev.org/wiki/INES_Mapper_034
+// BNROM (Deadly Towers), NES 2.0 submapper 2, has CHR RAM and bus conflicts:
+//     0x8000 -> 0xFFFF: 32 KB PRG bank
+// NINA-001 (Impossible Mission II), submapper 1, has its registers at the end
+// of the PRG RAM, writes go to both:
+//     0x7FFD          : 32 KB PRG bank
+//     0x7FFE          : 4 KB CHR bank at 0x0000
+//     0x7FFF          : 4 KB CHR bank at 0x1000
+// Plain iNES files don't tell them apart, only NINA-001 has more than 8 KB of
+// CHR ROM.
+pub struct Mapper034 {
+    pub prg_banks: u8,
+    pub chr_banks: u8,
+    nina001:       bool,
+    prg_bank:      u8,
+    chr:           [u8; 2],
+}
+
+impl Mapper034 {
+    pub fn new(prg_banks: u8, chr_banks: u8) -> Self {
+        if chr_banks > 1 { Self::nina001(prg_banks, chr_banks) } else { Self::bnrom(prg_banks, chr_banks) }
+    }
+
+    pub fn bnrom(prg_banks: u8, chr_banks: u8) -> Self {
+        Self {
+            prg_banks,
+            chr_banks,
+            nina001:  false,
+            prg_bank: 0,
+            chr:      [0, 1],
+        }
+    }
+
+    pub fn nina001(prg_banks: u8, chr_banks: u8) -> Self {
+        Self {
+            nina001: true,
+            ..Self::bnrom(prg_banks, chr_banks)
+        }
+    }
+}
+
+impl MapperInterface for Mapper034 {
+
+	//     CPU Address Bus          PRG ROM bank (32 KB)
+	//     0x8000 -> 0xFFFF:        switchable, 16 KB mirrored
+    fn cpu_map_read(&self, addr: u16) -> Option<usize> {
+        if addr < 0x8000 {
+            return None;
+        }
+
+        let banks = (self.prg_banks as usize / 2).max(1);
+        Some((self.prg_bank as usize % banks) * 0x8000 + (addr & (if self.prg_banks > 1 {0x7FFF} else {0x3FFF})) as usize)
+    }
+
+    fn cpu_map_write(&mut self, addr: u16, data: u8) -> Option<usize> {
+        match (addr, self.nina001) {
+            (0x8000..=0xFFFF, false) => self.prg_bank = data,
+            (0x7FFD, true)           => self.prg_bank = data & 0x01,
+            (0x7FFE, true)           => self.chr[0]   = data & 0x0F,
+            (0x7FFF, true)           => self.chr[1]   = data & 0x0F,
+            _                        => {},
+        }
+        None
+    }
+
+	//     PPU Address Bus          CHR bank (4 KB)
+	//     0x0000 -> 0x0FFF:        switchable on NINA-001
+	//     0x1000 -> 0x1FFF:        switchable on NINA-001
+    fn ppu_map_read(&self, addr: u16) -> Option<usize> {
+        if addr > 0x1FFF {
+            return None;
+        }
+
+        let banks = (self.chr_banks as usize * 2).max(2);
+        let bank  = self.chr[(addr >> 12) as usize] as usize;
+        Some((bank % banks) * 0x1000 + (addr & 0x0FFF) as usize)
+    }
+
+    // CHR RAM on BNROM
+    fn ppu_map_write(&mut self, addr: u16, data: u8) -> Option<usize> {
+        if self.chr_banks == 0 { self.ppu_map_read(addr) } else { None }
+    }
+
+    fn reset(&mut self) {
+        self.prg_bank = 0;
+    }
+
+    fn has_bus_conflicts(&self) -> bool {
+        !self.nina001
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        vec![self.prg_bank, self.chr[0], self.chr[1]]
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), EmuError> {
+        let [prg_bank, chr0, chr1] = *state else {
+            return Err(EmuError::InvalidMapperState);
+        };
+        self.prg_bank = prg_bank;
+        self.chr      = [chr0, chr1];
+        Ok(())
+    }
+}
@@ -0,0 +1,87 @@
+use crate::interfaces::{MapperInterface};
+use crate::error::EmuError;
+
+
+// GxROM, 32 KB PRG and 8 KB CHR banks selected by one register
+// https://www.nesdev.org/wiki/GxROM
+// Any write to 0x8000 -> 0xFFFF sets the bank register:
+//     bits 0 -> 1: 8 KB CHR bank at 0x0000
+//     bits 4 -> 5: 32 KB PRG bank at 0x8000
+// Like CNROM the boards have bus conflicts, they are off unless asked for.
+pub struct Mapper066 {
+    pub prg_banks:     u8,
+    pub chr_banks:     u8,
+    pub bus_conflicts: bool,
+    bank:              u8,
+}
+
+impl Mapper066 {
+    pub fn new(prg_banks: u8, chr_banks: u8) -> Self {
+        Self {
+            prg_banks,
+            chr_banks,
+            bus_conflicts: false,
+            bank:          0,
+        }
+    }
+
+    pub fn with_bus_conflicts(mut self, bus_conflicts: bool) -> Self {
+        self.bus_conflicts = bus_conflicts;
+        self
+    }
+}
+
+impl MapperInterface for Mapper066 {
+
+	//     CPU Address Bus          PRG ROM
+	//     0x8000 -> 0xFFFF: Map    bank * 0x8000 + 0x0000 -> 0x7FFF, 16 KB mirrored
+    fn cpu_map_read(&self, addr: u16) -> Option<usize> {
+        if addr >= 0x8000 {
+            let banks = (self.prg_banks as usize / 2).max(1);
+            Some((((self.bank >> 4) & 0x03) as usize % banks) * 0x8000 + (addr & (if self.prg_banks > 1 {0x7FFF} else {0x3FFF})) as usize)
+        } else {
+            None
+        }
+    }
+
+    fn cpu_map_write(&mut self, addr: u16, data: u8) -> Option<usize> {
+        if addr >= 0x8000 {
+            self.bank = data;
+        }
+        None
+    }
+
+	//     PPU Address Bus          CHR ROM
+	//     0x0000 -> 0x1FFF: Map    bank * 0x2000 + 0x0000 -> 0x1FFF
+    fn ppu_map_read(&self, addr: u16) -> Option<usize> {
+        if addr <= 0x1FFF {
+            let banks = (self.chr_banks as usize).max(1);
+            Some(((self.bank & 0x03) as usize % banks) * 0x2000 + addr as usize)
+        } else {
+            None
+        }
+    }
+
+    fn ppu_map_write(&mut self, addr: u16, data: u8) -> Option<usize> {
+        None
+    }
+
+    fn reset(&mut self) {
+        self.bank = 0;
+    }
+
+    fn has_bus_conflicts(&self) -> bool {
+        self.bus_conflicts
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        vec![self.bank]
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), EmuError> {
+        match state {
+            [bank] => { self.bank = *bank; Ok(()) },
+            _      => Err(EmuError::InvalidMapperState),
+        }
+    }
+}
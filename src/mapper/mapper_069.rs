@@ -0,0 +1,192 @@
+use crate::interfaces::{MapperInterface};
+use crate::cartridge::MIRROR;
+use crate::error::EmuError;
+use crate::audio::ExpansionChip;
+use super::sunsoft5b::Sunsoft5b;
+
+
+// Sunsoft FME-7 and 5B (Batman: Return of the Joker, Gimmick!)
+// https://www.nesdev.org/wiki/Sunsoft_FME-7
+//     0x8000 -> 0x9FFF: command
+//     0xA000 -> 0xBFFF: parameter of the command
+//     0xC000 -> 0xDFFF: 5B sound address port
+//     0xE000 -> 0xFFFF: 5B sound data port
+// Commands:
+//     0x0 -> 0x7: 1 KB CHR banks
+//     0x8       : 8 KB bank at 0x6000 (bits 0 -> 5), RAM instead of ROM (bit 6), RAM enable (bit 7)
+//     0x9 -> 0xB: 8 KB PRG banks at 0x8000, 0xA000 and 0xC000
+//     0xC       : mirroring
+//     0xD       : IRQ enable (bit 0), counter enable (bit 7), acknowledges the IRQ
+//     0xE -> 0xF: IRQ counter, low and high byte
+// The counter counts down every CPU cycle and triggers the IRQ when it wraps
+// around from 0 to 0xFFFF. Only the 5B has the sound ports, on the FME-7 the
+// writes just do nothing.
+pub struct Mapper069 {
+    pub prg_banks: u8,
+    pub chr_banks: u8,
+    command:       u8,
+    chr:           [u8; 8],
+    prg:           [u8; 4], // 0x6000, 0x8000, 0xA000, 0xC000
+    mirror:        u8,
+    irq_control:   u8,
+    irq_counter:   u16,
+    irq_pending:   bool,
+    audio:         Sunsoft5b,
+}
+
+impl Mapper069 {
+    pub fn new(prg_banks: u8, chr_banks: u8) -> Self {
+        Self {
+            prg_banks,
+            chr_banks,
+            command:     0,
+            chr:         [0; 8],
+            prg:         [0; 4],
+            mirror:      0,
+            irq_control: 0,
+            irq_counter: 0,
+            irq_pending: false,
+            audio:       Sunsoft5b::new(),
+        }
+    }
+
+    fn ram_selected(&self) -> bool {
+        self.prg[0] & 0x40 != 0
+    }
+
+    fn ram_enabled(&self) -> bool {
+        self.prg[0] & 0xC0 == 0xC0
+    }
+}
+
+impl MapperInterface for Mapper069 {
+
+	//     CPU Address Bus          PRG ROM bank (8 KB)
+	//     0x6000 -> 0x7FFF:        switchable, unless the PRG RAM is selected
+	//     0x8000 -> 0xDFFF:        switchable
+	//     0xE000 -> 0xFFFF:        fixed to the last bank
+    fn cpu_map_read(&self, addr: u16) -> Option<usize> {
+        if addr < 0x6000 || (addr < 0x8000 && self.ram_selected()) {
+            return None;
+        }
+
+        let banks = (self.prg_banks as usize * 2).max(1);
+        let bank  = match addr {
+            0xE000..=0xFFFF => banks - 1,
+            _               => (self.prg[((addr - 0x6000) >> 13) as usize] & 0x3F) as usize,
+        };
+        Some((bank % banks) * 0x2000 + (addr & 0x1FFF) as usize)
+    }
+
+    fn cpu_map_write(&mut self, addr: u16, data: u8) -> Option<usize> {
+        match addr {
+            0x8000..=0x9FFF => self.command = data & 0x0F,
+            0xA000..=0xBFFF => match self.command {
+                0x0..=0x7 => self.chr[self.command as usize]         = data,
+                0x8..=0xB => self.prg[(self.command - 0x8) as usize] = data,
+                0xC       => self.mirror = data & 0x03,
+                0xD       => {
+                    self.irq_control = data;
+                    self.irq_pending = false;
+                },
+                0xE       => self.irq_counter = (self.irq_counter & 0xFF00) | data as u16,
+                _         => self.irq_counter = (self.irq_counter & 0x00FF) | (data as u16) << 8,
+            },
+            0xC000..=0xDFFF => self.audio.write_address(data),
+            0xE000..=0xFFFF => self.audio.write_data(data),
+            _ => {},
+        }
+        None
+    }
+
+	//     PPU Address Bus          CHR bank (1 KB)
+	//     0x0000 -> 0x1FFF:        eight switchable banks
+    fn ppu_map_read(&self, addr: u16) -> Option<usize> {
+        if addr > 0x1FFF {
+            return None;
+        }
+
+        let banks = (self.chr_banks as usize * 8).max(8);
+        let bank  = self.chr[(addr >> 10) as usize] as usize;
+        Some((bank % banks) * 0x0400 + (addr & 0x03FF) as usize)
+    }
+
+    // Boards without CHR ROM have 8 KB of CHR RAM, banked the same way
+    fn ppu_map_write(&mut self, addr: u16, data: u8) -> Option<usize> {
+        if self.chr_banks == 0 { self.ppu_map_read(addr) } else { None }
+    }
+
+    fn reset(&mut self) {
+        self.irq_control = 0;
+        self.irq_pending = false;
+        self.audio.reset();
+    }
+
+    fn mirror(&self) -> Option<MIRROR> {
+        Some(match self.mirror {
+            0 => MIRROR::Vertical,
+            1 => MIRROR::Horizontal,
+            2 => MIRROR::OnescreenLo,
+            _ => MIRROR::OnescreenHi,
+        })
+    }
+
+    fn irq_state(&self) -> bool {
+        self.irq_pending
+    }
+
+    fn cpu_clock(&mut self) {
+        if self.irq_control & 0x80 != 0 {
+            self.irq_counter = self.irq_counter.wrapping_sub(1);
+            if self.irq_counter == 0xFFFF && self.irq_control & 0x01 != 0 {
+                self.irq_pending = true;
+            }
+        }
+        self.audio.cpu_clock();
+    }
+
+    fn audio_output(&self) -> f32 {
+        self.audio.output()
+    }
+
+    fn audio_chip(&self) -> Option<ExpansionChip> {
+        Some(ExpansionChip::Sunsoft5b)
+    }
+
+    fn prg_ram_readable(&self) -> bool {
+        self.ram_enabled()
+    }
+
+    fn prg_ram_writable(&self) -> bool {
+        self.ram_enabled()
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        let mut state = vec![self.command];
+        state.extend(self.chr);
+        state.extend(self.prg);
+        state.push(self.mirror);
+        state.push(self.irq_control);
+        state.extend(self.irq_counter.to_be_bytes());
+        state.push(self.irq_pending as u8);
+        state.extend(self.audio.save_state());
+        state
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), EmuError> {
+        if state.len() < 18 {
+            return Err(EmuError::InvalidMapperState);
+        }
+        let (registers, audio) = state.split_at(18);
+        self.audio.load_state(audio).ok_or(EmuError::InvalidMapperState)?;
+
+        self.command = registers[0] & 0x0F;
+        self.chr.copy_from_slice(&registers[1..9]);
+        self.prg.copy_from_slice(&registers[9..13]);
+        self.mirror      = registers[13] & 0x03;
+        self.irq_control = registers[14];
+        self.irq_counter = u16::from_be_bytes([registers[15], registers[16]]);
+        self.irq_pending = registers[17] != 0;
+        Ok(())
+    }
+}
@@ -0,0 +1,89 @@
+use crate::interfaces::{MapperInterface};
+use crate::cartridge::MIRROR;
+use crate::error::EmuError;
+use super::{mirror_to_u8, mirror_from_u8};
+
+
+// Camerica / Codemasters boards (BF909x)
+// https://www.nesdev.org/wiki/INES_Mapper_071
+//     0x8000 -> 0x9FFF: single screen page in bit 4, only on the Fire Hawk board
+//     0xC000 -> 0xFFFF: 16 KB PRG bank at 0x8000
+// Other boards ignore writes to 0x8000 -> 0x9FFF, so the mirroring follows the
+// header until the game selects a page. The boards have CHR RAM.
+pub struct Mapper071 {
+    pub prg_banks: u8,
+    prg_bank:      u8,
+    mirror:        Option<MIRROR>,
+}
+
+impl Mapper071 {
+    pub fn new(prg_banks: u8) -> Self {
+        Self {
+            prg_banks,
+            prg_bank: 0,
+            mirror:   None,
+        }
+    }
+}
+
+impl MapperInterface for Mapper071 {
+
+	//     CPU Address Bus          PRG ROM bank (16 KB)
+	//     0x8000 -> 0xBFFF:        switchable
+	//     0xC000 -> 0xFFFF:        fixed to the last bank
+    fn cpu_map_read(&self, addr: u16) -> Option<usize> {
+        if addr < 0x8000 {
+            return None;
+        }
+
+        let banks = (self.prg_banks as usize).max(1);
+        let bank  = match addr {
+            0x8000..=0xBFFF => self.prg_bank as usize % banks,
+            _               => banks - 1,
+        };
+        Some(bank * 0x4000 + (addr & 0x3FFF) as usize)
+    }
+
+    fn cpu_map_write(&mut self, addr: u16, data: u8) -> Option<usize> {
+        match addr {
+            0x8000..=0x9FFF => self.mirror   = Some(if data & 0x10 != 0 { MIRROR::OnescreenHi } else { MIRROR::OnescreenLo }),
+            0xC000..=0xFFFF => self.prg_bank = data,
+            _               => {},
+        }
+        None
+    }
+
+    fn ppu_map_read(&self, addr: u16) -> Option<usize> {
+        if addr <= 0x1FFF {
+            Some(addr as usize)
+        } else {
+            None
+        }
+    }
+
+    fn ppu_map_write(&mut self, addr: u16, data: u8) -> Option<usize> {
+        self.ppu_map_read(addr)
+    }
+
+    fn reset(&mut self) {
+        self.prg_bank = 0;
+    }
+
+    fn mirror(&self) -> Option<MIRROR> {
+        self.mirror
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        vec![self.prg_bank, mirror_to_u8(self.mirror)]
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), EmuError> {
+        let [prg_bank, mirror] = *state else {
+            return Err(EmuError::InvalidMapperState);
+        };
+
+        self.prg_bank = prg_bank;
+        self.mirror   = mirror_from_u8(mirror);
+        Ok(())
+    }
+}
@@ -0,0 +1,247 @@
+use crate::interfaces::{MapperInterface};
+use crate::cartridge::MIRROR;
+use crate::error::EmuError;
+use crate::audio::ExpansionChip;
+use super::opll::Opll;
+
+
+// The IRQ counter of the Konami VRC boards. It counts up from the latch and
+// triggers the IRQ when it wraps. In scanline mode a prescaler divides the CPU
+// clock by 113.667, the length of a scanline, otherwise every CPU cycle counts.
+pub(crate) struct VrcIrq {
+    pub latch:        u8,
+    counter:          u8,
+    prescaler:        i16,
+    enabled:          bool,
+    enable_after_ack: bool,
+    cycle_mode:       bool,
+    pub pending:      bool,
+}
+
+impl VrcIrq {
+    pub fn new() -> Self {
+        Self {
+            latch:            0,
+            counter:          0,
+            prescaler:        341,
+            enabled:          false,
+            enable_after_ack: false,
+            cycle_mode:       false,
+            pending:          false,
+        }
+    }
+
+    pub fn write_control(&mut self, data: u8) {
+        self.enable_after_ack = data & 0x01 != 0;
+        self.enabled          = data & 0x02 != 0;
+        self.cycle_mode       = data & 0x04 != 0;
+        self.pending          = false;
+        if self.enabled {
+            self.counter   = self.latch;
+            self.prescaler = 341;
+        }
+    }
+
+    pub fn acknowledge(&mut self) {
+        self.pending = false;
+        self.enabled = self.enable_after_ack;
+    }
+
+    pub fn cpu_clock(&mut self) {
+        if !self.enabled {
+            return;
+        }
+
+        if !self.cycle_mode {
+            self.prescaler -= 3;
+            if self.prescaler > 0 {
+                return;
+            }
+            self.prescaler += 341;
+        }
+
+        if self.counter == 0xFF {
+            self.counter = self.latch;
+            self.pending = true;
+        } else {
+            self.counter += 1;
+        }
+    }
+
+    pub fn save_state(&self) -> [u8; 5] {
+        let [prescaler_hi, prescaler_lo] = self.prescaler.to_be_bytes();
+        let flags = self.enabled as u8 | (self.enable_after_ack as u8) << 1 | (self.cycle_mode as u8) << 2 | (self.pending as u8) << 3;
+        [self.latch, self.counter, prescaler_hi, prescaler_lo, flags]
+    }
+
+    pub fn load_state(&mut self, state: [u8; 5]) {
+        self.latch            = state[0];
+        self.counter          = state[1];
+        self.prescaler        = i16::from_be_bytes([state[2], state[3]]);
+        self.enabled          = state[4] & 0x01 != 0;
+        self.enable_after_ack = state[4] & 0x02 != 0;
+        self.cycle_mode       = state[4] & 0x04 != 0;
+        self.pending          = state[4] & 0x08 != 0;
+    }
+}
+
+
+// VRC7 (Lagrange Point, Tiny Toon Adventures 2)
+// https://www.nesdev.org/wiki/VRC7
+// The two board revisions connect different address lines to the chip,
+// VRC7a uses A4 and VRC7b A3 to tell the second register of a pair apart.
+// Both are accepted.
+//     0x8000, 0x8010/8, 0x9000: 8 KB PRG banks at 0x8000, 0xA000 and 0xC000
+//     0x9010, 0x9030          : FM sound address and data port
+//     0xA000 -> 0xD010/8      : 1 KB CHR banks
+//     0xE000                  : mirroring (bits 0 -> 1), sound reset (bit 6), PRG RAM enable (bit 7)
+//     0xE010/8                : IRQ latch
+//     0xF000                  : IRQ control
+//     0xF010/8                : IRQ acknowledge
+pub struct Mapper085 {
+    pub prg_banks: u8,
+    pub chr_banks: u8,
+    prg:           [u8; 3],
+    chr:           [u8; 8],
+    control:       u8,
+    irq:           VrcIrq,
+    audio:         Opll,
+}
+
+impl Mapper085 {
+    pub fn new(prg_banks: u8, chr_banks: u8) -> Self {
+        Self {
+            prg_banks,
+            chr_banks,
+            prg:     [0; 3],
+            chr:     [0; 8],
+            control: 0,
+            irq:     VrcIrq::new(),
+            audio:   Opll::new(),
+        }
+    }
+}
+
+impl MapperInterface for Mapper085 {
+
+	//     CPU Address Bus          PRG ROM bank (8 KB)
+	//     0x8000 -> 0xDFFF:        switchable
+	//     0xE000 -> 0xFFFF:        fixed to the last bank
+    fn cpu_map_read(&self, addr: u16) -> Option<usize> {
+        if addr < 0x8000 {
+            return None;
+        }
+
+        let banks = (self.prg_banks as usize * 2).max(1);
+        let bank  = match addr {
+            0xE000..=0xFFFF => banks - 1,
+            _               => self.prg[((addr - 0x8000) >> 13) as usize] as usize,
+        };
+        Some((bank % banks) * 0x2000 + (addr & 0x1FFF) as usize)
+    }
+
+    fn cpu_map_write(&mut self, addr: u16, data: u8) -> Option<usize> {
+        let second = addr & 0x0018 != 0;
+
+        match (addr & 0xF000, second) {
+            (0x9000, true) if addr & 0x0020 != 0 => self.audio.write_data(data),
+            (0x9000, true)                       => self.audio.write_address(data),
+            (0x8000, _)     => self.prg[second as usize] = data & 0x3F,
+            (0x9000, false) => self.prg[2]               = data & 0x3F,
+            (0xA000..=0xD000, _) => {
+                let register = (((addr & 0xF000) - 0xA000) >> 11) as usize + second as usize;
+                self.chr[register] = data;
+            },
+            (0xE000, false) => {
+                if data & 0x40 != 0 {
+                    self.audio.reset();
+                }
+                self.control = data;
+            },
+            (0xE000, true)  => self.irq.latch = data,
+            (0xF000, false) => self.irq.write_control(data),
+            (0xF000, true)  => self.irq.acknowledge(),
+            _               => {},
+        }
+        None
+    }
+
+	//     PPU Address Bus          CHR bank (1 KB)
+	//     0x0000 -> 0x1FFF:        eight switchable banks
+    fn ppu_map_read(&self, addr: u16) -> Option<usize> {
+        if addr > 0x1FFF {
+            return None;
+        }
+
+        let banks = (self.chr_banks as usize * 8).max(8);
+        let bank  = self.chr[(addr >> 10) as usize] as usize;
+        Some((bank % banks) * 0x0400 + (addr & 0x03FF) as usize)
+    }
+
+    // Boards without CHR ROM have 8 KB of CHR RAM, banked the same way
+    fn ppu_map_write(&mut self, addr: u16, data: u8) -> Option<usize> {
+        if self.chr_banks == 0 { self.ppu_map_read(addr) } else { None }
+    }
+
+    fn reset(&mut self) {
+        self.audio.reset();
+    }
+
+    fn mirror(&self) -> Option<MIRROR> {
+        Some(match self.control & 0x03 {
+            0 => MIRROR::Vertical,
+            1 => MIRROR::Horizontal,
+            2 => MIRROR::OnescreenLo,
+            _ => MIRROR::OnescreenHi,
+        })
+    }
+
+    fn irq_state(&self) -> bool {
+        self.irq.pending
+    }
+
+    fn cpu_clock(&mut self) {
+        self.irq.cpu_clock();
+        self.audio.cpu_clock();
+    }
+
+    // The chip is held in reset while bit 6 is set
+    fn audio_output(&self) -> f32 {
+        if self.control & 0x40 != 0 { 0.0 } else { self.audio.output() }
+    }
+
+    fn audio_chip(&self) -> Option<ExpansionChip> {
+        Some(ExpansionChip::Vrc7)
+    }
+
+    fn prg_ram_readable(&self) -> bool {
+        self.control & 0x80 != 0
+    }
+
+    fn prg_ram_writable(&self) -> bool {
+        self.control & 0x80 != 0
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        let mut state = self.prg.to_vec();
+        state.extend(self.chr);
+        state.push(self.control);
+        state.extend(self.irq.save_state());
+        state.extend(self.audio.save_state());
+        state
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), EmuError> {
+        if state.len() < 17 {
+            return Err(EmuError::InvalidMapperState);
+        }
+        let (registers, audio) = state.split_at(17);
+        self.audio.load_state(audio).ok_or(EmuError::InvalidMapperState)?;
+
+        self.prg.copy_from_slice(&registers[0..3]);
+        self.chr.copy_from_slice(&registers[3..11]);
+        self.control = registers[11];
+        self.irq.load_state(registers[12..17].try_into().unwrap());
+        Ok(())
+    }
+}
@@ -0,0 +1,77 @@
+use crate::interfaces::{MapperInterface};
+use crate::error::EmuError;
+use super::Mapper004;
+
+
+// Namco 118 / Tengen MIMIC-1, DxROM boards (Babel no Tou, Gauntlet)
+// https://www.nesdev.org/wiki/INES_Mapper_206
+//     0x8000 -> 0x9FFF: bank select (even), bank data (odd)
+// The predecessor of the MMC3 with the same eight bank registers, but without
+// the PRG and CHR mode bits, mirroring control, PRG RAM and IRQ. Mirroring is
+// hardwired. Everything else is the MMC3 with its bank select limited to the
+// register number.
+pub struct Mapper206 {
+    pub prg_banks: u8,
+    pub chr_banks: u8,
+    mmc3:          Mapper004,
+}
+
+impl Mapper206 {
+    pub fn new(prg_banks: u8, chr_banks: u8) -> Self {
+        Self {
+            prg_banks,
+            chr_banks,
+            mmc3: Mapper004::new(prg_banks, chr_banks),
+        }
+    }
+}
+
+impl MapperInterface for Mapper206 {
+
+	//     CPU Address Bus          PRG ROM bank (8 KB)
+	//     0x8000 -> 0x9FFF:        R6
+	//     0xA000 -> 0xBFFF:        R7
+	//     0xC000 -> 0xFFFF:        fixed to the last two banks
+    fn cpu_map_read(&self, addr: u16) -> Option<usize> {
+        self.mmc3.cpu_map_read(addr)
+    }
+
+    fn cpu_map_write(&mut self, addr: u16, data: u8) -> Option<usize> {
+        match (addr, addr & 0x0001) {
+            (0x8000..=0x9FFF, 0) => self.mmc3.cpu_map_write(addr, data & 0x07),
+            (0x8000..=0x9FFF, _) => self.mmc3.cpu_map_write(addr, data),
+            _                    => None,
+        }
+    }
+
+	//     PPU Address Bus          CHR bank (1 KB)
+	//     0x0000 -> 0x0FFF:        R0, R1 (2 KB each)
+	//     0x1000 -> 0x1FFF:        R2 -> R5
+    fn ppu_map_read(&self, addr: u16) -> Option<usize> {
+        self.mmc3.ppu_map_read(addr)
+    }
+
+    fn ppu_map_write(&mut self, addr: u16, data: u8) -> Option<usize> {
+        self.mmc3.ppu_map_write(addr, data)
+    }
+
+    fn reset(&mut self) {
+        self.mmc3.reset();
+    }
+
+    fn prg_ram_readable(&self) -> bool {
+        false
+    }
+
+    fn prg_ram_writable(&self) -> bool {
+        false
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        self.mmc3.save_state()
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), EmuError> {
+        self.mmc3.load_state(state)
+    }
+}
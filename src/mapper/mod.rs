@@ -0,0 +1,134 @@
+use std::collections::HashMap;
+
+use crate::interfaces::MapperInterface;
+use crate::cartridge::MIRROR;
+use crate::error::EmuError;
+
+// Each board lives in its own file
+mod mapper_000;
+mod mapper_003;
+mod mapper_004;
+mod mapper_007;
+mod mapper_009;
+mod mapper_010;
+mod mapper_011;
+mod mapper_019;
+mod mapper_034;
+mod mapper_066;
+mod mapper_069;
+mod mapper_071;
+mod mapper_085;
+mod mapper_206;
+mod n163;
+mod opll;
+mod sunsoft5b;
+
+pub use mapper_000::Mapper000;
+pub use mapper_003::Mapper003;
+pub use mapper_004::Mapper004;
+pub use mapper_007::Mapper007;
+pub use mapper_009::Mapper009;
+pub use mapper_010::Mapper010;
+pub use mapper_011::Mapper011;
+pub use mapper_019::Mapper019;
+pub use mapper_034::Mapper034;
+pub use mapper_066::Mapper066;
+pub use mapper_069::Mapper069;
+pub use mapper_071::Mapper071;
+pub use mapper_085::Mapper085;
+pub use mapper_206::Mapper206;
+
+// Creates a board from the number of 16 KB PRG banks and 8 KB CHR banks
+pub type MapperConstructor = fn(prg_banks: u8, chr_banks: u8) -> Box<dyn MapperInterface>;
+
+// The boards a cartridge can be loaded with, keyed by iNES mapper number and
+// NES 2.0 submapper. Submapper 0 is the board used when the header doesn't
+// name a submapper or names one that isn't registered.
+// The default registry holds the boards built into the emulator, custom
+// boards can be registered on top of them or replace them.
+pub struct MapperRegistry {
+    constructors: HashMap<(u8, u8), MapperConstructor>,
+}
+
+impl MapperRegistry {
+    // A registry without any boards
+    pub fn empty() -> Self {
+        Self {
+            constructors: HashMap::new(),
+        }
+    }
+
+    pub fn new() -> Self {
+        let mut registry = Self::empty();
+        registry.register(0,   |prg_banks, chr_banks| Box::new(Mapper000 { prg_banks, chr_banks }));
+        registry.register(3,   |prg_banks, chr_banks| Box::new(Mapper003::new(prg_banks, chr_banks)));
+        registry.register(4,   |prg_banks, chr_banks| Box::new(Mapper004::new(prg_banks, chr_banks)));
+        registry.register(7,   |prg_banks, _|         Box::new(Mapper007::new(prg_banks)));
+        registry.register(9,   |prg_banks, chr_banks| Box::new(Mapper009::new(prg_banks, chr_banks)));
+        registry.register(10,  |prg_banks, chr_banks| Box::new(Mapper010::new(prg_banks, chr_banks)));
+        registry.register(11,  |prg_banks, chr_banks| Box::new(Mapper011::new(prg_banks, chr_banks)));
+        registry.register(19,  |prg_banks, chr_banks| Box::new(Mapper019::new(prg_banks, chr_banks)));
+        registry.register(34,  |prg_banks, chr_banks| Box::new(Mapper034::new(prg_banks, chr_banks)));
+        registry.register_submapper(34, 1, |prg_banks, chr_banks| Box::new(Mapper034::nina001(prg_banks, chr_banks)));
+        registry.register_submapper(34, 2, |prg_banks, chr_banks| Box::new(Mapper034::bnrom(prg_banks, chr_banks)));
+        registry.register(66,  |prg_banks, chr_banks| Box::new(Mapper066::new(prg_banks, chr_banks)));
+        registry.register(69,  |prg_banks, chr_banks| Box::new(Mapper069::new(prg_banks, chr_banks)));
+        registry.register(71,  |prg_banks, _|         Box::new(Mapper071::new(prg_banks)));
+        registry.register(85,  |prg_banks, chr_banks| Box::new(Mapper085::new(prg_banks, chr_banks)));
+        registry.register(206, |prg_banks, chr_banks| Box::new(Mapper206::new(prg_banks, chr_banks)));
+        registry
+    }
+
+    pub fn register(&mut self, id: u8, constructor: MapperConstructor) {
+        self.register_submapper(id, 0, constructor);
+    }
+
+    pub fn register_submapper(&mut self, id: u8, submapper: u8, constructor: MapperConstructor) {
+        self.constructors.insert((id, submapper), constructor);
+    }
+
+    pub fn is_supported(&self, id: u8) -> bool {
+        self.constructors.keys().any(|&(registered, _)| registered == id)
+    }
+
+    pub fn create(&self, id: u8, prg_banks: u8, chr_banks: u8) -> Result<Box<dyn MapperInterface>, EmuError> {
+        self.create_submapper(id, 0, prg_banks, chr_banks)
+    }
+
+    pub fn create_submapper(&self, id: u8, submapper: u8, prg_banks: u8, chr_banks: u8) -> Result<Box<dyn MapperInterface>, EmuError> {
+        self.constructors.get(&(id, submapper))
+            .or_else(|| self.constructors.get(&(id, 0)))
+            .map(|constructor| constructor(prg_banks, chr_banks))
+            .ok_or(EmuError::UnsupportedMapper(id))
+    }
+}
+
+impl Default for MapperRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Save state encoding of the mirroring set through a board register, None
+// until the game wrote it
+pub(crate) fn mirror_to_u8(mirror: Option<MIRROR>) -> u8 {
+    match mirror {
+        None                      => 0,
+        Some(MIRROR::Horizontal)  => 1,
+        Some(MIRROR::Vertical)    => 2,
+        Some(MIRROR::OnescreenLo) => 3,
+        Some(MIRROR::OnescreenHi) => 4,
+        Some(MIRROR::FourScreen)  => 5,
+    }
+}
+
+pub(crate) fn mirror_from_u8(mirror: u8) -> Option<MIRROR> {
+    match mirror {
+        0 => None,
+        1 => Some(MIRROR::Horizontal),
+        3 => Some(MIRROR::OnescreenLo),
+        4 => Some(MIRROR::OnescreenHi),
+        5 => Some(MIRROR::FourScreen),
+        _ => Some(MIRROR::Vertical),
+    }
+}
@@ -0,0 +1,128 @@
+// Namco 163 wavetable sound
+// https://www.nesdev.org/wiki/Namco_163_audio
+// 128 bytes of sound RAM hold the waveforms as 4 bit samples (low nibble
+// first) and, from 0x40 upwards, the registers of up to 8 channels:
+//     base + 0: frequency, bits 0 -> 7
+//     base + 1: phase, bits 0 -> 7
+//     base + 2: frequency, bits 8 -> 15
+//     base + 3: phase, bits 8 -> 15
+//     base + 4: frequency, bits 16 -> 17, wave length (256 - bits 2 -> 7) in samples
+//     base + 5: phase, bits 16 -> 23
+//     base + 6: wave address in samples
+//     base + 7: volume (bits 0 -> 3), number of enabled channels - 1 (bits 4 -> 6, 0x7F only)
+// Channel 7 sits at 0x78, channel 0 at 0x40. The chip updates one channel
+// every 15 CPU cycles, starting at channel 7 and going down through the
+// enabled ones. The real chip outputs the channels one after another, which
+// with many channels enabled is an audible whine, so they are averaged here.
+pub(crate) struct N163Audio {
+    ram:     [u8; 128],
+    address: u8, // bit 7: increment after each access of the data port
+    channel: u8,
+    cycles:  u8,
+    outputs: [f32; 8],
+}
+
+const CYCLES_PER_CHANNEL: u8 = 15;
+
+impl N163Audio {
+    pub fn new() -> Self {
+        Self {
+            ram:     [0; 128],
+            address: 0,
+            channel: 7,
+            cycles:  0,
+            outputs: [0.0; 8],
+        }
+    }
+
+    // The sound RAM survives a reset
+    pub fn reset(&mut self) {
+        self.channel = 7;
+        self.cycles  = 0;
+        self.outputs = [0.0; 8];
+    }
+
+    pub fn write_address(&mut self, data: u8) {
+        self.address = data;
+    }
+
+    pub fn read_data(&mut self) -> u8 {
+        let data = self.peek_data();
+        self.increment();
+        data
+    }
+
+    pub fn peek_data(&self) -> u8 {
+        self.ram[(self.address & 0x7F) as usize]
+    }
+
+    pub fn write_data(&mut self, data: u8) {
+        self.ram[(self.address & 0x7F) as usize] = data;
+        self.increment();
+    }
+
+    fn increment(&mut self) {
+        if self.address & 0x80 != 0 {
+            self.address = 0x80 | (self.address.wrapping_add(1) & 0x7F);
+        }
+    }
+
+    fn enabled_channels(&self) -> u8 {
+        ((self.ram[0x7F] >> 4) & 0x07) + 1
+    }
+
+    pub fn cpu_clock(&mut self) {
+        self.cycles += 1;
+        if self.cycles < CYCLES_PER_CHANNEL {
+            return;
+        }
+        self.cycles = 0;
+
+        self.update_channel(self.channel as usize);
+        self.channel = if self.channel <= 8 - self.enabled_channels() { 7 } else { self.channel - 1 };
+    }
+
+    fn update_channel(&mut self, channel: usize) {
+        let base = 0x40 + channel * 8;
+        let reg: [u32; 8] = std::array::from_fn(|offset| self.ram[base + offset] as u32);
+
+        let frequency = reg[0] | reg[2] << 8 | (reg[4] & 0x03) << 16;
+        let length    = 256 - (reg[4] & 0xFC);
+        let phase     = ((reg[1] | reg[3] << 8 | reg[5] << 16) + frequency) % (length << 16);
+
+        self.ram[base + 1] =  phase        as u8;
+        self.ram[base + 3] = (phase >> 8)  as u8;
+        self.ram[base + 5] = (phase >> 16) as u8;
+
+        let sample_addr = ((phase >> 16) + reg[6]) & 0xFF;
+        let byte        = self.ram[(sample_addr >> 1) as usize];
+        let sample      = if sample_addr & 1 != 0 { byte >> 4 } else { byte & 0x0F };
+
+        self.outputs[channel] = (sample as f32 - 8.0) * (reg[7] & 0x0F) as f32;
+    }
+
+    // Samples are -8 ..= 7 times a volume of up to 15
+    pub fn output(&self) -> f32 {
+        let enabled = self.enabled_channels() as usize;
+        self.outputs[8 - enabled..].iter().sum::<f32>() / (enabled as f32 * 120.0)
+    }
+
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut state = vec![self.address, self.channel, self.cycles];
+        state.extend(self.ram);
+        state
+    }
+
+    // The channel outputs are recomputed on their next update
+    pub fn load_state(&mut self, state: &[u8]) -> Option<()> {
+        if state.len() != 3 + 128 || state[1] > 7 || state[2] >= CYCLES_PER_CHANNEL {
+            return None;
+        }
+        self.address = state[0];
+        self.channel = state[1];
+        self.cycles  = state[2];
+        self.ram.copy_from_slice(&state[3..]);
+        self.outputs = [0.0; 8];
+        Some(())
+    }
+}
@@ -0,0 +1,346 @@
+use std::f32::consts::PI;
+
+// The FM sound chip of the VRC7, a cut down YM2413 (OPLL) with six channels
+// and its own set of built-in instruments
+// https://www.nesdev.org/wiki/VRC7_audio
+// Every channel is a modulator operator that modulates the phase of a carrier
+// operator. The registers are written through an address and a data port:
+//     0x00 -> 0x07: custom instrument
+//     0x10 -> 0x15: low 8 bits of the frequency number
+//     0x20 -> 0x25: sustain (bit 5), key on (bit 4), octave (bits 1 -> 3), frequency bit 8 (bit 0)
+//     0x30 -> 0x35: instrument (bits 4 -> 7), volume (bits 0 -> 3)
+// The chip isn't emulated bit-exactly, the envelopes and the LFOs follow the
+// documented rates but are computed in floating point.
+
+// The chip produces a sample every 36 CPU cycles, 3.58 MHz / 72
+pub const CYCLES_PER_SAMPLE: u8 = 36;
+const SAMPLE_RATE: f32 = 49716.0;
+
+const CHANNELS: usize = 6;
+
+// Instruments 1 -> 15, instrument 0 is the custom one. Dumped from the chip,
+// https://www.nesdev.org/wiki/VRC7_audio#Internal_patch_set
+const PATCHES: [[u8; 8]; 15] = [
+    [0x03, 0x21, 0x05, 0x06, 0xE8, 0x81, 0x42, 0x27],
+    [0x13, 0x41, 0x14, 0x0D, 0xD8, 0xF6, 0x23, 0x12],
+    [0x11, 0x11, 0x08, 0x08, 0xFA, 0xB2, 0x20, 0x12],
+    [0x31, 0x61, 0x0C, 0x07, 0xA8, 0x64, 0x61, 0x27],
+    [0x32, 0x21, 0x1E, 0x06, 0xE1, 0x76, 0x01, 0x28],
+    [0x02, 0x01, 0x06, 0x00, 0xA3, 0xE2, 0xF4, 0xF4],
+    [0x21, 0x61, 0x1D, 0x07, 0x82, 0x81, 0x11, 0x07],
+    [0x23, 0x21, 0x22, 0x17, 0xA2, 0x72, 0x01, 0x17],
+    [0x35, 0x11, 0x25, 0x00, 0x40, 0x73, 0x72, 0x01],
+    [0xB5, 0x01, 0x0F, 0x0F, 0xA8, 0xA5, 0x51, 0x02],
+    [0x17, 0xC1, 0x24, 0x07, 0xF8, 0xF8, 0x22, 0x12],
+    [0x71, 0x23, 0x11, 0x06, 0x65, 0x74, 0x18, 0x16],
+    [0x01, 0x02, 0xD3, 0x05, 0xC9, 0x95, 0x03, 0x02],
+    [0x61, 0x63, 0x0C, 0x00, 0x94, 0xC0, 0x33, 0xF6],
+    [0x21, 0x72, 0x0D, 0x00, 0xC1, 0xD5, 0x56, 0x06],
+];
+
+const MULTIPLIER: [f32; 16] = [0.5, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0, 10.0, 10.0, 12.0, 12.0, 15.0, 15.0];
+
+// Key scale level in dB at octave 7 by the upper four bits of the frequency
+// number, it drops by 6 dB per octave below
+const KSL_DB: [f32; 16] = [0.0, 9.0, 12.0, 13.875, 15.0, 16.125, 16.875, 17.625, 18.0, 18.75, 19.125, 19.5, 19.875, 20.25, 20.625, 21.0];
+const KSL_SCALE: [f32; 4] = [0.0, 0.5, 0.25, 1.0];
+
+// Phase offset of the modulator feedback in radians
+const FEEDBACK: [f32; 8] = [0.0, PI / 32.0, PI / 16.0, PI / 8.0, PI / 4.0, PI / 2.0, PI, 2.0 * PI];
+
+// Phase offset of the carrier at full modulator output
+const MODULATION: f32 = 4.0 * PI;
+
+// Tremolo and vibrato
+const AM_RATE:  f32 = 3.64;
+const AM_DEPTH: f32 = 4.875;  // dB
+const PM_RATE:  f32 = 6.4;
+const PM_DEPTH: f32 = 0.004;  // about 7 cents
+
+// Silence
+const MAX_ATTENUATION: f32 = 96.0;
+
+// Time to decay from 0 to 96 dB and to attack from 96 to 0 dB at the
+// slowest rate with any effect, every four steps of the rate halve it
+const DECAY_TIME:  f32 = 20.0;
+const ATTACK_TIME: f32 = 5.6;
+
+// ln(96 dB / 0.1 dB), the attack ends once it is within 0.1 dB of full volume
+const ATTACK_CURVE: f32 = 6.9;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Stage {
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+    Off,
+}
+
+// The settings of one operator, decoded from an instrument
+struct OperatorPatch {
+    am:         bool,
+    vib:        bool,
+    sustained:  bool,  // envelope holds at the sustain level, otherwise the tone is percussive
+    ksr:        bool,
+    mult:       f32,
+    ksl:        usize,
+    half_sine:  bool,
+    ar:         u8,
+    dr:         u8,
+    sl:         u8,
+    rr:         u8,
+}
+
+impl OperatorPatch {
+    // op is 0 for the modulator and 1 for the carrier
+    fn decode(patch: &[u8; 8], op: usize) -> Self {
+        Self {
+            am:        patch[op] & 0x80 != 0,
+            vib:       patch[op] & 0x40 != 0,
+            sustained: patch[op] & 0x20 != 0,
+            ksr:       patch[op] & 0x10 != 0,
+            mult:      MULTIPLIER[(patch[op] & 0x0F) as usize],
+            ksl:       (patch[2 + op] >> 6) as usize,
+            half_sine: patch[3] & (0x08 << op) != 0,
+            ar:        patch[4 + op] >> 4,
+            dr:        patch[4 + op] & 0x0F,
+            sl:        patch[6 + op] >> 4,
+            rr:        patch[6 + op] & 0x0F,
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+struct Operator {
+    phase:  f32,      // in cycles
+    env_db: f32,      // attenuation of the envelope
+    stage:  Stage,
+    output: [f32; 2], // last two outputs, the modulator feeds them back
+}
+
+impl Operator {
+    fn new() -> Self {
+        Self {
+            phase:  0.0,
+            env_db: MAX_ATTENUATION,
+            stage:  Stage::Off,
+            output: [0.0; 2],
+        }
+    }
+
+    fn key_on(&mut self) {
+        self.phase = 0.0;
+        self.stage = Stage::Attack;
+    }
+
+    fn key_off(&mut self) {
+        if self.stage != Stage::Off {
+            self.stage = Stage::Release;
+        }
+    }
+
+    // Attenuation change per sample in dB, rate is 0 -> 15 and rks the key
+    // scaling of the rate
+    fn step(rate: u8, rks: u8, time: f32) -> f32 {
+        if rate == 0 {
+            return 0.0;
+        }
+        let effective = (4 * rate + rks).min(63) as f32;
+        MAX_ATTENUATION / (time * SAMPLE_RATE) * (2.0f32).powf((effective - 4.0) / 4.0)
+    }
+
+    fn clock_envelope(&mut self, patch: &OperatorPatch, rks: u8, sustain: bool) {
+        match self.stage {
+            // The attack is exponential, fast at first and slowing down
+            // towards full volume
+            Stage::Attack => {
+                self.env_db -= self.env_db * Self::step(patch.ar, rks, ATTACK_TIME) * ATTACK_CURVE / MAX_ATTENUATION;
+                if patch.ar == 15 || self.env_db < 0.1 {
+                    self.env_db = 0.0;
+                    self.stage  = Stage::Decay;
+                }
+            },
+            Stage::Decay => {
+                let level = patch.sl as f32 * 3.0;
+                self.env_db += Self::step(patch.dr, rks, DECAY_TIME);
+                if self.env_db >= level {
+                    self.env_db = level;
+                    self.stage  = Stage::Sustain;
+                }
+            },
+            // Percussive tones keep decaying while the key is held
+            Stage::Sustain => {
+                if !patch.sustained {
+                    self.env_db += Self::step(patch.rr, rks, DECAY_TIME);
+                }
+            },
+            // The sustain bit of the channel slows down the release
+            Stage::Release => {
+                let rate = if sustain { 5 } else { patch.rr };
+                self.env_db += Self::step(rate, rks, DECAY_TIME);
+            },
+            Stage::Off => {},
+        }
+
+        if self.env_db >= MAX_ATTENUATION {
+            self.env_db = MAX_ATTENUATION;
+            self.stage  = Stage::Off;
+        }
+    }
+
+    // Advances the phase and returns the output for the given phase
+    // modulation, attenuated by attenuation_db on top of the envelope
+    fn clock(&mut self, increment: f32, modulation: f32, attenuation_db: f32, half_sine: bool) -> f32 {
+        self.phase = (self.phase + increment).fract();
+
+        let attenuation = self.env_db + attenuation_db;
+        let mut output = if self.stage == Stage::Off || attenuation >= MAX_ATTENUATION {
+            0.0
+        } else {
+            (2.0 * PI * self.phase + modulation).sin() * (10.0f32).powf(-attenuation / 20.0)
+        };
+        if half_sine && output < 0.0 {
+            output = 0.0;
+        }
+
+        self.output = [self.output[1], output];
+        output
+    }
+}
+
+pub(crate) struct Opll {
+    address:   u8,
+    registers: [u8; 0x40],
+    operators: [[Operator; 2]; CHANNELS], // [channel][modulator, carrier]
+    am_phase:  f32,
+    pm_phase:  f32,
+    cycles:    u8,
+    output:    f32,
+}
+
+impl Opll {
+    pub fn new() -> Self {
+        Self {
+            address:   0,
+            registers: [0; 0x40],
+            operators: [[Operator::new(); 2]; CHANNELS],
+            am_phase:  0.0,
+            pm_phase:  0.0,
+            cycles:    0,
+            output:    0.0,
+        }
+    }
+
+    // Silences the chip and clears the registers
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    pub fn write_address(&mut self, data: u8) {
+        self.address = data & 0x3F;
+    }
+
+    pub fn write_data(&mut self, data: u8) {
+        let addr = self.address as usize;
+        let channel = addr & 0x0F;
+
+        // Only the key on bit starts and ends notes
+        if (0x20..0x20 + CHANNELS).contains(&addr) {
+            let was_on = self.registers[addr] & 0x10 != 0;
+            let is_on  = data & 0x10 != 0;
+            if is_on && !was_on {
+                self.operators[channel].iter_mut().for_each(Operator::key_on);
+            } else if was_on && !is_on {
+                self.operators[channel].iter_mut().for_each(Operator::key_off);
+            }
+        }
+        self.registers[addr] = data;
+    }
+
+    pub fn output(&self) -> f32 {
+        self.output
+    }
+
+    pub fn cpu_clock(&mut self) {
+        self.cycles += 1;
+        if self.cycles == CYCLES_PER_SAMPLE {
+            self.cycles = 0;
+            self.output = self.sample();
+        }
+    }
+
+    fn patch(&self, instrument: u8) -> [u8; 8] {
+        match instrument {
+            0 => self.registers[0..8].try_into().unwrap(),
+            _ => PATCHES[instrument as usize - 1],
+        }
+    }
+
+    fn sample(&mut self) -> f32 {
+        self.am_phase = (self.am_phase + AM_RATE / SAMPLE_RATE).fract();
+        self.pm_phase = (self.pm_phase + PM_RATE / SAMPLE_RATE).fract();
+        let am_db     = (1.0 - (2.0 * PI * self.am_phase).cos()) / 2.0 * AM_DEPTH;
+        let pm        = 1.0 + (2.0 * PI * self.pm_phase).sin() * PM_DEPTH;
+
+        let mut sum = 0.0;
+        for channel in 0..CHANNELS {
+            let fnum       = self.registers[0x10 + channel] as u16 | ((self.registers[0x20 + channel] as u16 & 0x01) << 8);
+            let block      = (self.registers[0x20 + channel] >> 1) & 0x07;
+            let sustain    = self.registers[0x20 + channel] & 0x20 != 0;
+            let instrument = self.registers[0x30 + channel] >> 4;
+            let volume     = self.registers[0x30 + channel] & 0x0F;
+
+            let patch    = self.patch(instrument);
+            let ops      = [OperatorPatch::decode(&patch, 0), OperatorPatch::decode(&patch, 1)];
+            let base     = fnum as f32 * (2.0f32).powi(block as i32 - 19);
+            let ksl_db   = (KSL_DB[(fnum >> 5) as usize] - 6.0 * (7 - block) as f32).max(0.0);
+            let level_db = [(patch[2] & 0x3F) as f32 * 0.75, volume as f32 * 3.0];
+
+            let mut modulation = 0.0;
+            for (i, op) in ops.iter().enumerate() {
+                let rks       = if op.ksr { block * 2 + (fnum >> 8) as u8 } else { block / 2 };
+                let increment = base * op.mult * if op.vib { pm } else { 1.0 };
+                let attenuation = level_db[i] + ksl_db * KSL_SCALE[op.ksl] + if op.am { am_db } else { 0.0 };
+
+                let operator = &mut self.operators[channel][i];
+                operator.clock_envelope(op, rks, sustain);
+
+                if i == 0 {
+                    let feedback = (operator.output[0] + operator.output[1]) / 2.0 * FEEDBACK[(patch[3] & 0x07) as usize];
+                    modulation = operator.clock(increment, feedback, attenuation, op.half_sine) * MODULATION;
+                } else {
+                    sum += operator.clock(increment, modulation, attenuation, op.half_sine);
+                }
+            }
+        }
+        sum / CHANNELS as f32
+    }
+
+    // The registers for save states. The operators aren't saved, notes that
+    // are held continue at their sustain level after loading.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut state = vec![self.address];
+        state.extend(self.registers);
+        state
+    }
+
+    pub fn load_state(&mut self, state: &[u8]) -> Option<()> {
+        let (&address, registers) = state.split_first()?;
+
+        self.reset();
+        self.address   = address;
+        self.registers = registers.try_into().ok()?;
+
+        for channel in 0..CHANNELS {
+            if self.registers[0x20 + channel] & 0x10 != 0 {
+                let patch = self.patch(self.registers[0x30 + channel] >> 4);
+                for (i, operator) in self.operators[channel].iter_mut().enumerate() {
+                    operator.env_db = OperatorPatch::decode(&patch, i).sl as f32 * 3.0;
+                    operator.stage  = Stage::Sustain;
+                }
+            }
+        }
+        Some(())
+    }
+}
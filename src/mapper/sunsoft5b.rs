@@ -0,0 +1,205 @@
+// Sunsoft 5B sound, a YM2149 (AY-3-8910) inside the FME-7 of Gimmick!
+// https://www.nesdev.org/wiki/Sunsoft_5B_audio
+//     0x00 -> 0x05: tone period of channel A, B and C, 12 bit, low byte first
+//     0x06        : noise period, 5 bit
+//     0x07        : tone disable (bits 0 -> 2), noise disable (bits 3 -> 5) per channel
+//     0x08 -> 0x0A: volume (bits 0 -> 3) or envelope (bit 4) per channel
+//     0x0B -> 0x0C: envelope period, 16 bit, low byte first
+//     0x0D        : envelope shape, hold (bit 0), alternate (bit 1), attack (bit 2), continue (bit 3)
+// The chip runs at half the CPU clock and divides that by 8 for the tone and
+// noise generators, their periods are in steps of 16 CPU cycles. A square wave
+// toggles every period, the noise LFSR shifts every other period. The envelope
+// has 32 levels and steps every period of 16 CPU cycles, volumes map onto
+// every second level (3 dB apart).
+pub(crate) struct Sunsoft5b {
+    registers:     [u8; 16],
+    address:       u8,
+    prescaler:     u8,
+    tone_counter:  [u16; 3],
+    tone_out:      [bool; 3],
+    noise_counter: u8,
+    noise_toggle:  bool,
+    lfsr:          u32,
+    env_counter:   u16,
+    env_step:      u8,
+    env_attack:    bool,
+    env_holding:   bool,
+}
+
+const CYCLES_PER_TICK: u8 = 16;
+
+// Amplitude of the 32 levels, 1.5 dB apart
+fn level_amplitude(level: u8) -> f32 {
+    if level == 0 { 0.0 } else { 10f32.powf((level as f32 - 31.0) * 1.5 / 20.0) }
+}
+
+impl Sunsoft5b {
+    pub fn new() -> Self {
+        Self {
+            registers:     [0; 16],
+            address:       0,
+            prescaler:     0,
+            tone_counter:  [0; 3],
+            tone_out:      [false; 3],
+            noise_counter: 0,
+            noise_toggle:  false,
+            lfsr:          1,
+            env_counter:   0,
+            env_step:      0,
+            env_attack:    false,
+            env_holding:   false,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    // Only register numbers 0x00 -> 0x0F select a register
+    pub fn write_address(&mut self, data: u8) {
+        self.address = data;
+    }
+
+    pub fn write_data(&mut self, data: u8) {
+        if self.address > 0x0F {
+            return;
+        }
+        self.registers[self.address as usize] = data;
+
+        if self.address == 0x0D {
+            self.env_step    = 0;
+            self.env_attack  = data & 0x04 != 0;
+            self.env_holding = false;
+        }
+    }
+
+    fn tone_period(&self, channel: usize) -> u16 {
+        (self.registers[channel * 2] as u16 | ((self.registers[channel * 2 + 1] & 0x0F) as u16) << 8).max(1)
+    }
+
+    pub fn cpu_clock(&mut self) {
+        self.prescaler += 1;
+        if self.prescaler < CYCLES_PER_TICK {
+            return;
+        }
+        self.prescaler = 0;
+
+        for channel in 0..3 {
+            self.tone_counter[channel] += 1;
+            if self.tone_counter[channel] >= self.tone_period(channel) {
+                self.tone_counter[channel] = 0;
+                self.tone_out[channel]     = !self.tone_out[channel];
+            }
+        }
+
+        self.noise_counter += 1;
+        if self.noise_counter >= (self.registers[0x06] & 0x1F).max(1) {
+            self.noise_counter = 0;
+            self.noise_toggle  = !self.noise_toggle;
+            if self.noise_toggle {
+                let feedback = (self.lfsr ^ (self.lfsr >> 3)) & 0x01;
+                self.lfsr    = (self.lfsr >> 1) | (feedback << 16);
+            }
+        }
+
+        self.env_counter += 1;
+        let env_period = (self.registers[0x0B] as u16 | (self.registers[0x0C] as u16) << 8).max(1);
+        if self.env_counter >= env_period {
+            self.env_counter = 0;
+            self.clock_envelope();
+        }
+    }
+
+    fn clock_envelope(&mut self) {
+        if self.env_holding {
+            return;
+        }
+        if self.env_step < 31 {
+            self.env_step += 1;
+            return;
+        }
+
+        // At the end of a ramp: without continue the envelope drops to 0 and
+        // stays there, alternate flips the direction, hold keeps the level
+        let shape = self.registers[0x0D];
+        if shape & 0x08 == 0 {
+            self.env_attack  = false;
+            self.env_holding = true;
+            return;
+        }
+        if shape & 0x02 != 0 {
+            self.env_attack = !self.env_attack;
+        }
+        if shape & 0x01 != 0 {
+            self.env_holding = true;
+        } else {
+            self.env_step = 0;
+        }
+    }
+
+    fn env_level(&self) -> u8 {
+        if self.env_attack { self.env_step } else { 31 - self.env_step }
+    }
+
+    // Channels are unipolar, the sum is scaled to 0 ..= 1
+    pub fn output(&self) -> f32 {
+        let mixer = self.registers[0x07];
+        let noise = self.lfsr & 0x01 != 0;
+
+        (0..3).map(|channel| {
+            let tone_on  = mixer & (1 << channel) != 0 || self.tone_out[channel];
+            let noise_on = mixer & (8 << channel) != 0 || noise;
+            if !(tone_on && noise_on) {
+                return 0.0;
+            }
+
+            let volume = self.registers[0x08 + channel];
+            let level  = if volume & 0x10 != 0 {
+                self.env_level()
+            } else if volume & 0x0F == 0 {
+                0
+            } else {
+                (volume & 0x0F) * 2 + 1
+            };
+            level_amplitude(level)
+        }).sum::<f32>() / 3.0
+    }
+
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut state = self.registers.to_vec();
+        state.push(self.address);
+        state.push(self.prescaler);
+        for counter in self.tone_counter {
+            state.extend(counter.to_be_bytes());
+        }
+        let flags = self.tone_out[0] as u8 | (self.tone_out[1] as u8) << 1 | (self.tone_out[2] as u8) << 2
+            | (self.noise_toggle as u8) << 3 | (self.env_attack as u8) << 4 | (self.env_holding as u8) << 5;
+        state.push(flags);
+        state.push(self.noise_counter);
+        state.extend(self.lfsr.to_be_bytes());
+        state.extend(self.env_counter.to_be_bytes());
+        state.push(self.env_step);
+        state
+    }
+
+    pub fn load_state(&mut self, state: &[u8]) -> Option<()> {
+        if state.len() != 33 || state[17] >= CYCLES_PER_TICK || state[32] > 31 {
+            return None;
+        }
+        self.registers.copy_from_slice(&state[0..16]);
+        self.address   = state[16];
+        self.prescaler = state[17];
+        for channel in 0..3 {
+            self.tone_counter[channel] = u16::from_be_bytes([state[18 + channel * 2], state[19 + channel * 2]]);
+            self.tone_out[channel]     = state[24] & (1 << channel) != 0;
+        }
+        self.noise_toggle  = state[24] & 0x08 != 0;
+        self.env_attack    = state[24] & 0x10 != 0;
+        self.env_holding   = state[24] & 0x20 != 0;
+        self.noise_counter = state[25];
+        self.lfsr          = u32::from_be_bytes(state[26..30].try_into().unwrap()).max(1);
+        self.env_counter   = u16::from_be_bytes([state[30], state[31]]);
+        self.env_step      = state[32];
+        Some(())
+    }
+}
@@ -0,0 +1,76 @@
+use crate::error::EmuError;
+
+// Input movie
+// The buttons of the four ports for every frame since the console was reset.
+// Played back from the same ROM it reproduces a run exactly, which makes it a
+// good bug report and regression test.
+//
+// File layout, little endian:
+//     0 -> 3 : "NMV" 0x1A
+//     4 -> 7 : number of frames
+//     8 ->   : 4 bytes per frame, the BUTTON_* bits of ports 0 - 3
+const MAGIC: [u8; 4] = *b"NMV\x1A";
+pub const PORTS: usize = 4;
+
+#[derive(Default, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Movie {
+    frames: Vec<[u8; PORTS]>,
+}
+
+impl Movie {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, buttons: [u8; PORTS]) {
+        self.frames.push(buttons);
+    }
+
+    pub fn frame(&self, frame: usize) -> Option<[u8; PORTS]> {
+        self.frames.get(frame).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(8 + PORTS * self.frames.len());
+        bytes.extend_from_slice(&MAGIC);
+        bytes.extend_from_slice(&(self.frames.len() as u32).to_le_bytes());
+        for buttons in &self.frames {
+            bytes.extend_from_slice(buttons);
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, EmuError> {
+        if bytes.len() < 8 || bytes[0..4] != MAGIC {
+            return Err(EmuError::InvalidMovie);
+        }
+        let len = u32::from_le_bytes(bytes[4..8].try_into().unwrap()) as usize;
+        let data = &bytes[8..];
+        if data.len() != PORTS * len {
+            return Err(EmuError::InvalidMovie);
+        }
+
+        let frames = data.chunks_exact(PORTS)
+            .map(|buttons| buttons.try_into().unwrap())
+            .collect();
+        Ok(Self { frames })
+    }
+}
+
+// What the console does with the input at the end of every frame
+#[derive(Default)]
+pub enum MovieState {
+    #[default]
+    Idle,
+    Recording(Movie),
+    Playing { movie: Movie, frame: usize },
+}
@@ -1,29 +1,112 @@
 #![allow(dead_code, unused, unused_variables, unused_imports, unused_comparisons)]
-use crate::interfaces::BusInterface;
+use crate::interfaces::{BusInterface, Cpu6502};
 use crate::bus::Bus;
-use crate::cpu::Olc6502;
-use crate::ppu::Olc2c02;
-use crate::cartridge::{EmptyCartridge, Cartridge};
+use crate::cpu::{Olc6502, StatusFlags, IRQ_SOURCE_FRAME_COUNTER, IRQ_SOURCE_DMC, IRQ_SOURCE_MAPPER};
+use crate::ppu::{Olc2c02, OamEntry, PpuTiming, RenderMode};
+use crate::palette::Palette;
+use crate::apu::{Channel, ChannelState};
+use crate::audio::{AudioFilter, BlipBuffer, ExpansionChip, CPU_CLOCK_RATE, DEFAULT_SAMPLE_RATE, SAMPLE_RATES};
+use crate::clock::{MasterClock, TvSystem};
+use crate::controller::DeviceKind;
+use crate::movie::{Movie, MovieState, PORTS};
+use crate::slots::{SaveSlots, SlotInfo, timestamp};
+use crate::rewind::RewindBuffer;
+use crate::cartridge::{EmptyCartridge, Cartridge, RomInfo};
+use crate::debugger::{Debugger, BreakReason};
+use crate::error::EmuError;
+use crate::recorder::BusRecorder;
+use crate::mapper::MapperRegistry;
+use crate::rom_db::{RomDatabase, crc32};
+use crate::functional_test::{TestStatus, read_test_status};
 
-pub struct Nes {
-    cpu:                  Olc6502,
+// The CPU core is pluggable, Olc6502 is used unless another Cpu6502 is given
+pub struct Nes<C: Cpu6502 = Olc6502> {
+    cpu:                  C,
     bus:                  Bus,
-    system_clock_counter: u32,
+    master_clock:         MasterClock,
+    forced_tv_system:     Option<TvSystem>, // None follows the ROM header
+    warn_on_rom_write:    bool,
+    mappers:              MapperRegistry,
+    rom_db:               RomDatabase,
+    rom_info:             Option<RomInfo>,
+    palette:              Palette,
+    audio:                BlipBuffer,
+    movie:                MovieState,
+    run_ahead:            u8,
+    replaying:            bool, // frames run ahead or replayed for rewind are neither heard nor recorded
+    slots:                SaveSlots,
+    rewind:               RewindBuffer,
+    paused:               bool,
+}
+
+// Everything needed to go back to a point in time
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Snapshot {
+    rom_crc32:            u32, // 0 without a cartridge
+    cpu:                  Vec<u8>,
+    bus:                  Vec<u8>,
+    master_clock:         MasterClock,
+}
+
+// Save state file layout, little endian:
+//     0 -> 3     : "NSS" 0x1A
+//     4 -> 7     : format version, states of other versions are refused
+//     8 -> 61447 : thumbnail, the frame at half size as RGBA8888
+//     61448 ->   : the snapshot, bincode
+const SAVE_STATE_MAGIC:   [u8; 4] = *b"NSS\x1A";
+const SAVE_STATE_VERSION: u32     = 2;
+pub const THUMBNAIL_WIDTH:  usize = 128;
+pub const THUMBNAIL_HEIGHT: usize = 120;
+const THUMBNAIL_SIZE:     usize   = 4 * THUMBNAIL_WIDTH * THUMBNAIL_HEIGHT;
+
+// The thumbnail and the snapshot of a save state of this version
+fn split_save_state(state: &[u8]) -> Result<(&[u8], &[u8]), EmuError> {
+    if state.len() < 8 || state[0..4] != SAVE_STATE_MAGIC {
+        return Err(EmuError::InvalidSaveState);
+    }
+    let version = u32::from_le_bytes(state[4..8].try_into().unwrap());
+    if version != SAVE_STATE_VERSION {
+        return Err(EmuError::SaveStateVersion(version, SAVE_STATE_VERSION));
+    }
+    if state.len() < 8 + THUMBNAIL_SIZE {
+        return Err(EmuError::InvalidSaveState);
+    }
+    Ok(state[8..].split_at(THUMBNAIL_SIZE))
 }
 
 impl Nes {
     pub fn new() -> Self {
+        Self::with_cpu(Olc6502::new())
+    }
+}
+
+impl<C: Cpu6502> Nes<C> {
+    pub fn with_cpu(cpu: C) -> Self {
         Self {
-            cpu:                  Olc6502::new(),
+            cpu,
             bus:                  Bus::new(Box::new(EmptyCartridge)),
-            system_clock_counter: 0,
+            master_clock:         MasterClock::new(TvSystem::Ntsc),
+            forced_tv_system:     None,
+            warn_on_rom_write:    false,
+            mappers:              MapperRegistry::new(),
+            rom_db:               RomDatabase::new(),
+            rom_info:             None,
+            palette:              Palette::default(),
+            audio:                BlipBuffer::new(CPU_CLOCK_RATE, DEFAULT_SAMPLE_RATE),
+            movie:                MovieState::Idle,
+            run_ahead:            0,
+            replaying:            false,
+            slots:                SaveSlots::new(),
+            rewind:               RewindBuffer::new(0),
+            paused:               false,
         }
     }
 
     pub fn reset(&mut self) {
         self.bus.reset();
         self.cpu.reset(&mut self.bus);
-        self.system_clock_counter = 0; 
+        self.master_clock.reset();
     }
 
     pub fn cpu_clock(&mut self) {
@@ -31,55 +114,136 @@ impl Nes {
     }
 
     pub fn clock(&mut self) {
+        let frame = self.bus.ppu.frame_count();
         self.bus.clock();
+        if self.bus.ppu.frame_count() != frame && !self.replaying {
+            self.end_movie_frame();
+        }
 
-        if self.system_clock_counter % 3 == 0 {
-            // Once a DMA transfer is requested, we wait until the correct clock cycle required by the hardware and then start the transfer
-            // We read on even cycles and write on odd cycles until we are done
-            if self.bus.dma_transfer {
-                if self.bus.dma_dummy {
-                    if self.system_clock_counter % 2 == 1 {
-                        self.bus.dma_dummy = false;
-                    }
-                }
-                else // if self.bus.dma_dummy
-                {
-                    // On even cycles, read data from the CPU 
-                    if self.system_clock_counter % 2 == 0 {
-                        let addr     = ((self.bus.dma_page as u16) << 8) | self.bus.dma_addr as u16;
-                        let data      = self.bus.read(addr, false);
-                        self.bus.dma_data = data;
-                    }
-                    // On odd cycles, write to the PPU's memory 
-                    else {
-                        let addr      = self.bus.dma_addr;
-                        let data      = self.bus.dma_data;
-                        self.bus.ppu.oam.write(addr, data); 
-                        self.bus.dma_addr = self.bus.dma_addr.wrapping_add(1);
-
-                        // We know that the transfer has finished if dma_addr is zero again because it has wrapped around after 256 cycles
-                        if self.bus.dma_addr == 0x00 {
-                            self.bus.dma_transfer = false; 
-                            self.bus.dma_dummy    = true;
-                        }
-                    }
-                }                
-            } 
-            else // if self.bus.dma_transfer {
-            {
+        if self.master_clock.tick() {
+            self.bus.recorder.cycle = self.cpu.get_counters().0;
+            self.bus.clock_cartridge();
+            self.bus.clock_apu();
+            if !self.replaying {
+                let amplitude = self.bus.audio_output();
+                self.audio.clock(amplitude);
+            }
+
+            // While an OAM DMA transfer is running the CPU is halted, the
+            // transfer needs to know whether this is an even or odd CPU cycle
+            if self.bus.oam_dma.is_active() {
+                self.bus.clock_oam_dma(self.master_clock.odd_cpu_cycle());
+            } else {
+                // A DMC sample fetch holds RDY low and only counts the cycles
+                // the CPU actually spends halted
+                let odd_cycle = self.master_clock.odd_cpu_cycle();
+                self.cpu.set_rdy_line(!self.bus.dmc_dma.is_active());
                 self.cpu.clock(&mut self.bus);
+
+                if self.bus.dmc_dma.is_active() && self.cpu.is_stalled() {
+                    self.bus.clock_dmc_dma(odd_cycle);
+                }
             }
         }
 
-        if self.bus.ppu.nmi {
-            self.bus.ppu.nmi = false; 
-            self.cpu.nmi(&mut self.bus);
+        self.cpu.set_nmi_line(self.bus.ppu.nmi_line());
+        if self.bus.cartridge_irq() {
+            self.cpu.assert_irq(IRQ_SOURCE_MAPPER);
+        } else {
+            self.cpu.ack_irq(IRQ_SOURCE_MAPPER);
+        }
+        if self.bus.frame_irq() {
+            self.cpu.assert_irq(IRQ_SOURCE_FRAME_COUNTER);
+        } else {
+            self.cpu.ack_irq(IRQ_SOURCE_FRAME_COUNTER);
         }
+        if self.bus.dmc_irq() {
+            self.cpu.assert_irq(IRQ_SOURCE_DMC);
+        } else {
+            self.cpu.ack_irq(IRQ_SOURCE_DMC);
+        }
+    }
 
-        self.system_clock_counter += 1;
+    // Records the buttons the frame that just ended was played with, or sets
+    // the ones of the next frame from the movie
+    fn end_movie_frame(&mut self) {
+        match &mut self.movie {
+            MovieState::Idle => {},
+            MovieState::Recording(movie) => {
+                movie.push(std::array::from_fn(|port| self.bus.controllers().buttons(port)));
+            },
+            MovieState::Playing { movie, frame } => {
+                *frame += 1;
+                let buttons = movie.frame(*frame);
+                self.play_movie_frame(buttons);
+            },
+        }
     }
 
-    pub fn run_frame(&mut self) {
+    fn play_movie_frame(&mut self, buttons: Option<[u8; PORTS]>) {
+        match buttons {
+            Some(buttons) => {
+                for (port, &buttons) in buttons.iter().enumerate() {
+                    self.bus.set_buttons(port, buttons);
+                }
+            },
+            None => self.stop_movie(),
+        }
+    }
+
+    // Clocks CPU, PPU and APU until the PPU finished the next frame. A frame
+    // completed by clock() and not taken yet doesn't count. Returns the number
+    // of audio samples ready for take_audio_samples().
+    //
+    // While paused nothing is emulated, the picture stays and a frame of the
+    // held audio output is delivered so that the host's audio keeps running.
+    pub fn run_frame(&mut self) -> usize {
+        if self.paused {
+            self.idle_frame();
+            return self.audio.samples_available();
+        }
+        self.advance_one_frame()
+    }
+
+    // Stops run_frame() from emulating, clock() and the debugger's stepping
+    // still work
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    // Runs one frame like run_frame(), also while paused, which it stays
+    pub fn advance_one_frame(&mut self) -> usize {
+        self.bus.ppu.frame_complete = false;
+        if self.rewind.capacity() > 0 {
+            self.push_rewind_state();
+        }
+        self.emulate_frame();
+        if self.run_ahead > 0 {
+            self.run_ahead_frames();
+        }
+        self.audio.samples_available()
+    }
+
+    // The APU's output doesn't change while paused, the high-pass filters
+    // take it down to silence without a click
+    fn idle_frame(&mut self) {
+        let tv_system = self.tv_system();
+        let cycles    = (tv_system.cpu_clock_rate() / tv_system.frame_rate()).round() as usize;
+        let amplitude = self.bus.audio_output();
+        for _ in 0..cycles {
+            self.audio.clock(amplitude);
+        }
+    }
+
+    fn emulate_frame(&mut self) {
         while !self.bus.ppu.frame_complete {
             self.clock();  // advances PPU + CPU timing
         }
@@ -87,30 +251,461 @@ impl Nes {
         self.bus.ppu.frame_complete = false;
     }
 
-    pub fn insert_cartridge(&mut self, cartridge_data: &[u8]) -> Result<(), String> {
-        let cart = Cartridge::from_bytes(cartridge_data)?;
+    // Run-ahead hides the frames of lag between a button press and the game
+    // reacting to it. The console is saved, the next frames are run with the
+    // same buttons and only the picture of the last one is kept when going
+    // back. https://docs.libretro.com/guides/runahead/
+    pub fn set_run_ahead(&mut self, frames: u8) -> Result<(), EmuError> {
+        if frames > 0 && self.cpu.save_state().is_empty() {
+            return Err(EmuError::SaveStatesUnsupported);
+        }
+        self.run_ahead = frames;
+        Ok(())
+    }
+
+    pub fn run_ahead(&self) -> u8 {
+        self.run_ahead
+    }
+
+    #[cfg(feature = "serde")]
+    fn run_ahead_frames(&mut self) {
+        let snapshot = self.snapshot();
+
+        self.replaying = true;
+        for _ in 0..self.run_ahead {
+            self.emulate_frame();
+        }
+        self.replaying = false;
+
+        self.restore_snapshot(&snapshot).expect("the console loads its own snapshot");
+    }
+
+    #[cfg(not(feature = "serde"))]
+    fn run_ahead_frames(&mut self) {}
+
+    // Keeps the state before each of the last frames run by run_frame(), 0
+    // turns rewinding off. See rewind.rs for how they are stored.
+    pub fn set_rewind(&mut self, frames: usize) -> Result<(), EmuError> {
+        if frames > 0 && self.cpu.save_state().is_empty() {
+            return Err(EmuError::SaveStatesUnsupported);
+        }
+        self.rewind = RewindBuffer::new(frames);
+        Ok(())
+    }
+
+    // Frames that can be rewound
+    pub fn rewind_frames(&self) -> usize {
+        self.rewind.len()
+    }
+
+    // Bytes the rewind history takes
+    pub fn rewind_memory_usage(&self) -> usize {
+        self.rewind.memory_usage()
+    }
+
+    // Goes back one frame and shows its picture, false once the history is
+    // used up. Called instead of run_frame() for as long as the player holds
+    // the rewind button.
+    #[cfg(feature = "serde")]
+    pub fn rewind(&mut self) -> bool {
+        let Some(state) = self.rewind.pop() else {
+            return false;
+        };
+
+        // The picture is made by replaying the frame silently from the state
+        // before it, with the buttons it was played with. The host's buttons
+        // are given back after it.
+        if let Some(previous) = self.rewind.newest() {
+            let held: [u8; PORTS] = std::array::from_fn(|port| self.bus.controllers().buttons(port));
+            self.restore_rewind_state(&previous[PORTS..]);
+            for (port, &buttons) in previous[..PORTS].iter().enumerate() {
+                self.bus.set_buttons(port, buttons);
+            }
+            self.replaying = true;
+            self.emulate_frame();
+            self.replaying = false;
+            for (port, &buttons) in held.iter().enumerate() {
+                self.bus.set_buttons(port, buttons);
+            }
+        }
+        self.restore_rewind_state(&state[PORTS..]);
+        true
+    }
+
+    #[cfg(not(feature = "serde"))]
+    pub fn rewind(&mut self) -> bool {
+        false
+    }
+
+    // The buttons of the frame about to run, then the snapshot
+    #[cfg(feature = "serde")]
+    fn push_rewind_state(&mut self) {
+        let mut state: Vec<u8> = (0..PORTS).map(|port| self.bus.controllers().buttons(port)).collect();
+        bincode::serialize_into(&mut state, &self.snapshot()).expect("the snapshot always serializes");
+        self.rewind.push(state);
+    }
+
+    #[cfg(not(feature = "serde"))]
+    fn push_rewind_state(&mut self) {}
+
+    #[cfg(feature = "serde")]
+    fn restore_rewind_state(&mut self, state: &[u8]) {
+        let snapshot: Snapshot = bincode::deserialize(state).expect("the rewind states are the console's own");
+        self.restore_snapshot(&snapshot).expect("the console loads its own snapshot");
+    }
+
+    #[cfg(feature = "serde")]
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            rom_crc32:            self.rom_info.as_ref().map_or(0, |info| info.crc32),
+            cpu:                  self.cpu.save_state(),
+            bus:                  self.bus.save_state(),
+            master_clock:         self.master_clock.clone(),
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    fn restore_snapshot(&mut self, snapshot: &Snapshot) -> Result<(), EmuError> {
+        self.cpu.load_state(&snapshot.cpu)?;
+        self.bus.load_state(&snapshot.bus)?;
+        if snapshot.master_clock.tv_system() != self.tv_system() {
+            self.audio.set_clock_rate(snapshot.master_clock.tv_system().cpu_clock_rate());
+        }
+        self.master_clock = snapshot.master_clock.clone();
+        Ok(())
+    }
+
+    // CPU, RAM, PPU, APU, mapper and controller ports, to be loaded into the
+    // same game with load_state(). The cartridge's ROM and the buttons the
+    // host holds are not part of it. Empty if the CPU core can't save states.
+    #[cfg(feature = "serde")]
+    pub fn save_state(&self) -> Vec<u8> {
+        if self.cpu.save_state().is_empty() {
+            return Vec::new();
+        }
+
+        let mut state = Vec::new();
+        state.extend_from_slice(&SAVE_STATE_MAGIC);
+        state.extend_from_slice(&SAVE_STATE_VERSION.to_le_bytes());
+        state.extend_from_slice(&self.thumbnail());
+        bincode::serialize_into(&mut state, &self.snapshot()).expect("the snapshot always serializes");
+        state
+    }
+
+    // A state that doesn't load leaves the console as it was
+    #[cfg(feature = "serde")]
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), EmuError> {
+        if self.cpu.save_state().is_empty() {
+            return Err(EmuError::SaveStatesUnsupported);
+        }
+
+        let (_, snapshot) = split_save_state(state)?;
+        let snapshot: Snapshot = bincode::deserialize(snapshot).map_err(|_| EmuError::InvalidSaveState)?;
+        if snapshot.rom_crc32 != self.rom_info.as_ref().map_or(0, |info| info.crc32) {
+            return Err(EmuError::SaveStateMismatch);
+        }
+
+        let backup = self.snapshot();
+        if let Err(err) = self.restore_snapshot(&snapshot) {
+            self.restore_snapshot(&backup).expect("the console loads its own snapshot");
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    // Saves the console into one of the slots::SLOTS slots, overwriting it
+    pub fn save_slot(&mut self, slot: usize) -> Result<(), EmuError> {
+        let state = self.save_state();
+        if state.is_empty() {
+            return Err(EmuError::SaveStatesUnsupported);
+        }
+        self.slots.store(slot, state, timestamp())
+    }
+
+    pub fn load_slot(&mut self, slot: usize) -> Result<(), EmuError> {
+        let state = self.slots.state(slot)?.to_vec();
+        self.load_state(&state)
+    }
+
+    // The frame the slot was saved at, THUMBNAIL_WIDTH x THUMBNAIL_HEIGHT
+    // RGBA8888 for a load-state menu
+    pub fn slot_thumbnail(&self, slot: usize) -> Result<Vec<u8>, EmuError> {
+        let (thumbnail, _) = split_save_state(self.slots.state(slot)?)?;
+        Ok(thumbnail.to_vec())
+    }
+
+    // The frame scaled down by averaging 2 x 2 pixels
+    fn thumbnail(&self) -> Vec<u8> {
+        let frame = self.frame_rgba();
+        let pixel = |x: usize, y: usize, channel: usize| frame[4 * (256 * y + x) + channel] as u16;
+
+        let mut thumbnail = Vec::with_capacity(THUMBNAIL_SIZE);
+        for y in 0..THUMBNAIL_HEIGHT {
+            for x in 0..THUMBNAIL_WIDTH {
+                for channel in 0..4 {
+                    let sum = pixel(2 * x, 2 * y, channel)     + pixel(2 * x + 1, 2 * y, channel)
+                            + pixel(2 * x, 2 * y + 1, channel) + pixel(2 * x + 1, 2 * y + 1, channel);
+                    thumbnail.push((sum / 4) as u8);
+                }
+            }
+        }
+        thumbnail
+    }
+
+    pub fn delete_slot(&mut self, slot: usize) -> Result<(), EmuError> {
+        self.slots.delete(slot)
+    }
+
+    // The slots in use and when they were saved
+    pub fn list_slots(&self) -> Vec<SlotInfo> {
+        self.slots.list()
+    }
+
+    // The save state in the slot, for a front end that keeps the slots
+    // across sessions
+    pub fn export_slot(&self, slot: usize) -> Result<Vec<u8>, EmuError> {
+        Ok(self.slots.state(slot)?.to_vec())
+    }
+
+    // Puts back an exported slot, timestamp in ms since the Unix epoch
+    pub fn import_slot(&mut self, slot: usize, state: Vec<u8>, timestamp: u64) -> Result<(), EmuError> {
+        self.slots.store(slot, state, timestamp)
+    }
+
+    // Without serde there is nothing to save, like for a CPU core without
+    // save states
+    #[cfg(not(feature = "serde"))]
+    pub fn save_state(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    #[cfg(not(feature = "serde"))]
+    pub fn load_state(&mut self, _state: &[u8]) -> Result<(), EmuError> {
+        Err(EmuError::SaveStatesUnsupported)
+    }
+
+    // The TAS primitive: sets the buttons of the four ports, runs exactly one
+    // frame, also while paused, and returns its hash. The same inputs from the same state always
+    // give the same hashes.
+    pub fn advance_frame_with_input(&mut self, inputs: [u8; PORTS]) -> u32 {
+        for (port, &buttons) in inputs.iter().enumerate() {
+            self.bus.set_buttons(port, buttons);
+        }
+        self.advance_one_frame();
+        self.frame_hash()
+    }
+
+    // CRC32 of the colour indices of the frame
+    pub fn frame_hash(&self) -> u32 {
+        crc32(&self.bus.ppu.get_frame_buffer())
+    }
+
+    // Runs until a breakpoint is hit, at the latest until the end of the current
+    // frame so that a front end stays responsive (None is returned then). An
+    // execution breakpoint stops right before its instruction, a memory
+    // breakpoint right after the instruction that accessed the memory.
+    pub fn run_until_break(&mut self) -> Option<BreakReason> {
+        // Don't stop at the breakpoint we are sitting on, at least one
+        // instruction has to start first
+        let mut started = false;
+        self.cpu.debugger().take_hit();
+
+        while !self.bus.ppu.frame_complete {
+            self.clock();
+
+            let at_boundary = self.cpu.get_remaining_cycles() == 0;
+            if !at_boundary {
+                started = true;
+            } else if started {
+                if let Some(reason) = self.cpu.debugger().take_hit() {
+                    return Some(reason);
+                }
+
+                let (_, _, _, _, pc, _) = self.cpu.get_registers();
+                if self.cpu.debugger().is_breakpoint(pc) {
+                    return Some(BreakReason::Execute(pc));
+                }
+            }
+        }
+
+        self.bus.ppu.frame_complete = false;
+        None
+    }
+
+    pub fn debugger(&mut self) -> &mut Debugger {
+        self.cpu.debugger()
+    }
+
+    pub fn bus_recorder(&mut self) -> &mut BusRecorder {
+        &mut self.bus.recorder
+    }
+
+    // NROM has no mapper registers, its PRG ROM is marked read-only
+    pub fn insert_cartridge(&mut self, cartridge_data: &[u8]) -> Result<(), EmuError> {
+        let cart = Cartridge::from_bytes_with_db(cartridge_data, &self.mappers, &self.rom_db)?;
+        let nrom = cart.mapper_id() == 0;
+        self.rom_info = Some(cart.info().clone());
         self.bus.insert_cartridge(Box::new(cart));
+        self.apply_tv_system();
+
+        self.bus.clear_read_only_regions();
+        if nrom {
+            self.bus.add_read_only_region(0x8000, 0xFFFF);
+        }
+        self.sync_rom_regions();
         Ok(())
     }
 
+    // NTSC, PAL or Dendy timing. None follows the header of the inserted
+    // cartridge, NTSC if it doesn't say.
+    pub fn set_tv_system(&mut self, tv_system: Option<TvSystem>) {
+        self.forced_tv_system = tv_system;
+        self.apply_tv_system();
+    }
+
+    pub fn tv_system(&self) -> TvSystem {
+        self.master_clock.tv_system()
+    }
+
+    // Frames per second the console runs at, what a front end paces itself to
+    pub fn frame_rate(&self) -> f64 {
+        self.tv_system().frame_rate()
+    }
+
+    fn apply_tv_system(&mut self) {
+        let tv_system = self.forced_tv_system
+            .or_else(|| self.rom_info.as_ref().map(|info| TvSystem::from_region(info.region)))
+            .unwrap_or_default();
+        if tv_system == self.tv_system() {
+            return;
+        }
+
+        self.master_clock = MasterClock::new(tv_system);
+        self.bus.ppu.set_tv_system(tv_system);
+        self.bus.apu_mut().set_tv_system(tv_system);
+        self.audio.set_clock_rate(tv_system.cpu_clock_rate());
+    }
+
+    // Header corrections used by insert_cartridge()
+    pub fn rom_db(&mut self) -> &mut RomDatabase {
+        &mut self.rom_db
+    }
+
+    // Header information of the inserted cartridge
+    pub fn rom_info(&self) -> Option<&RomInfo> {
+        self.rom_info.as_ref()
+    }
+
+    // Boards used by insert_cartridge(), custom boards are registered here
+    pub fn mappers(&mut self) -> &mut MapperRegistry {
+        &mut self.mappers
+    }
+
+    // Inserts the cartridge and switches the console on: nothing of the
+    // previous game survives in RAM, movies, save slots, the rewind history
+    // and queued audio are dropped. A
+    // ROM that can't be parsed leaves the running game alone.
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), EmuError> {
+        self.insert_cartridge(rom)?;
+        self.movie = MovieState::Idle;
+        self.slots.clear();
+        self.rewind.clear();
+        self.bus.clear_ram();
+        self.audio.clear();
+        self.reset();
+        Ok(())
+    }
+
+    pub fn add_read_only_region(&mut self, start: u16, end: u16) {
+        self.bus.add_read_only_region(start, end);
+        self.sync_rom_regions();
+    }
+
+    pub fn clear_read_only_regions(&mut self) {
+        self.bus.clear_read_only_regions();
+        self.sync_rom_regions();
+    }
+
+    // Debug mode that reports every write the bus drops, with the PC of the
+    // instruction that wrote. Writes to ROM are usually a mapper the game
+    // expects but the cartridge doesn't implement.
+    pub fn set_warn_on_rom_write(&mut self, enabled: bool) {
+        self.warn_on_rom_write = enabled;
+        self.sync_rom_regions();
+    }
+
+    // Hides the background or the sprites, for debugging and screenshots
+    pub fn set_layer_visibility(&mut self, background: bool, sprites: bool) {
+        self.bus.ppu.set_layer_visibility(background, sprites);
+    }
+
+    // Scanline rendering trades the dot accurate PPU for speed
+    pub fn set_render_mode(&mut self, mode: RenderMode) {
+        self.bus.ppu.set_render_mode(mode);
+    }
+
+    // Accuracy option: OAM that the PPU doesn't refresh decays into garbage
+    pub fn set_oam_decay(&mut self, enabled: bool) {
+        self.bus.ppu.set_oam_decay(enabled);
+    }
+
+    fn sync_rom_regions(&mut self) {
+        let regions = if self.warn_on_rom_write { self.bus.read_only_regions().to_vec() } else { Vec::new() };
+        self.cpu.debugger().set_rom_regions(regions);
+    }
+
     pub fn frame(&self) -> Vec<u8> {
         self.bus.ppu.get_frame_buffer()
     }
 
+    // The frame as RGBA8888 through the master palette
+    pub fn frame_rgba(&self) -> Vec<u8> {
+        self.palette.to_rgba(&self.bus.ppu.get_frame_buffer(), self.bus.ppu.get_emphasis())
+    }
+
+    // Replaces the built-in master palette with the contents of a .pal file
+    pub fn set_palette(&mut self, pal: &[u8]) -> Result<(), EmuError> {
+        self.palette = Palette::from_pal(pal)?;
+        Ok(())
+    }
+
+    // The 64 colours of the master palette as RGBA8888
+    pub fn palette_lut(&self) -> Vec<u8> {
+        self.palette.rgba_lut()
+    }
+
+    // Set when the PPU finished a frame, for front ends that clock the system
+    // themselves instead of calling run_frame
+    pub fn frame_complete(&self) -> bool {
+        self.bus.ppu.frame_complete
+    }
+
+    // The finished frame as RGBA8888, acknowledges it
+    pub fn take_frame_rgba(&mut self) -> Vec<u8> {
+        self.bus.ppu.frame_complete = false;
+        self.frame_rgba()
+    }
+
+    // The finished frame as colour indices, one byte per pixel, acknowledges it
+    pub fn take_frame_indexed(&mut self) -> Vec<u8> {
+        self.bus.ppu.frame_complete = false;
+        self.frame()
+    }
+
     pub fn step_instruction(&mut self) { 
         self.cpu.step_instruction(&mut self.bus);
      }
 
-    pub fn load_program(&mut self, bytes: &[u8], offset: u16) { 
-        for (i, byte) in bytes.iter().enumerate() {
-            let addr: u16 = offset.wrapping_add(i as u16);
-            self.bus.write(addr, *byte);
-        }
+    // The program has to fit into the internal RAM
+    pub fn load_program(&mut self, bytes: &[u8], offset: u16) -> Result<(), EmuError> { 
+        self.bus.load_ram(offset, bytes)?;
+
         // Set reset vector so CPU starts at offset
         self.bus.write(0xFFFC, (offset & 0x00FF) as u8);
         self.bus.write(0xFFFD, (offset >> 8) as u8);
 
         self.cpu.reset(&mut self.bus);
+        Ok(())
     }
 
     pub fn get_registers(&self) -> Vec<u32> {
@@ -126,6 +721,19 @@ impl Nes {
         ]
     }
 
+    pub fn set_registers(&mut self, a: u8, x: u8, y: u8, s: u8, pc: u16, p: u8) {
+        self.cpu.set_registers(a, x, y, s, pc, p);
+    }
+
+    pub fn set_pc(&mut self, pc: u16) {
+        self.cpu.set_pc(pc);
+    }
+
+    pub fn get_flags(&self) -> StatusFlags {
+        let (_, _, _, _, _, status) = self.cpu.get_registers();
+        StatusFlags::from_status(status)
+    }
+
     pub fn get_cpu_state(&self) -> Vec<u32> {
         let (fetched, addr_abs, addr_rel, opcode, cycles)= self.cpu.get_state();
 
@@ -138,10 +746,167 @@ impl Nes {
         ]
     }
 
-    pub fn get_ram(&self, start: u16, len: usize) -> Vec<u8> {
+    // Scanline, dot and frame the PPU is at
+    pub fn get_ppu_timing(&self) -> PpuTiming {
+        self.bus.ppu.get_timing()
+    }
+
+    // (total CPU cycles, total instructions)
+    pub fn get_counters(&self) -> (u64, u64) {
+        self.cpu.get_counters()
+    }
+
+    pub fn set_cpu_state(&mut self, fetched: u8, addr_abs: u16, addr_rel: u16, opcode: u8, cycles: u8) {
+        self.cpu.set_state(fetched, addr_abs, addr_rel, opcode, cycles);
+    }
+
+    pub fn get_ram(&self, start: u16, len: usize) -> Result<Vec<u8>, EmuError> {
         self.bus.get_ram(start, len)
     }
 
+    // Anything in the CPU address space, as a memory viewer would show it
+    pub fn peek_memory(&mut self, start: u16, len: usize) -> Result<Vec<u8>, EmuError> {
+        if start as usize + len > 0x10000 {
+            return Err(EmuError::OutOfRange { start, len });
+        }
+        Ok((0..len).map(|i| self.bus.peek(start.wrapping_add(i as u16))).collect())
+    }
+
+    // Result of a blargg test ROM, None if the ROM doesn't report one (yet)
+    pub fn get_test_status(&mut self) -> Option<TestStatus> {
+        read_test_status(&mut self.bus)
+    }
+
+    // Battery backed PRG RAM, what a .sav file holds. None if the cartridge
+    // has no battery.
+    pub fn export_sram(&self) -> Option<Vec<u8>> {
+        self.bus.battery_ram().map(|ram| ram.to_vec())
+    }
+
+    // Restores the battery backed PRG RAM, best done before the reset so the
+    // game finds its saves when it boots
+    pub fn import_sram(&mut self, data: &[u8]) -> Result<(), EmuError> {
+        self.bus.load_battery_ram(data)
+    }
+
+    // Output of the APU's channels, 0.0 - 1.0
+    pub fn apu_output(&self) -> f32 {
+        self.bus.apu_output()
+    }
+
+    // Mutes or unmutes one of the channels in the audio output
+    pub fn set_channel_enabled(&mut self, channel: Channel, enabled: bool) {
+        self.bus.apu_mut().set_channel_enabled(channel, enabled);
+    }
+
+    // What a channel is playing right now, polled once per frame by visualizers
+    pub fn channel_state(&self, channel: Channel) -> Option<ChannelState> {
+        self.bus.apu().channel_state(channel)
+    }
+
+    pub fn set_apu_volume(&mut self, volume: f32) {
+        self.bus.mixer.set_apu_volume(volume);
+    }
+
+    // Volume of a cartridge sound chip relative to the APU, 0.5 by default
+    pub fn set_expansion_volume(&mut self, chip: ExpansionChip, volume: f32) {
+        self.bus.mixer.set_volume(chip, volume);
+    }
+
+    // The sound chip of the inserted cartridge
+    pub fn expansion_chip(&self) -> Option<ExpansionChip> {
+        self.bus.mixer.chip()
+    }
+
+    // The NES's high-pass and low-pass filters on the output, all enabled by default
+    pub fn set_audio_filter(&mut self, filter: AudioFilter, enabled: bool) {
+        self.audio.filters.set_enabled(filter, enabled);
+    }
+
+    // Records the audio output until stop_audio_recording()
+    pub fn start_audio_recording(&mut self) {
+        self.audio.start_recording();
+    }
+
+    // The recorded audio as a WAV file, empty if no recording was running
+    pub fn stop_audio_recording(&mut self) -> Vec<u8> {
+        self.audio.stop_recording().unwrap_or_default()
+    }
+
+    // Resets the console and records the buttons of every frame from then on
+    pub fn start_recording(&mut self) {
+        self.reset();
+        self.movie = MovieState::Recording(Movie::new());
+    }
+
+    // The recorded movie, empty if no recording was running
+    pub fn stop_recording(&mut self) -> Movie {
+        match std::mem::take(&mut self.movie) {
+            MovieState::Recording(movie) => movie,
+            other => {
+                self.movie = other;
+                Movie::new()
+            },
+        }
+    }
+
+    // Resets the console and replays the movie's buttons. The host's input is
+    // overwritten every frame until the movie ends, then all buttons are released.
+    pub fn play_movie(&mut self, movie: Movie) {
+        self.reset();
+        let first  = movie.frame(0);
+        self.movie = MovieState::Playing { movie, frame: 0 };
+        self.play_movie_frame(first);
+    }
+
+    pub fn stop_movie(&mut self) {
+        if let MovieState::Playing { .. } = self.movie {
+            self.movie = MovieState::Idle;
+            for port in 0..PORTS {
+                self.bus.set_buttons(port, 0);
+            }
+        }
+    }
+
+    pub fn is_playing_movie(&self) -> bool {
+        matches!(self.movie, MovieState::Playing { .. })
+    }
+
+    // Host rate the audio is resampled to, 44100 Hz unless changed
+    pub fn set_sample_rate(&mut self, sample_rate: u32) -> Result<(), EmuError> {
+        if !SAMPLE_RATES.contains(&sample_rate) {
+            return Err(EmuError::UnsupportedSampleRate(sample_rate));
+        }
+        self.audio.set_sample_rate(sample_rate);
+        Ok(())
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.audio.sample_rate()
+    }
+
+    // Audio at the host rate produced since the last call
+    pub fn take_audio_samples(&mut self) -> Vec<f32> {
+        self.audio.take_samples()
+    }
+
+    pub fn audio_samples_available(&self) -> usize {
+        self.audio.samples_available()
+    }
+
+    // Output of the cartridge's sound chip, 0.0 for boards without one
+    pub fn expansion_audio(&self) -> f32 {
+        self.bus.cartridge_audio()
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.cpu.is_halted()
+    }
+
+    pub fn unjam(&mut self) {
+        self.cpu.unjam();
+    }
+
 
     pub fn get_pattern_table(&self, table: u8, palette: u8) -> Vec<u8> {
         self.bus.get_pattern_table(table, palette)
@@ -151,6 +916,39 @@ impl Nes {
         self.bus.get_name_table()
     }
 
+    // The 64 sprites in OAM, for a sprite debugger
+    pub fn get_oam_entries(&self) -> Vec<OamEntry> {
+        self.bus.ppu.get_oam_entries()
+    }
+
+    pub fn get_sprite_thumbnail(&self, index: u8) -> Vec<u8> {
+        self.bus.get_sprite_thumbnail(index)
+    }
+
+    // buttons holds the controller::BUTTON_* bits
+    pub fn set_buttons(&mut self, player: usize, buttons: u8) {
+        self.bus.set_buttons(player, buttons);
+    }
+
+    pub fn buttons(&self, port: usize) -> u8 {
+        self.bus.controllers().buttons(port)
+    }
+
+    // A single button of the device in port, one of the controller::BUTTON_* bits
+    pub fn set_button_state(&mut self, port: usize, button: u8, pressed: bool) {
+        self.bus.set_button_state(port, button, pressed);
+    }
+
+    // Ports 2 and 3 are behind the Four Score
+    pub fn connect_device(&mut self, port: usize, kind: DeviceKind) -> Result<(), EmuError> {
+        self.bus.connect_device(port, kind)
+    }
+
+    // Four player adapter, for games like Gauntlet II
+    pub fn set_four_score(&mut self, connected: bool) {
+        self.bus.set_four_score(connected);
+    }
+
     pub fn set_controller(&mut self, i: usize, x: bool, z: bool, a: bool, s: bool, up: bool, down: bool, left: bool, right: bool) {
         self.bus.set_controller(i, x, z, a, s, up, down, left, right);
     } 
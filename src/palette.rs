@@ -0,0 +1,82 @@
+use crate::error::EmuError;
+
+// Master palette
+// The PPU doesn't output RGB but one of 64 colours picked from the palette
+// RAM, the frame buffer holds these indices. The RGB values are the ones
+// javidx9's emulator uses, the same the web frontend had.
+// https://www.nesdev.org/wiki/PPU_palettes
+pub const NES_PALETTE: [[u8; 3]; 64] = [
+    [ 84,  84,  84], [  0,  30, 116], [  8,  16, 144], [ 48,   0, 136], [ 68,   0, 100], [ 92,   0,  48], [ 84,   4,   0], [ 60,  24,   0],
+    [ 32,  42,   0], [  8,  58,   0], [  0,  64,   0], [  0,  60,   0], [  0,  50,  60], [  0,   0,   0], [  0,   0,   0], [  0,   0,   0],
+    [152, 150, 152], [  8,  76, 196], [ 48,  50, 236], [ 92,  30, 228], [136,  20, 176], [160,  20, 100], [152,  34,  32], [120,  60,   0],
+    [ 84,  90,   0], [ 40, 114,   0], [  8, 124,   0], [  0, 118,  40], [  0, 102, 120], [  0,   0,   0], [  0,   0,   0], [  0,   0,   0],
+    [236, 238, 236], [ 76, 154, 236], [120, 124, 236], [176,  98, 236], [228,  84, 236], [236,  88, 180], [236, 106, 100], [212, 136,  32],
+    [160, 170,   0], [116, 196,   0], [ 76, 208,  32], [ 56, 204, 108], [ 56, 180, 204], [ 60,  60,  60], [  0,   0,   0], [  0,   0,   0],
+    [236, 238, 236], [168, 204, 236], [188, 188, 236], [212, 178, 236], [236, 174, 236], [236, 174, 212], [236, 180, 176], [228, 196, 144],
+    [204, 210, 120], [180, 222, 120], [168, 226, 144], [152, 226, 180], [160, 214, 228], [160, 162, 160], [  0,   0,   0], [  0,   0,   0],
+];
+
+// The master palette as a 64 entry RGBA8888 lookup table, for front ends that
+// take the indexed frame and map the colours on the GPU
+pub fn rgba_lut() -> Vec<u8> {
+    NES_PALETTE.iter().flat_map(|&[r, g, b]| [r, g, b, 0xFF]).collect()
+}
+
+// Converts colour indices to RGBA8888, 4 bytes per pixel
+pub fn to_rgba(indices: &[u8]) -> Vec<u8> {
+    indices.iter()
+        .flat_map(|&index| {
+            let [r, g, b] = NES_PALETTE[(index & 0x3F) as usize];
+            [r, g, b, 0xFF]
+        })
+        .collect()
+}
+
+// A master palette loaded from a .pal file as FCEUX and Mesen write them: 64 RGB
+// triples, or 512 that hold the colours for each of the 8 combinations of the
+// emphasis bits, 64 after another
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Palette {
+    colours: Vec<[u8; 3]>,
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self { colours: NES_PALETTE.to_vec() }
+    }
+}
+
+impl Palette {
+    pub fn from_pal(bytes: &[u8]) -> Result<Self, EmuError> {
+        if bytes.len() != 64 * 3 && bytes.len() != 512 * 3 {
+            return Err(EmuError::InvalidPalette(bytes.len()));
+        }
+        Ok(Self { colours: bytes.chunks_exact(3).map(|rgb| [rgb[0], rgb[1], rgb[2]]).collect() })
+    }
+
+    // Colour index and emphasis bits (PPUMASK >> 5) to RGB. Palettes without
+    // emphasis colours ignore the emphasis.
+    pub fn rgb(&self, index: u8, emphasis: u8) -> [u8; 3] {
+        let emphasis = if self.colours.len() == 512 { (emphasis & 0x07) as usize } else { 0 };
+        self.colours[emphasis * 64 + (index & 0x3F) as usize]
+    }
+
+    // The frame as RGBA8888, emphasis holds the emphasis bits of each scanline
+    pub fn to_rgba(&self, indices: &[u8], emphasis: &[u8]) -> Vec<u8> {
+        indices.chunks(256)
+            .zip(emphasis.iter().chain(std::iter::repeat(&0)))
+            .flat_map(|(line, &emphasis)| line.iter().flat_map(move |&index| {
+                let [r, g, b] = self.rgb(index, emphasis);
+                [r, g, b, 0xFF]
+            }))
+            .collect()
+    }
+
+    // The 64 colours without emphasis as RGBA8888
+    pub fn rgba_lut(&self) -> Vec<u8> {
+        (0..64).flat_map(|index| {
+            let [r, g, b] = self.rgb(index, 0);
+            [r, g, b, 0xFF]
+        }).collect()
+    }
+}
@@ -1,4 +1,7 @@
 use crate::{interfaces::{CartridgeInterface, PpuInterface}};
+use crate::palette;
+use crate::clock::TvSystem;
+use wasm_bindgen::prelude::*;
 
 pub const SCREEN_W: usize = 256;
 pub const SCREEN_H: usize = 240;
@@ -7,6 +10,7 @@ pub const SCREEN_H: usize = 240;
 // Javidx9 goes via bitfields here but the bit gymnastics in Rust are bit too much for me
 // The following is much nicer than operating on a single u8 in Rust 
 #[derive(Copy, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 struct Loopy {
     coarse_x:    u8,      // 0..31
     coarse_y:    u8,      // 0..31
@@ -36,6 +40,7 @@ impl Loopy {
 }
 
 #[derive(Copy, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 struct Sprite {
     x:         u8, 
     y:         u8,
@@ -58,7 +63,7 @@ impl Sprite {
         match offset {
             0 => self.y         = data,
             1 => self.id        = data,
-            2 => self.attribute = data,
+            2 => self.attribute = data & 0xE3, // bits 2 -> 4 don't exist
             3 => self.x         = data,
             _ => unreachable!(),
         }
@@ -66,7 +71,9 @@ impl Sprite {
 }
 
 #[derive(Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SpriteArray<const N: usize> {
+    #[cfg_attr(feature = "serde", serde(with = "serde_big_array::BigArray"))]
     sprites: [Sprite; N],    
 }
 
@@ -101,18 +108,89 @@ impl<const N: usize> SpriteArray<N> {
     }
 }
 
+// Where in the frame the PPU is, for debuggers and front ends that sync to it
+#[wasm_bindgen]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PpuTiming {
+    pub scanline:          u16,  // 0..261 (0..311 on PAL and Dendy), the last one is the pre-render line
+    pub dot:               u16,  // 0..340
+    pub frame:             u64,  // frames completed since power on
+    pub rendering_enabled: bool,
+}
+
+// A sprite as a sprite debugger shows it, decoded from its 4 bytes of OAM
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct OamEntry {
+    pub x:                 u8,
+    pub y:                 u8,   // one less than the first scanline the sprite is on
+    pub tile:              u8,
+    pub palette:           u8,   // 0..3, of the sprite palettes
+    pub flip_horizontal:   bool,
+    pub flip_vertical:     bool,
+    pub behind_background: bool,
+}
+
+impl From<Sprite> for OamEntry {
+    fn from(sprite: Sprite) -> Self {
+        Self {
+            x:                 sprite.x,
+            y:                 sprite.y,
+            tile:              sprite.id,
+            palette:           sprite.attribute & 0x03,
+            flip_horizontal:   sprite.attribute & 0x40 != 0,
+            flip_vertical:     sprite.attribute & 0x80 != 0,
+            behind_background: sprite.attribute & 0x20 != 0,
+        }
+    }
+}
+
 pub type OAM = SpriteArray<64>;
 pub type SpriteScanline = SpriteArray<8>; 
 
+// The default mode renders dot by dot like the real PPU. The scanline mode
+// draws a whole line at once, which is a lot faster but misses changes to the
+// registers in the middle of a line.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RenderMode {
+    Dot,
+    Scanline,
+}
+
+#[cfg(feature = "serde")]
+fn blank_screen() -> Box<[u8; SCREEN_H*SCREEN_W]> {
+    Box::new([0x00; SCREEN_H*SCREEN_W])
+}
+
+#[cfg(feature = "serde")]
+fn no_emphasis() -> [u8; SCREEN_H] {
+    [0x00; SCREEN_H]
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Olc2c02 {
-    screen:                [u8; SCREEN_H*SCREEN_W],   // Frame buffer
+    // The frame buffer is output rather than state, it is redrawn by the next
+    // frame. Kept on the heap so that the PPU is cheap to move around.
+    #[cfg_attr(feature = "serde", serde(skip, default = "blank_screen"))]
+    screen:                Box<[u8; SCREEN_H*SCREEN_W]>, // Frame buffer
+    #[cfg_attr(feature = "serde", serde(skip, default = "no_emphasis"))]
+    line_emphasis:         [u8; SCREEN_H],            // emphasis bits of PPUMASK each scanline was drawn with
+    #[cfg_attr(feature = "serde", serde(with = "serde_big_array::BigArray"))]
     table_name:            [u8; 2*1024],              // 2 KB of physical VRAM for the name tables
     table_palette:         [u8; 32],                  // 32 Bytes physical VRAM for the palletes
+    #[cfg_attr(feature = "serde", serde(with = "serde_big_array::BigArray"))]
     table_pattern:         [u8; 2*4096],              // 8 KB of physical VRAM for the patterns
     scanline:               u16, 
     cycle:                  u16, 
     pub frame_complete:     bool,
+    odd_frame:              bool,
+    tv_system:              TvSystem,
+    dot_counter:            u64,
+    frame_counter:          u64,
     noise_state:            u32,
+    render_mode:            RenderMode,
+    show_background_layer:  bool,
+    show_sprite_layer:      bool,
 
     // Registers
     status:                 u8,
@@ -124,7 +202,8 @@ pub struct Olc2c02 {
 
     address_latch:          u8, 
     ppu_data_buffer:        u8, 
-    pub nmi:                bool, 
+    io_latch:               u8,                   // open bus of the CPU facing data bus
+    io_latch_refresh:       [u64; 8],             // dot each bit of io_latch was last driven
 
 	// Background rendering
     bg_shifter_pattern_hi:  u16,
@@ -139,6 +218,8 @@ pub struct Olc2c02 {
     // Sprite memory is public because we need access from the bus for the DMA operation
     pub oam:               OAM,
     pub oam_addr:          u8,
+    oam_decay:             bool,
+    oam_row_refresh:       [u64; 32],             // dot of the last access to each 8 byte row
 
     sprite_scanline:       SpriteScanline,
     sprite_count:          u8,
@@ -182,18 +263,32 @@ impl Olc2c02 {
     pub const CTRL_SLAVE_MODE:              u8 = 1 << 6;
     pub const CTRL_ENABLE_NMI:              u8 = 1 << 7;
 
+    // Unrefreshed OAM loses its contents after about 3000 CPU cycles
+    pub const OAM_DECAY_DOTS:               u64 = 3 * 3000;
+
+    // Bits of the I/O latch that are not driven fade to 0 after about 600 ms
+    pub const IO_LATCH_DECAY_DOTS:          u64 = 3_200_000;
+
 
 
     pub fn new() -> Self {
         Self {     
-            screen:                 [0x00; SCREEN_H * SCREEN_W],
+            screen:                 Box::new([0x00; SCREEN_H * SCREEN_W]),
+            line_emphasis:          [0x00; SCREEN_H],
             table_name:             [0x00; 2*1024], 
             table_palette:          [0x00; 32],
             table_pattern:          [0x00; 2*4096],    
             scanline:                0, 
             cycle:                   0,
             frame_complete:          false,
+            odd_frame:               false,
+            tv_system:               TvSystem::Ntsc,
+            dot_counter:             0,
+            frame_counter:           0,
             noise_state:             0x12345678,
+            render_mode:             RenderMode::Dot,
+            show_background_layer:   true,
+            show_sprite_layer:       true,
             status:                  0x00,
             mask:                    0x00,
             control:                 0x00,
@@ -202,7 +297,8 @@ impl Olc2c02 {
             fine_x:                  0x00,
             address_latch:           0x00, 
             ppu_data_buffer:         0x00, 
-            nmi:                     false, 
+            io_latch:                0x00,
+            io_latch_refresh:        [0; 8],
             bg_shifter_pattern_hi:   0x0000,
             bg_shifter_pattern_lo:   0x0000,
             bg_shifter_attrib_hi:    0x0000,
@@ -213,6 +309,8 @@ impl Olc2c02 {
             bg_next_tile_attrib:     0x00,
             oam:                     OAM::default(),
             oam_addr:                0x00,
+            oam_decay:               false,
+            oam_row_refresh:         [0; 32],
             sprite_scanline:         SpriteScanline::default(),
             sprite_count:            0x00,
             sp_shifter_pattern_hi:  [0x0000; 8],
@@ -228,6 +326,102 @@ impl Olc2c02 {
         }
     }
 
+    // The loopy registers only move and the PPU only fetches while either layer
+    // is switched on, with both off the CPU has free access to the VRAM
+    fn rendering_enabled(&self) -> bool {
+        self.mask & (Olc2c02::MASK_RENDER_BACKGROUND | Olc2c02::MASK_RENDER_SPRITES) != 0
+    }
+
+    // Rendering is switched on and the PPU is not in vertical blank
+    fn rendering_active(&self) -> bool {
+        self.rendering_enabled() && (self.scanline < 240 || self.scanline == self.prerender_line())
+    }
+
+    // The region decides the number of scanlines and where vertical blank starts
+    pub fn set_tv_system(&mut self, tv_system: TvSystem) {
+        self.tv_system = tv_system;
+    }
+
+    pub fn tv_system(&self) -> TvSystem {
+        self.tv_system
+    }
+
+    fn prerender_line(&self) -> u16 {
+        self.tv_system.scanlines() - 1
+    }
+
+    pub fn set_render_mode(&mut self, mode: RenderMode) {
+        self.render_mode = mode;
+    }
+
+    pub fn get_render_mode(&self) -> RenderMode {
+        self.render_mode
+    }
+
+    // Debug switches that leave a layer out of the picture whatever PPUMASK
+    // says. Emulation is not affected, sprite zero hits still happen.
+    pub fn set_layer_visibility(&mut self, background: bool, sprites: bool) {
+        self.show_background_layer = background;
+        self.show_sprite_layer     = sprites;
+    }
+
+    // OAM is dynamic RAM that is only refreshed by the sprite evaluation while
+    // rendering. With decay emulation a row that has not been accessed for too
+    // long is garbage, as on the real chip, games have to rewrite OAM after
+    // switching rendering off for a while.
+    pub fn set_oam_decay(&mut self, enabled: bool) {
+        self.oam_decay = enabled;
+    }
+
+    fn refresh_oam_row(&mut self, addr: u8) {
+        let row = (addr >> 3) as usize;
+        if self.oam_decay && self.dot_counter - self.oam_row_refresh[row] > Olc2c02::OAM_DECAY_DOTS {
+            for offset in 0..8 {
+                self.noise_state ^= self.noise_state << 13;
+                self.noise_state ^= self.noise_state >> 17;
+                self.noise_state ^= self.noise_state << 5;
+                self.oam.write((row * 8 + offset) as u8, self.noise_state as u8);
+            }
+        }
+        self.oam_row_refresh[row] = self.dot_counter;
+    }
+
+    // A write to OAMDATA, the OAM DMA writes through it as well
+    pub fn write_oam_data(&mut self, data: u8) {
+        // While rendering the write is dropped, but the address still moves
+        // on, glitching to the next sprite
+        if self.rendering_active() {
+            self.oam_addr = self.oam_addr.wrapping_add(4);
+            return;
+        }
+        self.refresh_oam_row(self.oam_addr);
+        self.oam.write(self.oam_addr, data);
+        self.oam_addr = self.oam_addr.wrapping_add(1);
+    }
+
+    // The PPU's data bus towards the CPU holds the last value driven on it.
+    // Reading a write-only register returns it, and so do the bits of
+    // PPUSTATUS and palette reads that aren't driven. Each bit decays on its
+    // own, 600 ms after it was last driven.
+    fn io_latch(&self) -> u8 {
+        let mut latch = self.io_latch;
+        for bit in 0..8 {
+            if self.dot_counter - self.io_latch_refresh[bit] > Olc2c02::IO_LATCH_DECAY_DOTS {
+                latch &= !(1 << bit);
+            }
+        }
+        latch
+    }
+
+    fn drive_io_latch(&mut self, data: u8, driven: u8) {
+        self.io_latch = (self.io_latch() & !driven) | (data & driven);
+        for bit in 0..8 {
+            if driven & (1 << bit) != 0 {
+                self.io_latch_refresh[bit] = self.dot_counter;
+            }
+        }
+    }
+
 	// Increment the background tile "pointer" one tile/column horizontally
     fn increment_scroll_x(&mut self) {
         if (self.mask & Olc2c02::MASK_RENDER_BACKGROUND != 0) || (self.mask & Olc2c02::MASK_RENDER_SPRITES != 0) {
@@ -311,18 +505,114 @@ impl Olc2c02 {
         }
     }
 
-    
+
+    // Pattern address of the low plane of a sprite's row on the current scanline
+    fn sprite_pattern_addr_lo(&self, sprite: Sprite) -> u16 {
+        let sprite_pattern_addr_lo: u16;
+        // 8x8 sprite mode
+        if (self.control & Olc2c02::CTRL_SPRITE_SIZE) == 0 {
+            // These 3 indices index into a 2D memory structure
+            // This is either 0k or 4k offset on the CPU bus
+            let offset1 = (((self.control & Olc2c02::CTRL_PATTERN_SPRITE) != 0) as u16) << 12; 
+            // << 4 = * 16 = each tile is 16 bytes in size 
+            let offset2 = (sprite.id as u16) << 4;        
+            // Which row of the tile are we currently in - Unsigned here because it should always be positive                                      
+            let offset3 = self.scanline - (sprite.y as u16);                                    
+
+            // Sprite is not flipped vertically, i.e. normal
+            if sprite.attribute & 0x80 == 0 {
+                sprite_pattern_addr_lo = offset1 | offset2 | offset3; 
+
+            } 
+            // Sprite is flipped vertically
+            // Sprite is not flipped vertically, i.e. normal
+            else 
+            {
+                sprite_pattern_addr_lo = offset1 | offset2 | (7 - offset3); 
+            }
+        } 
+        // 8x16 sprite mode
+        else 
+        {
+            
+            // Sprite is not flipped vertically, i.e. normal
+            if sprite.attribute & 0x80 == 0 {
+
+                // Read top half tile
+                if self.scanline - (sprite.y as u16) < 8 {
+                    
+                    let offset1 =  ((sprite.id & 0x01) as u16)      << 12; 
+                    let offset2 = (((sprite.id & 0xFE) as u16) + 0) <<  4;                                            
+                    let offset3 = (self.scanline - (sprite.y as u16)) & 0x0007;   
+
+                    sprite_pattern_addr_lo = offset1 | offset2 | offset3; 
+                } 
+                // Read bottom half tile
+                else 
+                {
+                    let offset1 =  ((sprite.id & 0x01) as u16)      << 12; 
+                    let offset2 = (((sprite.id & 0xFE) as u16) + 1) <<  4;                                            
+                    let offset3 = (self.scanline - (sprite.y as u16)) & 0x0007;   
+
+                    sprite_pattern_addr_lo = offset1 | offset2 | offset3; 
+
+                }
+
+            } 
+            // Sprite is flipped vertically
+            // Sprite is not flipped vertically, i.e. normal
+            else 
+            {
+                
+                // Read top half tile
+                if self.scanline - (sprite.y as u16) < 8 {
+                    
+                    let offset1 =  ((sprite.id & 0x01) as u16)      << 12; 
+                    let offset2 = (((sprite.id & 0xFE) as u16) + 1) <<  4;                                            
+                    let offset3 = 7 - ((self.scanline - (sprite.y as u16)) & 0x0007);   
+
+                    sprite_pattern_addr_lo = offset1 | offset2 | offset3; 
+                } 
+                // Read bottom half tile
+                else 
+                {
+                    let offset1 =  ((sprite.id & 0x01) as u16)      << 12; 
+                    let offset2 = (((sprite.id & 0xFE) as u16) + 0) <<  4;                                            
+                    let offset3 = 7  - ((self.scanline - (sprite.y as u16)) & 0x0007);   
+
+                    sprite_pattern_addr_lo = offset1 | offset2 | offset3; 
+
+                }
+            }
+            
+        } // End of if for setting sprite_pattern_addr_lo
+
+        sprite_pattern_addr_lo
+    }
+
+    // Pattern fetch while rendering, mappers like the MMC2 switch banks
+    // depending on the tiles the PPU fetches
+    fn fetch_pattern(&self, addr: u16, cartridge: &mut dyn CartridgeInterface) -> u8 {
+        let data = self.read_ppu(addr, cartridge).unwrap_or(0);
+        cartridge.ppu_fetch(addr);
+        data
+    }
+
     // This advances the PPU
     // Visible scanlines: 0 ... 239 
-    // Post-render: 240
-    // Vblank: 241...260
-    // Pre-render: 261 
+    // Post-render: 240 (240 ... 290 on Dendy)
+    // Vblank: 241...260 (PAL 241 ... 310, Dendy 291 ... 310)
+    // Pre-render: 261 (311 on PAL and Dendy)
     // Javidx9 uses -1 for pre-render since he uses a signed integer
     pub fn clock(&mut self, cartridge: &mut dyn CartridgeInterface)  {
 
-        let render_scanline = self.scanline < 240 || self.scanline == 261;
+        let prerender_line  = self.prerender_line();
+        let render_scanline = self.scanline < 240 || self.scanline == prerender_line;
+        let fetching        = render_scanline && self.rendering_enabled();
+
+        let per_dot         = self.render_mode == RenderMode::Dot;
 
-        if  render_scanline && ((self.cycle >= 2 && self.cycle < 258) || (self.cycle >= 321 && self.cycle < 338)) {
+        if  fetching && per_dot && ((self.cycle >= 2 && self.cycle < 258) || (self.cycle >= 321 && self.cycle < 338)) {
 
             self.update_shifters();
 
@@ -349,7 +639,7 @@ impl Olc2c02 {
                                   + ((self.bg_next_tile_id as u16) << 4) 
                                   + (self.vram_addr.fine_y as u16);
 
-                    self.bg_next_tile_lsb = self.read_ppu(addr, cartridge).unwrap_or(0);
+                    self.bg_next_tile_lsb = self.fetch_pattern(addr, cartridge);
                 }
 
                 6 => {
@@ -358,7 +648,7 @@ impl Olc2c02 {
                                   + (self.vram_addr.fine_y as u16)
                                   + 8;
 
-                    self.bg_next_tile_msb = self.read_ppu(addr, cartridge).unwrap_or(0);
+                    self.bg_next_tile_msb = self.fetch_pattern(addr, cartridge);
                 }
                 7 => {
                     self.increment_scroll_x();
@@ -367,6 +657,10 @@ impl Olc2c02 {
             }
         }
 
+        if !per_dot && self.scanline < 240 && self.cycle == 256 {
+            self.render_scanline(cartridge);
+        }
+
         if render_scanline && self.cycle == 256 {
             self.increment_scroll_y();
         }
@@ -376,7 +670,7 @@ impl Olc2c02 {
             self.transfer_address_x();
         }
 
-        if render_scanline && (self.cycle == 338 || self.cycle == 340) {
+        if fetching && per_dot && (self.cycle == 338 || self.cycle == 340) {
             
             let addr = 0x2000 | (self.vram_addr.to_u16() & 0x0FFF);
 
@@ -386,7 +680,7 @@ impl Olc2c02 {
         //////////////////////////
         // Foreground rendering 
         //////////////////////////
-        if render_scanline && (self.cycle == 257 && self.scanline < 240) {
+        if fetching && self.cycle == 257 && self.scanline < 240 {
             // Clear sprite scanline array
             for addr in 0u8..32 {
                 self.sprite_scanline.write(addr, 0xFF);
@@ -400,174 +694,172 @@ impl Olc2c02 {
 				self.sp_shifter_pattern_hi[i] = 0;
 			}
 
-            self.status &= !Olc2c02::STATUS_SPRITE_OVERFLOW;
-
             let sprite_size: i16 = if (self.control & Olc2c02::CTRL_SPRITE_SIZE) != 0 {16} else {8};
 
+            // The evaluation reads and so refreshes all of OAM
+            for row in 0u8..32 {
+                self.refresh_oam_row(row * 8);
+            }
+
             self.b_sp_0_hit_possible = false; 
 
-            for n_oam_entry in 0u8..64 {
+            let scanline = self.scanline as i16;
+            let in_range = |y: u8| {
+                let diff = scanline - y as i16;
+                diff >= 0 && diff < sprite_size
+            };
+
+            let mut n_oam_entry: u8 = 0;
+            while n_oam_entry < 64 && self.sprite_count < 8 {
                 let oam_sprite = self.oam.sprites[n_oam_entry as usize];
-                let diff = self.scanline as i16 - oam_sprite.y as i16;
-
-                if diff >= 0 && diff < sprite_size {
-                    // If we don't already have 8 sprites, we copy the sprite information from the OAM into the sprite scanline array
-                    if self.sprite_count < 8 {
-                        // Is this sprite sprite zero? 
-                        if n_oam_entry == 0 {
-                            self.b_sp_0_hit_possible = true;
-                        }
-                        self.sprite_scanline.sprites[self.sprite_count as usize] = self.oam.sprites[n_oam_entry as usize];
-                        self.sprite_count += 1;
-                    } else {
-                        self.status |=  Olc2c02::STATUS_SPRITE_OVERFLOW;
-                        break;
-                        
+
+                // Copy the sprite information from the OAM into the sprite scanline array
+                if in_range(oam_sprite.y) {
+                    // Is this sprite sprite zero? 
+                    if n_oam_entry == 0 {
+                        self.b_sp_0_hit_possible = true;
                     }
+                    self.sprite_scanline.sprites[self.sprite_count as usize] = oam_sprite;
+                    self.sprite_count += 1;
                 }
+                n_oam_entry += 1;
             }
-        }
-
-        if render_scanline && self.cycle == 340 {
-            for i in 0u8..self.sprite_count {
-
-                let mut sprite_pattern_bits_lo: u8;
-                let mut sprite_pattern_bits_hi: u8;
-                let sprite_pattern_addr_lo: u16;
-                let sprite_pattern_addr_hi: u16;
-
-                let sprite = self.sprite_scanline.sprites[i as usize];
 
+            // With 8 sprites found the PPU keeps looking for a ninth to set the
+            // overflow flag, but it has a bug: for every sprite that is not on
+            // the scanline it also moves on to the next byte within the sprite.
+            // OAM is scanned diagonally and tile ids, attributes and X positions
+            // are taken as Y coordinates, causing false positives and misses.
+            let mut m: u8 = 0;
+            while n_oam_entry < 64 {
+                if in_range(self.oam.read(n_oam_entry * 4 + m)) {
+                    self.status |= Olc2c02::STATUS_SPRITE_OVERFLOW;
+                    break;
+                }
+                n_oam_entry += 1;
+                m = (m + 1) & 0x03;
+            }
+        }
 
-                // 8x8 sprite mode
-                if (self.control & Olc2c02::CTRL_SPRITE_SIZE) == 0 {
-                    // These 3 indices index into a 2D memory structure
-                    // This is either 0k or 4k offset on the CPU bus
-                    let offset1 = (((self.control & Olc2c02::CTRL_PATTERN_SPRITE) != 0) as u16) << 12; 
-                    // << 4 = * 16 = each tile is 16 bytes in size 
-                    let offset2 = (sprite.id as u16) << 4;        
-                    // Which row of the tile are we currently in - Unsigned here because it should always be positive                                      
-                    let offset3 = self.scanline - (sprite.y as u16);                                    
-
-                    // Sprite is not flipped vertically, i.e. normal
-                    if sprite.attribute & 0x80 == 0 {
-                        sprite_pattern_addr_lo = offset1 | offset2 | offset3; 
-
-                    } 
-                    // Sprite is flipped vertically
-                    // Sprite is not flipped vertically, i.e. normal
-                    else 
-                    {
-                        sprite_pattern_addr_lo = offset1 | offset2 | (7 - offset3); 
-                    }
-                } 
-                // 8x16 sprite mode
-                else 
-                {
-                    
-                    // Sprite is not flipped vertically, i.e. normal
-                    if sprite.attribute & 0x80 == 0 {
-
-                        // Read top half tile
-                        if self.scanline - (sprite.y as u16) < 8 {
-                            
-                            let offset1 =  ((sprite.id & 0x01) as u16)      << 12; 
-                            let offset2 = (((sprite.id & 0xFE) as u16) + 0) <<  4;                                            
-                            let offset3 = (self.scanline - (sprite.y as u16)) & 0x0007;   
-
-                            sprite_pattern_addr_lo = offset1 | offset2 | offset3; 
-                        } 
-                        // Read bottom half tile
-                        else 
-                        {
-                            let offset1 =  ((sprite.id & 0x01) as u16)      << 12; 
-                            let offset2 = (((sprite.id & 0xFE) as u16) + 1) <<  4;                                            
-                            let offset3 = (self.scanline - (sprite.y as u16)) & 0x0007;   
-
-                            sprite_pattern_addr_lo = offset1 | offset2 | offset3; 
+        // OAMADDR is used to fetch the sprites' tiles and is left at zero
+        if fetching && self.cycle >= 257 && self.cycle <= 320 {
+            self.oam_addr = 0;
+        }
 
+        // The sprites' patterns are fetched during dots 257 to 320, one sprite
+        // every 8 dots with the low plane on the 5th and the high plane on
+        // the 7th dot, just like the background tiles. A CHR bank switch in
+        // horizontal blank only affects the sprites fetched after it.
+        if fetching && self.cycle >= 257 && self.cycle <= 320 {
+            let i      = ((self.cycle - 257) / 8) as u8;
+            let sprite = self.sprite_scanline.sprites[i as usize];
+
+            if i < self.sprite_count {
+                match (self.cycle - 257) % 8 {
+                    4 => {
+                        let mut sprite_pattern_bits_lo = self.fetch_pattern(self.sprite_pattern_addr_lo(sprite), cartridge);
+
+                        // if the sprite is flipped horizontally, we need to flip the pattern bytes
+                        if sprite.attribute & 0x40 != 0 {
+                            sprite_pattern_bits_lo = sprite_pattern_bits_lo.reverse_bits();
                         }
+                        self.sp_shifter_pattern_lo[i as usize] = sprite_pattern_bits_lo;
+                    }
+                    6 => {
+                        let mut sprite_pattern_bits_hi = self.fetch_pattern(self.sprite_pattern_addr_lo(sprite) + 8, cartridge);
 
-                    } 
-                    // Sprite is flipped vertically
-                    // Sprite is not flipped vertically, i.e. normal
-                    else 
-                    {
-                        
-                        // Read top half tile
-                        if self.scanline - (sprite.y as u16) < 8 {
-                            
-                            let offset1 =  ((sprite.id & 0x01) as u16)      << 12; 
-                            let offset2 = (((sprite.id & 0xFE) as u16) + 1) <<  4;                                            
-                            let offset3 = 7 - ((self.scanline - (sprite.y as u16)) & 0x0007);   
-
-                            sprite_pattern_addr_lo = offset1 | offset2 | offset3; 
-                        } 
-                        // Read bottom half tile
-                        else 
-                        {
-                            let offset1 =  ((sprite.id & 0x01) as u16)      << 12; 
-                            let offset2 = (((sprite.id & 0xFE) as u16) + 0) <<  4;                                            
-                            let offset3 = 7  - ((self.scanline - (sprite.y as u16)) & 0x0007);   
-
-                            sprite_pattern_addr_lo = offset1 | offset2 | offset3; 
-
+                        if sprite.attribute & 0x40 != 0 {
+                            sprite_pattern_bits_hi = sprite_pattern_bits_hi.reverse_bits();
                         }
+                        self.sp_shifter_pattern_hi[i as usize] = sprite_pattern_bits_hi;
                     }
-                    
-                } // End of if for setting sprite_pattern_addr_lo
-
-                sprite_pattern_addr_hi = sprite_pattern_addr_lo + 8; 
-
-                sprite_pattern_bits_lo = self.read_ppu(sprite_pattern_addr_lo, cartridge).unwrap_or(0);
-                sprite_pattern_bits_hi = self.read_ppu(sprite_pattern_addr_hi, cartridge).unwrap_or(0);
-
-                // if the sprite is flipped horizontally, we need to flip the pattern bytes
-                if sprite.attribute & 0x40 != 0 {
-                    sprite_pattern_bits_lo = sprite_pattern_bits_lo.reverse_bits();
-                    sprite_pattern_bits_hi = sprite_pattern_bits_hi.reverse_bits();
+                    _ => {}
                 }
-
-                
-				self.sp_shifter_pattern_lo[i as usize] = sprite_pattern_bits_lo;
-				self.sp_shifter_pattern_hi[i as usize] = sprite_pattern_bits_hi;
-            } // End of the loop over the active sprites
-        } // End of cycle 340
+            }
+        }
         
 
-        if self.scanline == 241 && self.cycle == 1 {
-            self.status |= Olc2c02::STATUS_VERTICAL_BLANK;
-            if self.control & Olc2c02::CTRL_ENABLE_NMI != 0 {
-                self.nmi = true;
+        // Mappers like the MMC3 count scanlines by watching address line A12
+        // during the pattern fetches. The sprite fetches start at cycle 257,
+        // in 8x16 mode the empty slots fetch tile $FF from $1000. The
+        // background fetches for the next scanline start at cycle 321.
+        if fetching {
+            if self.cycle == 260 {
+                cartridge.ppu_a12(self.control & (Olc2c02::CTRL_PATTERN_SPRITE | Olc2c02::CTRL_SPRITE_SIZE) != 0);
+            } else if self.cycle == 324 {
+                cartridge.ppu_a12(self.control & Olc2c02::CTRL_PATTERN_BACKGROUND != 0);
             }
+        }
 
+        if self.scanline == self.tv_system.vblank_line() && self.cycle == 1 {
+            self.status |= Olc2c02::STATUS_VERTICAL_BLANK;
         }
 
-        if self.scanline == 261 && self.cycle >= 280 && self.cycle < 305 {
+        if self.scanline == prerender_line && self.cycle >= 280 && self.cycle < 305 {
             self.transfer_address_y();
         }
         
 
         // Effectively start of new frame
-        if self.scanline == 261 && self.cycle == 1 {
+        if self.scanline == prerender_line && self.cycle == 1 {
             self.status &= !Olc2c02::STATUS_VERTICAL_BLANK;
             self.status &= !Olc2c02::STATUS_SPRITE_OVERFLOW;
             self.status &= !Olc2c02::STATUS_SPRITE_ZERO_HIT;
 
+            // No sprites on the first scanline, the ones found on the last
+            // visible scanline must not be fetched again for it
+            self.sprite_count = 0;
+
             for i in 0u8..8 {
                 self.sp_shifter_pattern_hi[i as usize] = 0;
                 self.sp_shifter_pattern_lo[i as usize] = 0;
             }
         }
 
-        // Compose foreground and background information 
+        if self.render_mode == RenderMode::Dot {
+            self.render_dot();
+        }
+
+        
+        // This is weird NES stuff
+        // There are 341 PPU cycles per scanline
+        self.cycle       += 1;
+        self.dot_counter += 1;
+
+        if self.cycle >= 341 {
+            self.cycle = 0;
+            self.scanline += 1;
+
+            if self.scanline >= self.tv_system.scanlines() {
+                self.scanline = 0;
+                self.frame_complete = true;
+                self.frame_counter += 1;
 
+                // With rendering on, every other NTSC frame skips the idle
+                // first dot, making it 341 * 262 - 1 dots long
+                self.odd_frame = !self.odd_frame;
+                if self.odd_frame && self.rendering_enabled() && self.tv_system.skips_odd_dot() {
+                    self.cycle = 1;
+                }
+            }
+        }
+    }
+
+    // Compose foreground and background information of the current dot
+    fn render_dot(&mut self) {
         // Background 
         let mut bg_pixel:   u8 = 0x00; 
         let mut bg_palette: u8 = 0x00;
 
 
-        if self.mask & Olc2c02::MASK_RENDER_BACKGROUND != 0 {
+        // PPUMASK can hide either layer in the leftmost 8 pixels of the screen
+        let left_column = self.cycle >= 1 && self.cycle <= 8;
+        let show_background = self.mask & Olc2c02::MASK_RENDER_BACKGROUND != 0
+            && (!left_column || self.mask & Olc2c02::MASK_RENDER_BACKGROUND_LEFT != 0);
+        let show_sprites    = self.mask & Olc2c02::MASK_RENDER_SPRITES != 0
+            && (!left_column || self.mask & Olc2c02::MASK_RENDER_SPRITES_LEFT != 0);
+
+        if show_background {
             let bit_mux: u16 = 0x8000 >> self.fine_x;
 
 
@@ -585,15 +877,16 @@ impl Olc2c02 {
             bg_palette       = (bg_pal1 << 1) | bg_pal0;
 
         }
-        
+    
 
         // Foreground 
         let mut fg_pixel:    u8 = 0x00;
         let mut fg_palette:  u8 = 0x00;
         let mut fg_priority: bool = false;
 
-        if self.mask & Olc2c02::MASK_RENDER_SPRITES != 0  {
-            self.b_sp_0_being_rendered = false; 
+        self.b_sp_0_being_rendered = false; 
+
+        if show_sprites {
 
             for i in 0u8..self.sprite_count {
                 let sprite = self.sprite_scanline.sprites[i as usize];
@@ -605,7 +898,7 @@ impl Olc2c02 {
                     let fg_pixel_hi: u8 = ((self.sp_shifter_pattern_hi[i as usize] & 0x80) > 0) as u8;
                     fg_pixel = (fg_pixel_hi << 1) | fg_pixel_lo;
 
-                    
+                
                     fg_palette  = (sprite.attribute & 0x03) + 0x04;
                     fg_priority = (sprite.attribute & 0x20) == 0;
 
@@ -620,6 +913,102 @@ impl Olc2c02 {
             } // Loop over sprites that we break out of
         } // Check whether sprites should be rendered
 
+        self.output_pixel(self.cycle.wrapping_sub(1), bg_pixel, bg_palette, fg_pixel, fg_palette, fg_priority);
+    }
+
+    // Scanline mode draws the whole line at once on its last visible dot, with
+    // the scroll and the registers as they are then. Register writes and bank
+    // switches in the middle of the line are missed, games relying on them
+    // (split screens are done in horizontal blank and still work) glitch.
+    fn render_scanline(&mut self, cartridge: &mut dyn CartridgeInterface) {
+        // The 33 tiles the line touches, [pattern lo, pattern hi, palette]
+        let mut tiles = [[0u8; 3]; 33];
+        if self.mask & Olc2c02::MASK_RENDER_BACKGROUND != 0 {
+            let mut v = self.vram_addr;
+            for tile in tiles.iter_mut() {
+                let id = self.read_ppu(0x2000 | (v.to_u16() & 0x0FFF), cartridge).unwrap_or(0);
+
+                let attrib_addr = (0x23C0 |  ((v.nametable_y as u16) << 11)
+                                          |  ((v.nametable_x as u16) << 10)
+                                          | (((v.coarse_y    as u16) >> 2) << 3))
+                                          |  ((v.coarse_x    as u16) >> 2);
+                let mut attrib = self.read_ppu(attrib_addr, cartridge).unwrap_or(0);
+                if v.coarse_y & 0x02 != 0 {attrib >>= 4;}
+                if v.coarse_x & 0x02 != 0 {attrib >>= 2;}
+
+                let addr = ((((self.control & Olc2c02::CTRL_PATTERN_BACKGROUND) as u16) >> 4) << 12)
+                         + ((id as u16) << 4)
+                         + (v.fine_y as u16);
+                *tile = [self.fetch_pattern(addr, cartridge), self.fetch_pattern(addr + 8, cartridge), attrib & 0x03];
+
+                if v.coarse_x == 31 {
+                    v.coarse_x     = 0;
+                    v.nametable_x ^= 1;
+                } else {
+                    v.coarse_x += 1;
+                }
+            }
+        }
+
+        for x in 0u16..256 {
+            let left_column = x < 8;
+            let show_background = self.mask & Olc2c02::MASK_RENDER_BACKGROUND != 0
+                && (!left_column || self.mask & Olc2c02::MASK_RENDER_BACKGROUND_LEFT != 0);
+            let show_sprites    = self.mask & Olc2c02::MASK_RENDER_SPRITES != 0
+                && (!left_column || self.mask & Olc2c02::MASK_RENDER_SPRITES_LEFT != 0);
+
+            let mut bg_pixel:   u8 = 0x00;
+            let mut bg_palette: u8 = 0x00;
+            if show_background {
+                let position = (x + self.fine_x as u16) as usize;
+                let [lo, hi, palette] = tiles[position / 8];
+                let bit = 7 - (position % 8);
+                bg_pixel   = (((hi >> bit) & 0x01) << 1) | ((lo >> bit) & 0x01);
+                bg_palette = palette;
+            }
+
+            let mut fg_pixel:    u8 = 0x00;
+            let mut fg_palette:  u8 = 0x00;
+            let mut fg_priority: bool = false;
+            self.b_sp_0_being_rendered = false;
+            if show_sprites {
+                for i in 0u8..self.sprite_count {
+                    let sprite = self.sprite_scanline.sprites[i as usize];
+                    if x < sprite.x as u16 || x - sprite.x as u16 >= 8 {
+                        continue;
+                    }
+
+                    // The shifters are not shifted in this mode, the pixel is picked directly
+                    let bit = 7 - (x - sprite.x as u16);
+                    fg_pixel    = (((self.sp_shifter_pattern_hi[i as usize] >> bit) & 0x01) << 1)
+                                |  ((self.sp_shifter_pattern_lo[i as usize] >> bit) & 0x01);
+                    fg_palette  = (sprite.attribute & 0x03) + 0x04;
+                    fg_priority = (sprite.attribute & 0x20) == 0;
+
+                    if fg_pixel != 0 {
+                        if i == 0 {
+                            self.b_sp_0_being_rendered = true;
+                        }
+                        break;
+                    }
+                }
+            }
+
+            self.output_pixel(x, bg_pixel, bg_palette, fg_pixel, fg_palette, fg_priority);
+        }
+    }
+
+    // Combines the pixels of both layers into pixel x of the current scanline
+    fn output_pixel(&mut self, x: u16, bg_pixel: u8, bg_palette: u8, fg_pixel: u8, fg_palette: u8, fg_priority: bool) {
+        // Both pixels are opaque, so neither layer is clipped here. There is
+        // no hit on the rightmost pixel.
+        if bg_pixel > 0 && fg_pixel > 0 && self.b_sp_0_being_rendered && self.b_sp_0_hit_possible && x < 255 {
+            self.status |= Olc2c02::STATUS_SPRITE_ZERO_HIT;
+        }
+
+        // Layers hidden for debugging still take part in sprite zero hits
+        let bg_pixel = if self.show_background_layer { bg_pixel } else { 0 };
+        let fg_pixel = if self.show_sprite_layer     { fg_pixel } else { 0 };
 
         // Combine background and foreground pixel
         let mut pixel: u8 = 0x00; 
@@ -642,53 +1031,55 @@ impl Olc2c02 {
                 pixel   = bg_pixel; 
                 palette = bg_palette; 
             }
-
-            if self.b_sp_0_being_rendered && self.b_sp_0_hit_possible {
-                if ((self.mask & Olc2c02::MASK_RENDER_BACKGROUND) != 0) && ((self.mask & Olc2c02::MASK_RENDER_SPRITES) != 0) {
-                    let left_edge_enabled =
-                        (self.mask & Olc2c02::MASK_RENDER_BACKGROUND_LEFT) != 0 &&
-                        (self.mask & Olc2c02::MASK_RENDER_SPRITES_LEFT) != 0;
-
-                    if !left_edge_enabled {
-                        if self.cycle >= 9 && self.cycle < 258 {
-                            self.status |= Olc2c02::STATUS_SPRITE_ZERO_HIT;
-                        }
-                    } else {
-                        if self.cycle >= 1 && self.cycle < 258 {
-                            self.status |= Olc2c02::STATUS_SPRITE_ZERO_HIT;
-                        }
-                    }
-                }
-            }
         }
 
 
-        let colour = self.get_colour_from_palette_ram(palette, pixel, cartridge).unwrap_or(0);
+        // Palette RAM is inside the PPU, no need to go through the bus
+        let mut colour = self.table_palette[(((palette << 2) | pixel) & 0x1F) as usize];
 
-        if self.scanline < 240 && self.cycle >= 1 && self.cycle <= 256 {
-            self.set_pixel((self.cycle - 1) as usize, self.scanline as usize, colour);
+        // Grayscale mode only keeps the column of greys of the master palette
+        if self.mask & Olc2c02::MASK_GRAYSCALE != 0 {
+            colour &= 0x30;
         }
 
-        
-        // This is weird NES stuff
-        // There are 341 PPU cycles per scanline
-        self.cycle += 1;
-
-        if self.cycle >= 341 {
-            self.cycle = 0;
-            self.scanline += 1;
+        self.set_pixel(x as usize, self.scanline as usize, colour);
 
-            if self.scanline >= 262 {
-                self.scanline = 0;
-                self.frame_complete = true;
-            }
+        // Colour emphasis is taken per scanline, changes in the middle of one are rare
+        if x == 0 && (self.scanline as usize) < SCREEN_H {
+            self.line_emphasis[self.scanline as usize] = self.mask >> 5;
         }
     }
 
+    // The PPU holds the CPU's NMI line for as long as it is in vertical blank
+    // with NMIs enabled. Enabling them in the middle of vertical blank therefore
+    // triggers an NMI as well.
+    pub fn nmi_line(&self) -> bool {
+        (self.status & Olc2c02::STATUS_VERTICAL_BLANK != 0) && (self.control & Olc2c02::CTRL_ENABLE_NMI != 0)
+    }
+
     pub fn get_frame_buffer(&self) -> Vec<u8> {
         self.screen.to_vec()
     }
 
+    // Frames completed since power on
+    pub fn frame_count(&self) -> u64 {
+        self.frame_counter
+    }
+
+    pub fn get_timing(&self) -> PpuTiming {
+        PpuTiming {
+            scanline:          self.scanline,
+            dot:               self.cycle,
+            frame:             self.frame_counter,
+            rendering_enabled: self.rendering_enabled(),
+        }
+    }
+
+    // The emphasis bits (PPUMASK >> 5) of each line of the frame buffer
+    pub fn get_emphasis(&self) -> &[u8] {
+        &self.line_emphasis
+    }
+
     // Depending on the increment mode flag, we either move horizontally (1 tile) or vertically (skip 32 tiles horizontally)
     fn ppu_addr_increment(&self) -> u16 {
         if (self.control & Olc2c02::CTRL_INCREMENT_MODE) != 0 {
@@ -698,10 +1089,18 @@ impl Olc2c02 {
         }
     }
 
+    // Takes over a deserialized PPU, the frame being shown stays
+    pub fn restore(&mut self, mut state: Olc2c02) {
+        std::mem::swap(&mut state.screen, &mut self.screen);
+        state.line_emphasis = self.line_emphasis;
+        *self = state;
+    }
+
     pub fn reset(&mut self) {
         self.scanline               = 0;
         self.cycle                  = 0;
         self.frame_complete         = false;
+        self.odd_frame              = false;
         self.noise_state            = 0x12345678;
         self.status                 = 0x00;
         self.mask                   = 0x00;
@@ -711,7 +1110,6 @@ impl Olc2c02 {
         self.fine_x                 = 0x00;
         self.address_latch          = 0x00; 
         self.ppu_data_buffer        = 0x00; 
-        self.nmi                    = false; 
         self.bg_shifter_pattern_hi  = 0x0000;
         self.bg_shifter_pattern_lo  = 0x0000;
         self.bg_shifter_attrib_hi   = 0x0000;
@@ -731,47 +1129,59 @@ impl Olc2c02 {
 
 
 impl PpuInterface for Olc2c02 {
-    fn read_cpu(&mut self, addr: u16, _read_only: bool, cartridge: &mut dyn CartridgeInterface) -> u8 {
+    // With read_only set the registers are only looked at, e.g. by the debugger
+    fn read_cpu(&mut self, addr: u16, read_only: bool, cartridge: &mut dyn CartridgeInterface) -> u8 {
     
-        let data = match addr {
-            0x0000 => 0x00, // Control
-            0x0001 => 0x00, // Mask
+        // The bits the register drives, the rest come from the I/O latch
+        let (data, driven) = match addr {
             // Status
             0x0002 => {
-                let temp = (self.status & 0xE0) | (self.ppu_data_buffer & 0x1F);
-                self.status &= !Olc2c02::STATUS_VERTICAL_BLANK;
-                self.address_latch = 0; 
-                temp
+                let temp = self.status & 0xE0;
+                if !read_only {
+                    self.status &= !Olc2c02::STATUS_VERTICAL_BLANK;
+                    self.address_latch = 0; 
+                }
+                (temp, 0xE0)
             }, 
-            // OAM Address - reading from here does not make sense as the CPU does not care about the OAM address
-            0x0003 => 0x00,
             // OAM Data
             0x0004 => {
-                return self.oam.read(self.oam_addr);
+                if !read_only {
+                    self.refresh_oam_row(self.oam_addr);
+                }
+                (self.oam.read(self.oam_addr), 0xFF)
             }, 
-            0x0005 => 0x00, // Scroll
-            0x0006 => 0x00, // PPU Address
+            0x0007 if read_only => (self.ppu_data_buffer, 0xFF),
             0x0007 => {
                 let temp         = self.ppu_data_buffer;
                 let addr        = self.vram_addr.to_u16();
                 self.ppu_data_buffer = self.read_ppu(addr, cartridge).unwrap_or(0x00);
+                cartridge.ppu_fetch(addr & 0x3FFF);
 
                 // Auto-increment for convenience - we rarely want to read/write the same location twice
                 let new_addr    = addr.wrapping_add(self.ppu_addr_increment());
                 self.vram_addr       = Loopy::from_u16(new_addr);
 
-                if addr >= 0x3F00 {
-                    self.ppu_data_buffer
+                // The palette RAM is only 6 bits wide
+                if (addr & 0x3FFF) >= 0x3F00 {
+                    (self.ppu_data_buffer, 0x3F)
                 } else {
-                    temp
+                    (temp, 0xFF)
                 }
             }, // PPU Data
-            _      => 0x00,
+            // Control, Mask, OAM Address, Scroll and PPU Address are write-only
+            _      => (0x00, 0x00),
         };
+
+        let data = (self.io_latch() & !driven) | (data & driven);
+        if !read_only {
+            self.drive_io_latch(data, driven);
+        }
         data
     }
 
     fn write_cpu(&mut self, addr: u16, data: u8, cartridge: &mut dyn CartridgeInterface)  {
+        self.drive_io_latch(data, 0xFF);
+
         match addr {
             // Control
             0x0000 => {
@@ -791,8 +1201,7 @@ impl PpuInterface for Olc2c02 {
             }, 
             // OAM Data
             0x0004 => {
-                self.oam.write(self.oam_addr, data); 
-                self.oam_addr = self.oam_addr.wrapping_add(1);
+                self.write_oam_data(data);
             }, 
             0x0005 => {
                 if self.address_latch == 0 {
@@ -888,8 +1297,9 @@ impl PpuInterface for Olc2c02 {
             if addr == 0x0014 {addr = 0x0004;}
             if addr == 0x0018 {addr = 0x0008;}
             if addr == 0x001C {addr = 0x000C;}
-                    
-            self.table_palette[addr as usize] = data;
+
+            // The palette RAM is only 6 bits wide
+            self.table_palette[addr as usize] = data & 0x3F;
         }
     }
 
@@ -901,6 +1311,40 @@ impl Olc2c02 {
         self.table_name[..1024].to_vec()
     }
 
+    pub fn get_oam_entries(&self) -> Vec<OamEntry> {
+        self.oam.sprites.iter().map(|&sprite| OamEntry::from(sprite)).collect()
+    }
+
+    // Sprite as RGBA8888 in its own palette, 8 pixels wide and 8 or 16 high
+    // depending on the sprite size. Transparent pixels have an alpha of 0.
+    pub fn get_sprite_thumbnail(&self, index: u8, cartridge: &dyn CartridgeInterface) -> Vec<u8> {
+        let sprite = self.oam.sprites[(index & 0x3F) as usize];
+        let height: u16 = if (self.control & Olc2c02::CTRL_SPRITE_SIZE) != 0 {16} else {8};
+
+        let mut thumbnail = Vec::with_capacity(8 * height as usize * 4);
+        for y in 0..height {
+            let row = if sprite.attribute & 0x80 != 0 { height - 1 - y } else { y };
+
+            let addr = if height == 8 {
+                ((((self.control & Olc2c02::CTRL_PATTERN_SPRITE) != 0) as u16) << 12) | ((sprite.id as u16) << 4) | row
+            } else {
+                (((sprite.id & 0x01) as u16) << 12) | ((((sprite.id & 0xFE) as u16) + (row >> 3)) << 4) | (row & 0x07)
+            };
+            let tile_lsb = self.read_ppu(addr,     cartridge).unwrap_or(0);
+            let tile_msb = self.read_ppu(addr + 8, cartridge).unwrap_or(0);
+
+            for x in 0..8 {
+                let bit   = if sprite.attribute & 0x40 != 0 { x } else { 7 - x };
+                let pixel = (((tile_msb >> bit) & 0x01) << 1) | ((tile_lsb >> bit) & 0x01);
+
+                let colour = self.get_colour_from_palette_ram((sprite.attribute & 0x03) + 0x04, pixel, cartridge).unwrap_or(0);
+                let [r, g, b] = palette::NES_PALETTE[(colour & 0x3F) as usize];
+                thumbnail.extend_from_slice(&[r, g, b, if pixel == 0 {0x00} else {0xFF}]);
+            }
+        }
+        thumbnail
+    }
+
     pub fn get_pattern_table(&self, i: u8, palette: u8, cartridge: &dyn CartridgeInterface) -> Vec<u8> {
         
         let mut sprite_pattern_table = [0u8; 128*128];
@@ -0,0 +1,107 @@
+use std::collections::VecDeque;
+use std::ops::RangeInclusive;
+
+// Only the most recent accesses are kept
+pub const DEFAULT_CAPACITY: usize = 32768;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AccessKind {
+    Read,
+    Write,
+}
+
+// Who drove the bus
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AccessOrigin {
+    Cpu,
+    OamDma,
+    DmcDma,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BusAccess {
+    pub cycle:  u64, // CPU cycle, as far as the owner of the bus keeps track of it
+    pub addr:   u16,
+    pub value:  u8,
+    pub kind:   AccessKind,
+    pub origin: AccessOrigin,
+}
+
+// Logs the accesses on a bus while it is enabled. Peeks are not bus activity
+// and never recorded. Without any filters every address is recorded, otherwise
+// only the addresses within one of the filter ranges.
+pub struct BusRecorder {
+    enabled:  bool,
+    capacity: usize,
+    filters:  Vec<RangeInclusive<u16>>,
+    log:      VecDeque<BusAccess>,
+
+    // Set by the owner of the bus before the accesses happen
+    pub cycle:  u64,
+    pub origin: AccessOrigin,
+}
+
+impl BusRecorder {
+    pub fn new() -> Self {
+        Self {
+            enabled:  false,
+            capacity: DEFAULT_CAPACITY,
+            filters:  Vec::new(),
+            log:      VecDeque::new(),
+            cycle:    0,
+            origin:   AccessOrigin::Cpu,
+        }
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity.max(1);
+        while self.log.len() > self.capacity {
+            self.log.pop_front();
+        }
+    }
+
+    pub fn add_filter(&mut self, start: u16, end: u16) {
+        self.filters.push(start..=end);
+    }
+
+    pub fn clear_filters(&mut self) {
+        self.filters.clear();
+    }
+
+    pub fn record(&mut self, addr: u16, value: u8, kind: AccessKind) {
+        if !self.enabled {
+            return;
+        }
+        if !self.filters.is_empty() && !self.filters.iter().any(|range| range.contains(&addr)) {
+            return;
+        }
+
+        if self.log.len() == self.capacity {
+            self.log.pop_front();
+        }
+        self.log.push_back(BusAccess { cycle: self.cycle, addr, value, kind, origin: self.origin });
+    }
+
+    // Oldest access first
+    pub fn get_log(&self) -> Vec<BusAccess> {
+        self.log.iter().copied().collect()
+    }
+
+    pub fn clear(&mut self) {
+        self.log.clear();
+    }
+}
+
+impl Default for BusRecorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
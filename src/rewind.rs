@@ -0,0 +1,160 @@
+use std::collections::VecDeque;
+
+// Rewind
+// A state per frame, but most of it doesn't change from one frame to the
+// next. Every KEYFRAME_INTERVAL frames the whole state is kept, the frames in
+// between only as the XOR with their keyframe, run length encoded. The XOR is
+// 0 wherever nothing changed, so a delta is usually a few hundred bytes
+// where a state is tens of kilobytes.
+//
+// Delta layout, repeated until the end of the state:
+//     number of unchanged bytes, LEB128
+//     number of changed bytes,   LEB128
+//     the changed bytes XOR the keyframe
+pub const KEYFRAME_INTERVAL: usize = 60;
+
+enum Entry {
+    Keyframe(Vec<u8>),
+    Delta(Vec<u8>), // against the last keyframe before it
+}
+
+#[derive(Default)]
+pub struct RewindBuffer {
+    capacity:       usize, // in frames, 0 keeps nothing
+    entries:        VecDeque<Entry>,
+    since_keyframe: usize, // entries from the last keyframe on
+}
+
+impl RewindBuffer {
+    pub fn new(capacity: usize) -> Self {
+        Self { capacity, ..Default::default() }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    // States that can be gone back to, capacity() and up to a keyframe
+    // interval more
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.since_keyframe = 0;
+    }
+
+    // Bytes held by the states, to compare with len() full states
+    pub fn memory_usage(&self) -> usize {
+        self.entries.iter()
+            .map(|entry| match entry {
+                Entry::Keyframe(state) | Entry::Delta(state) => state.len(),
+            })
+            .sum()
+    }
+
+    pub fn push(&mut self, state: Vec<u8>) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let entry = match self.keyframe() {
+            Some(keyframe) if self.since_keyframe < KEYFRAME_INTERVAL && keyframe.len() == state.len() => {
+                Entry::Delta(encode_delta(keyframe, &state))
+            },
+            _ => Entry::Keyframe(state),
+        };
+        self.since_keyframe = if let Entry::Keyframe(_) = entry { 1 } else { self.since_keyframe + 1 };
+        self.entries.push_back(entry);
+
+        // The oldest keyframe can only go together with its deltas, once the
+        // states after them are enough
+        loop {
+            let group = 1 + self.entries.iter().skip(1).take_while(|entry| matches!(entry, Entry::Delta(_))).count();
+            if self.entries.len() - group < self.capacity {
+                break;
+            }
+            self.entries.drain(..group);
+        }
+    }
+
+    pub fn newest(&self) -> Option<Vec<u8>> {
+        Some(match self.entries.back()? {
+            Entry::Keyframe(state) => state.clone(),
+            Entry::Delta(delta)    => decode_delta(self.keyframe().expect("a delta follows its keyframe"), delta),
+        })
+    }
+
+    // The newest state, taken out of the buffer
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        let state = self.newest()?;
+        self.entries.pop_back();
+        self.since_keyframe = self.entries.iter().rev().position(|entry| matches!(entry, Entry::Keyframe(_))).map_or(0, |deltas| deltas + 1);
+        Some(state)
+    }
+
+    fn keyframe(&self) -> Option<&[u8]> {
+        self.entries.iter().rev().find_map(|entry| match entry {
+            Entry::Keyframe(state) => Some(state.as_slice()),
+            Entry::Delta(_)        => None,
+        })
+    }
+}
+
+fn encode_delta(keyframe: &[u8], state: &[u8]) -> Vec<u8> {
+    let mut delta = Vec::new();
+    let mut i = 0;
+    while i < state.len() {
+        let unchanged = state[i..].iter().zip(&keyframe[i..]).take_while(|(a, b)| a == b).count();
+        i += unchanged;
+        let changed = state[i..].iter().zip(&keyframe[i..]).take_while(|(a, b)| a != b).count();
+        write_leb128(&mut delta, unchanged);
+        write_leb128(&mut delta, changed);
+        delta.extend(state[i..i + changed].iter().zip(&keyframe[i..]).map(|(a, b)| a ^ b));
+        i += changed;
+    }
+    delta
+}
+
+fn decode_delta(keyframe: &[u8], delta: &[u8]) -> Vec<u8> {
+    let mut state = keyframe.to_vec();
+    let mut i   = 0;
+    let mut pos = 0;
+    while pos < delta.len() {
+        i += read_leb128(delta, &mut pos);
+        let changed = read_leb128(delta, &mut pos);
+        for (byte, xor) in state[i..i + changed].iter_mut().zip(&delta[pos..pos + changed]) {
+            *byte ^= xor;
+        }
+        i   += changed;
+        pos += changed;
+    }
+    state
+}
+
+fn write_leb128(out: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        out.push((value & 0x7F) as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_leb128(data: &[u8], pos: &mut usize) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = data[*pos];
+        *pos  += 1;
+        value |= ((byte & 0x7F) as usize) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            return value;
+        }
+    }
+}
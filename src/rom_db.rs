@@ -0,0 +1,113 @@
+use std::collections::HashMap;
+
+use crate::cartridge::MIRROR;
+use crate::error::EmuError;
+
+// Many old dumps have iNES headers with the wrong mapper or mirroring. The
+// database is keyed by the CRC32 of PRG and CHR ROM, without header and
+// trainer, the same checksum the usual ROM databases list.
+// With the rom-db feature the list in rom_db.txt is built in, hosts can add
+// their own entries on top, e.g. from a larger database file.
+// One entry per line, fields separated by whitespace, '-' keeps the header's
+// value:
+//     <crc32 hex> <mapper> <mirroring: h, v, 4> <battery: 0, 1> [title]
+// Lines starting with '#' are comments.
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct HeaderFix {
+    pub mapper:    Option<u8>,
+    pub mirroring: Option<MIRROR>,
+    pub battery:   Option<bool>,
+}
+
+pub struct RomDatabase {
+    entries: HashMap<u32, HeaderFix>,
+}
+
+#[cfg(feature = "rom-db")]
+const BUILT_IN: &str = include_str!("rom_db.txt");
+#[cfg(not(feature = "rom-db"))]
+const BUILT_IN: &str = "";
+
+impl RomDatabase {
+    pub fn empty() -> Self {
+        Self {
+            entries: HashMap::new(),
+        }
+    }
+
+    // The built-in entries, none without the rom-db feature
+    pub fn new() -> Self {
+        let mut db = Self::empty();
+        db.parse(BUILT_IN).expect("built-in ROM database is valid");
+        db
+    }
+
+    pub fn insert(&mut self, crc: u32, fix: HeaderFix) {
+        self.entries.insert(crc, fix);
+    }
+
+    pub fn get(&self, crc: u32) -> Option<&HeaderFix> {
+        self.entries.get(&crc)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // Adds the entries of a database in the text format above
+    pub fn parse(&mut self, text: &str) -> Result<(), EmuError> {
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (crc, fix) = parse_entry(line).ok_or(EmuError::InvalidRomDatabase(i + 1))?;
+            self.insert(crc, fix);
+        }
+        Ok(())
+    }
+}
+
+impl Default for RomDatabase {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn parse_entry(line: &str) -> Option<(u32, HeaderFix)> {
+    let mut fields = line.split_whitespace();
+    let crc = u32::from_str_radix(fields.next()?, 16).ok()?;
+
+    let mapper = match fields.next()? {
+        "-"    => None,
+        mapper => Some(mapper.parse().ok()?),
+    };
+    let mirroring = match fields.next()? {
+        "-" => None,
+        "h" => Some(MIRROR::Horizontal),
+        "v" => Some(MIRROR::Vertical),
+        "4" => Some(MIRROR::FourScreen),
+        _   => return None,
+    };
+    let battery = match fields.next()? {
+        "-" => None,
+        "0" => Some(false),
+        "1" => Some(true),
+        _   => return None,
+    };
+    Some((crc, HeaderFix { mapper, mirroring, battery }))
+}
+
+// CRC-32 as used by zip and the ROM databases (polynomial 0xEDB88320)
+pub fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, &byte| {
+        (0..8).fold(crc ^ byte as u32, |crc, _| {
+            if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 }
+        })
+    })
+}
@@ -0,0 +1,80 @@
+use crate::error::EmuError;
+
+// Save slots
+// Numbered save states and when they were taken, the same for every front
+// end. They live in memory, a front end that wants them to outlast the page
+// or the process stores the states and their timestamps itself and puts
+// them back with store().
+pub const SLOTS: usize = 10;
+
+// What a load-state menu shows
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SlotInfo {
+    pub slot:      usize,
+    pub timestamp: u64, // ms since the Unix epoch
+}
+
+struct Slot {
+    state:     Vec<u8>,
+    timestamp: u64,
+}
+
+#[derive(Default)]
+pub struct SaveSlots {
+    slots: [Option<Slot>; SLOTS],
+}
+
+impl SaveSlots {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn store(&mut self, slot: usize, state: Vec<u8>, timestamp: u64) -> Result<(), EmuError> {
+        let entry = self.slots.get_mut(slot).ok_or(EmuError::InvalidSlot(slot))?;
+        *entry = Some(Slot { state, timestamp });
+        Ok(())
+    }
+
+    pub fn state(&self, slot: usize) -> Result<&[u8], EmuError> {
+        match self.slots.get(slot).ok_or(EmuError::InvalidSlot(slot))? {
+            Some(entry) => Ok(&entry.state),
+            None        => Err(EmuError::EmptySlot(slot)),
+        }
+    }
+
+    pub fn delete(&mut self, slot: usize) -> Result<(), EmuError> {
+        *self.slots.get_mut(slot).ok_or(EmuError::InvalidSlot(slot))? = None;
+        Ok(())
+    }
+
+    // The slots in use, in slot order
+    pub fn list(&self) -> Vec<SlotInfo> {
+        self.slots.iter().enumerate()
+            .filter_map(|(slot, entry)| entry.as_ref().map(|entry| SlotInfo { slot, timestamp: entry.timestamp }))
+            .collect()
+    }
+
+    pub fn clear(&mut self) {
+        self.slots = Default::default();
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen::prelude::wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_namespace = Date)]
+    fn now() -> f64;
+}
+
+// ms since the Unix epoch, std has no clock in the browser
+#[cfg(target_arch = "wasm32")]
+pub fn timestamp() -> u64 {
+    now() as u64
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn timestamp() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |time| time.as_millis() as u64)
+}
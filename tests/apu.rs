@@ -0,0 +1,348 @@
+use nes_emulator::apu::{Apu, Channel};
+use nes_emulator::interfaces::BusInterface;
+use nes_emulator::bus::Bus;
+use nes_emulator::cartridge::EmptyCartridge;
+use nes_emulator::recorder::AccessOrigin;
+
+// Clocks the APU for n CPU cycles and collects the output of pulse 1
+fn pulse1_wave(apu: &mut Apu, n: usize) -> Vec<u8> {
+    (0..n).map(|_| { apu.clock(); apu.pulse1.output() }).collect()
+}
+
+#[test]
+fn pulse_plays_its_duty_cycle() {
+    let mut apu = Apu::new();
+    apu.write(0x4015, 0x01);
+    apu.write(0x4000, 0b1011_1010); // 50%, halt, constant volume 10
+    apu.write(0x4002, 0x08);        // timer period 8: 9 APU cycles per step
+    apu.write(0x4003, 0x08);
+
+    // 8 steps of 18 CPU cycles each, half of them high
+    let wave = pulse1_wave(&mut apu, 8 * 18);
+    assert_eq!(wave.iter().filter(|&&v| v == 10).count(), 4 * 18);
+    assert!(wave.iter().all(|&v| v == 0 || v == 10));
+    assert!(apu.output() >= 0.0 && apu.output() <= 1.0);
+}
+
+#[test]
+fn disabled_channels_ignore_length_loads() {
+    let mut apu = Apu::new();
+    apu.write(0x4000, 0x3F);
+    apu.write(0x4002, 0x80);
+    apu.write(0x4003, 0x08);
+    assert!(!apu.pulse1.length.is_active());
+
+    apu.write(0x4015, 0x01);
+    apu.write(0x4003, 0x08);
+    assert!(apu.pulse1.length.is_active());
+
+    // Disabling clears the counter right away
+    apu.write(0x4015, 0x00);
+    assert!(!apu.pulse1.length.is_active());
+}
+
+#[test]
+fn length_counter_silences_the_note() {
+    let mut apu = Apu::new();
+    apu.write(0x4015, 0x01);
+    apu.write(0x4000, 0x1F);        // not halted
+    apu.write(0x4002, 0x80);
+    apu.write(0x4003, 0b0000_1000); // index 1: 254
+
+    for _ in 0..253 {
+        apu.clock_half_frame();
+    }
+    assert!(apu.pulse1.length.is_active());
+    apu.clock_half_frame();
+    assert!(!apu.pulse1.length.is_active());
+}
+
+#[test]
+fn envelope_decays_and_loops() {
+    let mut apu = Apu::new();
+    apu.write(0x4015, 0x01);
+    apu.write(0x4000, 0x21); // loop, period 1
+    apu.write(0x4003, 0x08);
+
+    apu.clock_quarter_frame(); // start
+    assert_eq!(apu.pulse1.envelope.volume(), 15);
+    for _ in 0..2 * 15 {
+        apu.clock_quarter_frame();
+    }
+    assert_eq!(apu.pulse1.envelope.volume(), 0);
+    apu.clock_quarter_frame();
+    apu.clock_quarter_frame();
+    assert_eq!(apu.pulse1.envelope.volume(), 15);
+}
+
+#[test]
+fn sweep_negates_differently_on_both_pulses() {
+    let mut apu = Apu::new();
+    apu.write(0x4015, 0x03);
+    for base in [0x4000, 0x4004] {
+        apu.write(base,     0x30);
+        apu.write(base + 1, 0b1000_1001); // enabled, period 0, negate, shift 1
+        apu.write(base + 2, 0x00);
+        apu.write(base + 3, 0x09);        // period 0x100
+    }
+
+    apu.clock_half_frame();
+    assert_eq!(apu.pulse1.period(), 0x100 - 0x80 - 1);
+    assert_eq!(apu.pulse2.period(), 0x100 - 0x80);
+}
+
+#[test]
+fn sweep_target_out_of_range_mutes() {
+    let mut apu = Apu::new();
+    apu.write(0x4015, 0x01);
+    apu.write(0x4000, 0xBF);        // 50%, constant volume 15
+    apu.write(0x4001, 0x00);        // sweep disabled, shift 0: target is twice the period
+    apu.write(0x4002, 0x00);
+    apu.write(0x4003, 0x0C);        // period 0x400
+
+    assert!(pulse1_wave(&mut apu, 20_000).iter().all(|&v| v == 0));
+
+    apu.write(0x4003, 0x0B);        // period 0x300
+    assert!(pulse1_wave(&mut apu, 20_000).contains(&15));
+}
+
+#[test]
+fn dmc_decodes_deltas() {
+    let mut apu = Apu::new();
+    apu.write(0x4010, 0x0F); // fastest rate, 54 cycles per bit
+    apu.write(0x4011, 0x40);
+    apu.write(0x4013, 0x00); // one byte
+    apu.write(0x4015, 0x10);
+    assert!(apu.dmc.wants_sample());
+    assert_eq!(apu.dmc.sample_addr(), 0xC000);
+    apu.dmc.fill_sample(0b0000_1111);
+    assert!(!apu.dmc.wants_sample());
+
+    // The output unit first finishes its silent byte, then the bits of the
+    // sample are played from the lowest up
+    let mut levels = Vec::new();
+    for _ in 0..16 * 54 {
+        apu.clock();
+        levels.push(apu.dmc.output());
+    }
+    levels.dedup();
+    assert_eq!(levels, vec![0x40, 0x42, 0x44, 0x46, 0x48, 0x46, 0x44, 0x42, 0x40]);
+}
+
+#[test]
+fn dmc_raises_its_irq_or_loops_at_the_end_of_the_sample() {
+    let mut apu = Apu::new();
+    apu.write(0x4010, 0x80);
+    apu.write(0x4012, 0xFF); // $FFC0
+    apu.write(0x4013, 0x04); // 65 bytes
+    apu.write(0x4015, 0x10);
+    for _ in 0..65 {
+        assert!(!apu.dmc.irq);
+        apu.dmc.fill_sample(0x00);
+        while !apu.dmc.wants_sample() && apu.dmc.is_active() {
+            apu.clock();
+        }
+    }
+    assert!(apu.dmc.irq);
+    // The address wrapped around from $FFFF to $8000
+    assert_eq!(apu.dmc.sample_addr(), 0x8001);
+
+    // Clearing the IRQ enable acknowledges it
+    apu.write(0x4010, 0x40);
+    assert!(!apu.dmc.irq);
+    apu.write(0x4015, 0x10);
+    for _ in 0..65 {
+        apu.dmc.fill_sample(0x00);
+    }
+    assert!(apu.dmc.is_active());
+    assert_eq!(apu.dmc.sample_addr(), 0xFFC0);
+}
+
+#[test]
+fn dmc_samples_are_fetched_through_the_bus() {
+    let mut bus = Bus::new(Box::new(EmptyCartridge));
+    bus.map(0xC000..=0xFFFF, |addr| (addr & 0xFF) as u8 + 1, |_, _| {});
+    bus.recorder.set_enabled(true);
+
+    bus.write(0x4012, 0x01); // $C040
+    bus.write(0x4013, 0x00);
+    bus.write(0x4015, 0x10);
+    bus.clock_apu();
+    assert!(bus.dmc_dma.is_active());
+    assert_eq!(bus.dmc_dma.addr, 0xC040);
+
+    // Halt, dummy and read cycle
+    for _ in 0..3 {
+        bus.clock_dmc_dma(false);
+    }
+    assert!(!bus.dmc_dma.is_active());
+    let fetch = bus.recorder.get_log().pop().unwrap();
+    assert_eq!((fetch.addr, fetch.value, fetch.origin), (0xC040, 0x41, AccessOrigin::DmcDma));
+
+    bus.clock_apu();
+    assert!(!bus.apu().dmc.is_active());
+    assert!(!bus.apu().dmc.wants_sample());
+}
+
+// Sets up pulse 1 so that the envelope decays on every quarter frame and the
+// sweep lowers the period on every half frame
+fn watch_frame_clocks(apu: &mut Apu) {
+    apu.write(0x4015, 0x01);
+    apu.write(0x4000, 0x00);
+    apu.write(0x4001, 0x89);
+    apu.write(0x4002, 0x00);
+    apu.write(0x4003, 0x0C); // period 0x400
+    apu.clock_quarter_frame();
+    apu.clock_half_frame();
+}
+
+// CPU cycles at which the frame counter clocked a quarter frame, and whether
+// it was a half frame as well
+fn frame_clocks(apu: &mut Apu, cycles: u32) -> Vec<(u32, bool)> {
+    let mut clocks = Vec::new();
+    let mut state  = (apu.pulse1.envelope.volume(), apu.pulse1.period());
+    for cycle in 1..=cycles {
+        apu.clock();
+        let (volume, period) = (apu.pulse1.envelope.volume(), apu.pulse1.period());
+        if volume != state.0 {
+            clocks.push((cycle, period != state.1));
+        }
+        state = (volume, period);
+    }
+    clocks
+}
+
+#[test]
+fn frame_counter_four_step_sequence() {
+    let mut apu = Apu::new();
+    watch_frame_clocks(&mut apu);
+
+    // The write happens on the first half of an APU cycle, so the sequence
+    // starts over 3 cycles later
+    apu.write(0x4017, 0x00);
+    let clocks = frame_clocks(&mut apu, 3 + 29830);
+    assert_eq!(clocks, vec![(3 + 7457, false), (3 + 14913, true), (3 + 22371, false), (3 + 29829, true)]);
+    assert!(apu.frame_counter.irq);
+}
+
+#[test]
+fn frame_counter_raises_the_irq_unless_inhibited() {
+    let mut apu = Apu::new();
+    apu.write(0x4017, 0x00);
+
+    let mut irq_at = None;
+    for cycle in 1..=3 + 29830 {
+        apu.clock();
+        if apu.frame_counter.irq && irq_at.is_none() {
+            irq_at = Some(cycle);
+        }
+    }
+    assert_eq!(irq_at, Some(3 + 29828));
+
+    // Inhibiting the IRQ acknowledges it
+    apu.write(0x4017, 0x40);
+    assert!(!apu.frame_counter.irq);
+    for _ in 0..2 * 29830 {
+        apu.clock();
+    }
+    assert!(!apu.frame_counter.irq);
+}
+
+#[test]
+fn frame_counter_five_step_sequence_clocks_right_away() {
+    let mut apu = Apu::new();
+    watch_frame_clocks(&mut apu);
+
+    // Written on the second half of an APU cycle the delay is 4 cycles,
+    // switching to the 5-step mode clocks a half frame
+    apu.clock();
+    apu.write(0x4017, 0x80);
+    let clocks = frame_clocks(&mut apu, 4 + 37282);
+    assert_eq!(clocks, vec![(4, true), (4 + 7457, false), (4 + 14913, true), (4 + 22371, false), (4 + 37281, true)]);
+    assert!(apu.frame_counter.is_five_step());
+    assert!(!apu.frame_counter.irq);
+}
+
+#[test]
+fn status_reports_the_channels_and_acknowledges_the_frame_irq() {
+    let mut bus = Bus::new(Box::new(EmptyCartridge));
+    bus.write(0x4015, 0x13);
+    bus.write(0x4003, 0x08);
+    bus.write(0x4010, 0x80);
+    bus.write(0x4013, 0x00);
+    assert_eq!(bus.read(0x4015, false) & 0xDF, 0x11);
+    bus.write(0x4007, 0x08);
+    assert_eq!(bus.read(0x4015, false) & 0xDF, 0x13);
+
+    // Run into both IRQs: the DMC plays its single byte, the frame counter
+    // finishes its first sequence
+    for _ in 0..29830 {
+        bus.clock_apu();
+        if bus.dmc_dma.is_active() {
+            bus.dmc_dma.sample = Some(0x00);
+            bus.dmc_dma.active = false;
+        }
+    }
+    assert_eq!(bus.peek(0x4015) & 0xD0, 0xC0);
+    assert_eq!(bus.peek(0x4015) & 0xD0, 0xC0);
+
+    // Only the frame IRQ is acknowledged by the read, the DMC's by a write
+    assert_eq!(bus.read(0x4015, false) & 0xD0, 0xC0);
+    assert_eq!(bus.read(0x4015, false) & 0xD0, 0x80);
+    bus.write(0x4015, 0x03);
+    assert_eq!(bus.read(0x4015, false), 0x03);
+}
+
+#[test]
+fn muted_channels_are_left_out_of_the_mix() {
+    let mut apu = Apu::new();
+    apu.write(0x4015, 0x03);
+    for base in [0x4000, 0x4004] {
+        apu.write(base,     0xFF); // 25% negated, constant volume 15
+        apu.write(base + 2, 0x80);
+        apu.write(base + 3, 0x08);
+    }
+    apu.write(0x4011, 0x40);
+    for _ in 0..4 {
+        apu.clock();
+    }
+    let all = apu.output();
+
+    apu.set_channel_enabled(Channel::Pulse1, false);
+    assert!(!apu.is_channel_enabled(Channel::Pulse1));
+    let without_pulse1 = apu.output();
+    assert!(without_pulse1 > 0.0 && without_pulse1 < all);
+
+    apu.set_channel_enabled(Channel::Pulse2, false);
+    apu.set_channel_enabled(Channel::Dmc, false);
+    assert_eq!(apu.output(), 0.0);
+
+    // The channels keep running while they are muted
+    apu.set_channel_enabled(Channel::Pulse1, true);
+    apu.set_channel_enabled(Channel::Pulse2, true);
+    apu.set_channel_enabled(Channel::Dmc, true);
+    assert_eq!(apu.output(), all);
+}
+
+#[test]
+fn channel_states_for_visualizers() {
+    let mut apu = Apu::new();
+    apu.write(0x4015, 0x01);
+    apu.write(0x4000, 0x9C); // 50%, constant volume 12
+    apu.write(0x4002, 0xFD); // A4
+    apu.write(0x4003, 0x08);
+
+    let pulse1 = apu.channel_state(Channel::Pulse1).unwrap();
+    assert!(pulse1.active);
+    assert_eq!((pulse1.period, pulse1.duty, pulse1.volume), (0xFD, 2, 12));
+    assert!((pulse1.frequency - 440.0).abs() < 1.0);
+    assert!(!apu.channel_state(Channel::Pulse2).unwrap().active);
+
+    apu.write(0x4010, 0x0F);
+    apu.write(0x4011, 0x30);
+    let dmc = apu.channel_state(Channel::Dmc).unwrap();
+    assert_eq!((dmc.active, dmc.period, dmc.volume), (false, 54, 0x30));
+
+    assert_eq!(apu.channel_state(Channel::Triangle), None);
+    assert_eq!(apu.channel_state(Channel::Expansion), None);
+}
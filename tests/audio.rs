@@ -0,0 +1,231 @@
+use nes_emulator::audio::{AudioFilter, BlipBuffer, FilterChain, ExpansionChip, CPU_CLOCK_RATE, DEFAULT_SAMPLE_RATE};
+use nes_emulator::bus::Bus;
+use nes_emulator::cartridge::EmptyCartridge;
+use nes_emulator::interfaces::CartridgeInterface;
+use nes_emulator::nes::Nes;
+use nes_emulator::error::EmuError;
+
+// Magnitude of the frequency in the samples, normalised to a sine of amplitude 1
+fn magnitude(samples: &[f32], frequency: f64) -> f64 {
+    let w = 2.0 * std::f64::consts::PI * frequency / DEFAULT_SAMPLE_RATE as f64;
+    let (re, im) = samples.iter().enumerate().fold((0.0, 0.0), |(re, im), (n, &x)| {
+        (re + x as f64 * (w * n as f64).cos(), im - x as f64 * (w * n as f64).sin())
+    });
+    2.0 * (re * re + im * im).sqrt() / samples.len() as f64
+}
+
+// Without the filters, that would take the DC offset away
+fn unfiltered() -> BlipBuffer {
+    let mut blip = BlipBuffer::new(CPU_CLOCK_RATE, DEFAULT_SAMPLE_RATE);
+    for filter in [AudioFilter::HighPass90, AudioFilter::HighPass440, AudioFilter::LowPass14k] {
+        blip.filters.set_enabled(filter, false);
+    }
+    blip
+}
+
+#[test]
+fn steps_settle_at_the_new_amplitude() {
+    let mut blip = unfiltered();
+    for _ in 0..CPU_CLOCK_RATE as usize / 100 {
+        blip.clock(0.5);
+    }
+
+    // 10 ms of audio, minus the samples still in flight
+    let samples = blip.take_samples();
+    assert!(samples.len() > 400 && samples.len() <= 441);
+    assert!((samples.last().unwrap() - 0.5).abs() < 1e-4);
+    assert!(samples.iter().all(|&s| s > -0.05 && s < 0.55));
+    assert_eq!(blip.samples_available(), 0);
+}
+
+#[test]
+fn high_notes_dont_alias() {
+    // A square wave of 9.3 kHz, its 5th harmonic at 46.6 kHz would fold back
+    // to 2.5 kHz if the output was just sampled
+    let half_period = 96;
+    let fundamental = CPU_CLOCK_RATE / (2 * half_period) as f64;
+    let alias       = 5.0 * fundamental - DEFAULT_SAMPLE_RATE as f64;
+
+    let mut blip = unfiltered();
+    for cycle in 0..CPU_CLOCK_RATE as usize / 2 {
+        blip.clock(if cycle / half_period % 2 == 0 { 0.25 } else { 0.0 });
+    }
+    let samples = blip.take_samples();
+
+    let wanted = magnitude(&samples, fundamental);
+    let folded = magnitude(&samples, alias);
+    assert!(wanted > 0.1);
+    assert!(folded < wanted / 500.0, "alias at {:.0} Hz: {} vs {}", alias, folded, wanted);
+}
+
+#[test]
+fn output_is_resampled_to_the_host_rate() {
+    let mut nes = Nes::new();
+    assert_eq!(nes.sample_rate(), 44_100);
+    assert_eq!(nes.set_sample_rate(1_000_000), Err(EmuError::UnsupportedSampleRate(1_000_000)));
+    nes.set_sample_rate(48_000).unwrap();
+
+    // One second: 3 PPU dots per CPU cycle
+    for _ in 0..3 * CPU_CLOCK_RATE as usize {
+        nes.clock();
+    }
+    let available = nes.audio_samples_available();
+    let samples   = nes.take_audio_samples();
+    assert_eq!(samples.len(), available);
+    assert!(available > 47_980 && available <= 48_000, "{} samples", available);
+    assert_eq!(nes.audio_samples_available(), 0);
+}
+
+#[test]
+fn audio_samples_are_taken_once() {
+    let mut nes = Nes::new();
+    nes.run_frame();
+
+    let available = nes.audio_samples_available();
+    assert!(available > 0);
+    assert_eq!(nes.take_audio_samples().len(), available);
+    assert_eq!(nes.audio_samples_available(), 0);
+    assert!(nes.take_audio_samples().is_empty());
+}
+
+// A board with a VRC6 that outputs a constant level
+struct Vrc6Board;
+
+impl CartridgeInterface for Vrc6Board {
+    fn read_cpu (&mut self, _addr: u16          ) -> Option<u8> { None }
+    fn write_cpu(&mut self, _addr: u16, _data: u8) -> Option<()> { None }
+    fn read_ppu (&    self, _addr: u16          ) -> Option<u8> { None }
+    fn write_ppu(&mut self, _addr: u16, _data: u8) -> Option<()> { None }
+    fn map_nametable_addr(&self, addr: u16) -> u16 { addr }
+    fn audio_output(&self) -> f32 { 0.8 }
+    fn audio_chip(&self) -> Option<ExpansionChip> { Some(ExpansionChip::Vrc6) }
+    fn reset(&mut self) {}
+}
+
+#[test]
+fn expansion_audio_is_mixed_with_the_volume_of_its_chip() {
+    let mut bus = Bus::new(Box::new(EmptyCartridge));
+    assert_eq!(bus.mixer.chip(), None);
+    bus.insert_cartridge(Box::new(Vrc6Board));
+    assert_eq!(bus.mixer.chip(), Some(ExpansionChip::Vrc6));
+    assert_eq!(bus.audio_output(), 0.4);
+
+    bus.mixer.set_volume(ExpansionChip::Vrc6, 1.0);
+    bus.mixer.set_volume(ExpansionChip::N163, 0.0);
+    assert_eq!(bus.audio_output(), 0.8);
+
+    // Other chips' volumes stay with them
+    bus.insert_cartridge(Box::new(EmptyCartridge));
+    assert_eq!(bus.mixer.chip(), None);
+    assert_eq!(bus.audio_output(), 0.0);
+    assert_eq!(bus.mixer.volume(ExpansionChip::Vrc6), 1.0);
+}
+
+#[test]
+fn filters_remove_the_dc_offset_and_the_highest_frequencies() {
+    let sine = |frequency: f64| -> Vec<f32> {
+        (0..DEFAULT_SAMPLE_RATE as usize).map(|n| {
+            (0.5 + 0.5 * (2.0 * std::f64::consts::PI * frequency * n as f64 / DEFAULT_SAMPLE_RATE as f64).sin()) as f32
+        }).collect()
+    };
+    let filter = |filters: &mut FilterChain, samples: Vec<f32>| -> Vec<f32> {
+        samples.into_iter().map(|sample| filters.process(sample)).collect()
+    };
+
+    let mut filters = FilterChain::new(DEFAULT_SAMPLE_RATE);
+    assert!(filters.is_enabled(AudioFilter::LowPass14k));
+
+    // 1 kHz passes, without its offset
+    let samples = filter(&mut filters, sine(1000.0));
+    let settled = &samples[DEFAULT_SAMPLE_RATE as usize / 2..];
+    let mean    = settled.iter().sum::<f32>() / settled.len() as f32;
+    assert!(mean.abs() < 0.01, "mean {}", mean);
+    assert!(magnitude(settled, 1000.0) > 0.8 * 0.5);
+
+    // 20 kHz is damped by the low-pass
+    filters.reset();
+    let samples = filter(&mut filters, sine(20_000.0));
+    assert!(magnitude(&samples[1000..], 20_000.0) < 0.6 * 0.5);
+
+    // Disabled filters let the signal through untouched
+    let mut filters = FilterChain::new(DEFAULT_SAMPLE_RATE);
+    filters.set_enabled(AudioFilter::HighPass90, false);
+    filters.set_enabled(AudioFilter::HighPass440, false);
+    filters.set_enabled(AudioFilter::LowPass14k, false);
+    assert_eq!(filters.process(0.75), 0.75);
+}
+
+#[test]
+fn recordings_are_wav_files() {
+    let mut nes = Nes::new();
+    assert!(nes.stop_audio_recording().is_empty());
+
+    nes.start_audio_recording();
+    for _ in 0..3 * CPU_CLOCK_RATE as usize / 10 {
+        nes.clock();
+    }
+    let wav = nes.stop_audio_recording();
+
+    // 100 ms of 16 bit mono at 44.1 kHz, recording doesn't take the samples
+    // from the queue
+    let data_len = u32::from_le_bytes(wav[40..44].try_into().unwrap()) as usize;
+    assert_eq!(&wav[0..4], b"RIFF");
+    assert_eq!(&wav[8..16], b"WAVEfmt ");
+    assert_eq!(u32::from_le_bytes(wav[24..28].try_into().unwrap()), DEFAULT_SAMPLE_RATE);
+    assert_eq!(&wav[36..40], b"data");
+    assert_eq!(wav.len(), 44 + data_len);
+    assert!(data_len / 2 > 4_390 && data_len / 2 <= 4_410, "{} samples", data_len / 2);
+    assert_eq!(nes.audio_samples_available(), data_len / 2);
+
+    // Only one recording
+    assert!(nes.stop_audio_recording().is_empty());
+}
+
+#[test]
+fn run_frame_delivers_a_frame_of_picture_and_sound() {
+    let mut nes = Nes::new();
+    while !nes.frame_complete() {
+        nes.clock();
+    }
+    let frame = nes.get_ppu_timing().frame;
+
+    // The frame completed by clock() was not taken, run_frame() still runs a
+    // whole new one
+    let available = nes.run_frame();
+    assert_eq!(nes.get_ppu_timing().frame, frame + 1);
+    assert!(!nes.frame_complete());
+
+    // 44100 Hz / 60.1 frames per second, plus the first frame
+    assert_eq!(available, nes.audio_samples_available());
+    nes.take_audio_samples();
+    let available = nes.run_frame();
+    assert!((730..=738).contains(&available), "{} samples", available);
+}
+
+#[test]
+fn paused_console_keeps_the_audio_running() {
+    let mut nes = Nes::new();
+    nes.run_frame();
+    nes.pause();
+    assert!(nes.is_paused());
+    let frame = nes.get_ppu_timing();
+    let cycles = nes.get_counters();
+
+    // A frame of audio, nothing emulated
+    nes.take_audio_samples();
+    let available = nes.run_frame();
+    assert!((730..=738).contains(&available), "{} samples", available);
+    assert_eq!(nes.get_ppu_timing(), frame);
+    assert_eq!(nes.get_counters(), cycles);
+
+    // Frame by frame, still paused after it
+    nes.advance_one_frame();
+    assert_eq!(nes.get_ppu_timing().frame, frame.frame + 1);
+    assert!(nes.is_paused());
+    nes.run_frame();
+    assert_eq!(nes.get_ppu_timing().frame, frame.frame + 1);
+
+    nes.resume();
+    nes.run_frame();
+    assert_eq!(nes.get_ppu_timing().frame, frame.frame + 2);
+}
@@ -0,0 +1,47 @@
+use nes_emulator::nes::Nes;
+
+// NROM image with 16 KB PRG ROM running program at $8000
+fn rom(program: &[u8]) -> Vec<u8> {
+    let mut rom = vec![0u8; 16 + 0x4000 + 0x2000];
+    rom[0..6].copy_from_slice(&[b'N', b'E', b'S', 0x1A, 1, 1]);
+    rom[16..16 + program.len()].copy_from_slice(program);
+    // Reset vector
+    rom[16 + 0x3FFC] = 0x00;
+    rom[16 + 0x3FFD] = 0x80;
+    rom
+}
+
+fn sta(value: u8, addr: u16) -> [u8; 5] {
+    [0xA9, value, 0x8D, addr as u8, (addr >> 8) as u8]
+}
+
+#[test]
+fn blargg_status_is_reported() {
+    let mut program = Vec::new();
+    for (addr, value) in [(0x6000, 0x80), (0x6001, 0xDE), (0x6002, 0xB0), (0x6003, 0x61),
+                          (0x6004, b'o'), (0x6005, b'k'), (0x6006, 0x00), (0x6000, 0x00)] {
+        program.extend(sta(value, addr));
+    }
+    let end = 0x8000 + program.len() as u16;
+    program.extend([0x4C, end as u8, (end >> 8) as u8]);
+
+    let mut nes = Nes::new();
+    nes.insert_cartridge(&rom(&program)).unwrap();
+    nes.reset();
+    assert_eq!(nes.get_test_status(), None);
+
+    // Running once the signature is there
+    for _ in 0..8 {
+        nes.step_instruction();
+    }
+    let status = nes.get_test_status().unwrap();
+    assert!(status.is_running());
+    assert_eq!(status.message, "");
+
+    for _ in 0..8 {
+        nes.step_instruction();
+    }
+    let status = nes.get_test_status().unwrap();
+    assert!(status.is_passed());
+    assert_eq!(status.message, "ok");
+}
@@ -0,0 +1,246 @@
+use std::cell::RefCell;
+use std::ops::RangeInclusive;
+use std::rc::Rc;
+
+use nes_emulator::interfaces::{BusDevice, BusInterface};
+use nes_emulator::bus::{Bus, SimpleBus};
+use nes_emulator::cpu::Olc6502;
+use nes_emulator::devices::CallbackDevice;
+use nes_emulator::cartridge::EmptyCartridge;
+use nes_emulator::recorder::{AccessKind, AccessOrigin};
+use nes_emulator::nes::Nes;
+use nes_emulator::debugger::RomWrite;
+
+// 8 KB of work RAM as found on many cartridges
+struct WorkRam {
+    ram: Vec<u8>,
+}
+
+impl BusDevice for WorkRam {
+    fn range(&self) -> RangeInclusive<u16> { 0x6000..=0x7FFF }
+    fn read (&mut self, addr: u16, _open_bus: u8) -> u8 { self.ram[(addr - 0x6000) as usize] }
+    fn peek (&mut self, addr: u16,  open_bus: u8) -> u8 { self.read(addr, open_bus) }
+    fn write(&mut self, addr: u16, data: u8) { self.ram[(addr - 0x6000) as usize] = data; }
+}
+
+#[test]
+fn attached_device_is_mapped() {
+    let mut bus = Bus::new(Box::new(EmptyCartridge));
+
+    // Nothing there yet, the read returns what is left on the data bus
+    bus.write(0x6000, 0x12);
+    bus.read(0x0000, false);
+    assert_eq!(bus.read(0x6000, false), 0x00);
+
+    bus.attach(Box::new(WorkRam { ram: vec![0; 0x2000] }));
+    bus.write(0x6000, 0x12);
+    bus.write(0x7FFF, 0x34);
+    assert_eq!(bus.read(0x6000, false), 0x12);
+    assert_eq!(bus.read(0x7FFF, true),  0x34);
+
+    // The built-in devices are still there, RAM is mirrored every 2 KB
+    bus.write(0x0801, 0x56);
+    assert_eq!(bus.read(0x1801, false), 0x56);
+}
+
+#[test]
+fn oam_dma_is_started_through_the_io_registers() {
+    let mut bus = Bus::new(Box::new(EmptyCartridge));
+
+    bus.write(0x4014, 0x02);
+    assert!(bus.oam_dma.is_active());
+    assert_eq!(bus.oam_dma.source_addr(), 0x0200);
+}
+
+#[test]
+fn internal_ram_is_mirrored_up_to_0x1fff() {
+    let mut bus = Bus::new(Box::new(EmptyCartridge));
+
+    for mirror in [0x0000, 0x0800, 0x1000, 0x1800] {
+        let data = (mirror >> 8) as u8 + 1;
+        bus.write(mirror + 0x0123, data);
+        for other in [0x0000, 0x0800, 0x1000, 0x1800] {
+            assert_eq!(bus.read(other + 0x0123, false), data);
+        }
+    }
+    assert_eq!(bus.get_ram(0x0123, 1).unwrap(), vec![0x19]);
+
+    // Without a cartridge nothing is mapped above the I/O registers
+    bus.write(0x8123, 0xFF);
+    assert_eq!(bus.read(0x0123, false), 0x19);
+    assert_eq!(bus.read(0x8123, false), 0x19);
+}
+
+#[test]
+fn ppu_registers_are_mirrored_up_to_0x3fff() {
+    let mut bus = Bus::new(Box::new(EmptyCartridge));
+
+    // OAMADDR and OAMDATA through their last mirrors
+    bus.write(0x3FFB, 0x10);
+    bus.write(0x3FFC, 0x77);
+
+    // and read back through another one
+    bus.write(0x200B, 0x10);
+    assert_eq!(bus.read(0x2A0C, false), 0x77);
+    assert_eq!(bus.ppu.oam.read(0x10), 0x77);
+}
+
+#[test]
+fn io_registers_are_not_ram() {
+    let mut bus = Bus::new(Box::new(EmptyCartridge));
+
+    // Only A pressed on controller 2, the strobe on $4016 latches both
+    bus.set_controller(1, true, false, false, false, false, false, false, false);
+    bus.write(0x4016, 0x01);
+    bus.write(0x4016, 0x40);
+    assert_eq!(bus.read(0x4017, false), 0x41);
+    assert_eq!(bus.read(0x4017, false), 0x40);
+
+    // Unmapped and write only registers are open bus
+    bus.write(0x0000, 0x5A);
+    for addr in [0x4000, 0x4013, 0x4018, 0x401F] {
+        bus.write(addr, 0xFF);
+        assert_eq!(bus.read(0x0000, false), 0x5A);
+        assert_eq!(bus.read(addr,   false), 0x5A);
+    }
+}
+
+#[test]
+fn peek_has_no_side_effects() {
+    let mut bus = Bus::new(Box::new(EmptyCartridge));
+
+    bus.set_controller(0, true, false, false, false, false, false, false, false);
+    bus.write(0x4016, 0x01);
+    bus.write(0x4016, 0x00);
+
+    assert_eq!(bus.peek(0x4016) & 0x01, 1);
+    assert_eq!(bus.peek(0x4016) & 0x01, 1);
+    assert_eq!(bus.read(0x4016, false) & 0x01, 1);
+    assert_eq!(bus.read(0x4016, false) & 0x01, 0);
+}
+
+#[test]
+fn only_reads_of_ppustatus_have_side_effects() {
+    let mut bus = Bus::new(Box::new(EmptyCartridge));
+
+    // Into vertical blank
+    for _ in 0..241 * 341 + 2 {
+        bus.clock();
+    }
+    assert_eq!(bus.peek(0x2002) & 0x80, 0x80);
+    assert_eq!(bus.peek(0x2002) & 0x80, 0x80);
+    assert_eq!(bus.read(0x2002, false) & 0x80, 0x80);
+    assert_eq!(bus.read(0x2002, false) & 0x80, 0x00);
+
+    // Peeking leaves the $2005/$2006 write toggle alone
+    bus.write(0x2006, 0x3F);
+    bus.peek(0x200A);
+    bus.write(0x2006, 0x01);
+    bus.write(0x2007, 0x11);
+
+    // reading resets it, this is the first write again
+    bus.write(0x2006, 0x12);
+    bus.read(0x200A, false);
+    bus.write(0x2006, 0x3F);
+    bus.write(0x2006, 0x01);
+    assert_eq!(bus.read(0x2007, false), 0x11);
+}
+
+#[test]
+fn recorder_logs_filtered_accesses() {
+    let mut bus = Bus::new(Box::new(EmptyCartridge));
+    bus.recorder.set_enabled(true);
+    bus.recorder.add_filter(0x0000, 0x00FF);
+
+    bus.write(0x0010, 0x42);
+    bus.write(0x0200, 0x43);
+    bus.read(0x0010, false);
+    bus.peek(0x0010);
+
+    let log = bus.recorder.get_log();
+    assert_eq!(log.len(), 2);
+    assert_eq!((log[0].addr, log[0].value, log[0].kind), (0x0010, 0x42, AccessKind::Write));
+    assert_eq!((log[1].addr, log[1].value, log[1].kind), (0x0010, 0x42, AccessKind::Read));
+    assert_eq!(log[1].origin, AccessOrigin::Cpu);
+}
+
+#[test]
+fn ram_bulk_access() {
+    let mut bus = Bus::new(Box::new(EmptyCartridge));
+
+    bus.load_ram(0x07FE, &[0x01, 0x02]).unwrap();
+    assert_eq!(bus.ram_slice(0x07FE..=0x07FF).unwrap(), &[0x01, 0x02]);
+    assert_eq!(bus.read(0x0FFF, false), 0x02);
+
+    // Nothing is written or read past the end of the RAM
+    assert!(bus.load_ram(0x07FF, &[0x03, 0x04]).is_err());
+    assert!(bus.get_ram(0x07FF, 2).is_err());
+    assert!(bus.get_ram(0xFFFF, usize::MAX).is_err());
+    assert_eq!(bus.get_ram(0x07FF, 1).unwrap(), vec![0x02]);
+}
+
+#[test]
+fn writes_to_read_only_regions_are_dropped() {
+    let mut bus = Bus::new(Box::new(EmptyCartridge));
+    bus.attach(Box::new(WorkRam { ram: vec![0; 0x2000] }));
+
+    bus.write(0x6000, 0x12);
+    bus.add_read_only_region(0x6000, 0x6FFF);
+    bus.write(0x6000, 0x34);
+    bus.write(0x7000, 0x56);
+    assert_eq!(bus.read(0x6000, false), 0x12);
+    assert_eq!(bus.read(0x7000, false), 0x56);
+
+    bus.clear_read_only_regions();
+    bus.write(0x6000, 0x34);
+    assert_eq!(bus.read(0x6000, false), 0x34);
+}
+
+#[test]
+fn rom_writes_are_reported_when_enabled() {
+    let mut nes = Nes::new();
+    // LDA #$42, STA $8000, STA $8001
+    nes.load_program(&[0xA9, 0x42, 0x8D, 0x00, 0x80, 0x8D, 0x01, 0x80], 0x0000).unwrap();
+    nes.set_pc(0x0000);
+    nes.add_read_only_region(0x8000, 0xFFFF);
+
+    nes.step_instruction();
+    nes.step_instruction();
+    assert!(nes.debugger().get_rom_writes().is_empty());
+
+    nes.set_warn_on_rom_write(true);
+    nes.step_instruction();
+    assert_eq!(nes.debugger().get_rom_writes(), vec![RomWrite { addr: 0x8001, data: 0x42, pc: 0x0005 }]);
+
+    nes.debugger().clear_rom_writes();
+    assert!(nes.debugger().get_rom_writes().is_empty());
+}
+
+#[test]
+fn host_callbacks_are_mapped() {
+    // A UART at $F000 that reads 'A' and collects what is written to it
+    let sent = Rc::new(RefCell::new(Vec::new()));
+    let tx   = Rc::clone(&sent);
+
+    let mut bus = SimpleBus::new();
+    bus.map(0xF000..=0xF000, |_| b'A', move |_, data| tx.borrow_mut().push(data));
+
+    // LDA $F000, CLC, ADC #1, STA $F000
+    bus.load_ram(0x0200, &[0xAD, 0x00, 0xF0, 0x18, 0x69, 0x01, 0x8D, 0x00, 0xF0]).unwrap();
+    let mut cpu = Olc6502::new();
+    cpu.set_registers(0x00, 0x00, 0x00, 0xFD, 0x0200, 0x24);
+    cpu.force_cycles_zero();
+    for _ in 0..4 {
+        cpu.step_instruction(&mut bus);
+    }
+    assert_eq!(*sent.borrow(), vec![b'B']);
+
+    // The RAM behind the device is untouched, peeks don't reach the device
+    assert_eq!(bus.ram_slice(0xF000..=0xF000).unwrap(), &[0x00]);
+    assert_eq!(bus.peek(0xF000), 0x00);
+
+    let mut bus = Bus::new(Box::new(EmptyCartridge));
+    bus.attach(Box::new(CallbackDevice::new(0x5000..=0x5FFF, |addr| (addr >> 8) as u8, |_, _| {}).with_peek(|_| 0x99)));
+    assert_eq!(bus.read(0x5123, false), 0x51);
+    assert_eq!(bus.peek(0x5123), 0x99);
+}
@@ -0,0 +1,711 @@
+use nes_emulator::cartridge::{Cartridge, MIRROR, Region};
+use nes_emulator::error::EmuError;
+use nes_emulator::interfaces::{CartridgeInterface, MapperInterface};
+use nes_emulator::mapper::{MapperRegistry, Mapper003, Mapper004};
+use nes_emulator::Nes;
+use nes_emulator::audio::ExpansionChip;
+
+fn rom(header: [u8; 16]) -> Vec<u8> {
+    let mut rom = header.to_vec();
+    rom.resize(16 + 0x4000 + 0x2000, 0);
+    rom
+}
+
+#[test]
+fn ines_header_is_parsed() {
+    let cart = Cartridge::from_bytes(&rom(*b"NES\x1A\x01\x01\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00")).unwrap();
+    assert_eq!(cart.mapper_id(), 0);
+
+    // Mapper 254 is not supported
+    let result = Cartridge::from_bytes(&rom(*b"NES\x1A\x01\x01\xE0\xF0\x00\x00\x00\x00\x00\x00\x00\x00"));
+    assert_eq!(result.err(), Some(EmuError::UnsupportedMapper(0xFE)));
+
+    assert_eq!(Cartridge::from_bytes(b"NES\x1A").err(), Some(EmuError::RomTooSmall));
+    assert_eq!(Cartridge::from_bytes(&[0u8; 16]).err(), Some(EmuError::InvalidRom));
+    assert_eq!(Cartridge::from_bytes(b"NES\x1A\x01\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00").err(), Some(EmuError::RomTruncated));
+}
+
+#[test]
+fn garbage_in_the_header_padding_is_ignored() {
+    let cart = Cartridge::from_bytes(&rom(*b"NES\x1A\x01\x01\x00DiskDude!")).unwrap();
+    assert_eq!(cart.mapper_id(), 0);
+    assert_eq!(cart.info().warnings, ["garbage in header bytes 7 to 15 (\"DiskDude!\") ignored"]);
+}
+
+#[test]
+fn rom_info_describes_the_header() {
+    let mut emu = Nes::new();
+    assert_eq!(emu.rom_info(), None);
+
+    // NES 2.0, mapper 34 submapper 1, CHR RAM, battery, vertical, PAL, 16 bytes too many
+    let mut data = b"NES\x1A\x02\x00\x23\x28\x10\x00\x00\x00\x01\x00\x00\x00".to_vec();
+    data.resize(16 + 0x8000 + 16, 0);
+    emu.load_rom(&data).unwrap();
+
+    let info = emu.rom_info().unwrap();
+    assert_eq!((info.mapper, info.submapper, info.nes2), (34, 1, true));
+    assert_eq!((info.prg_rom_size, info.chr_rom_size), (0x8000, 0));
+    assert_eq!((info.mirroring, info.battery, info.trainer), (MIRROR::Vertical, true, false));
+    assert_eq!(info.region, Region::Pal);
+    assert_eq!(info.warnings, ["trailing data after the ROM ignored (16 bytes)"]);
+}
+
+#[test]
+fn load_rom_switches_the_console_on() {
+    let mut emu = Nes::new();
+    emu.load_program(&[0xEA], 0x0000).unwrap();
+
+    // The reset vector at $FFFC points to $C000
+    let mut data = rom(*b"NES\x1A\x01\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00");
+    data[16 + 0x3FFD] = 0xC0;
+    emu.load_rom(&data).unwrap();
+    assert_eq!(emu.get_registers()[4], 0xC000);
+    assert_eq!(emu.peek_memory(0x0000, 1).unwrap(), [0x00]);
+
+    // A broken file is reported and the game keeps running
+    assert_eq!(emu.load_rom(b"NES\x1A"), Err(EmuError::RomTooSmall));
+    assert_eq!(emu.load_rom(&data[1..]), Err(EmuError::InvalidRom));
+    assert_eq!(emu.rom_info().unwrap().prg_rom_size, 0x4000);
+    assert_eq!(emu.get_registers()[4], 0xC000);
+}
+
+// Board with the PRG ROM mirrored all over $8000-$FFFF, single screen
+// mirroring and the IRQ line stuck low
+struct CustomBoard;
+
+impl MapperInterface for CustomBoard {
+    fn cpu_map_read (&    self, addr: u16          ) -> Option<usize> { (addr >= 0x8000).then_some((addr & 0x3FFF) as usize) }
+    fn cpu_map_write(&mut self, _addr: u16, _data: u8) -> Option<usize> { None }
+    fn ppu_map_read (&    self, addr: u16          ) -> Option<usize> { (addr < 0x2000).then_some(addr as usize) }
+    fn ppu_map_write(&mut self, _addr: u16, _data: u8) -> Option<usize> { None }
+    fn reset(&mut self) {}
+
+    fn mirror(&self) -> Option<MIRROR> { Some(MIRROR::OnescreenHi) }
+    fn irq_state(&self) -> bool { true }
+}
+
+#[test]
+fn custom_boards_can_be_registered() {
+    let mut data = rom(*b"NES\x1A\x01\x01\xE0\xF0\x00\x00\x00\x00\x00\x00\x00\x00");
+    data[16 + 0x0123] = 0x99;
+
+    let mut mappers = MapperRegistry::new();
+    assert!(!mappers.is_supported(0xFE));
+    mappers.register(0xFE, |_, _| Box::new(CustomBoard));
+
+    let mut cart = Cartridge::from_bytes_with(&data, &mappers).unwrap();
+    assert_eq!(cart.mapper_id(), 0xFE);
+    assert_eq!(cart.read_cpu(0xC123), Some(0x99));
+    assert_eq!(cart.map_nametable_addr(0x2000), 0x0400);
+    assert!(cart.irq_state());
+
+    assert!(MapperRegistry::empty().create(0, 1, 1).is_err());
+    assert!(CustomBoard.load_state(&[]).is_ok());
+    assert_eq!(CustomBoard.load_state(&[1]), Err(EmuError::InvalidMapperState));
+}
+
+#[test]
+fn nrom_mirrors_16k_prg_and_ignores_rom_writes() {
+    let mut data = rom(*b"NES\x1A\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00");
+    data[16]          = 0x11;
+    data[16 + 0x3FFF] = 0x22;
+    let mut cart = Cartridge::from_bytes(&data).unwrap();
+
+    assert_eq!(cart.read_cpu(0x8000), Some(0x11));
+    assert_eq!(cart.read_cpu(0xC000), Some(0x11));
+    assert_eq!(cart.read_cpu(0xFFFF), Some(0x22));
+    assert_eq!(cart.read_cpu(0x5000), None);
+
+    cart.write_cpu(0x8000, 0x33);
+    assert_eq!(cart.read_cpu(0x8000), Some(0x11));
+
+    // Without CHR ROM the pattern memory is RAM
+    cart.write_ppu(0x1234, 0x44);
+    assert_eq!(cart.read_ppu(0x1234), Some(0x44));
+}
+
+#[test]
+fn cnrom_switches_chr_banks() {
+    // 32 KB PRG ROM, 2 CHR banks
+    let mut data = b"NES\x1A\x02\x02\x30\x00\x00\x00\x00\x00\x00\x00\x00\x00".to_vec();
+    data.resize(16 + 0x8000 + 0x4000, 0);
+    data[16 + 0x8000 + 0x2000 + 0x0010] = 0x77;
+    data[16 + 0x7FF0] = 0x02; // ROM bytes under the bank register writes below
+    data[16 + 0x7FF1] = 0x01;
+
+    let mut cart = Cartridge::from_bytes(&data).unwrap();
+    assert_eq!(cart.read_ppu(0x0010), Some(0x00));
+    cart.write_cpu(0xFFF0, 0x01);
+    assert_eq!(cart.read_ppu(0x0010), Some(0x77));
+
+    // With bus conflicts the board sees the written value ANDed with the ROM
+    let mut mappers = MapperRegistry::new();
+    mappers.register(3, |prg_banks, chr_banks| Box::new(Mapper003::new(prg_banks, chr_banks).with_bus_conflicts(true)));
+    let mut cart = Cartridge::from_bytes_with(&data, &mappers).unwrap();
+    cart.write_cpu(0xFFF0, 0x01);
+    assert_eq!(cart.read_ppu(0x0010), Some(0x00));
+    cart.write_cpu(0xFFF1, 0xFF);
+    assert_eq!(cart.read_ppu(0x0010), Some(0x77));
+}
+
+#[test]
+fn mmc3_banking_and_scanline_irq() {
+    // 128 KB PRG ROM (16 banks of 8 KB), 64 KB CHR ROM (64 banks of 1 KB),
+    // every bank is filled with its number
+    let mut data = b"NES\x1A\x08\x08\x40\x00\x00\x00\x00\x00\x00\x00\x00\x00".to_vec();
+    for bank in 0..16 { data.extend([bank as u8; 0x2000]); }
+    for bank in 0..64 { data.extend([bank as u8; 0x0400]); }
+    let mut cart = Cartridge::from_bytes(&data).unwrap();
+
+    // R6 = 3, R7 = 5
+    for (register, bank) in [(6, 3), (7, 5)] {
+        cart.write_cpu(0x8000, register);
+        cart.write_cpu(0x8001, bank);
+    }
+    let prg = |cart: &mut Cartridge| [0x8000, 0xA000, 0xC000, 0xE000].map(|addr| cart.read_cpu(addr).unwrap());
+    assert_eq!(prg(&mut cart), [3, 5, 14, 15]);
+    cart.write_cpu(0x8000, 0x46);
+    assert_eq!(prg(&mut cart), [14, 5, 3, 15]);
+
+    // R0 = 2 KB at $0000, R2 = 1 KB at $1000, swapped with bit 7
+    for (register, bank) in [(0, 9), (2, 20)] {
+        cart.write_cpu(0x8000, register);
+        cart.write_cpu(0x8001, bank);
+    }
+    assert_eq!([cart.read_ppu(0x0000), cart.read_ppu(0x0400), cart.read_ppu(0x1000)], [Some(8), Some(9), Some(20)]);
+    cart.write_cpu(0x8000, 0x80);
+    assert_eq!([cart.read_ppu(0x0000), cart.read_ppu(0x1000), cart.read_ppu(0x1400)], [Some(20), Some(8), Some(9)]);
+
+    cart.write_cpu(0xA000, 0x01);
+    assert_eq!(cart.map_nametable_addr(0x2400), 0x0000);
+
+    // PRG RAM is write protected with bit 6 and disabled without bit 7
+    cart.write_cpu(0x6000, 0x12);
+    cart.write_cpu(0xA001, 0xC0);
+    cart.write_cpu(0x6000, 0x34);
+    assert_eq!(cart.read_cpu(0x6000), Some(0x12));
+    cart.write_cpu(0xA001, 0x00);
+    assert_eq!(cart.read_cpu(0x6000), None);
+
+    // IRQ after 3 scanlines, the first rise loads the latch
+    let state = cart.mapper_state();
+    cart.write_cpu(0xC000, 2);
+    cart.write_cpu(0xC001, 0);
+    cart.write_cpu(0xE001, 0);
+    for line in 0..3 {
+        assert!(!cart.irq_state(), "scanline {}", line);
+        cart.ppu_a12(false);
+        cart.ppu_a12(true);
+    }
+    assert!(cart.irq_state());
+    cart.write_cpu(0xE000, 0);
+    assert!(!cart.irq_state());
+
+    let mut mmc3 = Mapper004::new(8, 8);
+    assert!(mmc3.load_state(&state).is_ok());
+    assert_eq!(mmc3.save_state(), state);
+    assert_eq!(mmc3.load_state(&state[1..]), Err(EmuError::InvalidMapperState));
+}
+
+#[test]
+fn axrom_switches_32k_banks_and_the_nametable_page() {
+    // 128 KB PRG ROM, each 32 KB bank filled with its number, CHR RAM
+    let mut data = b"NES\x1A\x08\x00\x70\x00\x00\x00\x00\x00\x00\x00\x00\x00".to_vec();
+    for bank in 0..4 { data.extend(vec![bank as u8; 0x8000]); }
+    let mut cart = Cartridge::from_bytes(&data).unwrap();
+
+    assert_eq!((cart.read_cpu(0x8000), cart.read_cpu(0xFFFF)), (Some(0), Some(0)));
+    assert_eq!(cart.map_nametable_addr(0x2C00), 0x0000);
+
+    cart.write_cpu(0x8000, 0x12);
+    assert_eq!((cart.read_cpu(0x8000), cart.read_cpu(0xFFFF)), (Some(2), Some(2)));
+    assert_eq!(cart.map_nametable_addr(0x2000), 0x0400);
+
+    cart.write_ppu(0x0123, 0x45);
+    assert_eq!(cart.read_ppu(0x0123), Some(0x45));
+}
+
+#[test]
+fn axrom_mirrors_16k_prg() {
+    // 16 KB PRG ROM, CHR RAM
+    let mut data = b"NES\x1A\x01\x00\x70\x00\x00\x00\x00\x00\x00\x00\x00\x00".to_vec();
+    data.extend(vec![0x11; 0x4000]);
+    data[16 + 0x3FFF] = 0x22;
+    let mut cart = Cartridge::from_bytes(&data).unwrap();
+
+    cart.write_cpu(0x8000, 0x07);
+    assert_eq!((cart.read_cpu(0x8000), cart.read_cpu(0xBFFF)), (Some(0x11), Some(0x22)));
+    assert_eq!((cart.read_cpu(0xC000), cart.read_cpu(0xFFFF)), (Some(0x11), Some(0x22)));
+}
+
+#[test]
+fn mmc2_switches_chr_banks_on_latch_tiles() {
+    // 128 KB PRG ROM (16 banks of 8 KB), 128 KB CHR ROM (32 banks of 4 KB)
+    let mut data = b"NES\x1A\x08\x10\x90\x00\x00\x00\x00\x00\x00\x00\x00\x00".to_vec();
+    for bank in 0..16 { data.extend([bank as u8; 0x2000]); }
+    for bank in 0..32 { data.extend([bank as u8; 0x1000]); }
+    let mut cart = Cartridge::from_bytes(&data).unwrap();
+
+    cart.write_cpu(0xA000, 0x05);
+    let prg = |cart: &mut Cartridge| [0x8000, 0xA000, 0xC000, 0xE000].map(|addr| cart.read_cpu(addr).unwrap());
+    assert_eq!(prg(&mut cart), [5, 13, 14, 15]);
+
+    for (addr, bank) in [(0xB000, 1), (0xC000, 2), (0xD000, 3), (0xE000, 4)] {
+        cart.write_cpu(addr, bank);
+    }
+    assert_eq!((cart.read_ppu(0x0000), cart.read_ppu(0x1000)), (Some(2), Some(4)));
+
+    // Table 0 only reacts to the exact address, table 1 to the whole tile
+    cart.ppu_fetch(0x0FD9);
+    assert_eq!(cart.read_ppu(0x0000), Some(2));
+    cart.ppu_fetch(0x0FD8);
+    cart.ppu_fetch(0x1FDA);
+    assert_eq!((cart.read_ppu(0x0000), cart.read_ppu(0x1000)), (Some(1), Some(3)));
+
+    let state = cart.mapper_state();
+    cart.ppu_fetch(0x1FE8);
+    assert_eq!(cart.read_ppu(0x1000), Some(4));
+    cart.load_mapper_state(&state).unwrap();
+    assert_eq!(cart.read_ppu(0x1000), Some(3));
+}
+
+#[test]
+fn mmc4_switches_16k_prg_and_latches_whole_tiles() {
+    // 128 KB PRG ROM (8 banks of 16 KB), 128 KB CHR ROM (32 banks of 4 KB)
+    let mut data = b"NES\x1A\x08\x10\xA0\x00\x00\x00\x00\x00\x00\x00\x00\x00".to_vec();
+    for bank in 0..8  { data.extend(vec![bank as u8; 0x4000]); }
+    for bank in 0..32 { data.extend([bank as u8; 0x1000]); }
+    let mut cart = Cartridge::from_bytes(&data).unwrap();
+
+    cart.write_cpu(0xA000, 0x03);
+    assert_eq!((cart.read_cpu(0x8000), cart.read_cpu(0xBFFF), cart.read_cpu(0xC000)), (Some(3), Some(3), Some(7)));
+
+    cart.write_cpu(0xB000, 5);
+    cart.write_cpu(0xC000, 6);
+    assert_eq!(cart.read_ppu(0x0000), Some(6));
+    cart.ppu_fetch(0x0FDB);
+    assert_eq!(cart.read_ppu(0x0000), Some(5));
+    cart.ppu_fetch(0x0FEF);
+    assert_eq!(cart.read_ppu(0x0000), Some(6));
+
+    // PRG RAM
+    cart.write_cpu(0x7000, 0x42);
+    assert_eq!(cart.read_cpu(0x7000), Some(0x42));
+}
+
+#[test]
+fn mmc2_wraps_fixed_banks_on_16k_prg() {
+    // 16 KB PRG ROM (2 banks of 8 KB), 8 KB CHR ROM
+    let mut data = b"NES\x1A\x01\x01\x90\x00\x00\x00\x00\x00\x00\x00\x00\x00".to_vec();
+    for bank in 0..2 { data.extend([bank as u8; 0x2000]); }
+    data.extend([0; 0x2000]);
+    let mut cart = Cartridge::from_bytes(&data).unwrap();
+
+    cart.write_cpu(0xA000, 0x01);
+    let prg = |cart: &mut Cartridge| [0x8000, 0xA000, 0xC000, 0xE000].map(|addr| cart.read_cpu(addr).unwrap());
+    assert_eq!(prg(&mut cart), [1, 1, 0, 1]);
+}
+
+#[test]
+fn color_dreams_banks_through_bus_conflicts() {
+    // 128 KB PRG ROM (4 banks of 32 KB), 64 KB CHR ROM (8 banks of 8 KB)
+    let mut data = b"NES\x1A\x08\x08\xB0\x00\x00\x00\x00\x00\x00\x00\x00\x00".to_vec();
+    for bank in 0..4 { data.extend(vec![bank as u8; 0x8000]); }
+    for bank in 0..8 { data.extend(vec![bank as u8; 0x2000]); }
+    // Table of all values in every PRG bank, as the games have it
+    for bank in 0..4 {
+        for value in 0..=255u8 { data[16 + bank * 0x8000 + 0x7E00 + value as usize] = value; }
+    }
+    let mut cart = Cartridge::from_bytes(&data).unwrap();
+
+    cart.write_cpu(0xFE32, 0x32);
+    assert_eq!((cart.read_cpu(0x8000), cart.read_ppu(0x0000)), (Some(2), Some(3)));
+
+    // The ROM holds 2 at $8000, so only bit 1 gets through
+    cart.write_cpu(0x8000, 0xFF);
+    assert_eq!((cart.read_cpu(0x8000), cart.read_ppu(0x0000)), (Some(2), Some(0)));
+}
+
+#[test]
+fn color_dreams_mirrors_16k_prg() {
+    // 16 KB PRG ROM, 16 KB CHR ROM (2 banks of 8 KB)
+    let mut data = b"NES\x1A\x01\x02\xB0\x00\x00\x00\x00\x00\x00\x00\x00\x00".to_vec();
+    data.extend(vec![0xFF; 0x4000]);
+    for bank in 0..2 { data.extend(vec![bank as u8; 0x2000]); }
+    data[16 + 0x3FF0] = 0x10;
+    let mut cart = Cartridge::from_bytes(&data).unwrap();
+
+    // The bus conflict reads the mirrored byte back from $BFF0
+    cart.write_cpu(0xFFF0, 0xFF);
+    assert_eq!((cart.read_cpu(0xBFF0), cart.read_cpu(0xFFF0), cart.read_ppu(0x0000)), (Some(0x10), Some(0x10), Some(1)));
+}
+
+#[test]
+fn gxrom_switches_prg_and_chr_together() {
+    // 128 KB PRG ROM (4 banks of 32 KB), 32 KB CHR ROM (4 banks of 8 KB)
+    let mut data = b"NES\x1A\x08\x04\x20\x40\x00\x00\x00\x00\x00\x00\x00\x00".to_vec();
+    for bank in 0..4 { data.extend(vec![bank as u8; 0x8000]); }
+    for bank in 0..4 { data.extend(vec![bank as u8; 0x2000]); }
+    let mut cart = Cartridge::from_bytes(&data).unwrap();
+
+    assert_eq!((cart.read_cpu(0xFFFF), cart.read_ppu(0x1FFF)), (Some(0), Some(0)));
+    cart.write_cpu(0x8000, 0x21);
+    assert_eq!((cart.read_cpu(0x8000), cart.read_cpu(0xFFFF), cart.read_ppu(0x0000)), (Some(2), Some(2), Some(1)));
+}
+
+#[test]
+fn gxrom_mirrors_16k_prg() {
+    // 16 KB PRG ROM, 16 KB CHR ROM (2 banks of 8 KB)
+    let mut data = b"NES\x1A\x01\x02\x20\x40\x00\x00\x00\x00\x00\x00\x00\x00".to_vec();
+    data.extend(vec![0x11; 0x4000]);
+    for bank in 0..2 { data.extend(vec![bank as u8; 0x2000]); }
+    data[16 + 0x3FFF] = 0x22;
+    let mut cart = Cartridge::from_bytes(&data).unwrap();
+
+    cart.write_cpu(0x8000, 0x31);
+    assert_eq!((cart.read_cpu(0x8000), cart.read_cpu(0xBFFF)), (Some(0x11), Some(0x22)));
+    assert_eq!((cart.read_cpu(0xC000), cart.read_cpu(0xFFFF), cart.read_ppu(0x0000)), (Some(0x11), Some(0x22), Some(1)));
+}
+
+#[test]
+fn camerica_switches_prg_and_fire_hawk_mirroring() {
+    // 128 KB PRG ROM (8 banks of 16 KB), CHR RAM, vertical mirroring
+    let mut data = b"NES\x1A\x08\x00\x71\x40\x00\x00\x00\x00\x00\x00\x00\x00".to_vec();
+    for bank in 0..8 { data.extend(vec![bank as u8; 0x4000]); }
+    let mut cart = Cartridge::from_bytes(&data).unwrap();
+
+    cart.write_cpu(0xC000, 0x02);
+    assert_eq!((cart.read_cpu(0x8000), cart.read_cpu(0xC000)), (Some(2), Some(7)));
+    assert_eq!(cart.map_nametable_addr(0x2400), 0x0400);
+
+    cart.write_cpu(0x9000, 0x10);
+    assert_eq!(cart.map_nametable_addr(0x2000), 0x0400);
+    let state = cart.save_state();
+    cart.write_cpu(0x9000, 0x00);
+    assert_eq!(cart.map_nametable_addr(0x2400), 0x0000);
+
+    // The page survives a save state
+    cart.load_state(&state).unwrap();
+    assert_eq!(cart.map_nametable_addr(0x2000), 0x0400);
+}
+
+#[test]
+fn vrc7_banking_irq_and_fm_audio() {
+    // 128 KB PRG ROM (16 banks of 8 KB), 64 KB CHR ROM (64 banks of 1 KB)
+    let mut data = b"NES\x1A\x08\x08\x50\x50\x00\x00\x00\x00\x00\x00\x00\x00".to_vec();
+    for bank in 0..16 { data.extend([bank as u8; 0x2000]); }
+    for bank in 0..64 { data.extend([bank as u8; 0x0400]); }
+    let mut cart = Cartridge::from_bytes(&data).unwrap();
+
+    // VRC7a and VRC7b address the second register differently
+    cart.write_cpu(0x8000, 3);
+    cart.write_cpu(0x8010, 4);
+    cart.write_cpu(0x9000, 5);
+    let prg = |cart: &mut Cartridge| [0x8000, 0xA000, 0xC000, 0xE000].map(|addr| cart.read_cpu(addr).unwrap());
+    assert_eq!(prg(&mut cart), [3, 4, 5, 15]);
+    cart.write_cpu(0x8008, 6);
+    assert_eq!(prg(&mut cart), [3, 6, 5, 15]);
+
+    cart.write_cpu(0xA000, 10);
+    cart.write_cpu(0xD008, 20);
+    assert_eq!((cart.read_ppu(0x0000), cart.read_ppu(0x1C00)), (Some(10), Some(20)));
+
+    // Mirroring and PRG RAM enable
+    cart.write_cpu(0xE000, 0x81);
+    assert_eq!(cart.map_nametable_addr(0x2400), 0x0000);
+    cart.write_cpu(0x6000, 0x42);
+    assert_eq!(cart.read_cpu(0x6000), Some(0x42));
+
+    // IRQ in CPU cycle mode, the counter counts up from the latch
+    cart.write_cpu(0xE010, 0xFD);
+    cart.write_cpu(0xF000, 0x06);
+    for _ in 0..2 { cart.cpu_clock(); }
+    assert!(!cart.irq_state());
+    cart.cpu_clock();
+    assert!(cart.irq_state());
+    cart.write_cpu(0xF010, 0);
+    assert!(!cart.irq_state());
+
+    // Instrument 1 at full volume on channel 0
+    for (reg, value) in [(0x10, 0xAC), (0x30, 0x10), (0x20, 0x18)] {
+        cart.write_cpu(0x9010, reg);
+        cart.write_cpu(0x9030, value);
+    }
+    let mut peak: f32 = 0.0;
+    for _ in 0..36 * 500 {
+        cart.cpu_clock();
+        peak = peak.max(cart.audio_output().abs());
+    }
+    assert!(peak > 0.01 && peak <= 1.0, "peak {}", peak);
+    assert_eq!(cart.audio_chip(), Some(ExpansionChip::Vrc7));
+
+    // Held in reset the chip is silent
+    cart.write_cpu(0xE000, 0x40);
+    cart.cpu_clock();
+    assert_eq!(cart.audio_output(), 0.0);
+
+    let state = cart.mapper_state();
+    assert!(cart.load_mapper_state(&state).is_ok());
+    assert!(cart.load_mapper_state(&state[..20]).is_err());
+}
+
+#[test]
+fn namco163_banking_irq_and_wavetable_audio() {
+    // 128 KB PRG ROM (16 banks of 8 KB), 64 KB CHR ROM (64 banks of 1 KB)
+    let mut data = b"NES\x1A\x08\x08\x30\x10\x00\x00\x00\x00\x00\x00\x00\x00".to_vec();
+    for bank in 0..16 { data.extend([bank as u8; 0x2000]); }
+    for bank in 0..64 { data.extend([bank as u8; 0x0400]); }
+    let mut cart = Cartridge::from_bytes(&data).unwrap();
+
+    cart.write_cpu(0xE000, 3);
+    cart.write_cpu(0xE800, 4);
+    cart.write_cpu(0xF000, 5);
+    let prg = [0x8000, 0xA000, 0xC000, 0xE000].map(|addr| cart.read_cpu(addr).unwrap());
+    assert_eq!(prg, [3, 4, 5, 15]);
+
+    cart.write_cpu(0x8000, 10);
+    cart.write_cpu(0xB800, 20);
+    assert_eq!((cart.read_ppu(0x0000), cart.read_ppu(0x1C00)), (Some(10), Some(20)));
+
+    // Nametables from the console's VRAM
+    for (addr, page) in [(0xC000, 0xE0), (0xC800, 0xE0), (0xD000, 0xE1), (0xD800, 0xE1)] {
+        cart.write_cpu(addr, page);
+    }
+    assert_eq!(cart.map_nametable_addr(0x2800), 0x0400);
+
+    // PRG RAM is write protected until 0x4X is written to 0xF800
+    cart.write_cpu(0x6000, 0x42);
+    assert_eq!(cart.read_cpu(0x6000), Some(0x00));
+    cart.write_cpu(0xF800, 0x40);
+    cart.write_cpu(0x6000, 0x42);
+    assert_eq!(cart.read_cpu(0x6000), Some(0x42));
+
+    // The IRQ counter counts up to 0x7FFF and can be read back
+    cart.write_cpu(0x5000, 0xFD);
+    cart.write_cpu(0x5800, 0xFF);
+    cart.cpu_clock();
+    assert_eq!((cart.read_cpu(0x5000), cart.read_cpu(0x5800)), (Some(0xFE), Some(0xFF)));
+    assert!(!cart.irq_state());
+    cart.cpu_clock();
+    assert!(cart.irq_state());
+    cart.write_cpu(0x5800, 0x00);
+    assert!(!cart.irq_state());
+
+    // A square wave at 0x00 on the last channel, written with auto increment
+    cart.write_cpu(0xF800, 0x80);
+    for _ in 0..4 { cart.write_cpu(0x4800, 0xFF); }
+    cart.write_cpu(0xF800, 0xF8);
+    for value in [0x00, 0x00, 0x00, 0x00, 0xF0, 0x00, 0x00, 0x0F] {
+        cart.write_cpu(0x4800, value);
+    }
+    cart.write_cpu(0xF800, 0xFC);
+    assert_eq!(cart.peek_cpu(0x4800), Some(0xF0));
+    assert_eq!(cart.read_cpu(0x4800), Some(0xF0));
+    assert_eq!(cart.read_cpu(0x4800), Some(0x00));
+
+    // Frequency 0 keeps the phase at the start of the wave, where the samples are 15
+    for _ in 0..15 { cart.cpu_clock(); }
+    assert_eq!(cart.audio_output(), 7.0 * 15.0 / 120.0);
+    assert_eq!(cart.audio_chip(), Some(ExpansionChip::N163));
+
+    cart.write_cpu(0xE000, 0x40);
+    assert_eq!(cart.audio_output(), 0.0);
+
+    let state = cart.mapper_state();
+    assert!(cart.load_mapper_state(&state).is_ok());
+    assert!(cart.load_mapper_state(&state[..20]).is_err());
+}
+
+#[test]
+fn fme7_banking_irq_and_5b_audio() {
+    // 128 KB PRG ROM (16 banks of 8 KB), 64 KB CHR ROM (64 banks of 1 KB)
+    let mut data = b"NES\x1A\x08\x08\x50\x40\x00\x00\x00\x00\x00\x00\x00\x00".to_vec();
+    for bank in 0..16 { data.extend([bank as u8; 0x2000]); }
+    for bank in 0..64 { data.extend([bank as u8; 0x0400]); }
+    let mut cart = Cartridge::from_bytes(&data).unwrap();
+    let command = |cart: &mut Cartridge, command: u8, value: u8| {
+        cart.write_cpu(0x8000, command);
+        cart.write_cpu(0xA000, value);
+    };
+
+    for (i, bank) in [(0x8, 2), (0x9, 3), (0xA, 4), (0xB, 5)] { command(&mut cart, i, bank); }
+    let prg = |cart: &mut Cartridge| [0x6000, 0x8000, 0xA000, 0xC000, 0xE000].map(|addr| cart.read_cpu(addr).unwrap());
+    assert_eq!(prg(&mut cart), [2, 3, 4, 5, 15]);
+
+    command(&mut cart, 0x0, 10);
+    command(&mut cart, 0x7, 20);
+    assert_eq!((cart.read_ppu(0x0000), cart.read_ppu(0x1C00)), (Some(10), Some(20)));
+
+    command(&mut cart, 0xC, 1);
+    assert_eq!(cart.map_nametable_addr(0x2400), 0x0000);
+
+    // PRG RAM at 0x6000 once it is selected and enabled
+    command(&mut cart, 0x8, 0x40);
+    assert_eq!(cart.read_cpu(0x6000), None);
+    command(&mut cart, 0x8, 0xC0);
+    cart.write_cpu(0x6000, 0x42);
+    assert_eq!(cart.read_cpu(0x6000), Some(0x42));
+
+    // The IRQ fires when the counter wraps from 0 to 0xFFFF
+    command(&mut cart, 0xE, 0x01);
+    command(&mut cart, 0xF, 0x00);
+    command(&mut cart, 0xD, 0x81);
+    cart.cpu_clock();
+    assert!(!cart.irq_state());
+    cart.cpu_clock();
+    assert!(cart.irq_state());
+    command(&mut cart, 0xD, 0x00);
+    assert!(!cart.irq_state());
+
+    // A square wave on channel A at full volume
+    for (reg, value) in [(0x00, 0x10), (0x07, 0x3E), (0x08, 0x0F)] {
+        cart.write_cpu(0xC000, reg);
+        cart.write_cpu(0xE000, value);
+    }
+    let mut levels = Vec::new();
+    for _ in 0..16 * 0x10 * 4 {
+        cart.cpu_clock();
+        levels.push(cart.audio_output());
+    }
+    let peak = levels.iter().cloned().fold(0.0, f32::max);
+    assert!(peak > 0.3 && peak <= 1.0, "peak {}", peak);
+    assert!(levels.contains(&0.0));
+
+    let state = cart.mapper_state();
+    assert!(cart.load_mapper_state(&state).is_ok());
+    assert!(cart.load_mapper_state(&state[..20]).is_err());
+}
+
+#[test]
+fn namco118_banks_like_mmc3_without_the_extras() {
+    // 128 KB PRG ROM (16 banks of 8 KB), 64 KB CHR ROM (64 banks of 1 KB), vertical mirroring
+    let mut data = b"NES\x1A\x08\x08\xE1\xC0\x00\x00\x00\x00\x00\x00\x00\x00".to_vec();
+    for bank in 0..16 { data.extend([bank as u8; 0x2000]); }
+    for bank in 0..64 { data.extend([bank as u8; 0x0400]); }
+    let mut cart = Cartridge::from_bytes(&data).unwrap();
+    assert_eq!(cart.mapper_id(), 206);
+
+    // The mode bits of the bank select are not connected
+    for (register, bank) in [(0xC6, 3), (0xC7, 4), (0xC0, 10), (0xC5, 20)] {
+        cart.write_cpu(0x8000, register);
+        cart.write_cpu(0x8001, bank);
+    }
+    let prg = [0x8000, 0xA000, 0xC000, 0xE000].map(|addr| cart.read_cpu(addr).unwrap());
+    assert_eq!(prg, [3, 4, 14, 15]);
+    assert_eq!((cart.read_ppu(0x0000), cart.read_ppu(0x0400), cart.read_ppu(0x1C00)), (Some(10), Some(11), Some(20)));
+
+    // Neither mirroring control nor IRQ
+    cart.write_cpu(0xA000, 0x01);
+    assert_eq!(cart.map_nametable_addr(0x2800), 0x0000);
+    cart.write_cpu(0xC000, 0x00);
+    cart.write_cpu(0xE001, 0x00);
+    for _ in 0..2 { cart.ppu_a12(false); cart.ppu_a12(true); }
+    assert!(!cart.irq_state());
+}
+
+#[test]
+fn mapper34_is_bnrom_or_nina001() {
+    // BNROM: 64 KB PRG ROM (2 banks of 32 KB), CHR RAM, bus conflicts
+    let mut data = b"NES\x1A\x04\x00\x20\x20\x00\x00\x00\x00\x00\x00\x00\x00".to_vec();
+    for bank in 0..2u8 {
+        let mut prg = [bank; 0x8000];
+        prg[0x7FF0] = 0xFF;
+        data.extend(prg);
+    }
+    let mut cart = Cartridge::from_bytes(&data).unwrap();
+    cart.write_cpu(0x8000, 1);
+    assert_eq!(cart.read_cpu(0x8000), Some(0));
+    cart.write_cpu(0xFFF0, 1);
+    assert_eq!(cart.read_cpu(0x8000), Some(1));
+    cart.write_ppu(0x1000, 0x42);
+    assert_eq!(cart.read_ppu(0x1000), Some(0x42));
+
+    // NINA-001 named by the NES 2.0 submapper, though it only has 8 KB of CHR ROM
+    let mut data = b"NES\x1A\x04\x01\x20\x28\x10\x00\x00\x00\x00\x00\x00\x00".to_vec();
+    for bank in 0..2 { data.extend([bank as u8; 0x8000]); }
+    for bank in 0..2 { data.extend([bank as u8 + 0x10; 0x1000]); }
+    let mut cart = Cartridge::from_bytes(&data).unwrap();
+    cart.write_cpu(0x7FFD, 1);
+    cart.write_cpu(0x7FFE, 1);
+    cart.write_cpu(0x7FFF, 0);
+    assert_eq!(cart.read_cpu(0x8000), Some(1));
+    assert_eq!((cart.read_ppu(0x0000), cart.read_ppu(0x1000)), (Some(0x11), Some(0x10)));
+    // The registers are backed by the PRG RAM
+    assert_eq!(cart.read_cpu(0x7FFD), Some(1));
+
+    let state = cart.mapper_state();
+    assert!(cart.load_mapper_state(&state).is_ok());
+    assert!(cart.load_mapper_state(&state[..2]).is_err());
+}
+
+#[test]
+fn mapper34_mirrors_16k_prg() {
+    // BNROM: 16 KB PRG ROM, CHR RAM, bus conflicts
+    let mut data = b"NES\x1A\x01\x00\x20\x20\x00\x00\x00\x00\x00\x00\x00\x00".to_vec();
+    data.extend(vec![0x11; 0x4000]);
+    data[16 + 0x3FF0] = 0xFF;
+    let mut cart = Cartridge::from_bytes(&data).unwrap();
+    cart.write_cpu(0xFFF0, 1);
+    assert_eq!((cart.read_cpu(0x8000), cart.read_cpu(0xC000), cart.read_cpu(0xFFF0)), (Some(0x11), Some(0x11), Some(0xFF)));
+
+    // NINA-001: 16 KB PRG ROM, 8 KB CHR ROM
+    let mut data = b"NES\x1A\x01\x01\x20\x28\x10\x00\x00\x00\x00\x00\x00\x00".to_vec();
+    data.extend(vec![0x11; 0x4000]);
+    data.extend([0; 0x2000]);
+    data[16 + 0x3FFF] = 0x22;
+    let mut cart = Cartridge::from_bytes(&data).unwrap();
+    cart.write_cpu(0x7FFD, 1);
+    assert_eq!((cart.read_cpu(0x8000), cart.read_cpu(0xC000), cart.read_cpu(0xFFFF)), (Some(0x11), Some(0x11), Some(0x22)));
+}
+
+#[test]
+fn battery_ram_can_be_exported_and_imported() {
+    let mut emu = Nes::new();
+    emu.load_rom(&rom(*b"NES\x1A\x01\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00")).unwrap();
+    assert_eq!(emu.export_sram(), None);
+    assert_eq!(emu.import_sram(&[0; 0x2000]), Err(EmuError::NoBatteryRam));
+
+    // Flags 6 bit 1: battery
+    emu.load_rom(&rom(*b"NES\x1A\x01\x01\x02\x00\x00\x00\x00\x00\x00\x00\x00\x00")).unwrap();
+    let mut sram = vec![0; 0x2000];
+    sram[0x0010] = 0x42;
+    assert_eq!(emu.import_sram(&sram[..0x100]), Err(EmuError::SaveRamSize { expected: 0x2000, len: 0x100 }));
+    emu.import_sram(&sram).unwrap();
+    assert_eq!(emu.peek_memory(0x6010, 1).unwrap(), [0x42]);
+    assert_eq!(emu.export_sram(), Some(sram));
+}
+
+#[test]
+fn trainer_is_loaded_to_0x7000() {
+    // Flags 6 bit 2: 512 byte trainer before the PRG ROM
+    let mut data = b"NES\x1A\x01\x01\x04\x00\x00\x00\x00\x00\x00\x00\x00\x00".to_vec();
+    data.extend([0xAA; 512]);
+    data.extend([0x11; 0x4000]);
+    data.extend([0x22; 0x2000]);
+    let mut cart = Cartridge::from_bytes(&data).unwrap();
+    assert_eq!((cart.read_cpu(0x6FFF), cart.read_cpu(0x7000), cart.read_cpu(0x71FF)), (Some(0x00), Some(0xAA), Some(0xAA)));
+    assert_eq!((cart.read_cpu(0x8000), cart.read_ppu(0x0000)), (Some(0x11), Some(0x22)));
+
+    assert_eq!(Cartridge::from_bytes(&data[..16 + 256]).err(), Some(EmuError::RomTruncated));
+}
+
+#[test]
+fn four_screen_boards_bring_their_own_vram() {
+    let pages = |mirror: MIRROR| [0x2000, 0x2400, 0x2800, 0x2C00].map(|addr| mirror.nametable_page(addr));
+    assert_eq!(pages(MIRROR::Horizontal),  [0, 0, 1, 1]);
+    assert_eq!(pages(MIRROR::Vertical),    [0, 1, 0, 1]);
+    assert_eq!(pages(MIRROR::OnescreenHi), [1, 1, 1, 1]);
+    assert_eq!(pages(MIRROR::FourScreen),  [0, 1, 2, 3]);
+
+    // Flags 6 bit 3: four screen, here on an MMC3 (Rad Racer II)
+    let mut cart = Cartridge::from_bytes(&rom(*b"NES\x1A\x01\x01\x48\x00\x00\x00\x00\x00\x00\x00\x00\x00")).unwrap();
+    cart.write_cpu(0xA000, 0x01);
+    assert_eq!(cart.mirroring(), MIRROR::FourScreen);
+
+    // Nametables 0 and 1 are in the console, 2 and 3 on the board
+    assert_eq!(cart.map_nametable_addr(0x2400), 0x0400);
+    assert_eq!(cart.read_ppu(0x2400), None);
+    cart.write_ppu(0x2800, 0x12);
+    cart.write_ppu(0x2C00, 0x34);
+    assert_eq!((cart.read_ppu(0x2800), cart.read_ppu(0x3C00)), (Some(0x12), Some(0x34)));
+}
@@ -0,0 +1,105 @@
+use nes_emulator::clock::{MasterClock, TvSystem};
+use nes_emulator::Nes;
+
+mod common;
+use common::idle_nrom;
+
+// The idle game, byte 9 of the header selects PAL
+fn rom(pal: bool) -> Vec<u8> {
+    let mut rom = idle_nrom();
+    rom[9] = pal as u8;
+    rom
+}
+
+// CPU cycles of two frames, after the one the reset started in
+fn cycles_of_two_frames(emu: &mut Nes) -> u64 {
+    emu.run_frame();
+    let start = emu.get_counters().0;
+    emu.run_frame();
+    emu.run_frame();
+    emu.get_counters().0 - start
+}
+
+#[test]
+fn frame_rates_follow_the_region() {
+    assert!((TvSystem::Ntsc.frame_rate()  - 60.0988).abs() < 0.0001);
+    assert!((TvSystem::Pal.frame_rate()   - 50.0070).abs() < 0.0001);
+    assert!((TvSystem::Dendy.frame_rate() - 50.0070).abs() < 0.0001);
+    assert!((TvSystem::Pal.cpu_clock_rate() - 1_662_607.0).abs() < 1.0);
+}
+
+#[test]
+fn master_clock_divides_into_cpu_cycles() {
+    let cpu_cycles = |tv_system, dots| {
+        let mut clock = MasterClock::new(tv_system);
+        (0..dots).filter(|_| clock.tick()).count()
+    };
+    // 3 dots per CPU cycle on NTSC and Dendy, 3.2 on PAL
+    assert_eq!(cpu_cycles(TvSystem::Ntsc,  300), 100);
+    assert_eq!(cpu_cycles(TvSystem::Dendy, 300), 100);
+    assert_eq!(cpu_cycles(TvSystem::Pal,   16), 5);
+    assert_eq!(cpu_cycles(TvSystem::Pal,   320), 100);
+
+    // The first cycle after the reset is even
+    let mut clock = MasterClock::new(TvSystem::Pal);
+    assert!(clock.tick());
+    assert!(!clock.odd_cpu_cycle());
+    while !clock.tick() {}
+    assert!(clock.odd_cpu_cycle());
+}
+
+#[test]
+fn region_is_taken_from_the_rom_header() {
+    let mut emu = Nes::new();
+    assert_eq!(emu.tv_system(), TvSystem::Ntsc);
+
+    emu.load_rom(&rom(true)).unwrap();
+    assert_eq!(emu.tv_system(), TvSystem::Pal);
+    assert!((emu.frame_rate() - 50.007).abs() < 0.001);
+
+    // Forced by the host, also across ROM changes, until None gives it back
+    emu.set_tv_system(Some(TvSystem::Dendy));
+    emu.load_rom(&rom(false)).unwrap();
+    assert_eq!(emu.tv_system(), TvSystem::Dendy);
+    emu.set_tv_system(None);
+    assert_eq!(emu.tv_system(), TvSystem::Ntsc);
+}
+
+#[test]
+fn frames_take_as_long_as_in_the_region() {
+    // With rendering off no dot is skipped, 341 x 262 dots per frame
+    let mut emu = Nes::new();
+    emu.load_rom(&rom(false)).unwrap();
+    assert_eq!(cycles_of_two_frames(&mut emu), 2 * 341 * 262 / 3);
+
+    // 341 x 312 dots at 3.2 dots per CPU cycle
+    emu.load_rom(&rom(true)).unwrap();
+    assert_eq!(cycles_of_two_frames(&mut emu), 2 * 341 * 312 * 5 / 16);
+
+    emu.set_tv_system(Some(TvSystem::Dendy));
+    emu.load_rom(&rom(true)).unwrap();
+    assert_eq!(cycles_of_two_frames(&mut emu), 2 * 341 * 312 / 3);
+}
+
+#[test]
+fn dendy_starts_vblank_fifty_lines_late() {
+    let vblank_at = |emu: &mut Nes, scanline| {
+        while emu.get_ppu_timing().scanline != scanline {
+            emu.clock();
+        }
+        emu.peek_memory(0x2002, 1).unwrap()[0] & 0x80 != 0
+    };
+
+    let mut emu = Nes::new();
+    emu.set_tv_system(Some(TvSystem::Pal));
+    emu.load_rom(&rom(false)).unwrap();
+    assert!(vblank_at(&mut emu, 242));
+    assert!(vblank_at(&mut emu, 311));
+
+    emu.set_tv_system(Some(TvSystem::Dendy));
+    emu.load_rom(&rom(false)).unwrap();
+    assert!(!vblank_at(&mut emu, 290));
+    assert!(vblank_at(&mut emu, 292));
+    assert!(vblank_at(&mut emu, 311));
+    assert!(!vblank_at(&mut emu, 0));
+}
@@ -0,0 +1,37 @@
+// Fixtures shared by the integration tests, not every test uses all of them
+#![allow(dead_code)]
+
+// Strobes the pad in port 0 and writes its A button to the backdrop colour,
+// over and over
+pub const SHOW_BUTTON_A: [u8; 32] = [
+    0x78,                           // SEI, keep the frame counter IRQ out
+    0xA9, 0x01, 0x8D, 0x16, 0x40,   // LDA #$01, STA $4016
+    0xA9, 0x00, 0x8D, 0x16, 0x40,   // LDA #$00, STA $4016
+    0xAD, 0x16, 0x40, 0x29, 0x01,   // LDA $4016, AND #$01
+    0xA2, 0x3F, 0x8E, 0x06, 0x20,   // LDX #$3F, STX $2006
+    0xA2, 0x00, 0x8E, 0x06, 0x20,   // LDX #$00, STX $2006
+    0x8D, 0x07, 0x20,               // STA $2007
+    0x4C, 0x01, 0x00,               // JMP $0001
+];
+
+// Counts frames in $00 and writes the pad's A button to the backdrop colour
+pub const COUNT_AND_SHOW_A: [u8; 32] = [
+    0x78,                           // SEI
+    0xE6, 0x00,                     // INC $00
+    0xA9, 0x01, 0x8D, 0x16, 0x40,   // LDA #$01, STA $4016
+    0x4E, 0x16, 0x40,               // LSR $4016 (strobe low)
+    0xAD, 0x16, 0x40, 0x29, 0x01,   // LDA $4016, AND #$01
+    0xA2, 0x3F, 0x8E, 0x06, 0x20,   // LDX #$3F, STX $2006
+    0xA2, 0x00, 0x8E, 0x06, 0x20,   // LDX #$00, STX $2006
+    0x8D, 0x07, 0x20,               // STA $2007
+    0x4C, 0x01, 0x00,               // JMP $0001
+];
+
+// iNES file of an NROM game that runs SEI, JMP $C001
+pub fn idle_nrom() -> Vec<u8> {
+    let mut rom = b"NES\x1A\x01\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00".to_vec();
+    rom.resize(16 + 0x4000 + 0x2000, 0);
+    rom[16..20].copy_from_slice(&[0x78, 0x4C, 0x01, 0xC0]);
+    rom[16 + 0x3FFD] = 0xC0;
+    rom
+}
@@ -0,0 +1,142 @@
+use nes_emulator::interfaces::BusInterface;
+use nes_emulator::bus::Bus;
+use nes_emulator::cartridge::EmptyCartridge;
+use nes_emulator::error::EmuError;
+use nes_emulator::controller::{Controllers, DeviceKind, BUTTON_A, BUTTON_B, BUTTON_SELECT, BUTTON_START, BUTTON_UP, BUTTON_LEFT, BUTTON_RIGHT};
+
+// The lowest bits of eight reads
+fn read_report(bus: &mut Bus, addr: u16) -> Vec<u8> {
+    (0..8).map(|_| bus.read(addr, false) & 0x01).collect()
+}
+
+#[test]
+fn buttons_are_shifted_out_in_order() {
+    let mut bus = Bus::new(Box::new(EmptyCartridge));
+    bus.set_buttons(0, BUTTON_A | BUTTON_START | BUTTON_RIGHT);
+    bus.write(0x4016, 0x01);
+    bus.write(0x4016, 0x00);
+
+    assert_eq!(read_report(&mut bus, 0x4016), vec![1, 0, 0, 1, 0, 0, 0, 1]);
+    // An official pad returns 1s after the report
+    assert_eq!(read_report(&mut bus, 0x4016), vec![1; 8]);
+}
+
+#[test]
+fn strobe_high_keeps_returning_a() {
+    let mut controllers = Controllers::new();
+    controllers.set_buttons(0, BUTTON_A | BUTTON_LEFT);
+    controllers.write_strobe(true);
+    for _ in 0..10 {
+        assert_eq!(controllers.read(0), 1);
+    }
+
+    // The register follows the buttons while the strobe is high
+    controllers.set_buttons(0, BUTTON_LEFT);
+    assert_eq!(controllers.read(0), 0);
+    controllers.write_strobe(false);
+    let report: Vec<u8> = (0..8).map(|_| controllers.read(0)).collect();
+    assert_eq!(report, vec![0, 0, 0, 0, 0, 0, 1, 0]);
+
+    // Pressing buttons after the strobe doesn't change the report being read
+    controllers.write_strobe(true);
+    controllers.write_strobe(false);
+    controllers.set_buttons(0, BUTTON_A);
+    assert_eq!(controllers.read(0), 0);
+}
+
+#[test]
+fn upper_bits_are_open_bus() {
+    let mut bus = Bus::new(Box::new(EmptyCartridge));
+    bus.set_buttons(0, BUTTON_A);
+    bus.write(0x4016, 0x01);
+
+    // Bits 5 - 7 are what was last on the data bus, on a console usually the
+    // high byte of the address. Here it is the $40 written.
+    bus.write(0x4016, 0x40);
+    assert_eq!(bus.read(0x4016, false), 0x41);
+    assert_eq!(bus.peek(0x4016), 0x40);
+}
+
+#[test]
+fn player_two_reads_from_0x4017() {
+    let mut bus = Bus::new(Box::new(EmptyCartridge));
+    bus.set_buttons(0, BUTTON_A);
+    bus.set_buttons(1, BUTTON_B | BUTTON_UP);
+    bus.set_buttons(2, BUTTON_START); // no third port
+    bus.write(0x4016, 0x01);
+    bus.write(0x4016, 0x00);
+
+    // The ports shift independently
+    assert_eq!(bus.read(0x4016, false) & 0x01, 1);
+    assert_eq!(read_report(&mut bus, 0x4017), vec![0, 1, 0, 0, 1, 0, 0, 0]);
+    assert_eq!(read_report(&mut bus, 0x4016)[..7], [0; 7]);
+}
+
+#[test]
+fn writes_to_0x4017_go_to_the_frame_counter() {
+    let mut bus = Bus::new(Box::new(EmptyCartridge));
+    bus.set_buttons(1, BUTTON_A | BUTTON_B);
+    bus.write(0x4016, 0x01);
+    bus.write(0x4016, 0x00);
+    assert_eq!(bus.read(0x4017, false) & 0x01, 1);
+
+    // Bit 0 is no strobe here, the report goes on with B
+    bus.write(0x4017, 0x81);
+    assert_eq!(bus.read(0x4017, false) & 0x01, 1);
+    assert_eq!(bus.read(0x4017, false) & 0x01, 0);
+
+    for _ in 0..4 {
+        bus.clock_apu();
+    }
+    assert!(bus.apu().frame_counter.is_five_step());
+}
+
+#[test]
+fn four_score_reports_four_players_and_its_signature() {
+    let mut bus = Bus::new(Box::new(EmptyCartridge));
+    bus.set_four_score(true);
+    bus.set_buttons(0, BUTTON_A);
+    bus.set_buttons(1, BUTTON_B);
+    bus.set_buttons(2, BUTTON_SELECT);
+    bus.set_buttons(3, BUTTON_START | BUTTON_RIGHT);
+    bus.write(0x4016, 0x01);
+    bus.write(0x4016, 0x00);
+
+    // Player 1 or 2, then player 3 or 4, then the signature
+    assert_eq!(read_report(&mut bus, 0x4016), vec![1, 0, 0, 0, 0, 0, 0, 0]);
+    assert_eq!(read_report(&mut bus, 0x4016), vec![0, 0, 1, 0, 0, 0, 0, 0]);
+    assert_eq!(read_report(&mut bus, 0x4016), vec![0, 0, 0, 1, 0, 0, 0, 0]);
+    assert_eq!(read_report(&mut bus, 0x4016), vec![1; 8]);
+
+    assert_eq!(read_report(&mut bus, 0x4017), vec![0, 1, 0, 0, 0, 0, 0, 0]);
+    assert_eq!(read_report(&mut bus, 0x4017), vec![0, 0, 0, 1, 0, 0, 0, 1]);
+    assert_eq!(read_report(&mut bus, 0x4017), vec![0, 0, 1, 0, 0, 0, 0, 0]);
+
+    // Unplugged, players 3 and 4 are gone
+    bus.set_four_score(false);
+    bus.write(0x4016, 0x01);
+    bus.write(0x4016, 0x00);
+    read_report(&mut bus, 0x4016);
+    assert_eq!(read_report(&mut bus, 0x4016), vec![1; 8]);
+}
+
+#[test]
+fn devices_are_plugged_in_by_the_host() {
+    let mut bus = Bus::new(Box::new(EmptyCartridge));
+    bus.set_button_state(1, BUTTON_A, true);
+    bus.set_button_state(1, BUTTON_UP, true);
+    bus.set_button_state(1, BUTTON_A, false);
+    assert_eq!(bus.controllers().buttons(1), BUTTON_UP);
+
+    // An empty port reads 0, also after the report
+    bus.connect_device(0, DeviceKind::Unplugged).unwrap();
+    bus.set_button_state(0, BUTTON_A, true);
+    assert_eq!(bus.controllers().device_kind(0), Some(DeviceKind::Unplugged));
+    bus.write(0x4016, 0x01);
+    bus.write(0x4016, 0x00);
+    assert_eq!(read_report(&mut bus, 0x4016), vec![0; 8]);
+    assert_eq!(read_report(&mut bus, 0x4016), vec![0; 8]);
+    assert_eq!(read_report(&mut bus, 0x4017), vec![0, 0, 0, 0, 1, 0, 0, 0]);
+
+    assert_eq!(bus.connect_device(4, DeviceKind::StandardController), Err(EmuError::InvalidPort(4)));
+}
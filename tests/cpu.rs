@@ -0,0 +1,367 @@
+use std::cell::Cell;
+use std::rc::Rc;
+
+use nes_emulator::bus::SimpleBus;
+use nes_emulator::cpu::{ExecutionMode, Olc6502, IRQ_SOURCE_DMC, IRQ_SOURCE_FRAME_COUNTER, IRQ_SOURCE_MAPPER};
+use nes_emulator::debugger::Debugger;
+use nes_emulator::error::EmuError;
+use nes_emulator::interfaces::{BusInterface, Cpu6502};
+use nes_emulator::nes::Nes;
+use nes_emulator::recorder::{AccessKind, BusAccess};
+
+// The flat test bus with the program at $0200, recording every access
+fn bus_with(program: &[u8]) -> SimpleBus {
+    let mut bus = SimpleBus::new();
+    bus.load_ram(0x0200, program).unwrap();
+    bus.recorder.set_enabled(true);
+    bus
+}
+
+// A CPU about to fetch the instruction at $0200, interrupts disabled
+fn cpu_at_0200(mode: ExecutionMode) -> Olc6502 {
+    let mut cpu = Olc6502::with_mode(mode);
+    cpu.set_registers(0x00, 0x00, 0x00, 0xFD, 0x0200, 0x24);
+    cpu.force_cycles_zero();
+    cpu
+}
+
+// Clocks the CPU through one instruction, the accesses are stamped with the
+// cycle they happened in. Returns the cycles the instruction took.
+fn run_instruction(cpu: &mut Olc6502, bus: &mut SimpleBus) -> u64 {
+    let start = cpu.get_counters().0;
+    loop {
+        bus.recorder.cycle = cpu.get_counters().0;
+        cpu.clock(bus);
+        if cpu.get_remaining_cycles() == 0 {
+            return cpu.get_counters().0 - start;
+        }
+    }
+}
+
+// Clocks the CPU through the program at $0200 with the status register
+// given for 40 cycles and the rest of the instruction in flight.
+// lines(cpu, cycle) is called before every cycle to drive the interrupt
+// inputs. The NMI handler is a JMP to itself at $0400, the IRQ handler one
+// at $0408.
+fn run_with_lines(mode: ExecutionMode, status: u8, program: &[u8], lines: impl Fn(&mut Olc6502, u64)) -> (Olc6502, SimpleBus) {
+    let mut bus = bus_with(program);
+    bus.load_ram(0x0400, &[0x4C, 0x00, 0x04]).unwrap();
+    bus.load_ram(0x0408, &[0x4C, 0x08, 0x04]).unwrap();
+    bus.load_ram(0xFFFA, &[0x00, 0x04, 0x00, 0x00, 0x08, 0x04]).unwrap();
+
+    let mut cpu = cpu_at_0200(mode);
+    let (a, x, y, s, pc, _) = cpu.get_registers();
+    cpu.set_registers(a, x, y, s, pc, status);
+    for cycle in 0..40 {
+        lines(&mut cpu, cycle);
+        cpu.clock(&mut bus);
+    }
+    while cpu.get_remaining_cycles() > 0 {
+        cpu.clock(&mut bus);
+    }
+    (cpu, bus)
+}
+
+// The return address pushed by the interrupt, i.e. the instruction the
+// interrupt came before. None if there was no interrupt.
+fn interrupted_at((cpu, bus): &(Olc6502, SimpleBus)) -> Option<u16> {
+    let stack = bus.ram_slice(0x01FC..=0x01FD).unwrap();
+    (cpu.get_registers().3 == 0xFA).then(|| u16::from_le_bytes([stack[0], stack[1]]))
+}
+
+// Another core for the Nes: Olc6502 counting the cycles it is clocked, and
+// without save states
+struct CountingCpu {
+    inner:  Olc6502,
+    clocks: Rc<Cell<u64>>,
+}
+
+impl Cpu6502 for CountingCpu {
+    fn reset           (&mut self, bus: &mut dyn BusInterface) { self.inner.reset(bus) }
+    fn clock           (&mut self, bus: &mut dyn BusInterface) { self.clocks.set(self.clocks.get() + 1); self.inner.clock(bus) }
+    fn step_instruction(&mut self, bus: &mut dyn BusInterface) { self.inner.step_instruction(bus) }
+
+    fn set_nmi_line(&mut self, level: bool) { self.inner.set_nmi_line(level) }
+    fn set_irq_line(&mut self, level: bool) { self.inner.set_irq_line(level) }
+    fn assert_irq  (&mut self, source: u8)  { self.inner.assert_irq(source) }
+    fn ack_irq     (&mut self, source: u8)  { self.inner.ack_irq(source) }
+    fn set_rdy_line(&mut self, level: bool) { self.inner.set_rdy_line(level) }
+    fn is_stalled  (&    self) -> bool      { self.inner.is_stalled() }
+
+    fn get_registers       (&    self) -> (u8, u8, u8, u8, u16, u8) { self.inner.get_registers() }
+    fn set_registers       (&mut self, a: u8, x: u8, y: u8, s: u8, pc: u16, p: u8) { self.inner.set_registers(a, x, y, s, pc, p) }
+    fn get_state           (&    self) -> (u8, u16, u16, u8, u8) { self.inner.get_state() }
+    fn set_state           (&mut self, fetched: u8, addr_abs: u16, addr_rel: u16, opcode: u8, cycles: u8) { self.inner.set_state(fetched, addr_abs, addr_rel, opcode, cycles) }
+    fn set_pc              (&mut self, pc: u16) { self.inner.set_pc(pc) }
+    fn get_remaining_cycles(&    self) -> u8 { self.inner.get_remaining_cycles() }
+    fn get_counters        (&    self) -> (u64, u64) { self.inner.get_counters() }
+
+    fn is_halted(&    self) -> bool { self.inner.is_halted() }
+    fn unjam    (&mut self)         { self.inner.unjam() }
+
+    fn debugger(&mut self) -> &mut Debugger { self.inner.debugger() }
+}
+
+fn activity(bus: &SimpleBus) -> Vec<(u16, u8, AccessKind)> {
+    bus.recorder.get_log().iter().map(|access| (access.addr, access.value, access.kind)).collect()
+}
+
+fn writes(log: &[BusAccess]) -> Vec<(u16, u8)> {
+    log.iter().filter(|access| access.kind == AccessKind::Write).map(|access| (access.addr, access.value)).collect()
+}
+
+#[test]
+fn cycle_stepped_mode_matches_instant_mode() {
+    // LDX #$FF, LDA $0210,X (crosses a page), INC $10, STA $0300,X, JSR $0210,
+    // JAM, and at $0210: INX, PHA, PLA, RTS
+    let program = [
+        0xA2, 0xFF, 0xBD, 0x10, 0x02, 0xE6, 0x10, 0x9D, 0x00, 0x03, 0x20, 0x10, 0x02, 0x02, 0xEA, 0xEA,
+        0xE8, 0x48, 0x68, 0x60,
+    ];
+    let expected_cycles = [2, 5, 5, 5, 6, 2, 3, 4, 6, 2];
+
+    let mut instant     = cpu_at_0200(ExecutionMode::Instant);
+    let mut instant_bus = bus_with(&program);
+    let mut stepped     = cpu_at_0200(ExecutionMode::CycleStepped);
+    let mut stepped_bus = bus_with(&program);
+
+    for (i, &cycles) in expected_cycles.iter().enumerate() {
+        assert_eq!(run_instruction(&mut instant, &mut instant_bus), cycles, "instruction {}", i);
+        assert_eq!(run_instruction(&mut stepped, &mut stepped_bus), cycles, "instruction {}", i);
+        assert_eq!(stepped.get_registers(), instant.get_registers(), "instruction {}", i);
+    }
+    assert_eq!(stepped.get_registers().4, 0x020E);
+    assert!(stepped.is_halted() && instant.is_halted());
+    assert_eq!(stepped.get_counters(), instant.get_counters());
+    assert_eq!(stepped_bus.ram_slice(0x0000..=0x03FF).unwrap(), instant_bus.ram_slice(0x0000..=0x03FF).unwrap());
+
+    // The cycle-stepped CPU accesses the bus exactly once per cycle, the
+    // instant one does all the accesses of an instruction in its first cycle
+    let stepped_log = stepped_bus.recorder.get_log();
+    assert_eq!(stepped_log.len() as u64, stepped.get_counters().0);
+    assert!(stepped_log.iter().enumerate().all(|(cycle, access)| access.cycle == cycle as u64));
+
+    let instant_log = instant_bus.recorder.get_log();
+    let starts: Vec<u64> = expected_cycles.iter().scan(0, |cycle, &cycles| { *cycle += cycles; Some(*cycle - cycles) }).collect();
+    assert!(instant_log.iter().all(|access| starts.contains(&access.cycle)));
+
+    // Both write the same values to the same addresses in the same order
+    assert_eq!(writes(&stepped_log), writes(&instant_log));
+    assert_eq!(writes(&stepped_log), [(0x0010, 0x00), (0x0010, 0x01), (0x03FF, 0x00), (0x01FD, 0x02), (0x01FC, 0x0C), (0x01FB, 0x00)]);
+}
+
+#[test]
+fn interrupt_lines_are_polled_before_the_last_cycle() {
+    // LDA #$01 in cycles 0-1, LDA $10 in cycles 2-4, NOPs and JMP *
+    let program = [0xA9, 0x01, 0xA5, 0x10, 0xEA, 0xEA, 0xEA, 0x4C, 0x07, 0x02];
+
+    for mode in [ExecutionMode::Instant, ExecutionMode::CycleStepped] {
+        let nmi_from = |from: u64| interrupted_at(&run_with_lines(mode, 0x24, &program, |cpu, cycle| cpu.set_nmi_line(cycle >= from)));
+        assert_eq!(nmi_from(1), Some(0x0202), "{:?}", mode);
+        // Too late for LDA #$01, the interrupt waits for the next instruction
+        assert_eq!(nmi_from(2), Some(0x0204), "{:?}", mode);
+        assert_eq!(nmi_from(4), Some(0x0204), "{:?}", mode);
+        assert_eq!(nmi_from(5), Some(0x0205), "{:?}", mode);
+
+        let irq_in = |cycles: std::ops::Range<u64>, status| interrupted_at(&run_with_lines(mode, status, &program, |cpu, cycle| cpu.set_irq_line(cycles.contains(&cycle))));
+        assert_eq!(irq_in(1..40, 0x20), Some(0x0202), "{:?}", mode);
+        assert_eq!(irq_in(4..40, 0x20), Some(0x0204), "{:?}", mode);
+        assert_eq!(irq_in(1..40, 0x24), None, "{:?}", mode);
+
+        // Only the poll before the last cycle counts: an IRQ seen by it is
+        // taken even if the line drops, one released before it is lost
+        assert_eq!(irq_in(4..5, 0x20), Some(0x0204), "{:?}", mode);
+        assert_eq!(irq_in(2..4, 0x20), None, "{:?}", mode);
+    }
+}
+
+#[test]
+fn nmi_hijacks_brk_and_waits_for_taken_branches() {
+    // BRK, its padding byte and JMP *
+    let program = [0x00, 0xFF, 0x4C, 0x02, 0x02];
+    let brk_with_nmi_from = |mode, from: u64| run_with_lines(mode, 0x20, &program, |cpu, cycle| cpu.set_nmi_line(cycle >= from));
+
+    // An NMI raised before BRK fetches its vector turns it into an NMI, the B
+    // flag on the stack tells them apart. Only the cycle-stepped mode sees
+    // one that arrives while BRK is already running.
+    let (instant, bus) = brk_with_nmi_from(ExecutionMode::Instant, 0);
+    assert_eq!(instant.get_registers().4, 0x0400);
+    assert_eq!(bus.ram_slice(0x01FB..=0x01FB).unwrap(), &[0x30]);
+
+    let (stepped, bus) = brk_with_nmi_from(ExecutionMode::CycleStepped, 4);
+    assert_eq!(stepped.get_registers().4, 0x0400);
+    assert_eq!(interrupted_at(&(stepped, bus)), Some(0x0202));
+
+    // Later BRK goes to the IRQ handler, and the NMI comes after its first instruction
+    let (stepped, bus) = brk_with_nmi_from(ExecutionMode::CycleStepped, 5);
+    assert_eq!(stepped.get_registers().4, 0x0400);
+    assert_eq!(bus.ram_slice(0x01F8..=0x01FD).unwrap(), &[0x24, 0x08, 0x04, 0x30, 0x02, 0x02]);
+
+    // BEQ to the next instruction is taken in cycles 0-2, then NOPs and JMP *
+    let program = [0xF0, 0x00, 0xEA, 0xEA, 0x4C, 0x04, 0x02];
+    for mode in [ExecutionMode::Instant, ExecutionMode::CycleStepped] {
+        let nmi_from = |from: u64| interrupted_at(&run_with_lines(mode, 0x26, &program, |cpu, cycle| cpu.set_nmi_line(cycle >= from)));
+        assert_eq!(nmi_from(1), Some(0x0202), "{:?}", mode);
+        // The taken branch doesn't poll before its last cycle, the NOP after it runs first
+        assert_eq!(nmi_from(2), Some(0x0203), "{:?}", mode);
+    }
+}
+
+#[test]
+fn irq_is_held_while_any_source_asserts_it() {
+    // LDA #$01 in cycles 0-1, LDA $10 in cycles 2-4, NOPs and JMP *
+    let program = [0xA9, 0x01, 0xA5, 0x10, 0xEA, 0xEA, 0xEA, 0x4C, 0x07, 0x02];
+
+    for mode in [ExecutionMode::Instant, ExecutionMode::CycleStepped] {
+        // The frame counter and the mapper assert the line, one of them is acknowledged
+        let run = |acked: u8| interrupted_at(&run_with_lines(mode, 0x20, &program, |cpu, cycle| {
+            if cycle == 0 {
+                cpu.assert_irq(IRQ_SOURCE_FRAME_COUNTER | IRQ_SOURCE_MAPPER);
+            } else if cycle == 1 {
+                cpu.ack_irq(acked);
+                assert_eq!(cpu.irq_line(), acked != IRQ_SOURCE_FRAME_COUNTER | IRQ_SOURCE_MAPPER);
+            }
+        }));
+        assert_eq!(run(IRQ_SOURCE_FRAME_COUNTER), Some(0x0202), "{:?}", mode);
+        assert_eq!(run(IRQ_SOURCE_MAPPER | IRQ_SOURCE_DMC), Some(0x0202), "{:?}", mode);
+        assert_eq!(run(IRQ_SOURCE_FRAME_COUNTER | IRQ_SOURCE_MAPPER), None, "{:?}", mode);
+
+        // Releasing the external line leaves the sources alone
+        let external = interrupted_at(&run_with_lines(mode, 0x20, &program, |cpu, cycle| {
+            if cycle == 0 {
+                cpu.assert_irq(IRQ_SOURCE_DMC);
+                cpu.set_irq_line(true);
+            } else if cycle == 1 {
+                cpu.set_irq_line(false);
+            }
+        }));
+        assert_eq!(external, Some(0x0202), "{:?}", mode);
+    }
+}
+
+#[test]
+fn cli_sei_and_plp_change_the_polled_i_flag_one_instruction_late() {
+    for mode in [ExecutionMode::Instant, ExecutionMode::CycleStepped] {
+        let irq = |status, program: &[u8]| interrupted_at(&run_with_lines(mode, status, program, |cpu, _| cpu.set_irq_line(true)));
+
+        // CLI, NOP, JMP *: the IRQ comes after the NOP
+        assert_eq!(irq(0x24, &[0x58, 0xEA, 0x4C, 0x02, 0x02]), Some(0x0202), "{:?}", mode);
+
+        // SEI, NOP, JMP *: SEI is still interrupted
+        assert_eq!(irq(0x20, &[0x78, 0xEA, 0x4C, 0x02, 0x02]), Some(0x0201), "{:?}", mode);
+
+        // LDA #$20, PHA, PLP, NOP, JMP *: PLP clears I, the IRQ comes after the NOP
+        assert_eq!(irq(0x24, &[0xA9, 0x20, 0x48, 0x28, 0xEA, 0x4C, 0x05, 0x02]), Some(0x0205), "{:?}", mode);
+    }
+}
+
+#[test]
+fn jam_halts_until_unjammed() {
+    // JAM, LDA #$42
+    let program = [0x02, 0xA9, 0x42];
+
+    for mode in [ExecutionMode::Instant, ExecutionMode::CycleStepped] {
+        let mut cpu = cpu_at_0200(mode);
+        let mut bus = bus_with(&program);
+        run_instruction(&mut cpu, &mut bus);
+        assert!(cpu.is_halted(), "{:?}", mode);
+
+        // The cycles go by without the CPU touching the bus or the registers
+        bus.recorder.clear();
+        let registers = cpu.get_registers();
+        let (cycles, instructions) = cpu.get_counters();
+        for _ in 0..20 {
+            cpu.clock(&mut bus);
+        }
+        assert!(bus.recorder.get_log().is_empty(), "{:?}", mode);
+        assert_eq!(cpu.get_registers(), registers, "{:?}", mode);
+        assert_eq!(cpu.get_counters(), (cycles + 20, instructions), "{:?}", mode);
+        assert_eq!(registers.4, 0x0201, "{:?}", mode);
+
+        // Going on with the byte after the JAM
+        cpu.unjam();
+        assert!(!cpu.is_halted(), "{:?}", mode);
+        assert_eq!(run_instruction(&mut cpu, &mut bus), 2, "{:?}", mode);
+        assert_eq!(cpu.get_registers().0, 0x42, "{:?}", mode);
+        assert_eq!(bus.recorder.get_log().iter().map(|access| access.addr).collect::<Vec<_>>(), [0x0201, 0x0202], "{:?}", mode);
+    }
+}
+
+#[test]
+fn read_modify_write_writes_the_old_value_first() {
+    use AccessKind::{Read, Write};
+
+    // LDX #$01, INC $10, ASL $02FF,X
+    let program = [0xA2, 0x01, 0xE6, 0x10, 0x1E, 0xFF, 0x02];
+
+    for mode in [ExecutionMode::Instant, ExecutionMode::CycleStepped] {
+        let mut cpu = cpu_at_0200(mode);
+        let mut bus = bus_with(&program);
+        bus.load_ram(0x0010, &[0x41]).unwrap();
+        bus.load_ram(0x0300, &[0x81]).unwrap();
+        run_instruction(&mut cpu, &mut bus);
+
+        bus.recorder.clear();
+        assert_eq!(run_instruction(&mut cpu, &mut bus), 5, "{:?}", mode);
+        assert_eq!(activity(&bus), [
+            (0x0202, 0xE6, Read), (0x0203, 0x10, Read),
+            (0x0010, 0x41, Read), (0x0010, 0x41, Write), (0x0010, 0x42, Write),
+        ], "{:?}", mode);
+
+        // Behind the dummy read of the uncorrected address
+        bus.recorder.clear();
+        assert_eq!(run_instruction(&mut cpu, &mut bus), 7, "{:?}", mode);
+        assert_eq!(activity(&bus), [
+            (0x0204, 0x1E, Read), (0x0205, 0xFF, Read), (0x0206, 0x02, Read), (0x0200, 0xA2, Read),
+            (0x0300, 0x81, Read), (0x0300, 0x81, Write), (0x0300, 0x02, Write),
+        ], "{:?}", mode);
+    }
+}
+
+#[test]
+fn indexing_reads_the_uncorrected_address_first() {
+    use AccessKind::{Read, Write};
+
+    // One instruction with A = $55, X = Y = $10 and the pointer $02F8 at $10
+    let run = |mode, program: &[u8]| {
+        let mut cpu = cpu_at_0200(mode);
+        let mut bus = bus_with(program);
+        bus.load_ram(0x0010, &[0xF8, 0x02]).unwrap();
+        cpu.set_registers(0x55, 0x10, 0x10, 0xFD, 0x0200, 0x24);
+        let cycles = run_instruction(&mut cpu, &mut bus);
+        let accesses: Vec<(u16, AccessKind)> = activity(&bus).iter().skip(program.len()).map(|&(addr, _, kind)| (addr, kind)).collect();
+        (cycles, accesses)
+    };
+
+    for mode in [ExecutionMode::Instant, ExecutionMode::CycleStepped] {
+        // Reads within the page have nothing to correct
+        assert_eq!(run(mode, &[0xBD, 0x20, 0x02]), (4, vec![(0x0230, Read)]), "{:?}", mode);
+
+        // Across a page $0308 is read from $0208 first
+        assert_eq!(run(mode, &[0xBD, 0xF8, 0x02]), (5, vec![(0x0208, Read), (0x0308, Read)]), "{:?}", mode);
+        assert_eq!(run(mode, &[0xB1, 0x10]), (6, vec![(0x0010, Read), (0x0011, Read), (0x0208, Read), (0x0308, Read)]), "{:?}", mode);
+
+        // Stores always read before they write, within the page the right address
+        assert_eq!(run(mode, &[0x9D, 0x20, 0x02]), (5, vec![(0x0230, Read), (0x0230, Write)]), "{:?}", mode);
+        assert_eq!(run(mode, &[0x9D, 0xF8, 0x02]), (5, vec![(0x0208, Read), (0x0308, Write)]), "{:?}", mode);
+    }
+}
+
+#[test]
+fn nes_runs_other_cpu_cores() {
+    let clocks  = Rc::new(Cell::new(0));
+    let mut nes = Nes::with_cpu(CountingCpu { inner: Olc6502::new(), clocks: Rc::clone(&clocks) });
+
+    // SEI, LDA #$42, STA $10, JMP *
+    nes.load_program(&[0x78, 0xA9, 0x42, 0x85, 0x10, 0x4C, 0x05, 0x00], 0x0000).unwrap();
+    nes.run_frame();
+    assert_eq!(nes.get_ram(0x0010, 1).unwrap(), [0x42]);
+    assert_eq!(nes.get_counters().0, clocks.get());
+    assert!(clocks.get() > 29_000);
+
+    // Without save states in the core the console has none either
+    assert!(nes.save_state().is_empty());
+    assert_eq!(nes.load_state(&Nes::new().save_state()), Err(EmuError::SaveStatesUnsupported));
+    assert_eq!(nes.save_slot(0), Err(EmuError::SaveStatesUnsupported));
+    assert_eq!(nes.set_rewind(60), Err(EmuError::SaveStatesUnsupported));
+}
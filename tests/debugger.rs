@@ -0,0 +1,226 @@
+use std::cell::RefCell;
+use std::io::Write;
+use std::rc::Rc;
+
+use nes_emulator::bus::SimpleBus;
+use nes_emulator::cpu::{Olc6502, StatusFlags};
+use nes_emulator::debugger::{BreakReason, StackDiagnostics, WatchHit, WatchKind};
+use nes_emulator::nes::Nes;
+
+// The flat test bus with the program at $0200
+fn bus_with(program: &[u8]) -> SimpleBus {
+    let mut bus = SimpleBus::new();
+    bus.load_ram(0x0200, program).unwrap();
+    bus
+}
+
+// A CPU about to fetch the instruction at $0200
+fn cpu_at_0200() -> Olc6502 {
+    let mut cpu = Olc6502::new();
+    cpu.set_registers(0x00, 0x00, 0x00, 0xFD, 0x0200, 0x24);
+    cpu.force_cycles_zero();
+    cpu
+}
+
+// SEI, LDX #$00, then in a loop LDA $10, STA $11, INX, JMP $0003. $10 holds $5A.
+fn looping_nes() -> Nes {
+    let mut program = vec![0x78, 0xA2, 0x00, 0xA5, 0x10, 0x85, 0x11, 0xE8, 0x4C, 0x03, 0x00];
+    program.resize(0x11, 0x00);
+    program[0x10] = 0x5A;
+
+    let mut nes = Nes::new();
+    nes.load_program(&program, 0x0000).unwrap();
+    nes
+}
+
+// Collects what the CPU writes to its trace writer
+#[derive(Clone, Default)]
+struct TraceBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl Write for TraceBuffer {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(data);
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn trace_lines_follow_nestest() {
+    // LDA #$42, STA $10, ISC $10,X, BNE -8
+    let mut bus = bus_with(&[0xA9, 0x42, 0x85, 0x10, 0xF7, 0x10, 0xD0, 0xF8]);
+    let mut cpu = cpu_at_0200();
+    let trace   = TraceBuffer::default();
+    cpu.set_trace_writer(Some(Box::new(trace.clone())));
+    for _ in 0..4 {
+        cpu.step_instruction(&mut bus);
+    }
+
+    // A line per instruction before it runs, unofficial opcodes are marked
+    let trace = String::from_utf8(trace.0.borrow().clone()).unwrap();
+    assert_eq!(trace.lines().collect::<Vec<_>>(), [
+        "0200  A9 42     LDA #$42                        A:00 X:00 Y:00 P:24 SP:FD CYC:0",
+        "0202  85 10     STA $10                         A:42 X:00 Y:00 P:24 SP:FD CYC:2",
+        "0204  F7 10    *ISB $10,X                       A:42 X:00 Y:00 P:24 SP:FD CYC:5",
+        "0206  D0 F8     BNE $0200                       A:FE X:00 Y:00 P:A4 SP:FD CYC:11",
+    ]);
+
+    // The line of the next instruction, without the trace writer
+    assert_eq!(cpu.trace(&mut bus), "0200  A9 42     LDA #$42                        A:FE X:00 Y:00 P:A4 SP:FD CYC:14");
+}
+
+#[test]
+fn breakpoints_stop_at_the_right_instruction() {
+    let mut nes = looping_nes();
+    let pc = |nes: &Nes| nes.get_registers()[4];
+    let x  = |nes: &Nes| nes.get_registers()[1];
+
+    // Right before INX, once per time around the loop
+    nes.debugger().add_breakpoint(0x0007);
+    assert_eq!(nes.run_until_break(), Some(BreakReason::Execute(0x0007)));
+    assert_eq!((pc(&nes), x(&nes)), (0x0007, 0));
+    assert_eq!(nes.run_until_break(), Some(BreakReason::Execute(0x0007)));
+    assert_eq!((pc(&nes), x(&nes)), (0x0007, 1));
+    nes.debugger().remove_breakpoint(0x0007);
+
+    // Memory breakpoints stop after the instruction that accessed the memory
+    nes.debugger().add_read_breakpoint(0x0010);
+    assert_eq!(nes.run_until_break(), Some(BreakReason::Read { addr: 0x0010, pc: 0x0003 }));
+    assert_eq!(pc(&nes), 0x0005);
+    nes.debugger().remove_read_breakpoint(0x0010);
+
+    nes.debugger().add_write_breakpoint(0x0011);
+    assert_eq!(nes.run_until_break(), Some(BreakReason::Write { addr: 0x0011, data: 0x5A, pc: 0x0005 }));
+    assert_eq!(pc(&nes), 0x0007);
+
+    // Without breakpoints the frame runs to its end
+    nes.debugger().clear_breakpoints();
+    assert_eq!(nes.run_until_break(), None);
+}
+
+#[test]
+fn watchpoints_record_accesses_within_their_range() {
+    // LDA #$01, STA $20, STA $21, STA $20, LDA #$02, STA $20, LDA $21, LDA $31
+    let mut bus = bus_with(&[0xA9, 0x01, 0x85, 0x20, 0x85, 0x21, 0x85, 0x20, 0xA9, 0x02, 0x85, 0x20, 0xA5, 0x21, 0xA5, 0x31]);
+    let mut cpu = cpu_at_0200();
+    cpu.debugger().add_watchpoint(0x0020, 0x0021, WatchKind::Write);
+    cpu.debugger().add_watchpoint(0x0020, 0x0020, WatchKind::Change);
+    cpu.debugger().add_watchpoint(0x0021, 0x0030, WatchKind::Read);
+    for _ in 0..8 {
+        cpu.step_instruction(&mut bus);
+    }
+
+    // Writing the value already stored is no change
+    let hit = |kind, addr, pc, value| WatchHit { kind, addr, pc, value };
+    assert_eq!(cpu.debugger().get_watch_hits(), [
+        hit(WatchKind::Write,  0x0020, 0x0202, 0x01),
+        hit(WatchKind::Change, 0x0020, 0x0202, 0x01),
+        hit(WatchKind::Write,  0x0021, 0x0204, 0x01),
+        hit(WatchKind::Write,  0x0020, 0x0206, 0x01),
+        hit(WatchKind::Write,  0x0020, 0x020A, 0x02),
+        hit(WatchKind::Change, 0x0020, 0x020A, 0x02),
+        hit(WatchKind::Read,   0x0021, 0x020C, 0x01),
+    ]);
+
+    cpu.debugger().clear_watch_hits();
+    cpu.debugger().remove_watchpoint(0x0020, 0x0021, WatchKind::Write);
+    assert!(cpu.debugger().get_watch_hits().is_empty());
+    assert_eq!(cpu.debugger().get_watchpoints().len(), 2);
+}
+
+#[test]
+fn counters_count_cycles_and_instructions() {
+    // LDA #$01, STA $0300, LDX #$FF, LDA $0201,X (crosses a page), BNE +0 (taken)
+    let mut bus = bus_with(&[0xA9, 0x01, 0x8D, 0x00, 0x03, 0xA2, 0xFF, 0xBD, 0x01, 0x02, 0xD0, 0x00]);
+    let mut cpu = cpu_at_0200();
+    assert_eq!(cpu.get_counters(), (0, 0));
+    for _ in 0..5 {
+        cpu.step_instruction(&mut bus);
+    }
+    assert_eq!(cpu.get_counters(), (2 + 4 + 2 + 5 + 3, 5));
+
+    // The 7 cycles of a reset are no instruction
+    bus.load_ram(0xFFFC, &[0x00, 0x02]).unwrap();
+    let mut cpu = Olc6502::new();
+    cpu.reset(&mut bus);
+    cpu.step_instruction(&mut bus);
+    assert_eq!(cpu.get_counters(), (7 + 2, 1));
+}
+
+#[test]
+fn flags_are_named_bits_of_the_status_register() {
+    let flags = |carry, zero, overflow, negative| StatusFlags { carry, zero, irq_disable: true, decimal: false, break_: false, overflow, negative };
+
+    // SEC, LDA #$80, CLC, ADC #$80
+    let mut bus = bus_with(&[0x38, 0xA9, 0x80, 0x18, 0x69, 0x80]);
+    let mut cpu = cpu_at_0200();
+    cpu.step_instruction(&mut bus);
+    cpu.step_instruction(&mut bus);
+    assert_eq!(cpu.get_flags(), flags(true, false, false, true));
+    cpu.step_instruction(&mut bus);
+    cpu.step_instruction(&mut bus);
+    assert_eq!(cpu.get_flags(), flags(true, true, true, false));
+
+    // The same through the Nes, with all flags set and with none
+    let mut nes = Nes::new();
+    nes.set_registers(0x00, 0x00, 0x00, 0xFD, 0x0000, 0xFF);
+    assert_eq!(nes.get_flags(), StatusFlags { carry: true, zero: true, irq_disable: true, decimal: true, break_: true, overflow: true, negative: true });
+    nes.set_registers(0x00, 0x00, 0x00, 0xFD, 0x0000, 0x20);
+    assert_eq!(nes.get_flags(), StatusFlags { irq_disable: false, ..flags(false, false, false, false) });
+}
+
+#[test]
+fn execution_goes_on_from_a_state_set_by_the_debugger() {
+    // LDA #$01 at $0200, LDA #$02 at $0210
+    let mut program = vec![0xA9, 0x01];
+    program.resize(0x10, 0xEA);
+    program.extend_from_slice(&[0xA9, 0x02]);
+    let mut bus = bus_with(&program);
+    let mut cpu = cpu_at_0200();
+
+    // Moved at an instruction boundary, the next instruction is fetched from there
+    cpu.step_instruction(&mut bus);
+    cpu.set_pc(0x0210);
+    cpu.step_instruction(&mut bus);
+    assert_eq!((cpu.get_registers().0, cpu.get_registers().4), (0x02, 0x0212));
+
+    // Cycles left by set_state() are finished before the next instruction
+    cpu.set_pc(0x0200);
+    cpu.set_state(0x12, 0x3456, 0x0078, 0xEA, 3);
+    assert_eq!(cpu.get_state(), (0x12, 0x3456, 0x0078, 0xEA, 3));
+    assert_eq!(cpu.get_remaining_cycles(), 3);
+    let (cycles, instructions) = cpu.get_counters();
+    cpu.step_instruction(&mut bus);
+    assert_eq!(cpu.get_counters(), (cycles + 3 + 2, instructions + 1));
+    assert_eq!(cpu.get_registers().0, 0x01);
+
+    // and through the Nes
+    let mut nes = Nes::new();
+    nes.set_cpu_state(0x12, 0x3456, 0x0078, 0xEA, 3);
+    assert_eq!(nes.get_cpu_state(), [0x12, 0x3456, 0x0078, 0xEA, 3]);
+    nes.set_pc(0x1234);
+    assert_eq!(nes.get_registers()[4], 0x1234);
+}
+
+#[test]
+fn stack_wraps_are_reported() {
+    // PHA, PLA, PLA, PHA with the stack pointer at $00
+    let mut bus = bus_with(&[0x48, 0x68, 0x68, 0x48]);
+    let mut cpu = cpu_at_0200();
+    cpu.set_registers(0x00, 0x00, 0x00, 0x00, 0x0200, 0x24);
+
+    // The push wraps from $0100 to $01FF and the pull back
+    cpu.step_instruction(&mut bus);
+    cpu.step_instruction(&mut bus);
+    assert_eq!(cpu.debugger().get_stack_diagnostics(), StackDiagnostics { overflows: 1, last_overflow_pc: 0x0200, underflows: 1, last_underflow_pc: 0x0201 });
+
+    // Pulling from $0101 and pushing to it again doesn't wrap
+    cpu.debugger().clear_stack_diagnostics();
+    cpu.step_instruction(&mut bus);
+    cpu.step_instruction(&mut bus);
+    assert_eq!(cpu.get_registers().3, 0x00);
+    assert_eq!(cpu.debugger().get_stack_diagnostics(), StackDiagnostics::default());
+}
@@ -0,0 +1,96 @@
+use nes_emulator::bus::Bus;
+use nes_emulator::cartridge::EmptyCartridge;
+use nes_emulator::cpu::{ExecutionMode, Olc6502};
+use nes_emulator::interfaces::BusInterface;
+use nes_emulator::recorder::{AccessKind, AccessOrigin};
+
+#[test]
+fn oam_dma_takes_513_or_514_cycles() {
+    let page: Vec<u8> = (0..=255u8).map(|i| i ^ 0xA5).collect();
+
+    // Started by a write in an odd or in an even cycle
+    for (first_cycle_odd, expected) in [(true, 513), (false, 514)] {
+        let mut bus = Bus::new(Box::new(EmptyCartridge));
+        bus.load_ram(0x0200, &page).unwrap();
+        bus.write(0x4014, 0x02);
+        bus.recorder.set_enabled(true);
+
+        let odd = |cycle: u64| (cycle % 2 == 1) != first_cycle_odd;
+        let mut cycles = 0;
+        while bus.oam_dma.is_active() {
+            bus.recorder.cycle = cycles;
+            bus.clock_oam_dma(odd(cycles));
+            cycles += 1;
+        }
+        assert_eq!(cycles, expected);
+
+        // The page is read in order on the get (even) cycles
+        let log = bus.recorder.get_log();
+        assert_eq!(log.len(), 256);
+        for (i, access) in log.iter().enumerate() {
+            assert_eq!((access.addr, access.value, access.kind, access.origin), (0x0200 + i as u16, page[i], AccessKind::Read, AccessOrigin::OamDma));
+            assert!(!odd(access.cycle));
+        }
+        // and ends up in OAM, where the attribute bytes lack bits 2 -> 4
+        let mask = |i: u8| if i % 4 == 2 { 0xE3 } else { 0xFF };
+        assert!((0..=255u8).all(|i| bus.ppu.oam.read(i) == page[i as usize] & mask(i)));
+    }
+}
+
+// Runs NOP, STA $10 and more NOPs for 20 cycles like the Nes does, with a
+// DMC sample fetch from $0400 requested before the given cycle. Returns the
+// cycles the CPU spent halted.
+fn run_with_dmc_fetch(request_at: u64) -> (Vec<u64>, Bus) {
+    let mut bus = Bus::new(Box::new(EmptyCartridge));
+    bus.load_ram(0x0000, &[0xEA, 0xEA, 0x85, 0x10, 0xEA, 0xEA, 0xEA, 0xEA, 0xEA, 0xEA, 0xEA, 0xEA]).unwrap();
+    bus.load_ram(0x0400, &[0x77]).unwrap();
+    bus.recorder.set_enabled(true);
+
+    let mut cpu = Olc6502::with_mode(ExecutionMode::CycleStepped);
+    cpu.set_registers(0x00, 0x00, 0x00, 0xFD, 0x0000, 0x24);
+    cpu.force_cycles_zero();
+
+    let mut halted = Vec::new();
+    for cycle in 0..20 {
+        if cycle == request_at {
+            bus.dmc_dma.request(0x0400);
+        }
+        bus.recorder.cycle = cycle;
+        cpu.set_rdy_line(!bus.dmc_dma.is_active());
+        cpu.clock(&mut bus);
+        if bus.dmc_dma.is_active() && cpu.is_stalled() {
+            halted.push(cycle);
+            bus.clock_dmc_dma(cycle % 2 == 1);
+        }
+    }
+    (halted, bus)
+}
+
+#[test]
+fn dmc_dma_halts_the_cpu_for_3_or_4_cycles() {
+    // Halted on an even cycle the read happens on the third one, on an odd
+    // cycle there is one more to wait for the next get cycle
+    assert_eq!(run_with_dmc_fetch(4).0, [4, 5, 6]);
+    assert_eq!(run_with_dmc_fetch(3).0, [3, 4, 5, 6]);
+
+    // STA writes in cycle 6, the CPU only halts on the read after it
+    let (halted, mut bus) = run_with_dmc_fetch(6);
+    assert_eq!(halted, [7, 8, 9, 10]);
+    assert_eq!(bus.dmc_dma.take_sample(), Some(0x77));
+
+    // While halted the CPU keeps repeating its read, the sample is read in the last cycle
+    let log = bus.recorder.get_log();
+    let accesses: Vec<(u64, u16, AccessKind, AccessOrigin)> = log.iter()
+        .filter(|access| (6..=11).contains(&access.cycle))
+        .map(|access| (access.cycle, access.addr, access.kind, access.origin))
+        .collect();
+    assert_eq!(accesses, [
+        (6,  0x0010, AccessKind::Write, AccessOrigin::Cpu),
+        (7,  0x0003, AccessKind::Read,  AccessOrigin::Cpu),
+        (8,  0x0003, AccessKind::Read,  AccessOrigin::Cpu),
+        (9,  0x0003, AccessKind::Read,  AccessOrigin::Cpu),
+        (10, 0x0003, AccessKind::Read,  AccessOrigin::Cpu),
+        (10, 0x0400, AccessKind::Read,  AccessOrigin::DmcDma),
+        (11, 0x0004, AccessKind::Read,  AccessOrigin::Cpu),
+    ]);
+}
@@ -5,7 +5,8 @@ use serde::Deserialize;
 
 use nes_emulator::interfaces::BusInterface;
 use nes_emulator::bus::SimpleBus;
-use nes_emulator::cpu::Olc6502;
+use nes_emulator::cpu::{ExecutionMode, Olc6502};
+use nes_emulator::recorder::AccessKind;
 
 //
 // JSON structs
@@ -31,6 +32,24 @@ struct HarteState {
     ram: Vec<(u16, u8)>,
 }
 
+//
+// Bus activity recorder
+//
+
+// The activity recorded by the flat test bus, in the same
+// (address, value, "read"/"write") form as the `cycles` entries of a case
+fn recorded_activity(bus: &SimpleBus) -> Vec<(u16, u8, String)> {
+    bus.recorder.get_log().iter()
+        .map(|access| {
+            let kind = match access.kind {
+                AccessKind::Read  => "read",
+                AccessKind::Write => "write",
+            };
+            (access.addr, access.value, kind.to_string())
+        })
+        .collect()
+}
+
 //
 // Helpers
 //
@@ -76,7 +95,7 @@ fn set_cpu_from_state(cpu: &mut Olc6502, state: &HarteState) {
     cpu.force_cycles_zero();
 }
 
-fn run_one_instruction(cpu: &mut Olc6502, bus: &mut SimpleBus) -> usize {
+fn run_one_instruction(cpu: &mut Olc6502, bus: &mut dyn BusInterface) -> usize {
     // Run cycles until the instruction finishes.
     // The most robust approach:
     // - tick once (starts instruction)
@@ -141,6 +160,21 @@ fn assert_ram_matches(bus: &mut SimpleBus, expected: &HarteState, case_name: &st
     }
 }
 
+fn format_bus_log(log: &[(u16, u8, String)]) -> String {
+    log.iter()
+        .map(|(addr, val, kind)| format!("  {:04X} {:02X} {}", addr, val, kind))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn assert_bus_activity_matches(log: &[(u16, u8, String)], expected: &[(u16, u8, String)], case_name: &str) {
+    assert!(
+        log == expected,
+        "[{}] bus activity mismatch:\nGOT:\n{}\nEXPECTED:\n{}",
+        case_name, format_bus_log(log), format_bus_log(expected)
+    );
+}
+
 
 //
 // Main test
@@ -155,11 +189,19 @@ macro_rules! harte_test {
 }
 
 fn run_opcode_file(filename : &str) {
+    // The instant mode can only be checked for its final state and cycle count,
+    // the cycle-stepped mode is checked cycle by cycle against the recorded bus activity
+    run_opcode_file_in_mode(filename, ExecutionMode::Instant);
+    run_opcode_file_in_mode(filename, ExecutionMode::CycleStepped);
+}
+
+fn run_opcode_file_in_mode(filename : &str, mode: ExecutionMode) {
     let path = Path::new("tests/harte/nes6502/v1").join(filename);
 
 
-    let mut cpu = Olc6502::new();
+    let mut cpu = Olc6502::with_mode(mode);
     let mut bus = SimpleBus::new();
+    bus.recorder.set_enabled(true);
 
     let opcode_file = path
         .file_name()
@@ -180,6 +222,7 @@ fn run_opcode_file(filename : &str) {
         // Setup
         init_bus_from_state(&mut bus, &case.initial);
         set_cpu_from_state(&mut cpu, &case.initial);
+        bus.recorder.clear();
 
         // Run exactly one instruction
         let cycles_taken = run_one_instruction(&mut cpu, &mut bus);
@@ -227,6 +270,11 @@ fn run_opcode_file(filename : &str) {
 
         // Validate final RAM state (only specified addresses)
         assert_ram_matches(&mut bus, &case.final_state, &format!("{} case {} '{}'", opcode_file, i, case.name));
+
+        // Validate every bus access, including the dummy reads and writes
+        if mode == ExecutionMode::CycleStepped {
+            assert_bus_activity_matches(&recorded_activity(&bus), &case.cycles, &format!("{} case {} '{}'", opcode_file, i, case.name));
+        }
     }
 }
 
@@ -381,4 +429,101 @@ harte_test!(opcode_f6, "f6.json");
 harte_test!(opcode_f8, "f8.json");
 harte_test!(opcode_f9, "f9.json");
 harte_test!(opcode_fd, "fd.json");
-harte_test!(opcode_fe, "fe.json");
\ No newline at end of file
+harte_test!(opcode_fe, "fe.json");
+
+// Unofficial opcodes
+harte_test!(opcode_03, "03.json");
+harte_test!(opcode_07, "07.json");
+harte_test!(opcode_0b, "0b.json");
+harte_test!(opcode_0f, "0f.json");
+harte_test!(opcode_13, "13.json");
+harte_test!(opcode_17, "17.json");
+harte_test!(opcode_1b, "1b.json");
+harte_test!(opcode_1f, "1f.json");
+harte_test!(opcode_23, "23.json");
+harte_test!(opcode_27, "27.json");
+harte_test!(opcode_2b, "2b.json");
+harte_test!(opcode_2f, "2f.json");
+harte_test!(opcode_33, "33.json");
+harte_test!(opcode_37, "37.json");
+harte_test!(opcode_3b, "3b.json");
+harte_test!(opcode_3f, "3f.json");
+harte_test!(opcode_43, "43.json");
+harte_test!(opcode_47, "47.json");
+harte_test!(opcode_4b, "4b.json");
+harte_test!(opcode_4f, "4f.json");
+harte_test!(opcode_53, "53.json");
+harte_test!(opcode_57, "57.json");
+harte_test!(opcode_5b, "5b.json");
+harte_test!(opcode_5f, "5f.json");
+harte_test!(opcode_63, "63.json");
+harte_test!(opcode_67, "67.json");
+harte_test!(opcode_6b, "6b.json");
+harte_test!(opcode_6f, "6f.json");
+harte_test!(opcode_73, "73.json");
+harte_test!(opcode_77, "77.json");
+harte_test!(opcode_7b, "7b.json");
+harte_test!(opcode_7f, "7f.json");
+harte_test!(opcode_83, "83.json");
+harte_test!(opcode_87, "87.json");
+harte_test!(opcode_8b, "8b.json");
+harte_test!(opcode_8f, "8f.json");
+harte_test!(opcode_93, "93.json");
+harte_test!(opcode_97, "97.json");
+harte_test!(opcode_9b, "9b.json");
+harte_test!(opcode_9c, "9c.json");
+harte_test!(opcode_9e, "9e.json");
+harte_test!(opcode_9f, "9f.json");
+harte_test!(opcode_a3, "a3.json");
+harte_test!(opcode_a7, "a7.json");
+harte_test!(opcode_ab, "ab.json");
+harte_test!(opcode_af, "af.json");
+harte_test!(opcode_b3, "b3.json");
+harte_test!(opcode_b7, "b7.json");
+harte_test!(opcode_bb, "bb.json");
+harte_test!(opcode_bf, "bf.json");
+harte_test!(opcode_c3, "c3.json");
+harte_test!(opcode_c7, "c7.json");
+harte_test!(opcode_cb, "cb.json");
+harte_test!(opcode_cf, "cf.json");
+harte_test!(opcode_d3, "d3.json");
+harte_test!(opcode_d7, "d7.json");
+harte_test!(opcode_db, "db.json");
+harte_test!(opcode_df, "df.json");
+harte_test!(opcode_e3, "e3.json");
+harte_test!(opcode_e7, "e7.json");
+harte_test!(opcode_eb, "eb.json");
+harte_test!(opcode_ef, "ef.json");
+harte_test!(opcode_f3, "f3.json");
+harte_test!(opcode_f7, "f7.json");
+harte_test!(opcode_fb, "fb.json");
+harte_test!(opcode_ff, "ff.json");
+
+// Unofficial NOPs
+harte_test!(opcode_04, "04.json");
+harte_test!(opcode_0c, "0c.json");
+harte_test!(opcode_14, "14.json");
+harte_test!(opcode_1a, "1a.json");
+harte_test!(opcode_1c, "1c.json");
+harte_test!(opcode_34, "34.json");
+harte_test!(opcode_3a, "3a.json");
+harte_test!(opcode_3c, "3c.json");
+harte_test!(opcode_44, "44.json");
+harte_test!(opcode_54, "54.json");
+harte_test!(opcode_5a, "5a.json");
+harte_test!(opcode_5c, "5c.json");
+harte_test!(opcode_64, "64.json");
+harte_test!(opcode_74, "74.json");
+harte_test!(opcode_7a, "7a.json");
+harte_test!(opcode_7c, "7c.json");
+harte_test!(opcode_80, "80.json");
+harte_test!(opcode_82, "82.json");
+harte_test!(opcode_89, "89.json");
+harte_test!(opcode_c2, "c2.json");
+harte_test!(opcode_d4, "d4.json");
+harte_test!(opcode_da, "da.json");
+harte_test!(opcode_dc, "dc.json");
+harte_test!(opcode_e2, "e2.json");
+harte_test!(opcode_f4, "f4.json");
+harte_test!(opcode_fa, "fa.json");
+harte_test!(opcode_fc, "fc.json");
@@ -0,0 +1,35 @@
+use std::fs;
+use std::path::Path;
+
+use nes_emulator::functional_test::{self, Outcome, START_ADDR, SUCCESS_ADDR};
+
+// The binary is not part of the repository, it has to be assembled with
+// disable_decimal = 1 and placed next to the Harte test data
+const BINARY: &str = "tests/klaus/6502_functional_test.bin";
+
+#[test]
+fn klaus_functional_test() {
+    let path = Path::new(BINARY);
+    if !path.exists() {
+        eprintln!("skipping, {} not found", BINARY);
+        return;
+    }
+
+    let binary = fs::read(path).unwrap();
+    let report = functional_test::run(&binary, SUCCESS_ADDR, functional_test::MAX_INSTRUCTIONS);
+
+    assert_eq!(report.outcome, Outcome::Passed, "after {} instructions", report.instructions);
+}
+
+#[test]
+fn traps_are_detected() {
+    // JMP $3469 at the start, the success address jumps to itself
+    let mut binary = vec![0u8; 0x10000];
+    binary[START_ADDR as usize..START_ADDR as usize + 3].copy_from_slice(&[0x4C, 0x69, 0x34]);
+    binary[0x3469..0x346C].copy_from_slice(&[0x4C, 0x69, 0x34]);
+    assert_eq!(functional_test::run(&binary, SUCCESS_ADDR, 10).outcome, Outcome::Passed);
+
+    // BNE * with Z clear is a failing check
+    binary[START_ADDR as usize..START_ADDR as usize + 2].copy_from_slice(&[0xD0, 0xFE]);
+    assert_eq!(functional_test::run(&binary, SUCCESS_ADDR, 10).outcome, Outcome::Trapped(START_ADDR));
+}
@@ -0,0 +1,66 @@
+use nes_emulator::nes::Nes;
+use nes_emulator::movie::Movie;
+use nes_emulator::error::EmuError;
+use nes_emulator::controller::{BUTTON_A, BUTTON_START, BUTTON_LEFT};
+
+mod common;
+use common::SHOW_BUTTON_A;
+
+#[test]
+fn movies_survive_the_file_format() {
+    let mut movie = Movie::new();
+    movie.push([BUTTON_A, 0, 0, 0]);
+    movie.push([0, BUTTON_START, 0, BUTTON_LEFT]);
+
+    let bytes = movie.to_bytes();
+    assert_eq!(bytes.len(), 8 + 2 * 4);
+    assert_eq!(Movie::from_bytes(&bytes), Ok(movie));
+
+    assert_eq!(Movie::from_bytes(&bytes[..bytes.len() - 1]), Err(EmuError::InvalidMovie));
+    assert_eq!(Movie::from_bytes(b"RIFF\x00\x00\x00\x00"), Err(EmuError::InvalidMovie));
+}
+
+#[test]
+fn recorded_buttons_are_played_back_frame_by_frame() {
+    let inputs = [BUTTON_A, 0, BUTTON_START | BUTTON_LEFT];
+
+    let mut nes = Nes::new();
+    assert!(nes.stop_recording().is_empty());
+    nes.start_recording();
+    for &buttons in &inputs {
+        nes.set_buttons(1, buttons);
+        nes.run_frame();
+    }
+    let movie = nes.stop_recording();
+    assert_eq!(movie.len(), 3);
+    assert_eq!(movie.frame(2), Some([0, BUTTON_START | BUTTON_LEFT, 0, 0]));
+
+    // The host's input doesn't get through while the movie plays
+    nes.set_buttons(1, BUTTON_LEFT);
+    nes.play_movie(movie);
+    for &buttons in &inputs {
+        assert!(nes.is_playing_movie());
+        assert_eq!(nes.buttons(1), buttons);
+        nes.set_buttons(1, BUTTON_A);
+        nes.run_frame();
+    }
+    assert!(!nes.is_playing_movie());
+    assert_eq!(nes.buttons(1), 0);
+}
+
+fn hashes(inputs: &[u8]) -> Vec<u32> {
+    let mut nes = Nes::new();
+    nes.load_program(&SHOW_BUTTON_A, 0x0000).unwrap();
+    inputs.iter().map(|&buttons| nes.advance_frame_with_input([buttons, 0, 0, 0])).collect()
+}
+
+#[test]
+fn frame_advance_is_deterministic() {
+    let inputs = [0, BUTTON_A, BUTTON_A, 0, BUTTON_START];
+    let first  = hashes(&inputs);
+    assert_eq!(hashes(&inputs), first);
+
+    // The input shows in the frame
+    assert_ne!(first[1], first[3]);
+    assert_eq!(first[0], first[4]);
+}
@@ -0,0 +1,581 @@
+use nes_emulator::cartridge::Cartridge;
+use nes_emulator::interfaces::{CartridgeInterface, PpuInterface};
+use nes_emulator::ppu::{Olc2c02, OamEntry, PpuTiming, RenderMode, SCREEN_W};
+use nes_emulator::palette::{self, Palette, NES_PALETTE};
+use nes_emulator::error::EmuError;
+use nes_emulator::Nes;
+
+const DOTS_PER_FRAME: usize = 341 * 262;
+
+// NROM with vertical mirroring, tiles 1 to 3 are solid in their colour
+fn rom() -> Vec<u8> {
+    let mut rom = b"NES\x1A\x01\x01\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00".to_vec();
+    rom.resize(16 + 0x4000 + 0x2000, 0);
+    let chr = 16 + 0x4000;
+    rom[chr + 0x10..chr + 0x18].fill(0xFF);
+    rom[chr + 0x28..chr + 0x30].fill(0xFF);
+    rom[chr + 0x30..chr + 0x40].fill(0xFF);
+    rom
+}
+
+fn cartridge() -> Cartridge {
+    Cartridge::from_bytes(&rom()).unwrap()
+}
+
+fn write_vram(ppu: &mut Olc2c02, cart: &mut Cartridge, addr: u16, data: &[u8]) {
+    ppu.write_cpu(0x0006, (addr >> 8) as u8, cart);
+    ppu.write_cpu(0x0006, addr as u8, cart);
+    for &byte in data {
+        ppu.write_cpu(0x0007, byte, cart);
+    }
+}
+
+// Palette reads are not buffered
+fn read_palette(ppu: &mut Olc2c02, cart: &mut Cartridge, addr: u16) -> u8 {
+    ppu.write_cpu(0x0006, (addr >> 8) as u8, cart);
+    ppu.write_cpu(0x0006, addr as u8, cart);
+    ppu.read_cpu(0x0007, false, cart)
+}
+
+fn run_frames(ppu: &mut Olc2c02, cart: &mut Cartridge, frames: usize) {
+    for _ in 0..frames * DOTS_PER_FRAME {
+        ppu.clock(cart);
+    }
+}
+
+#[test]
+fn background_scrolls_across_nametables() {
+    let mut cart = cartridge();
+    let mut ppu  = Olc2c02::new();
+
+    // Nametable 0 is blank, nametable 1 is tile 1 with a row of tile 2
+    write_vram(&mut ppu, &mut cart, 0x2400, &[1; 0x3C0]);
+    write_vram(&mut ppu, &mut cart, 0x2400 + 4 * 32, &[2; 32]);
+    write_vram(&mut ppu, &mut cart, 0x3F00, &[0x0F, 0x16, 0x2A]);
+
+    // Scroll 100 pixels right (12 tiles and fine x 4) and 35 pixels down
+    ppu.write_cpu(0x0006, 0x00, &mut cart);
+    ppu.write_cpu(0x0006, 0x00, &mut cart);
+    ppu.write_cpu(0x0005, 100,  &mut cart);
+    ppu.write_cpu(0x0005, 35,   &mut cart);
+    ppu.write_cpu(0x0000, 0x00, &mut cart);
+    ppu.write_cpu(0x0001, 0x0A, &mut cart);
+    run_frames(&mut ppu, &mut cart, 2);
+
+    let frame = ppu.get_frame_buffer();
+    let pixel = |x: usize, y: usize| frame[y * SCREEN_W + x];
+
+    // Nametable 1 starts 256 - 100 pixels into the screen
+    assert_eq!(pixel(155, 100), 0x0F);
+    assert_eq!(pixel(156, 100), 0x16);
+    assert_eq!(pixel(255, 100), 0x16);
+
+    // Row 4 covers lines 32 to 39 of the nametable, only 5 are left on screen
+    assert_eq!(pixel(200, 0),  0x2A);
+    assert_eq!(pixel(200, 4),  0x2A);
+    assert_eq!(pixel(200, 5),  0x16);
+    assert_eq!(pixel(100, 5),  0x0F);
+}
+
+#[test]
+fn vram_is_not_touched_while_rendering_is_off() {
+    let mut cart = cartridge();
+    let mut ppu  = Olc2c02::new();
+
+    // The CPU's VRAM address must survive a frame with rendering off
+    ppu.write_cpu(0x0006, 0x23, &mut cart);
+    ppu.write_cpu(0x0006, 0x45, &mut cart);
+    run_frames(&mut ppu, &mut cart, 1);
+    ppu.write_cpu(0x0007, 0x99, &mut cart);
+
+    ppu.write_cpu(0x0006, 0x23, &mut cart);
+    ppu.write_cpu(0x0006, 0x45, &mut cart);
+    ppu.read_cpu(0x0007, false, &mut cart);
+    assert_eq!(ppu.read_cpu(0x0007, false, &mut cart), 0x99);
+}
+
+fn write_oam(ppu: &mut Olc2c02, cart: &mut Cartridge, sprites: &[[u8; 4]]) {
+    ppu.write_cpu(0x0003, 0x00, cart);
+    for byte in sprites.iter().flatten() {
+        ppu.write_cpu(0x0004, *byte, cart);
+    }
+    for _ in sprites.len() * 4..256 {
+        ppu.write_cpu(0x0004, 0xFF, cart);
+    }
+}
+
+#[test]
+fn only_eight_sprites_are_drawn_per_scanline() {
+    let mut cart = cartridge();
+    let mut ppu  = Olc2c02::new();
+
+    // Nine sprites next to each other on lines 50 to 57, one below the screen
+    let mut sprites: Vec<[u8; 4]> = (0..9).map(|i| [49, 1, 0x00, i * 16]).collect();
+    sprites.push([239, 1, 0x00, 0]);
+    write_oam(&mut ppu, &mut cart, &sprites);
+    write_vram(&mut ppu, &mut cart, 0x3F00, &[0x0F]);
+    write_vram(&mut ppu, &mut cart, 0x3F11, &[0x16]);
+
+    ppu.write_cpu(0x0001, 0x14, &mut cart);
+    run_frames(&mut ppu, &mut cart, 2);
+
+    let frame = ppu.get_frame_buffer();
+    let pixel = |x: usize, y: usize| frame[y * SCREEN_W + x];
+
+    // Sprites are drawn one line below their Y coordinate
+    assert_eq!(pixel(0, 49), 0x0F);
+    assert_eq!(pixel(0, 50), 0x16);
+    assert_eq!(pixel(7, 57), 0x16);
+    assert_eq!(pixel(8, 57), 0x0F);
+    assert_eq!(pixel(0, 58), 0x0F);
+
+    for i in 0..8 {
+        assert_eq!(pixel(i * 16 + 3, 53), 0x16);
+    }
+    assert_eq!(pixel(8 * 16 + 3, 53), 0x0F);
+
+    // Sprites found on the last scanline don't wrap around to the top
+    assert_eq!(pixel(0, 0), 0x0F);
+}
+
+#[test]
+fn tall_sprites_flip_and_go_behind_the_background() {
+    let mut cart = cartridge();
+    let mut ppu  = Olc2c02::new();
+
+    // Background on lines 96 to 103 only
+    write_vram(&mut ppu, &mut cart, 0x2000 + 12 * 32, &[1; 32]);
+    write_vram(&mut ppu, &mut cart, 0x3F00, &[0x0F, 0x2A]);
+    write_vram(&mut ppu, &mut cart, 0x3F11, &[0x16, 0x27, 0x30]);
+
+    // 8x16 sprites of tiles 2 and 3 on lines 96 to 111: behind the
+    // background, in front of it and flipped vertically
+    write_oam(&mut ppu, &mut cart, &[
+        [95, 0x02, 0x20, 8],
+        [95, 0x02, 0x00, 40],
+        [95, 0x02, 0x80, 80],
+    ]);
+
+    ppu.write_cpu(0x0006, 0x00, &mut cart);
+    ppu.write_cpu(0x0006, 0x00, &mut cart);
+    ppu.write_cpu(0x0000, 0x20, &mut cart);
+    ppu.write_cpu(0x0001, 0x1E, &mut cart);
+    run_frames(&mut ppu, &mut cart, 2);
+
+    let frame = ppu.get_frame_buffer();
+    let pixel = |x: usize, y: usize| frame[y * SCREEN_W + x];
+
+    assert_eq!(pixel(10, 100), 0x2A);
+    assert_eq!(pixel(10, 108), 0x30);
+    assert_eq!(pixel(42, 100), 0x27);
+    assert_eq!(pixel(42, 108), 0x30);
+    assert_eq!(pixel(82, 100), 0x30);
+    assert_eq!(pixel(82, 108), 0x27);
+    assert_eq!(pixel(82, 112), 0x0F);
+}
+
+// Whether the sprite overflow flag is set by line 60 with the sprites on
+// lines 50 to 57 plus some elsewhere
+fn sprite_overflow(sprites: &[[u8; 4]]) -> bool {
+    let mut cart = cartridge();
+    let mut ppu  = Olc2c02::new();
+    write_oam(&mut ppu, &mut cart, sprites);
+    ppu.write_cpu(0x0001, 0x18, &mut cart);
+
+    run_frames(&mut ppu, &mut cart, 1);
+    for _ in 0..60 * 341 {
+        ppu.clock(&mut cart);
+    }
+    ppu.read_cpu(0x0002, false, &mut cart) & Olc2c02::STATUS_SPRITE_OVERFLOW != 0
+}
+
+#[test]
+fn sprite_overflow_scans_oam_diagonally() {
+    let on_line: Vec<[u8; 4]> = (0..8).map(|i| [49, 1, 0x00, i * 8]).collect();
+    assert!(!sprite_overflow(&on_line));
+
+    let with = |extra: &[[u8; 4]]| sprite_overflow(&[on_line.as_slice(), extra].concat());
+
+    // A ninth sprite right after the eighth is found
+    assert!(with(&[[49, 1, 0x00, 0]]));
+
+    // One sprite later the PPU compares the tile id instead of the Y coordinate
+    assert!(!with(&[[0xF0, 1, 0x00, 0], [49, 1, 0x00, 0]]));
+    assert!(with(&[[0xF0, 1, 0x00, 0], [0xF0, 45, 0x00, 0]]));
+
+    // two sprites later the attributes, which lack bits 2 to 4 (49 reads as 33)
+    assert!(!with(&[[0xF0, 1, 0x00, 0], [0xF0, 1, 0x00, 0], [0xF0, 1, 49, 0]]));
+
+    // and three sprites later the X position
+    assert!(with(&[[0xF0, 1, 0x00, 0], [0xF0, 1, 0x00, 0], [0xF0, 1, 0x00, 0], [0xF0, 1, 0x00, 49]]));
+}
+
+#[test]
+fn palette_ram_is_mirrored_and_6_bits_wide() {
+    let mut cart = cartridge();
+    let mut ppu  = Olc2c02::new();
+
+    // The sprite palettes' background colours are the background's
+    write_vram(&mut ppu, &mut cart, 0x3F10, &[0xD5]);
+    assert_eq!(read_palette(&mut ppu, &mut cart, 0x3F00), 0x15);
+    write_vram(&mut ppu, &mut cart, 0x3F04, &[0x21]);
+    assert_eq!(read_palette(&mut ppu, &mut cart, 0x3F14), 0x21);
+
+    // Mirrored every 32 bytes, the top 2 bits are open bus
+    assert_eq!(read_palette(&mut ppu, &mut cart, 0x3FE0) & 0x3F, 0x15);
+    assert_eq!(read_palette(&mut ppu, &mut cart, 0x3F11), 0x00);
+}
+
+#[test]
+fn frame_converts_to_rgba() {
+    assert_eq!(palette::to_rgba(&[0x00, 0x30, 0x4D]), [
+         84,  84,  84, 0xFF,
+        236, 238, 236, 0xFF,
+          0,   0,   0, 0xFF,
+    ]);
+
+    // Grayscale mode draws the blue background in grey
+    let mut cart = cartridge();
+    let mut ppu  = Olc2c02::new();
+    write_vram(&mut ppu, &mut cart, 0x3F00, &[0x12]);
+    ppu.write_cpu(0x0001, 0x09, &mut cart);
+    run_frames(&mut ppu, &mut cart, 1);
+
+    let frame = palette::to_rgba(&ppu.get_frame_buffer());
+    assert_eq!(frame.len(), 256 * 240 * 4);
+    assert_eq!(frame[..4], [NES_PALETTE[0x10][0], NES_PALETTE[0x10][1], NES_PALETTE[0x10][2], 0xFF]);
+}
+
+#[test]
+fn finished_frames_are_flagged_until_taken() {
+    let mut emu = Nes::new();
+    emu.load_rom(&rom()).unwrap();
+    assert!(!emu.frame_complete());
+
+    let mut clocks = 0;
+    while !emu.frame_complete() {
+        emu.clock();
+        clocks += 1;
+    }
+    assert!(clocks <= DOTS_PER_FRAME);
+
+    assert_eq!(emu.take_frame_rgba().len(), 256 * 240 * 4);
+    assert!(!emu.frame_complete());
+}
+
+#[test]
+fn indexed_frames_map_through_the_lut() {
+    let mut emu = Nes::new();
+    emu.load_rom(&rom()).unwrap();
+    emu.run_frame();
+    while !emu.frame_complete() {
+        emu.clock();
+    }
+
+    let rgba    = emu.frame_rgba();
+    let indices = emu.take_frame_indexed();
+    assert!(!emu.frame_complete());
+    assert_eq!(indices.len(), 256 * 240);
+
+    let lut = palette::rgba_lut();
+    assert_eq!(lut.len(), 64 * 4);
+    let mapped: Vec<u8> = indices.iter().flat_map(|&i| lut[i as usize * 4..i as usize * 4 + 4].to_vec()).collect();
+    assert_eq!(mapped, rgba);
+}
+
+// Dots of the next four frames
+fn frame_lengths(ppu: &mut Olc2c02, cart: &mut Cartridge) -> Vec<usize> {
+    (0..4).map(|_| {
+        let mut dots = 0;
+        ppu.frame_complete = false;
+        while !ppu.frame_complete {
+            ppu.clock(cart);
+            dots += 1;
+        }
+        dots
+    }).collect()
+}
+
+#[test]
+fn odd_frames_are_one_dot_shorter_while_rendering() {
+    let mut cart = cartridge();
+    let mut ppu  = Olc2c02::new();
+
+    assert_eq!(frame_lengths(&mut ppu, &mut cart), [DOTS_PER_FRAME; 4]);
+
+    ppu.write_cpu(0x0001, 0x08, &mut cart);
+    assert_eq!(frame_lengths(&mut ppu, &mut cart), [DOTS_PER_FRAME, DOTS_PER_FRAME - 1, DOTS_PER_FRAME, DOTS_PER_FRAME - 1]);
+}
+
+#[test]
+fn oamdata_follows_oamaddr() {
+    let mut cart = cartridge();
+    let mut ppu  = Olc2c02::new();
+
+    ppu.write_cpu(0x0003, 0x10, &mut cart);
+    ppu.write_cpu(0x0004, 0x20, &mut cart);
+    ppu.write_cpu(0x0004, 0xFF, &mut cart);
+    ppu.write_cpu(0x0004, 0xFF, &mut cart);
+
+    // Reads don't move the address, the attribute byte has no bits 2 to 4
+    ppu.write_cpu(0x0003, 0x11, &mut cart);
+    assert_eq!(ppu.read_cpu(0x0004, false, &mut cart), 0xFF);
+    assert_eq!(ppu.read_cpu(0x0004, false, &mut cart), 0xFF);
+    ppu.write_cpu(0x0003, 0x12, &mut cart);
+    assert_eq!(ppu.read_cpu(0x0004, false, &mut cart), 0xE3);
+    ppu.write_cpu(0x0003, 0x10, &mut cart);
+    assert_eq!(ppu.read_cpu(0x0004, false, &mut cart), 0x20);
+}
+
+#[test]
+fn oam_decays_only_when_enabled() {
+    let oam_after_vblank = |decay: bool| {
+        let mut cart = cartridge();
+        let mut ppu  = Olc2c02::new();
+        ppu.set_oam_decay(decay);
+        ppu.write_cpu(0x0003, 0x00, &mut cart);
+        ppu.write_cpu(0x0004, 0x42, &mut cart);
+
+        // Rendering is off, nothing refreshes OAM for a frame
+        run_frames(&mut ppu, &mut cart, 1);
+        ppu.write_cpu(0x0003, 0x00, &mut cart);
+        ppu.read_cpu(0x0004, false, &mut cart)
+    };
+    assert_eq!(oam_after_vblank(false), 0x42);
+    assert_ne!(oam_after_vblank(true), 0x42);
+}
+
+// Pixels on line 100 and the sprite zero hit flag with the background on
+// lines 96 to 103 and sprite 0 covering x 0 to 7 of lines 100 to 107
+fn left_column(mask: u8) -> (Vec<u8>, bool) {
+    let mut cart = cartridge();
+    let mut ppu  = Olc2c02::new();
+    write_vram(&mut ppu, &mut cart, 0x2000 + 12 * 32, &[1; 32]);
+    write_vram(&mut ppu, &mut cart, 0x3F00, &[0x0F, 0x2A]);
+    write_vram(&mut ppu, &mut cart, 0x3F11, &[0x16]);
+    write_oam(&mut ppu, &mut cart, &[[99, 1, 0x00, 0]]);
+
+    ppu.write_cpu(0x0006, 0x00, &mut cart);
+    ppu.write_cpu(0x0006, 0x00, &mut cart);
+    ppu.write_cpu(0x0001, mask, &mut cart);
+    run_frames(&mut ppu, &mut cart, 1);
+    for _ in 0..110 * 341 {
+        ppu.clock(&mut cart);
+    }
+
+    let hit   = ppu.read_cpu(0x0002, false, &mut cart) & Olc2c02::STATUS_SPRITE_ZERO_HIT != 0;
+    let frame = ppu.get_frame_buffer();
+    (frame[100 * SCREEN_W..100 * SCREEN_W + 9].to_vec(), hit)
+}
+
+#[test]
+fn left_column_clips_background_and_sprites() {
+    let (line, hit) = left_column(0x1E);
+    assert_eq!(line[..8], [0x16; 8]);
+    assert!(hit);
+
+    // Only the background is left in the first 8 pixels
+    let (line, hit) = left_column(0x1A);
+    assert_eq!(line[..8], [0x2A; 8]);
+    assert!(!hit);
+
+    // Only the sprite
+    let (line, hit) = left_column(0x1C);
+    assert_eq!(line[..8], [0x16; 8]);
+    assert!(!hit);
+
+    // Both clipped, the backdrop shows
+    let (line, hit) = left_column(0x18);
+    assert_eq!(line[..8], [0x0F; 8]);
+    assert_eq!(line[8], 0x2A);
+    assert!(!hit);
+}
+
+#[test]
+fn chr_bank_switches_in_hblank_only_affect_later_sprite_fetches() {
+    // CNROM, tile 1 is solid in CHR bank 0 and blank in bank 1
+    let mut rom = b"NES\x1A\x01\x02\x30\x00\x00\x00\x00\x00\x00\x00\x00\x00".to_vec();
+    rom.resize(16 + 0x4000 + 0x4000, 0);
+    rom[16 + 0x4000 + 0x10..16 + 0x4000 + 0x18].fill(0xFF);
+    let mut cart = Cartridge::from_bytes(&rom).unwrap();
+    let mut ppu  = Olc2c02::new();
+
+    write_vram(&mut ppu, &mut cart, 0x3F00, &[0x0F]);
+    write_vram(&mut ppu, &mut cart, 0x3F11, &[0x16]);
+    write_oam(&mut ppu, &mut cart, &[[99, 1, 0x00, 0], [99, 1, 0x00, 16]]);
+    ppu.write_cpu(0x0001, 0x14, &mut cart);
+    run_frames(&mut ppu, &mut cart, 1);
+
+    // Switch banks on line 99 between the fetches of sprite 0 and sprite 1
+    for _ in 0..99 * 341 + 266 {
+        ppu.clock(&mut cart);
+    }
+    cart.write_cpu(0x8000, 1);
+    run_frames(&mut ppu, &mut cart, 1);
+
+    let frame = ppu.get_frame_buffer();
+    let pixel = |x: usize, y: usize| frame[y * SCREEN_W + x];
+    assert_eq!(pixel(0,  100), 0x16);
+    assert_eq!(pixel(16, 100), 0x0F);
+    assert_eq!(pixel(0,  101), 0x0F);
+}
+
+#[test]
+fn write_only_registers_read_back_the_io_latch() {
+    let mut cart = cartridge();
+    let mut ppu  = Olc2c02::new();
+
+    ppu.write_cpu(0x0003, 0x5A, &mut cart);
+    assert_eq!(ppu.read_cpu(0x0000, false, &mut cart), 0x5A);
+    assert_eq!(ppu.read_cpu(0x0006, false, &mut cart), 0x5A);
+
+    // PPUSTATUS only drives its top 3 bits and palette reads the low 6
+    assert_eq!(ppu.read_cpu(0x0002, false, &mut cart), 0x1A);
+    write_vram(&mut ppu, &mut cart, 0x3F00, &[0x15]);
+    assert_eq!(read_palette(&mut ppu, &mut cart, 0x3FC0), 0xD5);
+
+    // Every bit decays on its own once it isn't driven anymore
+    ppu.write_cpu(0x0003, 0xFF, &mut cart);
+    for _ in 0..Olc2c02::IO_LATCH_DECAY_DOTS / 2 {
+        ppu.clock(&mut cart);
+    }
+    let status = ppu.read_cpu(0x0002, false, &mut cart);
+    assert_eq!(status & 0x1F, 0x1F);
+
+    for _ in 0..Olc2c02::IO_LATCH_DECAY_DOTS / 2 + 1 {
+        ppu.clock(&mut cart);
+    }
+    assert_eq!(ppu.read_cpu(0x0001, false, &mut cart), status & 0xE0);
+}
+
+#[test]
+fn sprites_decode_for_the_inspector() {
+    let mut cart = cartridge();
+    let mut ppu  = Olc2c02::new();
+    write_vram(&mut ppu, &mut cart, 0x3F15, &[0x16, 0x27, 0x30]);
+    write_oam(&mut ppu, &mut cart, &[[20, 1, 0x00, 30], [40, 2, 0xE1, 50]]);
+
+    let sprites = ppu.get_oam_entries();
+    assert_eq!(sprites.len(), 64);
+    assert_eq!(sprites[1], OamEntry {
+        x: 50, y: 40, tile: 2, palette: 1,
+        flip_horizontal: true, flip_vertical: true, behind_background: true,
+    });
+
+    // Tile 2 only has its high plane set in rows 0 to 7
+    let thumbnail = ppu.get_sprite_thumbnail(1, &cart);
+    assert_eq!(thumbnail.len(), 8 * 8 * 4);
+    assert_eq!(thumbnail[..4], [NES_PALETTE[0x27][0], NES_PALETTE[0x27][1], NES_PALETTE[0x27][2], 0xFF]);
+
+    // Tall sprites flip both tiles, tile 3 is solid, tile 2 half
+    ppu.write_cpu(0x0000, 0x20, &mut cart);
+    let thumbnail = ppu.get_sprite_thumbnail(1, &cart);
+    assert_eq!(thumbnail.len(), 8 * 16 * 4);
+    assert_eq!(thumbnail[3], 0xFF);
+    assert_eq!(thumbnail[4 * 8 * 8..4 * 8 * 8 + 4], [NES_PALETTE[0x27][0], NES_PALETTE[0x27][1], NES_PALETTE[0x27][2], 0xFF]);
+
+    let thumbnail = ppu.get_sprite_thumbnail(0, &cart);
+    assert_eq!(thumbnail[3], 0x00);
+}
+
+// Scrolled background with sprites in front of and behind it, sprite 0 hits
+fn render_scene(mode: RenderMode, background: bool, sprites: bool) -> (Vec<u8>, u8) {
+    let mut cart = cartridge();
+    let mut ppu  = Olc2c02::new();
+    ppu.set_render_mode(mode);
+    ppu.set_layer_visibility(background, sprites);
+
+    write_vram(&mut ppu, &mut cart, 0x2400, &[1; 0x3C0]);
+    write_vram(&mut ppu, &mut cart, 0x2000 + 4 * 32, &[2; 32]);
+    write_vram(&mut ppu, &mut cart, 0x27C0, &[0x55; 0x40]);
+    write_vram(&mut ppu, &mut cart, 0x3F00, &[0x0F, 0x16, 0x2A, 0x27, 0x0F, 0x11, 0x21, 0x31]);
+    write_vram(&mut ppu, &mut cart, 0x3F11, &[0x14, 0x24, 0x34]);
+    write_oam(&mut ppu, &mut cart, &[
+        [20, 3, 0x00, 150],
+        [60, 2, 0x20, 170],
+        [90, 1, 0x40, 3],
+    ]);
+
+    ppu.write_cpu(0x0006, 0x00, &mut cart);
+    ppu.write_cpu(0x0006, 0x00, &mut cart);
+    ppu.write_cpu(0x0005, 100,  &mut cart);
+    ppu.write_cpu(0x0005, 13,   &mut cart);
+    ppu.write_cpu(0x0000, 0x00, &mut cart);
+    ppu.write_cpu(0x0001, 0x1C, &mut cart);
+    run_frames(&mut ppu, &mut cart, 2);
+    for _ in 0..100 * 341 {
+        ppu.clock(&mut cart);
+    }
+
+    let status = ppu.read_cpu(0x0002, false, &mut cart);
+    (ppu.get_frame_buffer(), status & Olc2c02::STATUS_SPRITE_ZERO_HIT)
+}
+
+#[test]
+fn scanline_mode_draws_static_scenes_like_the_dot_renderer() {
+    let (dot, dot_hit) = render_scene(RenderMode::Dot, true, true);
+    let (scanline, scanline_hit) = render_scene(RenderMode::Scanline, true, true);
+    assert!(dot.iter().any(|&colour| colour == 0x34));
+    assert_eq!(dot, scanline);
+    assert_eq!(dot_hit, Olc2c02::STATUS_SPRITE_ZERO_HIT);
+    assert_eq!(scanline_hit, dot_hit);
+}
+
+#[test]
+fn hidden_layers_are_left_out_of_the_picture() {
+    let (both, _) = render_scene(RenderMode::Dot, true, true);
+    let (background, background_hit) = render_scene(RenderMode::Dot, true, false);
+    let (sprites, sprites_hit) = render_scene(RenderMode::Dot, false, true);
+
+    // Sprite 0 is drawn with colour 0x34 over the background's 0x2A
+    assert!(both.contains(&0x34));
+    assert!(!background.contains(&0x34));
+    assert!(sprites.contains(&0x34));
+    assert!(!sprites.contains(&0x2A) && background.contains(&0x2A));
+
+    assert_eq!(background_hit, Olc2c02::STATUS_SPRITE_ZERO_HIT);
+    assert_eq!(sprites_hit, Olc2c02::STATUS_SPRITE_ZERO_HIT);
+}
+
+#[test]
+fn pal_files_replace_the_master_palette() {
+    let mut emu = Nes::new();
+    assert_eq!(emu.set_palette(&[0; 100]), Err(EmuError::InvalidPalette(100)));
+
+    // Colour i is (i, 0, 0), for emphasis e in the 512 colour file (i, e, 0)
+    let pal_64: Vec<u8>  = (0..64).flat_map(|i| [i as u8, 0, 0]).collect();
+    let pal_512: Vec<u8> = (0..512).flat_map(|i| [(i % 64) as u8, (i / 64) as u8, 0]).collect();
+
+    emu.set_palette(&pal_64).unwrap();
+    assert_eq!(emu.palette_lut()[0x21 * 4..0x22 * 4], [0x21, 0, 0, 0xFF]);
+
+    // Red and blue emphasis on the whole frame
+    let mut cart = cartridge();
+    let mut ppu  = Olc2c02::new();
+    write_vram(&mut ppu, &mut cart, 0x3F00, &[0x21]);
+    ppu.write_cpu(0x0001, 0xA0, &mut cart);
+    run_frames(&mut ppu, &mut cart, 1);
+    assert_eq!(ppu.get_emphasis(), [0x05; 240]);
+
+    let frame = ppu.get_frame_buffer();
+    let rgba  = Palette::from_pal(&pal_64).unwrap().to_rgba(&frame, ppu.get_emphasis());
+    assert_eq!(rgba[..4], [0x21, 0, 0, 0xFF]);
+    let rgba  = Palette::from_pal(&pal_512).unwrap().to_rgba(&frame, ppu.get_emphasis());
+    assert_eq!(rgba[..4], [0x21, 0x05, 0, 0xFF]);
+}
+
+#[test]
+fn timing_reports_the_position_in_the_frame() {
+    let mut cart = cartridge();
+    let mut ppu  = Olc2c02::new();
+
+    for _ in 0..10 * 341 + 5 {
+        ppu.clock(&mut cart);
+    }
+    assert_eq!(ppu.get_timing(), PpuTiming { scanline: 10, dot: 5, frame: 0, rendering_enabled: false });
+
+    // The odd frame is one dot shorter
+    ppu.write_cpu(0x0001, 0x08, &mut cart);
+    run_frames(&mut ppu, &mut cart, 2);
+    assert_eq!(ppu.get_timing(), PpuTiming { scanline: 10, dot: 6, frame: 2, rendering_enabled: true });
+}
@@ -0,0 +1,87 @@
+#![cfg(feature = "serde")]
+
+use nes_emulator::nes::Nes;
+use nes_emulator::rewind::{RewindBuffer, KEYFRAME_INTERVAL};
+use nes_emulator::controller::BUTTON_A;
+
+mod common;
+use common::COUNT_AND_SHOW_A;
+
+// 32 KB that change in a few places from one frame to the next
+fn state(frame: usize) -> Vec<u8> {
+    let mut state: Vec<u8> = (0..0x8000).map(|i| (i * 7) as u8).collect();
+    state[0] = frame as u8;
+    state[0x1234] = (frame / 3) as u8;
+    for i in 0..frame % 20 {
+        state[0x4000 + 16 * i] ^= 0xFF;
+    }
+    state
+}
+
+#[test]
+fn deltas_give_back_the_states() {
+    let mut rewind = RewindBuffer::new(150);
+    for frame in 0..400 {
+        rewind.push(state(frame));
+    }
+
+    // The oldest keyframe goes with its deltas
+    assert!(rewind.len() >= 150 && rewind.len() < 150 + KEYFRAME_INTERVAL);
+    assert!(rewind.memory_usage() * 10 < rewind.len() * state(0).len());
+
+    let kept = rewind.len();
+    for frame in (400 - kept..400).rev() {
+        assert_eq!(rewind.pop(), Some(state(frame)), "frame {}", frame);
+    }
+    assert_eq!(rewind.pop(), None);
+
+    // Going on after popping some
+    rewind.push(state(1));
+    rewind.push(state(2));
+    rewind.pop();
+    rewind.push(state(3));
+    assert_eq!(rewind.pop(), Some(state(3)));
+    assert_eq!(rewind.pop(), Some(state(1)));
+
+    // Off
+    let mut rewind = RewindBuffer::new(0);
+    rewind.push(state(0));
+    assert!(rewind.is_empty());
+}
+
+#[test]
+fn rewind_goes_back_frame_by_frame() {
+    let buttons = |frame: usize| if frame % 8 < 3 { BUTTON_A } else { 0 };
+
+    let mut emu = Nes::new();
+    emu.load_program(&COUNT_AND_SHOW_A, 0x0000).unwrap();
+    emu.set_rewind(600).unwrap();
+
+    // What the console looks like after every frame
+    let mut history = Vec::new();
+    for frame in 0..100 {
+        let hash = emu.advance_frame_with_input([buttons(frame), 0, 0, 0]);
+        history.push((hash, emu.get_counters(), emu.get_ram(0x0000, 0x800).unwrap()));
+    }
+    assert_eq!(emu.rewind_frames(), 100);
+
+    // Rewinding ten frames shows frame 90, the buttons held now don't matter
+    emu.set_buttons(0, 0);
+    for _ in 0..10 {
+        assert!(emu.rewind());
+    }
+    let (hash, counters, ram) = &history[89];
+    assert_eq!(emu.frame_hash(), *hash);
+    assert_eq!(emu.get_counters(), *counters);
+    assert_eq!(emu.get_ram(0x0000, 0x800).unwrap(), *ram);
+    assert_eq!(emu.rewind_frames(), 90);
+
+    // and the game goes on from there as it did before
+    for frame in 90..100 {
+        assert_eq!(emu.advance_frame_with_input([buttons(frame), 0, 0, 0]), history[frame].0);
+    }
+
+    // Down to the state before the first frame
+    while emu.rewind() {}
+    assert!(emu.get_counters().0 < history[0].1 .0);
+}
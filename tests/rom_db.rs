@@ -0,0 +1,45 @@
+use nes_emulator::cartridge::MIRROR;
+use nes_emulator::error::EmuError;
+use nes_emulator::rom_db::{RomDatabase, HeaderFix, crc32};
+use nes_emulator::Nes;
+
+#[test]
+fn crc32_matches_the_reference() {
+    assert_eq!(crc32(b""), 0x0000_0000);
+    assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+}
+
+#[test]
+fn database_text_is_parsed() {
+    let mut db = RomDatabase::empty();
+    db.parse("# comment\n\n0000ABCD 4 v 1 Some Game\n1234 - 4 -\n").unwrap();
+    assert_eq!(db.len(), 2);
+    assert_eq!(db.get(0xABCD), Some(&HeaderFix { mapper: Some(4), mirroring: Some(MIRROR::Vertical), battery: Some(true) }));
+    assert_eq!(db.get(0x1234), Some(&HeaderFix { mapper: None, mirroring: Some(MIRROR::FourScreen), battery: None }));
+
+    assert_eq!(db.parse("0000ABCD 4 x 1"), Err(EmuError::InvalidRomDatabase(1)));
+    assert_eq!(db.parse("\nZZZZ 4 v 1"), Err(EmuError::InvalidRomDatabase(2)));
+
+    // Panics if the built-in list is broken
+    RomDatabase::new();
+}
+
+#[test]
+fn bad_headers_are_corrected_on_load() {
+    // NROM with CNROM banking in the ROM, a typical bad dump header
+    let mut rom = b"NES\x1A\x01\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00".to_vec();
+    rom.extend([0x11; 0x4000]);
+    rom.extend([0x22; 0x2000]);
+    let crc = crc32(&rom[16..]);
+
+    let mut emu = Nes::new();
+    emu.rom_db().insert(crc, HeaderFix { mapper: Some(3), mirroring: Some(MIRROR::Vertical), battery: None });
+    emu.load_rom(&rom).unwrap();
+
+    let info = emu.rom_info().unwrap();
+    assert_eq!((info.crc32, info.mapper, info.mirroring), (crc, 3, MIRROR::Vertical));
+    assert_eq!(info.warnings, [
+        "mapper 0 corrected to 3 by the ROM database",
+        "Horizontal mirroring corrected to Vertical by the ROM database",
+    ]);
+}
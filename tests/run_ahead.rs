@@ -0,0 +1,39 @@
+#![cfg(feature = "serde")]
+
+use nes_emulator::nes::Nes;
+use nes_emulator::controller::BUTTON_A;
+
+mod common;
+use common::SHOW_BUTTON_A;
+
+#[test]
+fn run_ahead_shows_the_next_frame_without_advancing() {
+    let inputs = [0, 0, BUTTON_A, BUTTON_A, BUTTON_A, 0, 0];
+
+    let mut reference = Nes::new();
+    reference.load_program(&SHOW_BUTTON_A, 0x0000).unwrap();
+    let mut ahead = Nes::new();
+    ahead.load_program(&SHOW_BUTTON_A, 0x0000).unwrap();
+    ahead.set_run_ahead(1).unwrap();
+    assert_eq!(ahead.run_ahead(), 1);
+
+    let hashes: Vec<u32> = inputs.iter().map(|&buttons| reference.advance_frame_with_input([buttons, 0, 0, 0])).collect();
+    let mut reference = Nes::new();
+    reference.load_program(&SHOW_BUTTON_A, 0x0000).unwrap();
+
+    for (i, &buttons) in inputs.iter().enumerate() {
+        let shown = ahead.advance_frame_with_input([buttons, 0, 0, 0]);
+        reference.advance_frame_with_input([buttons, 0, 0, 0]);
+
+        // The state is the one of the frame, the picture the one after it
+        // as long as the buttons stay the same
+        assert_eq!(ahead.get_registers(), reference.get_registers());
+        assert_eq!(ahead.get_counters(), reference.get_counters());
+        if inputs.get(i + 1) == Some(&buttons) {
+            assert_eq!(shown, hashes[i + 1], "frame {}", i);
+        }
+    }
+
+    // Audio only comes from the frames that count
+    assert_eq!(ahead.audio_samples_available(), reference.audio_samples_available());
+}
@@ -0,0 +1,131 @@
+#![cfg(feature = "serde")]
+
+use nes_emulator::nes::{Nes, THUMBNAIL_WIDTH, THUMBNAIL_HEIGHT};
+use nes_emulator::controller::{BUTTON_A, BUTTON_B};
+use nes_emulator::error::EmuError;
+use nes_emulator::slots::{SlotInfo, SLOTS};
+
+mod common;
+use common::{COUNT_AND_SHOW_A, idle_nrom};
+
+// The idle game, told apart by a byte of its PRG ROM
+fn rom(crc: u8) -> Vec<u8> {
+    let mut rom = idle_nrom();
+    rom[16 + 0x1000] = crc;
+    rom
+}
+
+#[test]
+fn loading_a_state_resumes_exactly() {
+    let inputs = [BUTTON_A, 0, BUTTON_A | BUTTON_B, BUTTON_A, 0];
+
+    let mut emu = Nes::new();
+    emu.load_program(&COUNT_AND_SHOW_A, 0x0000).unwrap();
+    emu.advance_frame_with_input([BUTTON_A, 0, 0, 0]);
+    emu.set_buttons(0, BUTTON_A);
+    let state = emu.save_state();
+    assert_eq!(&state[0..4], b"NSS\x1A");
+
+    let hashes: Vec<u32> = inputs.iter().map(|&buttons| emu.advance_frame_with_input([buttons, 0, 0, 0])).collect();
+    let registers = emu.get_registers();
+    let counters  = emu.get_counters();
+    let ram       = emu.get_ram(0x0000, 0x800).unwrap();
+
+    emu.load_state(&state).unwrap();
+    let replayed: Vec<u32> = inputs.iter().map(|&buttons| emu.advance_frame_with_input([buttons, 0, 0, 0])).collect();
+    assert_eq!(replayed, hashes);
+    assert_eq!(emu.get_registers(), registers);
+    assert_eq!(emu.get_counters(), counters);
+    assert_eq!(emu.get_ram(0x0000, 0x800).unwrap(), ram);
+
+    // Also into another console running the same game
+    let mut other = Nes::new();
+    other.load_program(&[0xEA], 0x0000).unwrap();
+    other.load_state(&state).unwrap();
+    let replayed: Vec<u32> = inputs.iter().map(|&buttons| other.advance_frame_with_input([buttons, 0, 0, 0])).collect();
+    assert_eq!(replayed, hashes);
+}
+
+#[test]
+fn broken_states_are_refused() {
+    let mut emu = Nes::new();
+    emu.load_rom(&rom(1)).unwrap();
+    emu.run_frame();
+    let state = emu.save_state();
+    emu.run_frame();
+    let counters = emu.get_counters();
+
+    assert_eq!(emu.load_state(&[]), Err(EmuError::InvalidSaveState));
+    assert_eq!(emu.load_state(&state[..state.len() / 2]), Err(EmuError::InvalidSaveState));
+
+    let mut newer = state.clone();
+    newer[4] = 3;
+    assert_eq!(emu.load_state(&newer), Err(EmuError::SaveStateVersion(3, 2)));
+
+    let mut other = Nes::new();
+    other.load_rom(&rom(2)).unwrap();
+    assert_eq!(other.load_state(&state), Err(EmuError::SaveStateMismatch));
+
+    // Nothing was touched
+    assert_eq!(emu.get_counters(), counters);
+    emu.load_state(&state).unwrap();
+    assert!(emu.get_counters().0 < counters.0);
+}
+
+#[test]
+fn slots_keep_states_with_their_time() {
+    let mut emu = Nes::new();
+    emu.load_rom(&rom(1)).unwrap();
+    assert_eq!(emu.list_slots(), []);
+    assert_eq!(emu.load_slot(3), Err(EmuError::EmptySlot(3)));
+    assert_eq!(emu.save_slot(SLOTS), Err(EmuError::InvalidSlot(SLOTS)));
+
+    emu.run_frame();
+    let before = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_millis() as u64;
+    emu.save_slot(3).unwrap();
+    let counters = emu.get_counters();
+    emu.run_frame();
+    emu.save_slot(0).unwrap();
+
+    let slots = emu.list_slots();
+    assert_eq!(slots.iter().map(|info| info.slot).collect::<Vec<_>>(), [0, 3]);
+    assert!(slots[1].timestamp >= before && slots[1].timestamp <= slots[0].timestamp);
+
+    emu.load_slot(3).unwrap();
+    assert_eq!(emu.get_counters(), counters);
+
+    // Exported and imported by a front end that keeps them
+    let exported = emu.export_slot(3).unwrap();
+    emu.delete_slot(3).unwrap();
+    assert_eq!(emu.list_slots().len(), 1);
+    emu.import_slot(5, exported, 1_700_000_000_000).unwrap();
+    assert_eq!(emu.list_slots()[1], SlotInfo { slot: 5, timestamp: 1_700_000_000_000 });
+    emu.run_frame();
+    emu.load_slot(5).unwrap();
+    assert_eq!(emu.get_counters(), counters);
+
+    // A new game starts without slots
+    emu.load_rom(&rom(2)).unwrap();
+    assert_eq!(emu.list_slots(), []);
+}
+
+#[test]
+fn slots_show_the_frame_they_were_saved_at() {
+    let mut emu = Nes::new();
+    emu.load_program(&COUNT_AND_SHOW_A, 0x0000).unwrap();
+    emu.advance_frame_with_input([0, 0, 0, 0]);
+    emu.advance_frame_with_input([0, 0, 0, 0]);
+    emu.save_slot(0).unwrap();
+    emu.advance_frame_with_input([BUTTON_A, 0, 0, 0]);
+    emu.advance_frame_with_input([BUTTON_A, 0, 0, 0]);
+    emu.save_slot(1).unwrap();
+
+    // The backdrop, the whole frame in one colour
+    let backdrop = emu.frame_rgba()[0..4].to_vec();
+    let thumbnail = emu.slot_thumbnail(1).unwrap();
+    assert_eq!(thumbnail.len(), 4 * THUMBNAIL_WIDTH * THUMBNAIL_HEIGHT);
+    assert!(thumbnail.chunks(4).all(|pixel| pixel == backdrop));
+    assert_ne!(emu.slot_thumbnail(0).unwrap(), thumbnail);
+
+    assert_eq!(emu.slot_thumbnail(2), Err(EmuError::EmptySlot(2)));
+}
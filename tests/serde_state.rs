@@ -0,0 +1,48 @@
+#![cfg(feature = "serde")]
+
+use nes_emulator::interfaces::BusInterface;
+use nes_emulator::bus::{Bus, SimpleBus};
+use nes_emulator::cartridge::EmptyCartridge;
+use nes_emulator::cpu::Olc6502;
+
+#[test]
+fn cpu_state_round_trip() {
+    let mut bus = SimpleBus::new();
+    let mut cpu = Olc6502::new();
+
+    // LDA #$42; STA $10; INX
+    for (i, b) in [0xA9, 0x42, 0x85, 0x10, 0xE8].iter().enumerate() {
+        bus.write(0x0200 + i as u16, *b);
+    }
+    cpu.set_registers(0, 0, 0, 0xFD, 0x0200, 0x24);
+    cpu.force_cycles_zero();
+    cpu.step_instruction(&mut bus);
+
+    let json = serde_json::to_string(&cpu).unwrap();
+    let mut restored: Olc6502 = serde_json::from_str(&json).unwrap();
+
+    assert_eq!(restored.get_registers(), cpu.get_registers());
+    assert_eq!(restored.get_state(),     cpu.get_state());
+    assert_eq!(restored.get_counters(),  cpu.get_counters());
+
+    // Both copies carry on identically
+    cpu.step_instruction(&mut bus);
+    restored.step_instruction(&mut bus);
+    assert_eq!(restored.get_registers(), cpu.get_registers());
+    assert_eq!(restored.get_counters(),  cpu.get_counters());
+}
+
+#[test]
+fn bus_state_round_trip() {
+    let mut bus = Bus::new(Box::new(EmptyCartridge));
+    bus.write(0x0000, 0x12);
+    bus.write(0x07FF, 0x34);
+    bus.oam_dma.start(0x02);
+
+    let json = serde_json::to_string(&bus).unwrap();
+    let restored: Bus = serde_json::from_str(&json).unwrap();
+
+    assert_eq!(restored.get_ram(0x0000, 0x0800).unwrap(), bus.get_ram(0x0000, 0x0800).unwrap());
+    assert!(restored.oam_dma.is_active());
+    assert_eq!(restored.oam_dma.source_addr(), 0x0200);
+}